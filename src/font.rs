@@ -0,0 +1,499 @@
+//! Decodes downloadable fonts of @font-face rules into the OpenType/TrueType (sfnt) format that
+//! Pango loads.
+//! https://www.w3.org/TR/css-fonts-4/#font-face-rule
+//! https://www.w3.org/TR/WOFF/
+
+use std::vec::Vec;
+
+/// Returns the font `data` in the sfnt format. Returns None if the format is not supported or
+/// the data is broken.
+/// https://www.w3.org/TR/css-fonts-4/#src-desc
+pub fn decode_font(data: &[u8]) -> Option<Vec<u8>> {
+    match data.get(0..4)? {
+        // TrueType ("\0\x01\0\0" and "true") and OpenType with CFF data ("OTTO") are loaded as
+        // they are.
+        [0, 1, 0, 0] | b"true" | b"OTTO" => Some(data.to_vec()),
+        b"wOFF" => decode_woff(data),
+        b"wOF2" => {
+            // TODO: support WOFF 2.0, which is compressed by Brotli.
+            println!("warning: WOFF 2.0 fonts are not supported yet");
+            None
+        }
+        _ => {
+            println!("warning: unknown font format {:?}", &data[0..4]);
+            None
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Converts a WOFF 1.0 file to the sfnt font that it wraps.
+/// https://www.w3.org/TR/WOFF/#conform-restoresfnt
+fn decode_woff(data: &[u8]) -> Option<Vec<u8>> {
+    // https://www.w3.org/TR/WOFF/#WOFFHeader
+    let flavor = read_u32(data, 4)?;
+    let num_tables = read_u16(data, 12)? as usize;
+    // A font without tables has nothing to load.
+    if num_tables == 0 {
+        return None;
+    }
+
+    // "The table directory is an array of WOFF table directory entries"
+    // https://www.w3.org/TR/WOFF/#TableDirectory
+    struct Table {
+        tag: u32,
+        checksum: u32,
+        data: Vec<u8>,
+    }
+    let mut tables = Vec::new();
+    for i in 0..num_tables {
+        let entry = 44 + i * 20;
+        let tag = read_u32(data, entry)?;
+        let offset = read_u32(data, entry + 4)? as usize;
+        let comp_length = read_u32(data, entry + 8)? as usize;
+        let orig_length = read_u32(data, entry + 12)? as usize;
+        let checksum = read_u32(data, entry + 16)?;
+
+        let table_data = data.get(offset..offset + comp_length)?;
+        // "If compLength is less than origLength, the table is compressed ... with the
+        // "compress2" function of zlib." Otherwise, it's stored as it is.
+        let table_data = if comp_length < orig_length {
+            zlib_decompress(table_data, orig_length)?
+        } else {
+            table_data.to_vec()
+        };
+        if table_data.len() != orig_length {
+            return None;
+        }
+        tables.push(Table {
+            tag,
+            checksum,
+            data: table_data,
+        });
+    }
+
+    // The offset table of the sfnt font.
+    // https://learn.microsoft.com/en-us/typography/opentype/spec/otff#table-directory
+    let mut entry_selector = 0;
+    while (2 << entry_selector) <= num_tables {
+        entry_selector += 1;
+    }
+    let search_range = (1 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&flavor.to_be_bytes());
+    font.extend_from_slice(&(num_tables as u16).to_be_bytes());
+    font.extend_from_slice(&(search_range as u16).to_be_bytes());
+    font.extend_from_slice(&(entry_selector as u16).to_be_bytes());
+    font.extend_from_slice(&(range_shift as u16).to_be_bytes());
+
+    // The table records, followed by the tables that are padded to 4-byte boundaries.
+    let mut offset = 12 + num_tables * 16;
+    for table in &tables {
+        font.extend_from_slice(&table.tag.to_be_bytes());
+        font.extend_from_slice(&table.checksum.to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        offset += (table.data.len() + 3) & !3;
+    }
+    for table in &tables {
+        font.extend_from_slice(&table.data);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+
+    Some(font)
+}
+
+/// Decompresses zlib data, which is a DEFLATE stream with a 2-byte header and a checksum.
+/// Returns None if the data is longer than `limit` bytes.
+/// https://www.rfc-editor.org/rfc/rfc1950
+fn zlib_decompress(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let cmf = *data.first()?;
+    let flg = *data.get(1)?;
+    // "CM = 8 denotes the "deflate" compression method"
+    // "FDICT ... If set, a DICT dictionary identifier is present" but a preset dictionary is
+    // not used by WOFF.
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) || flg & 0x20 != 0 {
+        return None;
+    }
+    inflate(&data[2..], limit)
+}
+
+/// Reads the bits of a DEFLATE stream from the least significant bit of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Some(value)
+    }
+
+    /// Discards the remaining bits of the current byte.
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// A canonical Huffman code, given by the number of codes of each length and the symbols
+/// ordered by their codes.
+/// https://www.rfc-editor.org/rfc/rfc1951#section-3.2.2
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::new();
+        for length in 1..16 {
+            for (symbol, l) in lengths.iter().enumerate() {
+                if *l as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        // The codes of the same length are consecutive, so the code is compared with the range
+        // of the codes of each length.
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc1951#section-3.2.5
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Decompresses a DEFLATE stream. Returns None if the data is longer than `limit` bytes.
+/// https://www.rfc-editor.org/rfc/rfc1951#section-3.2.3
+fn inflate(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let is_final = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            // "00 - no compression"
+            0 => {
+                reader.align_to_byte();
+                let start = reader.position;
+                let length = u16::from_le_bytes([*data.get(start)?, *data.get(start + 1)?]);
+                // "NLEN is the one's complement of LEN."
+                let nlen = u16::from_le_bytes([*data.get(start + 2)?, *data.get(start + 3)?]);
+                if nlen != !length || output.len() + length as usize > limit {
+                    return None;
+                }
+                let data_start = start + 4;
+                output.extend_from_slice(data.get(data_start..data_start + length as usize)?);
+                reader.position = data_start + length as usize;
+            }
+            // "01 - compressed with fixed Huffman codes"
+            // https://www.rfc-editor.org/rfc/rfc1951#section-3.2.6
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[0..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+            // "10 - compressed with dynamic Huffman codes"
+            // https://www.rfc-editor.org/rfc/rfc1951#section-3.2.7
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+            // "11 - reserved (error)"
+            _ => return None,
+        }
+
+        if is_final {
+            return Some(output);
+        }
+    }
+}
+
+/// Reads the Huffman codes of a block compressed with dynamic Huffman codes.
+fn read_dynamic_codes(reader: &mut BitReader) -> Option<(Huffman, Huffman)> {
+    let hlit = reader.bits(5)? as usize + 257;
+    let hdist = reader.bits(5)? as usize + 1;
+    let hclen = reader.bits(4)? as usize + 4;
+
+    // "code lengths for the code length alphabet given just above, in the order: 16, 17, 18,
+    // 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15"
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    let mut code_lengths = [0; 19];
+    for i in 0..hclen {
+        code_lengths[ORDER[i]] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::new();
+    while lengths.len() < hlit + hdist {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            // "0 - 15: Represent code lengths of 0 - 15"
+            0..=15 => (symbol as u8, 1),
+            // "16: Copy the previous code length 3 - 6 times."
+            16 => (*lengths.last()?, 3 + reader.bits(2)?),
+            // "17: Repeat a code length of 0 for 3 - 10 times."
+            17 => (0, 3 + reader.bits(3)?),
+            // "18: Repeat a code length of 0 for 11 - 138 times"
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != hlit + hdist {
+        return None;
+    }
+
+    Some((
+        Huffman::new(&lengths[..hlit]),
+        Huffman::new(&lengths[hlit..]),
+    ))
+}
+
+/// Decodes the literals and the <length, backward distance> pairs of a compressed block.
+/// Returns None if the output gets longer than `limit` bytes.
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+) -> Option<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            if output.len() >= limit {
+                return None;
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        // "256: end of block"
+        if symbol == 256 {
+            return Some(());
+        }
+
+        let index = symbol - 257;
+        let length = *LENGTH_BASES.get(index)? as usize
+            + reader.bits(*LENGTH_EXTRA_BITS.get(index)? as u32)? as usize;
+        let index = distances.decode(reader)? as usize;
+        let distance = *DISTANCE_BASES.get(index)? as usize
+            + reader.bits(*DISTANCE_EXTRA_BITS.get(index)? as u32)? as usize;
+        if distance > output.len() || output.len() + length > limit {
+            return None;
+        }
+
+        // The copied bytes can overlap the bytes being written.
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello hello hello" compressed with fixed Huffman codes.
+    const FIXED: [u8; 10] = [203, 72, 205, 201, 201, 87, 200, 64, 144, 0];
+
+    /// `DYNAMIC_TEXT` compressed with dynamic Huffman codes.
+    const DYNAMIC: [u8; 31] = [
+        29, 136, 199, 17, 0, 0, 12, 130, 102, 181, 236, 63, 67, 36, 62, 144, 67, 78, 162, 109, 176,
+        222, 138, 46, 154, 27, 75, 52, 79, 209, 1,
+    ];
+    const DYNAMIC_TEXT: &[u8] = b"abcccaaaacaabacaaaadcaabccabaabcabadaaaabbadabaaba";
+
+    /// Returns a WOFF file of the `tables`, which are tuples of a tag, the data in the file and
+    /// the length of the original data.
+    fn woff(tables: &[(&[u8; 4], &[u8], usize)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"wOFF");
+        data.extend_from_slice(&[0, 1, 0, 0]);
+        data.resize(12, 0);
+        data.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        data.resize(44, 0);
+
+        let mut offset = 44 + tables.len() * 20;
+        for (tag, table, orig_length) in tables {
+            data.extend_from_slice(*tag);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            data.extend_from_slice(&(*orig_length as u32).to_be_bytes());
+            data.extend_from_slice(&[0, 0, 0, 0]);
+            offset += table.len();
+        }
+        for (_, table, _) in tables {
+            data.extend_from_slice(table);
+        }
+        data
+    }
+
+    #[test]
+    fn inflate_stored_block() {
+        let data = [0x01, 3, 0, !3, 0xff, b'a', b'b', b'c'];
+        assert_eq!(inflate(&data, 3), Some(b"abc".to_vec()));
+        // Longer than the limit.
+        assert_eq!(inflate(&data, 2), None);
+        // NLEN doesn't match LEN.
+        assert_eq!(inflate(&[0x01, 3, 0, 0, 0, b'a', b'b', b'c'], 3), None);
+        // Truncated.
+        assert_eq!(inflate(&data[..6], 3), None);
+    }
+
+    #[test]
+    fn inflate_fixed_block() {
+        assert_eq!(inflate(&FIXED, 100), Some(b"hello hello hello".to_vec()));
+        assert_eq!(inflate(&FIXED, 16), None);
+        assert_eq!(inflate(&FIXED[..5], 100), None);
+    }
+
+    #[test]
+    fn inflate_dynamic_block() {
+        assert_eq!(inflate(&DYNAMIC, 100), Some(DYNAMIC_TEXT.to_vec()));
+        assert_eq!(inflate(&DYNAMIC, DYNAMIC_TEXT.len() - 1), None);
+        assert_eq!(inflate(&DYNAMIC[..20], 100), None);
+    }
+
+    #[test]
+    fn inflate_bogus_data() {
+        assert_eq!(inflate(&[], 100), None);
+        // "11 - reserved (error)"
+        assert_eq!(inflate(&[0x07], 100), None);
+        // A non-final block without the next block.
+        assert_eq!(inflate(&[0x00, 0, 0, 0xff, 0xff], 100), None);
+    }
+
+    #[test]
+    fn dynamic_codes() {
+        let mut reader = BitReader::new(&DYNAMIC);
+        // BFINAL and BTYPE.
+        assert_eq!(reader.bits(3), Some(0b101));
+        let (literals, distances) = read_dynamic_codes(&mut reader).unwrap();
+        let mut output = Vec::new();
+        inflate_block(&mut reader, &mut output, &literals, &distances, 100).unwrap();
+        assert_eq!(output, DYNAMIC_TEXT);
+
+        // The code length 16 repeats the previous code length, but there is none.
+        // HLIT = 0, HDIST = 0, HCLEN = 0 and the code lengths of 16, 17, 18 and 0 are 1, 0, 0
+        // and 1, so the codes of 0 and 16 are 0 and 1.
+        let data = [0x00, 0x40, 0x80, 0x04];
+        let mut reader = BitReader::new(&data);
+        assert!(read_dynamic_codes(&mut reader).is_none());
+        // Truncated.
+        let mut reader = BitReader::new(&DYNAMIC[..3]);
+        reader.bits(3);
+        assert!(read_dynamic_codes(&mut reader).is_none());
+    }
+
+    #[test]
+    fn woff_round_trip() {
+        let glyf = b"glyf data glyf data glyf data glyf data";
+        let compressed = [
+            120, 156, 75, 207, 169, 76, 83, 72, 73, 44, 73, 84, 72, 199, 199, 2, 0, 26, 62, 14, 17,
+        ];
+        let data = woff(&[(b"glyf", &compressed, glyf.len()), (b"head", b"head!", 5)]);
+        let font = decode_font(&data).unwrap();
+
+        // The offset table: sfntVersion, numTables, searchRange, entrySelector and rangeShift.
+        assert_eq!(&font[0..12], &[0, 1, 0, 0, 0, 2, 0, 32, 0, 1, 0, 0]);
+        // The table records: tag, checksum, offset and length.
+        assert_eq!(&font[12..16], b"glyf");
+        assert_eq!(read_u32(&font, 20), Some(44));
+        assert_eq!(read_u32(&font, 24), Some(glyf.len() as u32));
+        assert_eq!(&font[28..32], b"head");
+        assert_eq!(read_u32(&font, 36), Some(84));
+        assert_eq!(read_u32(&font, 40), Some(5));
+        // The tables are padded to 4-byte boundaries.
+        assert_eq!(&font[44..44 + glyf.len()], glyf);
+        assert_eq!(&font[84..], b"head!\0\0\0");
+    }
+
+    #[test]
+    fn broken_woff() {
+        assert_eq!(decode_font(&woff(&[])), None);
+        // The original length doesn't match the data.
+        assert_eq!(decode_font(&woff(&[(b"head", b"head!", 4)])), None);
+        let data = woff(&[(b"head", b"head!", 5)]);
+        assert_eq!(decode_font(&data[..data.len() - 1]), None);
+        assert_eq!(decode_font(b"wOF"), None);
+    }
+}
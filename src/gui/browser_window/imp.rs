@@ -1,3 +1,5 @@
+use crate::renderer::page::Page;
+use core::cell::RefCell;
 use glib::subclass::InitializingObject;
 use glib::subclass::Signal;
 use gtk4::prelude::*;
@@ -11,7 +13,11 @@ pub struct BrowserWindow {
     #[template_child]
     pub entry: TemplateChild<SearchEntry>,
     #[template_child]
+    pub scrolled_window: TemplateChild<gtk4::ScrolledWindow>,
+    #[template_child]
     pub content_area: TemplateChild<gtk4::Box>,
+    /// The page shown in the content area.
+    pub page: RefCell<Option<Page>>,
 }
 
 #[glib::object_subclass]
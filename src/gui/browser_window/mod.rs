@@ -1,10 +1,17 @@
 mod imp;
 
+use crate::renderer::css::values::{ColorScheme, Viewport};
+use crate::renderer::html::serializer::serialize_node;
+use crate::renderer::page::Page;
 use glib::{clone, Object};
 use gtk4::gio::SimpleAction;
 use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
 use gtk4::{gio, glib, Application};
+use std::fs;
+
+/// The file that "save page" writes the current document to.
+const SAVED_PAGE_PATH: &str = "saved_page.html";
 
 glib::wrapper! {
     pub struct BrowserWindow(ObjectSubclass<imp::BrowserWindow>)
@@ -43,13 +50,70 @@ impl BrowserWindow {
             window.emit_by_name::<()>("start-handle-input", &[&uri]);
         }));
         self.add_action(&action_count);
+
+        let action_save = SimpleAction::new("save", None);
+        action_save.connect_activate(clone!(@weak self as window => move |_action, _parameter| {
+            window.save_page();
+        }));
+        self.add_action(&action_save);
+    }
+
+    pub fn set_page(&self, page: Page) {
+        self.imp().page.replace(Some(page));
+    }
+
+    pub fn page(&self) -> Option<Page> {
+        self.imp().page.borrow().clone()
+    }
+
+    /// Writes the serialization of the current document, including changes made by scripts, to
+    /// a file.
+    fn save_page(&self) {
+        let document = match self.page() {
+            Some(p) => p.document(),
+            None => return,
+        };
+
+        let document = document.borrow();
+        match fs::write(SAVED_PAGE_PATH, serialize_node(&document, document.root())) {
+            Ok(_) => println!("page saved to {:?}", SAVED_PAGE_PATH),
+            Err(e) => println!("failed to save page: {:?}", e),
+        }
     }
 
     pub fn get_content_area(&self) -> gtk4::Box {
         self.imp().content_area.get()
     }
 
-    fn clear_content_area(&self) {
+    /// Returns the size of the area that shows the page, with the scale factor of the monitor
+    /// and the color scheme of the GTK theme. The default size of the window is used before the
+    /// area is allocated.
+    pub fn viewport(&self) -> Viewport {
+        let scrolled_window = self.imp().scrolled_window.get();
+        let mut viewport = if scrolled_window.width() > 0 && scrolled_window.height() > 0 {
+            Viewport::new(
+                scrolled_window.width() as f64,
+                scrolled_window.height() as f64,
+            )
+        } else {
+            let (width, height) = self.default_size();
+            Viewport::new(width as f64, height as f64)
+        };
+
+        viewport.resolution = self.scale_factor() as f64;
+        if let Some(settings) = gtk4::Settings::default() {
+            let prefer_dark: bool = settings.property("gtk-application-prefer-dark-theme");
+            let theme_name: Option<String> = settings.property("gtk-theme-name");
+            let dark_theme = theme_name.map_or(false, |name| name.ends_with("-dark"));
+            if prefer_dark || dark_theme {
+                viewport.color_scheme = ColorScheme::Dark;
+            }
+        }
+
+        viewport
+    }
+
+    pub fn clear_content_area(&self) {
         while let Some(child) = self.imp().content_area.get().first_child() {
             self.imp().content_area.get().remove(&child);
         }
@@ -0,0 +1,68 @@
+//! Makes the fonts of @font-face rules available to Pango, which finds fonts through fontconfig.
+//! https://www.w3.org/TR/css-fonts-4/#font-face-rule
+
+use core::cell::RefCell;
+use gtk4::glib;
+use gtk4::glib::translate::ToGlibPtr;
+use gtk4::pango;
+use gtk4::prelude::*;
+use gtk4::Widget;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+#[link(name = "fontconfig")]
+extern "C" {
+    /// https://www.freedesktop.org/software/fontconfig/fontconfig-devel/fcconfigappfontaddfile.html
+    fn FcConfigAppFontAddFile(config: *mut c_void, file: *const c_char) -> c_int;
+}
+
+#[link(name = "pangoft2-1.0")]
+extern "C" {
+    /// https://docs.gtk.org/PangoFc/method.FontMap.config_changed.html
+    fn pango_fc_font_map_config_changed(fcfontmap: *mut pango::ffi::PangoFontMap);
+}
+
+thread_local! {
+    /// The font files that are already added to fontconfig.
+    static REGISTERED_FONT_FILES: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+}
+
+/// Adds `files` to the fonts that `widget` can use. The fonts are identified by the family names
+/// in the files, so the font-family of an @font-face rule is expected to match it.
+pub fn register_font_files(widget: &Widget, files: &[PathBuf]) {
+    let mut changed = false;
+    for file in files {
+        let is_new =
+            REGISTERED_FONT_FILES.with(|registered| registered.borrow_mut().insert(file.clone()));
+        if !is_new {
+            continue;
+        }
+        let path = match CString::new(file.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        // A null config is the current configuration.
+        if unsafe { FcConfigAppFontAddFile(std::ptr::null_mut(), path.as_ptr()) } == 0 {
+            println!("failed to add the font {:?}", file);
+            continue;
+        }
+        changed = true;
+    }
+
+    if !changed {
+        return;
+    }
+    // The font map caches the fonts that fontconfig found, so it needs to know that the
+    // configuration is changed.
+    // Note: only the font maps of fontconfig, which GTK uses on Linux, are supported.
+    if let Some(font_map) = widget.pango_context().font_map() {
+        let is_fc_font_map = glib::Type::from_name("PangoFcFontMap")
+            .map_or(false, |fc_font_map| font_map.type_().is_a(fc_font_map));
+        if is_fc_font_map {
+            unsafe { pango_fc_font_map_config_changed(font_map.to_glib_none().0) };
+        }
+    }
+}
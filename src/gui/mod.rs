@@ -1,78 +1,179 @@
 mod browser_window;
+mod fonts;
 
-use crate::renderer::html::dom::{ElementKind, NodeKind};
-use crate::renderer::layout::render_tree::{DisplayType, FontSize, RenderObject, RenderTree};
+use crate::renderer::css::values::Viewport;
+use crate::renderer::html::dom::{Document, ElementKind, NodeId, NodeKind};
+use crate::renderer::layout::render_tree::{DisplayType, FontWeight, RenderObject};
+use crate::renderer::page::Page;
 use browser_window::BrowserWindow;
 use core::cell::RefCell;
 use glib::{clone, closure_local};
 use gtk4::glib;
 use gtk4::prelude::*;
 use gtk4::{
-    Align, Application, Box, DrawingArea, Inhibit, Justification, Label, LinkButton, ListBox,
-    Orientation,
+    Align, Application, Box, DrawingArea, EventControllerMotion, GestureClick, Inhibit,
+    Justification, Label, LinkButton, ListBox, Orientation, Widget,
 };
 use std::rc::Rc;
+use std::time::Duration;
 
-fn should_create_new_box(kind: &NodeKind) -> bool {
+/// The title of the window when the page doesn't have a title.
+const DEFAULT_WINDOW_TITLE: &str = "vulbr";
+
+/// Returns true if `obj` paints its children in a new box. A block-level box stacks its children
+/// vertically, and a text and a link have their own boxes so that they're laid out as a unit.
+fn should_create_new_box(obj: &RenderObject, kind: &NodeKind) -> bool {
     match kind {
         NodeKind::Document => false,
-        NodeKind::Element(element) => match element.kind() {
-            ElementKind::Html
-            | ElementKind::Head
-            | ElementKind::Style
-            | ElementKind::Script
-            | ElementKind::H1
-            | ElementKind::H2
-            | ElementKind::P => false,
-            // TODO: correct?
-            ElementKind::Li | ElementKind::Ul => true,
-            ElementKind::Body | ElementKind::Div | ElementKind::A => true,
-        },
+        // A link paints its text as the label of the link button, which assumes that the text is
+        // in its own box. See `paint_render_object`.
+        NodeKind::Element(element) if element.kind() == ElementKind::A => true,
+        NodeKind::Element(_) => obj.style.display() != DisplayType::Inline,
         NodeKind::Text(_) => true,
     }
 }
 
-fn paint_render_object(obj: &Rc<RefCell<RenderObject>>, content_area: &Box) {
-    match &obj.borrow().kind() {
+/// Returns the ordinal of the list item `node` among its element siblings, starting from 1.
+/// Note: this doesn't support the start attribute and the value attribute.
+/// https://html.spec.whatwg.org/multipage/grouping-content.html#ordinal-value
+fn list_item_ordinal(document: &Document, node: NodeId) -> usize {
+    let mut ordinal = 1;
+    let mut sibling = document.node(node).previous_sibling();
+    while let Some(s) = sibling {
+        if document.node(s).element().is_some() {
+            ordinal += 1;
+        }
+        sibling = document.node(s).previous_sibling();
+    }
+    ordinal
+}
+
+/// Repaints the window that `widget` belongs to after the current event is handled, because the
+/// widgets are recreated by repainting.
+fn repaint_later(widget: &Widget) {
+    if let Some(window) = widget
+        .root()
+        .and_then(|root| root.downcast::<BrowserWindow>().ok())
+    {
+        glib::idle_add_local_once(move || paint_page(&window));
+    }
+}
+
+/// Tracks the pointer over `widget` that paints `node`, so that `node` matches :hover while the
+/// pointer is over it.
+/// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-hover
+fn connect_hover(widget: &impl IsA<Widget>, node: NodeId, page: &Page) {
+    let controller = EventControllerMotion::new();
+    controller.connect_enter(clone!(@strong page => move |controller, _x, _y| {
+        if page.set_hovered_element(Some(node)) {
+            repaint_later(&controller.widget());
+        }
+    }));
+    // The pointer goes back to the parent when it leaves `node`.
+    controller.connect_leave(clone!(@strong page => move |controller| {
+        let parent = {
+            let document = page.document();
+            let document = document.borrow();
+            document
+                .node(node)
+                .parent()
+                .filter(|p| document.node(*p).element().is_some())
+        };
+        if page.set_hovered_element(parent) {
+            repaint_later(&controller.widget());
+        }
+    }));
+    widget.add_controller(&controller);
+}
+
+fn paint_render_object(obj: &Rc<RefCell<RenderObject>>, content_area: &Box, page: &Page) {
+    let document = page.document();
+    let document = document.borrow();
+
+    // "The list-item keyword causes the element to generate a ::marker pseudo-element box"
+    // https://www.w3.org/TR/css-display-3/#valdef-display-list-item
+    if obj.borrow().style.display() == DisplayType::ListItem {
+        let ordinal = list_item_ordinal(&document, obj.borrow().node());
+        if let Some(marker) = obj.borrow().style.list_style_type().marker(ordinal) {
+            let marker = Label::builder()
+                .label(&marker)
+                .justify(Justification::Left)
+                .build();
+            content_area.append(&marker);
+        }
+    }
+
+    match obj.borrow().kind(&document) {
         NodeKind::Document => {}
         NodeKind::Element(element) => match element.kind() {
             ElementKind::Html
             | ElementKind::Head
+            | ElementKind::Title
+            | ElementKind::Link
+            | ElementKind::Meta
             | ElementKind::Style
             | ElementKind::Script
             | ElementKind::H1
             | ElementKind::H2
+            | ElementKind::H3
+            | ElementKind::H4
+            | ElementKind::H5
+            | ElementKind::H6
             | ElementKind::P
-            | ElementKind::Body => {}
-            ElementKind::Li => {
-                let bullet = Label::builder()
-                    .label("•")
-                    .justify(Justification::Left)
-                    .build();
-                content_area.append(&bullet);
-            }
-            ElementKind::Ul => {
+            | ElementKind::Button
+            | ElementKind::Select
+            | ElementKind::Optgroup
+            | ElementKind::Option
+            | ElementKind::Body
+            | ElementKind::Dl
+            | ElementKind::Dt
+            | ElementKind::Dd
+            | ElementKind::Table
+            | ElementKind::B
+            | ElementKind::Big
+            | ElementKind::Br
+            | ElementKind::Code
+            | ElementKind::Em
+            | ElementKind::Font
+            | ElementKind::I
+            | ElementKind::Img
+            | ElementKind::Nobr
+            | ElementKind::S
+            | ElementKind::Small
+            | ElementKind::Strike
+            | ElementKind::Strong
+            | ElementKind::Tt
+            | ElementKind::U
+            | ElementKind::Unknown
+            | ElementKind::Hr
+            | ElementKind::Li => {}
+            ElementKind::Ul | ElementKind::Ol => {
                 let list_box = ListBox::new();
                 content_area.append(&list_box);
             }
             ElementKind::Div => {
-                let width = obj.borrow().style.width();
-                let height = obj.borrow().style.height();
+                let width = obj.borrow().width();
+                let height = obj.borrow().height();
                 let div = DrawingArea::builder()
                     .content_height(height as i32)
                     .content_width(width as i32)
-                    .margin_start(obj.borrow().style.margin_left() as i32)
-                    .margin_top(obj.borrow().style.margin_top() as i32)
-                    .margin_end(obj.borrow().style.margin_right() as i32)
-                    .margin_bottom(obj.borrow().style.margin_bottom() as i32)
+                    .margin_start(obj.borrow().margin_left() as i32)
+                    .margin_top(obj.borrow().margin_top() as i32)
+                    .margin_end(obj.borrow().margin_right() as i32)
+                    .margin_bottom(obj.borrow().margin_bottom() as i32)
                     .build();
 
-                let bg_rgb = obj.borrow().style.background_color().rgb();
-                let padding_top = obj.borrow().style.padding_top();
-                let padding_right = obj.borrow().style.padding_right();
-                let padding_bottom = obj.borrow().style.padding_bottom();
-                let padding_left = obj.borrow().style.padding_left();
+                let background_color = obj.borrow().style.background_color();
+                let padding_top = obj.borrow().padding_top();
+                let padding_right = obj.borrow().padding_right();
+                let padding_bottom = obj.borrow().padding_bottom();
+                let padding_left = obj.borrow().padding_left();
                 div.set_draw_func(move |_drawing_area, cairo_context, _w, _h| {
+                    // A transparent background isn't painted.
+                    let bg_rgb = match &background_color {
+                        Some(color) => color.rgb(),
+                        None => return,
+                    };
                     cairo_context.rectangle(
                         padding_left as f64,
                         padding_top as f64,
@@ -86,13 +187,13 @@ fn paint_render_object(obj: &Rc<RefCell<RenderObject>>, content_area: &Box) {
             }
             ElementKind::A => {
                 let link = LinkButton::builder()
-                    .margin_start(obj.borrow().style.margin_left() as i32)
-                    .margin_top(obj.borrow().style.margin_top() as i32)
-                    .margin_end(obj.borrow().style.margin_right() as i32)
-                    .margin_bottom(obj.borrow().style.margin_bottom() as i32)
+                    .margin_start(obj.borrow().margin_left() as i32)
+                    .margin_top(obj.borrow().margin_top() as i32)
+                    .margin_end(obj.borrow().margin_right() as i32)
+                    .margin_bottom(obj.borrow().margin_bottom() as i32)
                     .build();
 
-                let attrs = match obj.borrow().kind() {
+                let attrs = match obj.borrow().kind(&document) {
                     NodeKind::Element(element) => match element.kind() {
                         ElementKind::A => element.attributes(),
                         _ => Vec::new(),
@@ -106,14 +207,52 @@ fn paint_render_object(obj: &Rc<RefCell<RenderObject>>, content_area: &Box) {
                     }
                 }
 
-                link.connect_activate_link(move |link| {
+                let node = obj.borrow().node();
+                connect_hover(&link, node, page);
+
+                // A link is activated and focused while a mouse button is pressed on it.
+                // Note: the link isn't repainted for :active and :focus here, because repainting
+                // recreates the link button and the click would never complete.
+                // https://html.spec.whatwg.org/multipage/semantics-other.html#selector-active
+                let gesture = GestureClick::new();
+                gesture.connect_pressed(clone!(@strong page => move |_gesture, _n, _x, _y| {
+                    page.set_active_element(Some(node));
+                    page.set_focused_element(Some(node));
+                }));
+                gesture.connect_released(clone!(@strong page => move |_gesture, _n, _x, _y| {
+                    page.set_active_element(None);
+                }));
+                link.add_controller(&gesture);
+
+                // Fire `click` at the <a> element first, so that a listener can cancel the
+                // navigation by `preventDefault()`.
+                link.connect_activate_link(clone!(@strong page => move |link| {
+                    let not_canceled = page.fire_event(node, "click");
+
+                    // Listeners may have changed the DOM tree and the title.
+                    if let Some(window) = link
+                        .root()
+                        .and_then(|root| root.downcast::<BrowserWindow>().ok())
+                    {
+                        if page.update_rendering() {
+                            paint_page(&window);
+                        } else {
+                            update_window_title(&window);
+                        }
+                    }
+
+                    if !not_canceled {
+                        println!("link click canceled by an event listener");
+                        return Inhibit(true);
+                    }
+
                     let uri: String = link.property("uri");
 
                     link.activate_action("win.clicked", Some(&uri.to_variant()))
                         .expect("failed to fire win.clicked action");
 
                     return Inhibit(true);
-                });
+                }));
 
                 content_area.append(&link);
             }
@@ -150,27 +289,41 @@ fn paint_render_object(obj: &Rc<RefCell<RenderObject>>, content_area: &Box) {
                 markup_attrs.push_str(&format!("foreground=\"{color_name}\" "));
             }
 
-            if obj.borrow().style.font_size() == FontSize::XXLarge {
-                markup_attrs.push_str(&format!("size=\"xx-large\""));
-            } else if obj.borrow().style.font_size() == FontSize::XLarge {
-                markup_attrs.push_str(&format!("size=\"x-large\""));
+            // "size: Font size in 1024ths of a point" and "1pt = 1/72th of 1in" and "1in = 96px".
+            let size = (obj.borrow().style.font_size() * 72.0 / 96.0 * 1024.0) as i32;
+            markup_attrs.push_str(&format!("size=\"{size}\" "));
+
+            if obj.borrow().style.font_weight() == FontWeight::Bold {
+                markup_attrs.push_str("weight=\"bold\" ");
+            }
+
+            if let Some(family) = obj.borrow().style.font_family() {
+                let family = glib::markup_escape_text(&family);
+                markup_attrs.push_str(&format!("font_family=\"{family}\" "));
             }
 
             // TODO: investigate why this needs.
             label.set_xalign(0.0);
 
+            // The text is escaped since it may contain "<" and "&", which start a markup.
+            let text = glib::markup_escape_text(text);
             label.set_markup(&format!("<span {markup_attrs}>{text}</span>"));
             content_area.append(&label);
         }
     }
 }
 
-fn paint_render_tree(obj: &Option<Rc<RefCell<RenderObject>>>, parent_content_area: &Box) {
+fn paint_render_tree(
+    obj: &Option<Rc<RefCell<RenderObject>>>,
+    parent_content_area: &Box,
+    page: &Page,
+) {
     match obj {
         Some(o) => {
-            paint_render_object(o, &parent_content_area);
+            paint_render_object(o, &parent_content_area, page);
 
-            if should_create_new_box(&o.borrow().kind()) {
+            let document = page.document();
+            if should_create_new_box(&o.borrow(), o.borrow().kind(&document.borrow())) {
                 let new_content_area = if o.borrow().style.display() == DisplayType::Inline {
                     Box::builder()
                         .valign(Align::Start)
@@ -181,39 +334,145 @@ fn paint_render_tree(obj: &Option<Rc<RefCell<RenderObject>>>, parent_content_are
                     Box::builder()
                         .valign(Align::Start)
                         .halign(Align::Start)
-                        .width_request(o.borrow().style.width() as i32)
+                        .width_request(o.borrow().width() as i32)
                         .orientation(Orientation::Vertical)
                         .build()
                 };
 
+                if let NodeKind::Element(_) = o.borrow().kind(&document.borrow()) {
+                    connect_hover(&new_content_area, o.borrow().node(), page);
+                }
                 parent_content_area.append(&new_content_area);
 
-                paint_render_tree(&o.borrow().first_child(), &new_content_area);
-                paint_render_tree(&o.borrow().next_sibling(), parent_content_area);
+                paint_render_tree(&o.borrow().first_child(), &new_content_area, page);
+                paint_render_tree(&o.borrow().next_sibling(), parent_content_area, page);
             } else {
-                paint_render_tree(&o.borrow().first_child(), parent_content_area);
-                paint_render_tree(&o.borrow().next_sibling(), parent_content_area);
+                paint_render_tree(&o.borrow().first_child(), parent_content_area, page);
+                paint_render_tree(&o.borrow().next_sibling(), parent_content_area, page);
             }
         }
         None => return,
     }
 }
 
-pub fn start_browser_window(handle_input: fn(String) -> RenderTree) {
+/// Shows the title of the current page as the title of `window`.
+/// https://html.spec.whatwg.org/multipage/dom.html#document.title
+fn update_window_title(window: &BrowserWindow) {
+    let title = match window.page() {
+        Some(p) => p.title(),
+        None => String::new(),
+    };
+
+    if title.is_empty() {
+        window.set_title(Some(DEFAULT_WINDOW_TITLE));
+    } else {
+        window.set_title(Some(&title));
+    }
+}
+
+/// Navigates to the URL requested by a meta element of the current page after the time it
+/// specifies, unless the window has moved to another page by then.
+/// https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn schedule_refresh(window: &BrowserWindow) {
+    let page = match window.page() {
+        Some(p) => p,
+        None => return,
+    };
+    let refresh = match page.refresh() {
+        Some(r) => r,
+        None => return,
+    };
+
+    println!(
+        "refresh to {:?} in {} seconds",
+        refresh.url, refresh.seconds
+    );
+
+    let document = page.document();
+    glib::timeout_add_local_once(
+        Duration::from_secs(refresh.seconds),
+        clone!(@weak window => move || {
+            let is_current_page = match window.page() {
+                Some(p) => Rc::ptr_eq(&p.document(), &document),
+                None => false,
+            };
+            if !is_current_page {
+                return;
+            }
+
+            window
+                .activate_action("win.clicked", Some(&refresh.url.to_variant()))
+                .expect("failed to fire win.clicked action");
+        }),
+    );
+}
+
+/// Clears the content area of `window` and paints the render tree of the current page.
+fn paint_page(window: &BrowserWindow) {
+    let page = match window.page() {
+        Some(p) => p,
+        None => return,
+    };
+
+    update_window_title(window);
+    fonts::register_font_files(window.upcast_ref(), &page.font_files());
+    window.clear_content_area();
+    let render_tree = page.render_tree();
+    paint_render_tree(
+        &render_tree.borrow().root,
+        &window.get_content_area(),
+        &page,
+    );
+}
+
+/// Restyles the current page of `window` after the window is laid out, if the viewport is
+/// changed, e.g. by resizing the window. The media queries are evaluated again by restyling.
+/// https://www.w3.org/TR/mediaqueries-5/#mq-list
+fn update_viewport_later(window: &BrowserWindow) {
+    glib::idle_add_local_once(clone!(@weak window => move || {
+        if let Some(page) = window.page() {
+            if page.set_viewport(window.viewport()) {
+                paint_page(&window);
+            }
+        }
+    }));
+}
+
+pub fn start_browser_window(handle_input: fn(String, Viewport) -> Page) {
     let application = Application::builder().application_id("vulbr").build();
+    application.set_accels_for_action("win.save", &["<Primary>s"]);
 
     application.connect_activate(
         clone!(@strong application, @strong handle_input => move |_| {
             let window = BrowserWindow::new(&application);
             window.set_default_size(1280, 800);
-            window.set_title(Some("vulbr"));
+            window.set_title(Some(DEFAULT_WINDOW_TITLE));
 
             window.connect_closure("start-handle-input", false, closure_local!(move |window: BrowserWindow, url: String| {
                 println!("start-handle-input {:?}", url);
-                let render_tree = handle_input(url);
-                paint_render_tree(&render_tree.root, &window.get_content_area());
+                let page = handle_input(url, window.viewport());
+                window.set_page(page);
+                paint_page(&window);
+                schedule_refresh(&window);
             }));
 
+            // The size of a window is saved in the default size when it's resized.
+            for property in ["default-width", "default-height", "maximized", "fullscreened"] {
+                window.connect_notify_local(Some(property), |window, _| {
+                    update_viewport_later(window);
+                });
+            }
+            if let Some(settings) = gtk4::Settings::default() {
+                for property in ["gtk-application-prefer-dark-theme", "gtk-theme-name"] {
+                    settings.connect_notify_local(
+                        Some(property),
+                        clone!(@weak window => move |_, _| {
+                            update_viewport_later(&window);
+                        }),
+                    );
+                }
+            }
+
             window.show();
         }),
     );
@@ -0,0 +1,40 @@
+//! The global history of the URLs that the browser has visited, which tells which links are
+//! visited links for the `:visited` pseudo-class.
+//! https://html.spec.whatwg.org/multipage/semantics-other.html#selector-visited
+
+use crate::url::ParsedUrl;
+use core::cell::RefCell;
+use std::string::String;
+use std::string::ToString;
+use std::vec::Vec;
+
+thread_local! {
+    static VISITED_URLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns `url` in the form of "scheme://host:port/path", so that the same URLs written in
+/// different ways are equal.
+fn normalize(url: &str) -> String {
+    // ParsedUrl supports only HTTP and HTTPS.
+    if url.starts_with("http://") || url.starts_with("https://") {
+        ParsedUrl::new(url.to_string()).join("")
+    } else {
+        url.to_string()
+    }
+}
+
+/// Adds `url` to the history.
+pub fn add_visited_url(url: &str) {
+    let url = normalize(url);
+    VISITED_URLS.with(|urls| {
+        if !urls.borrow().contains(&url) {
+            urls.borrow_mut().push(url);
+        }
+    });
+}
+
+/// Returns true if `url` is in the history.
+pub fn is_visited(url: &str) -> bool {
+    let url = normalize(url);
+    VISITED_URLS.with(|urls| urls.borrow().contains(&url))
+}
@@ -48,8 +48,9 @@ impl HttpClient {
 
         stream.write(request.as_bytes())?;
 
-        let mut buf = String::new();
-        stream.read_to_string(&mut buf)?;
+        // The body can be binary data such as a font, so the response is read as bytes.
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf)?;
 
         Ok(HttpResponse::new(buf))
     }
@@ -78,8 +79,8 @@ impl HttpClient {
 
         stream.write(request.as_bytes())?;
 
-        let mut buf = String::new();
-        stream.read_to_string(&mut buf)?;
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf)?;
 
         Ok(HttpResponse::new(buf))
     }
@@ -91,20 +92,42 @@ pub struct HttpResponse {
     status_code: u32,
     _reason: String,
     headers: Vec<Header>,
-    body: String,
+    body: Vec<u8>,
+}
+
+/// Splits `raw_response` into the status line and the headers, and the body. Only the former is
+/// decoded as text, because the body can be binary data.
+fn split_body(raw_response: &[u8]) -> (String, Vec<u8>) {
+    let mut i = 0;
+    while i < raw_response.len() {
+        if raw_response[i..].starts_with(b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&raw_response[..i + 2]).to_string();
+            return (head + "\n", raw_response[i + 4..].to_vec());
+        }
+        if raw_response[i..].starts_with(b"\n\n") {
+            let head = String::from_utf8_lossy(&raw_response[..i + 1]).to_string();
+            return (head + "\n", raw_response[i + 2..].to_vec());
+        }
+        i += 1;
+    }
+    (
+        String::from_utf8_lossy(raw_response).to_string(),
+        Vec::new(),
+    )
 }
 
 impl HttpResponse {
-    pub fn new(raw_response: String) -> Self {
-        let preprocessed_response = raw_response.replace("\n\r", "\n");
+    pub fn new(raw_response: Vec<u8>) -> Self {
+        let (head, body) = split_body(&raw_response);
+        let preprocessed_response = head.replace("\n\r", "\n");
 
         let (status_line, remaining) = match preprocessed_response.split_once("\n") {
             Some((s, r)) => (s, r),
             None => panic!("http response doesn't have a new line"),
         };
 
-        let (headers, body) = match remaining.split_once("\n\n") {
-            Some((h, b)) => {
+        let headers = match remaining.split_once("\n\n") {
+            Some((h, _)) => {
                 let mut headers = Vec::new();
                 for header in h.split("\n") {
                     // TODO: remove a new line cleaned_header
@@ -117,9 +140,9 @@ impl HttpResponse {
                         String::from(splitted_header[1].replacen(" ", "", 1)),
                     ));
                 }
-                (headers, b)
+                headers
             }
-            None => (Vec::new(), remaining),
+            None => Vec::new(),
         };
 
         let statuses: Vec<&str> = status_line.split(" ").collect();
@@ -132,7 +155,7 @@ impl HttpResponse {
             },
             _reason: statuses[2].to_string(),
             headers,
-            body: body.to_string(),
+            body,
         }
     }
 
@@ -140,7 +163,13 @@ impl HttpResponse {
         self.status_code
     }
 
+    /// Returns the body decoded as UTF-8.
     pub fn body(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// Returns the body as it is, e.g. for a font.
+    pub fn body_bytes(&self) -> Vec<u8> {
         self.body.clone()
     }
 
@@ -0,0 +1,149 @@
+//! Loads the subresources of a page: style sheets, the style sheets that they import and the
+//! fonts of @font-face rules.
+
+use crate::font::decode_font;
+use crate::http::HttpClient;
+use crate::renderer::css::cssom::{CssParser, FontFaceRule, Rule, StyleSheet};
+use crate::renderer::css::token::CssTokenizer;
+use crate::url::ParsedUrl;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::string::String;
+use std::vec::Vec;
+
+/// The font formats that can be decoded by `decode_font`.
+/// https://www.w3.org/TR/css-fonts-4/#font-format-definitions
+const SUPPORTED_FONT_FORMATS: [&str; 3] = ["truetype", "opentype", "woff"];
+
+/// Fetches the resource at `url`. Returns None if it fails.
+fn fetch(url: &str) -> Option<Vec<u8>> {
+    let parsed_url = ParsedUrl::new(url.to_string());
+    let client = HttpClient::new();
+    match client.get(&parsed_url) {
+        Ok(res) if res.status_code() == 200 => Some(res.body_bytes()),
+        Ok(res) => {
+            println!(
+                "failed to load {:?}: status code {:?}",
+                url,
+                res.status_code()
+            );
+            None
+        }
+        Err(e) => {
+            println!("failed to load {:?}: {:?}", url, e);
+            None
+        }
+    }
+}
+
+/// Fetches the style sheet linked from the page at `base_url` and the resources that it refers
+/// to. Returns None if it fails, so that the page is rendered without the style sheet.
+/// https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+pub fn fetch_style_sheet(base_url: &ParsedUrl, href: &str) -> Option<StyleSheet> {
+    let url = base_url.join(href);
+    println!("---------- fetch style sheet ----------");
+    println!("{:?}", url);
+
+    // "If the resource's Content-Type metadata is not text/css, then set success to false."
+    // Note: we don't check the Content-Type header.
+    let content = fetch(&url)?;
+    let mut style_sheet = parse_style_sheet(&content);
+    load_subresources(&mut style_sheet, &url, &mut vec![url.clone()]);
+    Some(style_sheet)
+}
+
+/// Parses the style sheet in the style element of the page at `document_url` and loads the
+/// resources that it refers to.
+pub fn load_inline_style_sheet(document_url: &str, content: String) -> StyleSheet {
+    let mut style_sheet = CssParser::new(CssTokenizer::new(content)).parse_stylesheet();
+    load_subresources(&mut style_sheet, document_url, &mut Vec::new());
+    style_sheet
+}
+
+fn parse_style_sheet(content: &[u8]) -> StyleSheet {
+    let content = String::from_utf8_lossy(content).to_string();
+    CssParser::new(CssTokenizer::new(content)).parse_stylesheet()
+}
+
+/// Loads the style sheets of the @import rules and the fonts of the @font-face rules in
+/// `style_sheet`, whose URL is `base_url`. `ancestors` are the URLs of the style sheets that
+/// import `style_sheet` directly or indirectly.
+fn load_subresources(style_sheet: &mut StyleSheet, base_url: &str, ancestors: &mut Vec<String>) {
+    load_rules(&mut style_sheet.rules, base_url, ancestors);
+}
+
+fn load_rules(rules: &mut [Rule], base_url: &str, ancestors: &mut Vec<String>) {
+    for rule in rules {
+        match rule {
+            Rule::QualifiedRule(_) => {}
+            Rule::AtRule(at_rule) => load_rules(&mut at_rule.rules, base_url, ancestors),
+            Rule::Supports(supports_rule) => {
+                load_rules(&mut supports_rule.rules, base_url, ancestors)
+            }
+            // https://www.w3.org/TR/css-cascade-4/#fetch-an-import
+            Rule::Import(import_rule) => {
+                let url = ParsedUrl::new(base_url.to_string()).join(&import_rule.href);
+                // A style sheet that imports itself directly or indirectly would be loaded
+                // forever, so the @import rule that makes the cycle is ignored.
+                if ancestors.contains(&url) {
+                    println!("warning: @import of {:?} makes a cycle", url);
+                    continue;
+                }
+
+                println!("---------- fetch imported style sheet ----------");
+                println!("{:?}", url);
+                let content = match fetch(&url) {
+                    Some(content) => content,
+                    None => continue,
+                };
+                let mut imported = parse_style_sheet(&content);
+                ancestors.push(url.clone());
+                load_subresources(&mut imported, &url, ancestors);
+                ancestors.pop();
+                import_rule.style_sheet = Some(imported);
+            }
+            Rule::FontFace(font_face_rule) => load_font(font_face_rule, base_url),
+        }
+    }
+}
+
+/// Loads the font of `font_face_rule` from the first source that can be loaded, and saves it as
+/// a file that Pango can load.
+/// https://www.w3.org/TR/css-fonts-4/#font-face-loading
+fn load_font(font_face_rule: &mut FontFaceRule, base_url: &str) {
+    for source in &font_face_rule.sources {
+        // "If the format hint is not supported, the user agent must skip downloading the
+        // resource."
+        if let Some(format) = &source.format {
+            if !SUPPORTED_FONT_FORMATS.contains(&format.as_str()) {
+                continue;
+            }
+        }
+
+        let url = ParsedUrl::new(base_url.to_string()).join(&source.url);
+        println!("---------- fetch font ----------");
+        println!("{:?}", url);
+        let font = match fetch(&url).and_then(|data| decode_font(&data)) {
+            Some(font) => font,
+            None => continue,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        let extension = if font.starts_with(b"OTTO") {
+            "otf"
+        } else {
+            "ttf"
+        };
+        let file: PathBuf =
+            std::env::temp_dir().join(format!("vulbr-font-{:016x}.{}", hasher.finish(), extension));
+        match std::fs::write(&file, font) {
+            Ok(()) => {
+                font_face_rule.file = Some(file);
+                return;
+            }
+            Err(e) => println!("failed to save the font {:?}: {:?}", url, e),
+        }
+    }
+}
@@ -1,82 +1,51 @@
+mod font;
 mod gui;
+mod history;
 mod http;
+mod loader;
 mod renderer;
 mod url;
 
 use crate::http::HttpClient;
 use crate::renderer::css::cssom::*;
-use crate::renderer::css::token::*;
+use crate::renderer::css::values::Viewport;
 use crate::renderer::html::dom::*;
 use crate::renderer::html::token::*;
 use crate::renderer::js::ast::{JsParser, Program};
 use crate::renderer::js::runtime::JsRuntime;
 use crate::renderer::js::token::JsLexer;
 use crate::renderer::layout::render_tree::*;
+use crate::renderer::page::Page;
 use crate::url::ParsedUrl;
 use core::cell::RefCell;
 use std::rc::Rc;
 use std::string::String;
 
 /// for debug
-fn print_dom(node: &Option<Rc<RefCell<Node>>>, depth: usize) {
+fn print_dom(document: &Document, node: Option<NodeId>, depth: usize) {
     match node {
         Some(n) => {
             print!("{}", "  ".repeat(depth));
-            println!("{:?}", n.borrow().kind());
-            print_dom(&n.borrow().first_child(), depth + 1);
-            print_dom(&n.borrow().next_sibling(), depth);
+            println!("{:?}", document.node(n).kind());
+            print_dom(document, document.node(n).first_child(), depth + 1);
+            print_dom(document, document.node(n).next_sibling(), depth);
         }
         None => return,
     }
 }
 
 /// for debug
-fn print_render_object(node: &Option<Rc<RefCell<RenderObject>>>, depth: usize) {
+fn print_render_object(
+    document: &Document,
+    node: &Option<Rc<RefCell<RenderObject>>>,
+    depth: usize,
+) {
     match node {
         Some(n) => {
             print!("{}", "  ".repeat(depth));
-            println!("{:?} {:?}", n.borrow().kind(), n.borrow().style);
-            print_render_object(&n.borrow().first_child(), depth + 1);
-            print_render_object(&n.borrow().next_sibling(), depth);
-        }
-        None => return,
-    }
-}
-
-fn dom_to_html(node: &Option<Rc<RefCell<Node>>>, html: &mut String) {
-    match node {
-        Some(n) => {
-            // open tag
-            match n.borrow().kind() {
-                NodeKind::Document => {}
-                NodeKind::Element(ref e) => {
-                    html.push_str("<");
-                    html.push_str(&Element::element_kind_to_string(e.kind()));
-                    for attr in e.attributes() {
-                        html.push_str(" ");
-                        html.push_str(&attr.name);
-                        html.push_str("=");
-                        html.push_str(&attr.value);
-                    }
-                    html.push_str(">");
-                }
-                NodeKind::Text(ref s) => html.push_str(s),
-            }
-
-            dom_to_html(&n.borrow().first_child(), html);
-
-            // close tag
-            match n.borrow().kind() {
-                NodeKind::Document => {}
-                NodeKind::Element(ref e) => {
-                    html.push_str("</");
-                    html.push_str(&Element::element_kind_to_string(e.kind()));
-                    html.push_str(">");
-                }
-                NodeKind::Text(_s) => {}
-            }
-
-            dom_to_html(&n.borrow().next_sibling(), html);
+            println!("{:?} {:?}", n.borrow().kind(document), n.borrow().style);
+            print_render_object(document, &n.borrow().first_child(), depth + 1);
+            print_render_object(document, &n.borrow().next_sibling(), depth);
         }
         None => return,
     }
@@ -89,7 +58,7 @@ fn print_ast(program: &Program) {
     }
 }
 
-fn handle_input(url: String) -> RenderTree {
+fn handle_input(url: String, viewport: Viewport) -> Page {
     // parse url
     let parsed_url = ParsedUrl::new(url.to_string());
     println!("---------- input url ----------");
@@ -125,55 +94,88 @@ fn handle_input(url: String) -> RenderTree {
     // html
     let html = response.body();
     let html_tokenizer = HtmlTokenizer::new(html);
-    let dom_root = HtmlParser::new(html_tokenizer).construct_tree();
-    println!("---------- document object model (dom) ----------");
-    print_dom(&Some(dom_root.clone()), 0);
-
-    // css
-    let style = get_style_content(dom_root.clone());
-    //load_css(style.as_bytes());
-    let css_tokenizer = CssTokenizer::new(style);
-    let cssom = CssParser::new(css_tokenizer).parse_stylesheet();
-
-    println!("---------- css object model (cssom) ----------");
-    println!("{:?}", cssom);
+    let mut html_parser = HtmlParser::new(html_tokenizer);
+    html_parser.document().borrow_mut().set_url(url.clone());
 
     // js
-    let js = get_js_content(dom_root.clone());
-    let lexer = JsLexer::new(js);
-
-    let mut parser = JsParser::new(lexer);
-    let ast = parser.parse_ast();
-    println!("---------- javascript abstract syntax tree (ast) ----------");
-    print_ast(&ast);
+    // Each script is executed as soon as the parser inserts it, so a script sees the DOM changes
+    // made by the scripts before it and the nodes that come after it aren't parsed yet.
+    let mut runtime = JsRuntime::new(html_parser.document(), url.clone());
+    while let Some(script) = html_parser.parse_until_script() {
+        let js = child_text_content(&html_parser.document().borrow(), script);
+        let lexer = JsLexer::new(js);
+
+        let mut parser = JsParser::new(lexer);
+        let ast = parser.parse_ast();
+        println!("---------- javascript abstract syntax tree (ast) ----------");
+        print_ast(&ast);
+
+        println!("---------- javascript runtime ----------");
+        runtime.execute(&ast);
+    }
 
-    println!("---------- javascript runtime ----------");
-    let mut runtime = JsRuntime::new(dom_root.clone(), url.clone());
-    runtime.execute(&ast);
+    let document = html_parser.document();
+
+    // Note: we don't load images, so every image with a src attribute fails to load as if its
+    // image data were broken.
+    // https://html.spec.whatwg.org/multipage/images.html#update-the-image-data
+    let root = document.borrow().root();
+    let imgs = Document::get_elements_by_tag_name(root, "img").elements(&document.borrow());
+    for img in imgs {
+        let has_src = match document.borrow().node(img).element() {
+            Some(e) => e.has_attribute("src"),
+            None => false,
+        };
+        if has_src {
+            runtime.fire_event(img, "error", false, false);
+        }
+    }
 
-    if runtime.dom_modified() {
-        println!("---------- modified document object model (dom) ----------");
-        let mut modified_html = String::new();
-        dom_to_html(&runtime.dom_root(), &mut modified_html);
+    // https://html.spec.whatwg.org/multipage/parsing.html#the-end
+    runtime.fire_event(root, "DOMContentLoaded", true, false);
+    // The load event is fired at the body element instead of the Window object that we don't
+    // have, because the onload attribute of a body element is the event handler of Window.
+    let body = Document::get_elements_by_tag_name(root, "body").item(&document.borrow(), 0);
+    if let Some(body) = body {
+        runtime.fire_event(body, "load", false, false);
+    }
 
-        let html_tokenizer = HtmlTokenizer::new(modified_html);
-        let modified_dom_root = HtmlParser::new(html_tokenizer).construct_tree();
-        print_dom(&Some(modified_dom_root.clone()), 0);
+    // The parser may have inserted nodes after the last script, so mutation observers have
+    // records to be delivered.
+    runtime.perform_microtask_checkpoint();
 
-        // apply css to html and create RenderTree
-        let render_tree = RenderTree::new(modified_dom_root.clone(), &cssom);
-        println!("---------- render tree ----------");
-        print_render_object(&render_tree.root, 0);
+    println!("---------- document object model (dom) ----------");
+    print_dom(&document.borrow(), Some(root), 0);
 
-        return render_tree;
+    // css
+    // Rules in a later style sheet come after rules in an earlier one, so the rules of each style
+    // sheet are appended in tree order of the style and link elements.
+    let mut cssom = StyleSheet::new();
+    for source in get_style_sheet_sources(&document.borrow()) {
+        let style_sheet = match source {
+            StyleSheetSource::Inline(content) => loader::load_inline_style_sheet(&url, content),
+            StyleSheetSource::External(href) => match loader::fetch_style_sheet(&parsed_url, &href)
+            {
+                Some(style_sheet) => style_sheet,
+                None => continue,
+            },
+        };
+        cssom.rules.extend(style_sheet.rules);
     }
 
+    println!("---------- css object model (cssom) ----------");
+    println!("{:?}", cssom);
+
+    // The page is loaded, so the links to it are visited links from now on.
+    history::add_visited_url(&url);
+
     // apply css to html and create RenderTree
-    let render_tree = RenderTree::new(dom_root.clone(), &cssom);
+    let render_tree = RenderTree::new(&document.borrow(), &cssom, viewport);
     println!("---------- render tree ----------");
-    print_render_object(&render_tree.root, 0);
+    print_render_object(&document.borrow(), &render_tree.root, 0);
 
-    return render_tree;
+    // keep the runtime alive to run event listeners while the page is shown
+    Page::new(url, document, runtime, cssom, render_tree)
 }
 
 fn main() {
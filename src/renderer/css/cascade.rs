@@ -0,0 +1,211 @@
+//! This is a part of "CSS Cascading and Inheritance Level 4".
+//! https://www.w3.org/TR/css-cascade-4/#cascading
+//!
+//! The cascade takes the declarations of all the rules that match an element and finds the
+//! winning declaration for each property, which is the cascaded value.
+
+use crate::renderer::css::cssom::{
+    ComplexSelector, CssStyleDeclaration, Declaration, Origin, PseudoClass, Selector, StyleSheet,
+};
+use crate::renderer::css::selector::matches_complex;
+use crate::renderer::css::values::Viewport;
+use crate::renderer::html::dom::{Document, NodeId};
+use std::vec::Vec;
+
+/// The specificity of a selector as (A, B, C), where A is the number of ID selectors, B is the
+/// number of class selectors, attribute selectors and pseudo-classes, and C is the number of
+/// type selectors. Specificities are compared lexicographically.
+/// https://www.w3.org/TR/selectors-4/#specificity-rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+impl Specificity {
+    fn add(self, other: Specificity) -> Self {
+        Specificity(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+/// https://www.w3.org/TR/selectors-4/#specificity-rules
+pub fn specificity(selector: &ComplexSelector) -> Specificity {
+    selector
+        .compounds
+        .iter()
+        .flatten()
+        .fold(Specificity::default(), |total, simple| {
+            total.add(simple_selector_specificity(simple))
+        })
+}
+
+fn simple_selector_specificity(selector: &Selector) -> Specificity {
+    match selector {
+        // "count the number of ID selectors in the selector (= A)"
+        Selector::IdSelector(_) => Specificity(1, 0, 0),
+        // "count the number of class selectors, attributes selectors, and pseudo-classes in the
+        // selector (= B)"
+        Selector::ClassSelector(_) | Selector::AttributeSelector(_) => Specificity(0, 1, 0),
+        Selector::PseudoClass(pseudo_class) => match pseudo_class {
+            // "The specificity of an :is(), :not(), or :has() pseudo-class is replaced by the
+            // specificity of the most specific complex selector in its selector list argument."
+            PseudoClass::Is(selectors) | PseudoClass::Not(selectors) => {
+                selectors.iter().map(specificity).max().unwrap_or_default()
+            }
+            // "The specificity of a :where() pseudo-class is replaced by zero."
+            PseudoClass::Where(_) => Specificity::default(),
+            _ => Specificity(0, 1, 0),
+        },
+        // "count the number of type selectors and pseudo-elements in the selector (= C)"
+        Selector::TypeSelector(_) => Specificity(0, 0, 1),
+        // "ignore the universal selector"
+        Selector::UniversalSelector => Specificity::default(),
+    }
+}
+
+/// The cascaded values of an element. Each property has at most one value, which is the value
+/// of the winning declaration. The properties are kept in the order of the cascade, i.e. the
+/// property whose winning declaration has the highest precedence comes last.
+/// https://www.w3.org/TR/css-cascade-4/#cascaded
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CascadedValues {
+    declarations: Vec<Declaration>,
+}
+
+impl CascadedValues {
+    pub fn new() -> Self {
+        Self {
+            declarations: Vec::new(),
+        }
+    }
+
+    /// Sets the winning declaration of its property, replacing the previous one.
+    fn set(&mut self, declaration: Declaration) {
+        self.declarations
+            .retain(|d| d.property != declaration.property);
+        self.declarations.push(declaration);
+    }
+
+    /// Returns the winning declaration of `property`, or None if no declaration applies.
+    pub fn get(&self, property: &str) -> Option<&Declaration> {
+        self.declarations.iter().find(|d| d.property == property)
+    }
+
+    /// Returns the winning declarations in the order of the cascade.
+    pub fn declarations(&self) -> Vec<Declaration> {
+        self.declarations.clone()
+    }
+}
+
+/// A declaration that applies to an element, with the information that the cascade sorts by.
+struct MatchedDeclaration {
+    declaration: Declaration,
+    origin: Origin,
+    /// True if the declaration is in the style attribute of the element.
+    element_attached: bool,
+    specificity: Specificity,
+    /// The position of the declaration in the order of appearance.
+    order: usize,
+}
+
+impl MatchedDeclaration {
+    /// "Declarations from origins earlier in this list win over declarations from later
+    /// origins:
+    /// 1. Transition declarations
+    /// 2. Important user agent declarations
+    /// 3. Important user declarations
+    /// 4. Important author declarations
+    /// 5. Animation declarations
+    /// 6. Normal author declarations
+    /// 7. Normal user declarations
+    /// 8. Normal user agent declarations"
+    ///
+    /// Returns the precedence of the origin and importance, where a larger number wins.
+    /// https://www.w3.org/TR/css-cascade-4/#cascade-origin
+    fn origin_precedence(&self) -> u8 {
+        let normal = match self.origin {
+            Origin::UserAgent => 0,
+            Origin::User => 1,
+            Origin::Author => 2,
+        };
+        if self.declaration.important {
+            // "the precedence of the origins is reversed for important declarations"
+            5 - normal
+        } else {
+            normal
+        }
+    }
+}
+
+/// Returns the cascaded values of `node` from the rules in `style_sheets` that match it. The
+/// style sheets are given in the order of appearance, and the rules in @media rules apply only
+/// if their media queries match `viewport`.
+/// https://www.w3.org/TR/css-cascade-4/#cascade-sort
+pub fn cascade(
+    style_sheets: &[&StyleSheet],
+    document: &Document,
+    node: NodeId,
+    viewport: &Viewport,
+) -> CascadedValues {
+    let mut matched: Vec<MatchedDeclaration> = Vec::new();
+    for style_sheet in style_sheets {
+        for rule in style_sheet.style_rules(viewport) {
+            // "If the selector is a selector list, this number is calculated for each selector in
+            // the list. For a given matching process against the list, the specificity in effect
+            // is that of the most specific selector in the list that matches."
+            // https://www.w3.org/TR/selectors-4/#specificity-rules
+            let specificity = match rule
+                .selectors
+                .iter()
+                .filter(|s| matches_complex(s, document, node))
+                .map(specificity)
+                .max()
+            {
+                Some(s) => s,
+                None => continue,
+            };
+
+            for declaration in &rule.declarations {
+                let order = matched.len();
+                matched.push(MatchedDeclaration {
+                    declaration: declaration.clone(),
+                    origin: style_sheet.origin,
+                    element_attached: false,
+                    specificity,
+                    order,
+                });
+            }
+        }
+    }
+
+    // "The declarations in a style attribute apply to the element to which the attribute
+    // belongs. In the cascade, these declarations are considered to have author origin and a
+    // specificity higher than any selector."
+    // https://www.w3.org/TR/css-style-attr/#interpret
+    for declaration in CssStyleDeclaration::new(node).declarations(document) {
+        let order = matched.len();
+        matched.push(MatchedDeclaration {
+            declaration,
+            origin: Origin::Author,
+            element_attached: true,
+            specificity: Specificity::default(),
+            order,
+        });
+    }
+
+    // "The cascade sorts declarations according to the following criteria, in descending order
+    // of precedence: Origin and Importance, Context, Element-Attached Styles, Layers,
+    // Specificity, Order of Appearance"
+    // The declaration that wins comes last after sorting, so it overwrites the others.
+    matched.sort_by_key(|m| {
+        (
+            m.origin_precedence(),
+            m.element_attached,
+            m.specificity,
+            m.order,
+        )
+    });
+
+    let mut values = CascadedValues::new();
+    for m in matched {
+        values.set(m.declaration);
+    }
+    values
+}
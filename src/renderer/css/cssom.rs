@@ -4,8 +4,18 @@
 //! 5. Parsing
 //! https://www.w3.org/TR/css-syntax-3/#parsing
 
+use crate::renderer::css::media::{
+    matches_media_query_list, parse_media_query_list, MediaQueryList,
+};
+use crate::renderer::css::shorthand::{expand_shorthand, longhands};
+use crate::renderer::css::supports::{
+    evaluate_supports_condition, parse_supports_condition, SupportsCondition,
+};
 use crate::renderer::css::token::*;
+use crate::renderer::css::values::{Length, Viewport};
+use crate::renderer::html::dom::{Document, NodeId};
 
+use std::path::PathBuf;
 use std::string::String;
 use std::string::ToString;
 use std::vec::Vec;
@@ -36,46 +46,211 @@ use std::vec::Vec;
 /// https://www.w3.org/TR/cssom-1/#cssstylesheet
 pub struct StyleSheet {
     /// https://drafts.csswg.org/cssom/#dom-cssstylesheet-cssrules
-    pub rules: Vec<QualifiedRule>,
+    pub rules: Vec<Rule>,
+    /// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+    pub origin: Origin,
 }
 
 impl StyleSheet {
+    /// Creates an empty style sheet of the author origin.
     pub fn new() -> Self {
-        Self { rules: Vec::new() }
+        Self {
+            rules: Vec::new(),
+            origin: Origin::Author,
+        }
     }
 
-    pub fn set_rules(&mut self, rules: Vec<QualifiedRule>) {
+    pub fn set_rules(&mut self, rules: Vec<Rule>) {
         self.rules = rules;
     }
+
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = origin;
+    }
+
+    /// Returns the style rules that apply in `viewport` in the order of appearance. The rules in
+    /// an @media rule apply only if its media query list matches `viewport`, and the rules in an
+    /// @supports rule apply only if its condition is true. The rules of an imported style sheet
+    /// are at the place of the @import rule.
+    /// https://www.w3.org/TR/css-conditional-3/#processing
+    /// https://www.w3.org/TR/css-cascade-4/#at-import
+    pub fn style_rules(&self, viewport: &Viewport) -> Vec<&QualifiedRule> {
+        let mut style_rules = Vec::new();
+        collect_style_rules(&self.rules, viewport, &mut style_rules);
+        style_rules
+    }
+
+    /// Returns the @font-face rules in this style sheet and in the imported style sheets.
+    /// Note: the @font-face rules in @media and @supports rules are always returned.
+    pub fn font_face_rules(&self) -> Vec<&FontFaceRule> {
+        let mut font_face_rules = Vec::new();
+        collect_font_face_rules(&self.rules, &mut font_face_rules);
+        font_face_rules
+    }
+}
+
+fn collect_font_face_rules<'a>(rules: &'a [Rule], font_face_rules: &mut Vec<&'a FontFaceRule>) {
+    for rule in rules {
+        match rule {
+            Rule::QualifiedRule(_) => {}
+            Rule::AtRule(at_rule) => collect_font_face_rules(&at_rule.rules, font_face_rules),
+            Rule::Import(import_rule) => {
+                if let Some(style_sheet) = &import_rule.style_sheet {
+                    collect_font_face_rules(&style_sheet.rules, font_face_rules);
+                }
+            }
+            Rule::Supports(supports_rule) => {
+                collect_font_face_rules(&supports_rule.rules, font_face_rules)
+            }
+            Rule::FontFace(font_face_rule) => font_face_rules.push(font_face_rule),
+        }
+    }
+}
+
+fn collect_style_rules<'a>(
+    rules: &'a [Rule],
+    viewport: &Viewport,
+    style_rules: &mut Vec<&'a QualifiedRule>,
+) {
+    for rule in rules {
+        match rule {
+            Rule::QualifiedRule(qualified_rule) => style_rules.push(qualified_rule),
+            Rule::AtRule(at_rule) => {
+                if matches_media_query_list(&at_rule.media_queries, viewport) {
+                    collect_style_rules(&at_rule.rules, viewport, style_rules);
+                }
+            }
+            // "The @import rule ... can be conditioned on media queries". A style sheet that
+            // failed to load is treated as an empty style sheet.
+            Rule::Import(import_rule) => {
+                if let Some(style_sheet) = &import_rule.style_sheet {
+                    if matches_media_query_list(&import_rule.media_queries, viewport) {
+                        collect_style_rules(&style_sheet.rules, viewport, style_rules);
+                    }
+                }
+            }
+            Rule::Supports(supports_rule) => {
+                if evaluate_supports_condition(&supports_rule.condition) {
+                    collect_style_rules(&supports_rule.rules, viewport, style_rules);
+                }
+            }
+            Rule::FontFace(_) => {}
+        }
+    }
+}
+
+/// The origin of a style sheet. The variants are in the order of precedence for normal
+/// declarations, from the lowest to the highest.
+/// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    /// https://www.w3.org/TR/css-cascade-4/#cascade-origin-ua
+    UserAgent,
+    /// https://www.w3.org/TR/css-cascade-4/#cascade-origin-user
+    User,
+    /// https://www.w3.org/TR/css-cascade-4/#cascade-origin-author
+    Author,
 }
 
+/// https://www.w3.org/TR/css-syntax-3/#parsing
 #[derive(Debug, Clone, PartialEq)]
-// TODO: implement it properly
+pub enum Rule {
+    QualifiedRule(QualifiedRule),
+    /// An @media rule.
+    AtRule(AtRule),
+    Import(ImportRule),
+    Supports(SupportsRule),
+    FontFace(FontFaceRule),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// https://www.w3.org/TR/css-syntax-3/#at-rule
+/// https://www.w3.org/TR/css-conditional-3/#at-media
+/// An @media rule, which has a media query list and the rules in its block. The other supported
+/// at-rules have their own variants of `Rule`.
 pub struct AtRule {
-    // TODO: support list of media query
+    /// The name of the at-rule without "@", e.g. "media".
+    pub name: String,
     /// https://www.w3.org/TR/mediaqueries-5/#typedef-media-query-list
-    pub prelude: String,
-    pub rule: QualifiedRule,
+    /// The prelude of the @media rule is parsed as a <media-query-list>.
+    pub media_queries: MediaQueryList,
+    /// The content of the @media rule's block is parsed as a list of rules.
+    pub rules: Vec<Rule>,
 }
 
-// TODO: support list of media query
 impl AtRule {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            media_queries: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// https://www.w3.org/TR/css-cascade-4/#at-import
+/// https://drafts.csswg.org/cssom/#the-cssimportrule-interface
+pub struct ImportRule {
+    /// The URL of the imported style sheet, which is relative to the URL of the style sheet that
+    /// has this rule.
+    pub href: String,
+    /// The style sheet is imported only if the media query list matches. An empty list matches
+    /// all media.
+    pub media_queries: MediaQueryList,
+    /// The imported style sheet, which is set when it's loaded. None if it's not loaded yet or
+    /// it failed to load.
+    pub style_sheet: Option<StyleSheet>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// https://www.w3.org/TR/css-conditional-3/#at-supports
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// https://www.w3.org/TR/css-fonts-4/#font-face-rule
+/// Only the font-family and src descriptors are supported.
+pub struct FontFaceRule {
+    /// https://www.w3.org/TR/css-fonts-4/#font-family-desc
+    pub family: Option<String>,
+    /// https://www.w3.org/TR/css-fonts-4/#src-desc
+    /// The URLs of the font and their format hints in the order of preference. local() sources
+    /// are skipped.
+    pub sources: Vec<FontFaceSource>,
+    /// The path of the font file that is loaded from one of the sources. None if it's not
+    /// loaded yet or all the sources failed to load.
+    pub file: Option<PathBuf>,
+}
+
+impl FontFaceRule {
     pub fn new() -> Self {
         Self {
-            prelude: String::new(),
-            rule: QualifiedRule::new(),
+            family: None,
+            sources: Vec::new(),
+            file: None,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// <url> [ format(<font-format>) ]?
+/// https://www.w3.org/TR/css-fonts-4/#src-desc
+pub struct FontFaceSource {
+    pub url: String,
+    /// The format hint in ASCII lowercase, e.g. "woff".
+    pub format: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// https://www.w3.org/TR/css-syntax-3/#qualified-rule
 /// https://www.w3.org/TR/css-syntax-3/#style-rules
 pub struct QualifiedRule {
-    // TODO: support multiple selectors
     /// https://www.w3.org/TR/selectors-4/#typedef-selector-list
     /// The prelude of the qualified rule is parsed as a <selector-list>.
-    pub selector: Selector,
+    pub selectors: SelectorList,
     /// https://www.w3.org/TR/css-syntax-3/#parse-a-list-of-declarations
     /// The content of the qualified rule’s block is parsed as a list of declarations.
     pub declarations: Vec<Declaration>,
@@ -84,13 +259,13 @@ pub struct QualifiedRule {
 impl QualifiedRule {
     pub fn new() -> Self {
         Self {
-            selector: Selector::TypeSelector("".to_string()),
+            selectors: Vec::new(),
             declarations: Vec::new(),
         }
     }
 
-    pub fn set_selector(&mut self, selector: Selector) {
-        self.selector = selector;
+    pub fn set_selectors(&mut self, selectors: SelectorList) {
+        self.selectors = selectors;
     }
 
     pub fn set_declarations(&mut self, declarations: Vec<Declaration>) {
@@ -98,23 +273,130 @@ impl QualifiedRule {
     }
 }
 
+/// "A selector list is a comma-separated list of selectors". It matches an element if any of
+/// the selectors matches.
+/// https://www.w3.org/TR/selectors-4/#selector-list
+pub type SelectorList = Vec<ComplexSelector>;
+
+/// "A compound selector is a sequence of simple selectors that are not separated by a
+/// combinator". It matches an element if all of the simple selectors match.
+/// https://www.w3.org/TR/selectors-4/#compound
+pub type CompoundSelector = Vec<Selector>;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-/// https://www.w3.org/TR/selectors-3/#selectors
+/// "A complex selector is a sequence of one or more compound selectors separated by
+/// combinators."
+/// https://www.w3.org/TR/selectors-4/#complex
+pub struct ComplexSelector {
+    /// The compound selectors from left to right. The last one is the subject of the selector.
+    pub compounds: Vec<CompoundSelector>,
+    /// The combinators between the compound selectors. `combinators[i]` is the combinator
+    /// between `compounds[i]` and `compounds[i + 1]`.
+    pub combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// https://www.w3.org/TR/selectors-4/#combinators
+pub enum Combinator {
+    /// https://www.w3.org/TR/selectors-4/#descendant-combinators
+    /// e.g. "A B"
+    Descendant,
+    /// https://www.w3.org/TR/selectors-4/#child-combinators
+    /// e.g. "A > B"
+    Child,
+    /// https://www.w3.org/TR/selectors-4/#adjacent-sibling-combinators
+    /// e.g. "A + B"
+    NextSibling,
+    /// https://www.w3.org/TR/selectors-4/#general-sibling-combinators
+    /// e.g. "A ~ B"
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A simple selector.
+/// https://www.w3.org/TR/selectors-4/#simple
 pub enum Selector {
-    /// https://www.w3.org/TR/selectors-3/#type-selectors
+    /// https://www.w3.org/TR/selectors-4/#type-selectors
     TypeSelector(String),
-    /// https://www.w3.org/TR/selectors-3/#class-html
+    /// https://www.w3.org/TR/selectors-4/#the-universal-selector
+    UniversalSelector,
+    /// https://www.w3.org/TR/selectors-4/#class-html
     ClassSelector(String),
-    /// https://www.w3.org/TR/selectors-3/#id-selectors
+    /// https://www.w3.org/TR/selectors-4/#id-selectors
     IdSelector(String),
-    /// This is an unofficial selector.
-    UnknownSelector,
+    /// https://www.w3.org/TR/selectors-4/#attribute-selectors
+    AttributeSelector(AttributeSelector),
+    /// https://www.w3.org/TR/selectors-4/#pseudo-classes
+    PseudoClass(PseudoClass),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// https://www.w3.org/TR/selectors-4/#pseudo-classes
+pub enum PseudoClass {
+    /// https://www.w3.org/TR/selectors-4/#first-child-pseudo
+    FirstChild,
+    /// https://www.w3.org/TR/selectors-4/#last-child-pseudo
+    LastChild,
+    /// https://www.w3.org/TR/selectors-4/#nth-child-pseudo
+    /// ":nth-child(An+B)" with A and B.
+    NthChild(i64, i64),
+    /// https://www.w3.org/TR/selectors-4/#negation
+    Not(SelectorList),
+    /// https://www.w3.org/TR/selectors-4/#matches
+    Is(SelectorList),
+    /// https://www.w3.org/TR/selectors-4/#zero-matches
+    Where(SelectorList),
+    /// https://www.w3.org/TR/selectors-4/#root-pseudo
+    Root,
+    /// https://www.w3.org/TR/selectors-4/#empty-pseudo
+    Empty,
+    /// https://www.w3.org/TR/selectors-4/#link
+    Link,
+    /// https://www.w3.org/TR/selectors-4/#visited-pseudo
+    Visited,
+    /// https://www.w3.org/TR/selectors-4/#hover-pseudo
+    Hover,
+    /// https://www.w3.org/TR/selectors-4/#active-pseudo
+    Active,
+    /// https://www.w3.org/TR/selectors-4/#focus-pseudo
+    Focus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// https://www.w3.org/TR/selectors-4/#attribute-selectors
+pub struct AttributeSelector {
+    pub name: String,
+    /// None for "[att]", which matches an element that has the attribute whatever the value is.
+    pub matcher: Option<(AttributeMatcher, String)>,
+    /// True if the value is compared ASCII case-insensitively, i.e. "[att=val i]".
+    /// https://www.w3.org/TR/selectors-4/#attribute-case
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// https://www.w3.org/TR/selectors-4/#attribute-representation
+/// https://www.w3.org/TR/selectors-4/#attribute-substrings
+pub enum AttributeMatcher {
+    /// "[att=val]"
+    Equal,
+    /// "[att~=val]"
+    Includes,
+    /// "[att|=val]"
+    DashMatch,
+    /// "[att^=val]"
+    Prefix,
+    /// "[att$=val]"
+    Suffix,
+    /// "[att*=val]"
+    Substring,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub property: String,
     pub value: ComponentValue,
+    /// https://www.w3.org/TR/css-cascade-4/#importance
+    pub important: bool,
 }
 
 /// https://www.w3.org/TR/css-syntax-3/#declaration
@@ -123,6 +405,7 @@ impl Declaration {
         Self {
             property: String::new(),
             value: ComponentValue::Keyword(String::new()),
+            important: false,
         }
     }
 
@@ -144,19 +427,301 @@ pub enum ComponentValue {
     /// https://www.w3.org/TR/css-values-3/#numeric-types
     /// This is one of basic data types.
     Number(f64),
+    /// https://www.w3.org/TR/css-values-4/#lengths
+    /// https://www.w3.org/TR/css-values-4/#percentages
+    Length(Length),
     /// https://www.w3.org/TR/css-syntax-3/#current-input-token
     /// The token from the list of tokens produced by the tokenizer.
     InputToken(CssToken),
+    /// https://www.w3.org/TR/css-syntax-3/#function
+    /// A function name and its arguments, e.g. "rgb(0, 0, 0)". The arguments include whitespace.
+    Function(String, Vec<ComponentValue>),
+}
+
+/// https://drafts.csswg.org/cssom/#serialize-a-css-component-value
+pub fn serialize_component_value(value: &ComponentValue) -> String {
+    match value {
+        ComponentValue::Keyword(keyword) => keyword.to_string(),
+        ComponentValue::Number(number) => format!("{}", number),
+        ComponentValue::Length(length) => match length {
+            Length::Px(v) => format!("{}px", v),
+            Length::Pt(v) => format!("{}pt", v),
+            Length::Em(v) => format!("{}em", v),
+            Length::Rem(v) => format!("{}rem", v),
+            Length::Ch(v) => format!("{}ch", v),
+            Length::Vw(v) => format!("{}vw", v),
+            Length::Vh(v) => format!("{}vh", v),
+            Length::Percentage(v) => format!("{}%", v),
+        },
+        ComponentValue::InputToken(token) => match token {
+            CssToken::Ident(ident) => ident.to_string(),
+            CssToken::HashToken(value, _) => format!("#{}", value),
+            CssToken::StringToken(string) => format!("\"{}\"", string),
+            CssToken::Url(url) => format!("url({})", url),
+            CssToken::Delim(c) => c.to_string(),
+            CssToken::Number(v) => format!("{}", v),
+            CssToken::Percentage(v) => format!("{}%", v),
+            CssToken::Dimension(v, unit) => format!("{}{}", v, unit),
+            CssToken::Comma => String::from(","),
+            CssToken::Whitespace => String::from(" "),
+            _ => String::new(),
+        },
+        ComponentValue::Function(name, arguments) => format!(
+            "{}({})",
+            name,
+            arguments
+                .iter()
+                .map(serialize_component_value)
+                .collect::<Vec<String>>()
+                .join("")
+        ),
+    }
+}
+
+/// https://drafts.csswg.org/cssom/#serialize-a-css-declaration-block
+pub fn serialize_declarations(declarations: &[Declaration]) -> String {
+    declarations
+        .iter()
+        .map(|d| {
+            // https://drafts.csswg.org/cssom/#serialize-a-css-declaration
+            let important = if d.important { " !important" } else { "" };
+            format!(
+                "{}: {}{};",
+                d.property,
+                serialize_component_value(&d.value),
+                important
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Returns the CSS property name for an IDL attribute name of CSSStyleDeclaration, e.g.
+/// "background-color" for "backgroundColor".
+/// https://drafts.csswg.org/cssom/#idl-attribute-to-css-property
+pub fn idl_attribute_to_css_property(attribute: &str) -> String {
+    // "For each character c in attribute: If c is in the range U+0041 to U+005A (ASCII
+    // uppercase), append U+002D (-) followed by c with 0x20 added to its code point to output.
+    // Otherwise, append c to output."
+    let mut output = String::new();
+    for c in attribute.chars() {
+        if c.is_ascii_uppercase() {
+            output.push('-');
+            output.push(c.to_ascii_lowercase());
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// https://drafts.csswg.org/cssom/#the-cssstyledeclaration-interface
+/// The CSS declaration block of the style attribute of an element, i.e. `element.style`. The
+/// declarations are parsed from the attribute every time they are accessed, and every change is
+/// written back to the attribute, which restyles the element.
+#[derive(Debug, Clone)]
+pub struct CssStyleDeclaration {
+    element: NodeId,
+}
+
+impl CssStyleDeclaration {
+    pub fn new(element: NodeId) -> Self {
+        Self { element }
+    }
+
+    /// Returns the declarations in the style attribute.
+    /// https://html.spec.whatwg.org/multipage/dom.html#the-style-attribute
+    pub fn declarations(&self, document: &Document) -> Vec<Declaration> {
+        let style = match document.node(self.element).element() {
+            Some(e) => e.get_attribute("style").unwrap_or_default(),
+            None => String::new(),
+        };
+        CssParser::new(CssTokenizer::new(style)).parse_list_of_declarations()
+    }
+
+    /// https://drafts.csswg.org/cssom/#update-style-attribute-for
+    fn update(&self, document: &mut Document, declarations: &[Declaration]) {
+        // "Set an attribute value for owner node using "style" and the result of serializing
+        // declaration block."
+        document
+            .set_attribute(self.element, "style", &serialize_declarations(declarations))
+            .expect("style is a valid attribute name");
+    }
+
+    /// https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-csstext
+    pub fn css_text(&self, document: &Document) -> String {
+        serialize_declarations(&self.declarations(document))
+    }
+
+    /// https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-csstext
+    pub fn set_css_text(&self, document: &mut Document, text: &str) {
+        // "Empty the declarations."
+        // "Parse the given value and, if the return value is not the empty list, insert the
+        // items in the list into the declarations, in specified order."
+        let declarations =
+            CssParser::new(CssTokenizer::new(text.to_string())).parse_list_of_declarations();
+        // "Update style attribute for the CSS declaration block."
+        self.update(document, &declarations);
+    }
+
+    /// https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-getpropertyvalue
+    pub fn get_property_value(&self, document: &Document, property: &str) -> String {
+        // "If property is a case-sensitive match for a property name of a CSS declaration in
+        // the declarations, then return the result of invoking serialize a CSS value of that
+        // declaration."
+        // "Return the empty string."
+        let property = property.to_ascii_lowercase();
+        match self
+            .declarations(document)
+            .iter()
+            .find(|d| d.property == property)
+        {
+            Some(d) => serialize_component_value(&d.value),
+            None => String::new(),
+        }
+    }
+
+    /// https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-setproperty
+    pub fn set_property(
+        &self,
+        document: &mut Document,
+        property: &str,
+        value: &str,
+        priority: &str,
+    ) {
+        let property = property.to_ascii_lowercase();
+        // "If value is the empty string, invoke removeProperty() with property as argument
+        // and return."
+        if value.is_empty() {
+            self.remove_property(document, &property);
+            return;
+        }
+
+        // "If priority is not the empty string and is not an ASCII case-insensitive match for
+        // the string "important", then return."
+        let important = priority.eq_ignore_ascii_case("important");
+        if !priority.is_empty() && !important {
+            return;
+        }
+
+        // "Let component value list be the result of parsing value for property property."
+        // "If component value list is null, then return."
+        // A shorthand property is parsed into the declarations of its longhand properties.
+        let parsed =
+            CssParser::new(CssTokenizer::new(value.to_string())).parse_value(&property, important);
+        if parsed.is_empty() {
+            return;
+        }
+
+        // "If property is a shorthand property, then for each longhand property longhand that
+        // property maps to, in canonical order, ... set the CSS declaration longhand with the
+        // appropriate value(s) from component value list". "Otherwise, let updated be the result
+        // of set the CSS declaration property with value component value list".
+        let mut declarations = self.declarations(document);
+        for declaration in parsed {
+            match declarations
+                .iter_mut()
+                .find(|d| d.property == declaration.property)
+            {
+                Some(d) => *d = declaration,
+                None => declarations.push(declaration),
+            }
+        }
+        // "If updated is true, update style attribute for the CSS declaration block."
+        self.update(document, &declarations);
+    }
+
+    /// https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-removeproperty
+    pub fn remove_property(&self, document: &mut Document, property: &str) -> String {
+        let property = property.to_ascii_lowercase();
+        // "Let value be the return value of invoking getPropertyValue() with property as
+        // argument."
+        let value = self.get_property_value(document, &property);
+        // "If property is a shorthand property, for each longhand property longhand that
+        // property maps to: If longhand is not a property name of a CSS declaration in the
+        // declarations, continue. Remove that CSS declaration and let removed be true."
+        // "Otherwise, if property is a case-sensitive match for a property name of a CSS
+        // declaration in the declarations, remove that CSS declaration and let removed be true."
+        let removed = longhands(&property).unwrap_or_else(|| vec![property.as_str()]);
+        let mut declarations = self.declarations(document);
+        let length = declarations.len();
+        declarations.retain(|d| !removed.contains(&d.property.as_str()));
+        // "If removed is true, Update style attribute for the CSS declaration block."
+        if declarations.len() != length {
+            self.update(document, &declarations);
+        }
+        value
+    }
+}
+
+/// The tokens for the parser with the positions where they start in the style sheet.
+#[derive(Debug, Clone)]
+struct TokenStream {
+    tokens: Vec<(CssToken, Option<SourcePosition>)>,
+    pos: usize,
+}
+
+impl TokenStream {
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<&CssToken> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// Returns the position of the last consumed token, or of the first token if no token is
+    /// consumed yet. Returns None if the tokens don't come from a style sheet.
+    fn position(&self) -> Option<SourcePosition> {
+        let i = self
+            .pos
+            .saturating_sub(1)
+            .min(self.tokens.len().checked_sub(1)?);
+        self.tokens[i].1
+    }
+}
+
+impl Iterator for TokenStream {
+    type Item = CssToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.tokens.get(self.pos)?.0.clone();
+        self.pos += 1;
+        Some(token)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CssParser {
-    t: std::iter::Peekable<CssTokenizer>,
+    t: TokenStream,
 }
 
 impl CssParser {
-    pub fn new(t: CssTokenizer) -> Self {
-        Self { t: t.peekable() }
+    pub fn new(mut t: CssTokenizer) -> Self {
+        let mut tokens = Vec::new();
+        while let Some((token, position)) = t.next_token() {
+            tokens.push((token, Some(position)));
+        }
+        Self {
+            t: TokenStream { tokens, pos: 0 },
+        }
+    }
+
+    /// Creates a parser for the tokens that are already tokenized, e.g. the value of a
+    /// declaration in an @supports condition.
+    pub fn from_tokens(tokens: Vec<CssToken>) -> Self {
+        Self {
+            t: TokenStream {
+                tokens: tokens.into_iter().map(|token| (token, None)).collect(),
+                pos: 0,
+            },
+        }
+    }
+
+    /// Prints a parse error or an unsupported feature with the line and the column of the last
+    /// consumed token.
+    fn warn(&self, message: &str) {
+        match self.t.position() {
+            Some(p) => println!("warning: {}:{}: {}", p.line, p.column, message),
+            None => println!("warning: {}", message),
+        }
     }
 
     fn consume_ident(&mut self) -> String {
@@ -183,77 +748,497 @@ impl CssParser {
         match token {
             CssToken::Ident(ident) => ComponentValue::Keyword(ident.to_string()),
             CssToken::Number(num) => ComponentValue::Number(num.clone()),
+            CssToken::Dimension(..) | CssToken::Percentage(_) => match Length::from_token(&token) {
+                Some(length) => ComponentValue::Length(length),
+                None => ComponentValue::InputToken(token),
+            },
+            // "If the current input token is a <function-token>, consume a function and return
+            // it."
+            CssToken::Function(name) => self.consume_function(name),
             _ => ComponentValue::InputToken(token),
         }
     }
 
-    /// https://www.w3.org/TR/css-syntax-3/#qualified-rule
-    /// Note: Most qualified rules will be style rules, where the prelude is a selector [SELECT]
-    /// and the block a list of declarations.
-    fn consume_selector(&mut self) -> Selector {
-        let token = match self.t.next() {
-            Some(t) => t,
-            None => panic!("should have a token but got None"),
+    /// Consumes the arguments of the function `name` whose <function-token> is already
+    /// consumed.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-function
+    fn consume_function(&mut self, name: String) -> ComponentValue {
+        let mut arguments = Vec::new();
+        loop {
+            match self.t.peek() {
+                // "<)-token>: Return the function."
+                Some(CssToken::CloseParenthesis) => {
+                    self.t.next();
+                    break;
+                }
+                // "<EOF-token>: This is a parse error. Return the function."
+                // A function that is not closed ends at the end of the declaration too, so that
+                // it doesn't consume the rest of the declaration block.
+                None | Some(CssToken::SemiColon) | Some(CssToken::CloseCurly) => break,
+                // "anything else: Reconsume the current input token. Consume a component value
+                // and append the returned value to the function's value."
+                Some(_) => arguments.push(self.consume_component_value()),
+            }
+        }
+        ComponentValue::Function(name, arguments)
+    }
+
+    /// Consumes a <selector-list> until a "{" or the end of the input, or a ")" of a functional
+    /// pseudo-class. Returns None if it's not a valid selector list.
+    /// https://www.w3.org/TR/selectors-4/#typedef-selector-list
+    fn consume_selector_list(&mut self) -> Option<SelectorList> {
+        // <selector-list> = <complex-selector-list>
+        // <complex-selector-list> = <complex-selector>#
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(self.consume_complex_selector()?);
+            match self.t.peek() {
+                Some(CssToken::Comma) => {
+                    self.t.next();
+                }
+                _ => return Some(selectors),
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/selectors-4/#typedef-complex-selector
+    fn consume_complex_selector(&mut self) -> Option<ComplexSelector> {
+        // <complex-selector> = <compound-selector> [ <combinator>? <compound-selector> ]*
+        self.consume_whitespace();
+        let mut selector = ComplexSelector {
+            compounds: vec![self.consume_compound_selector()?],
+            combinators: Vec::new(),
         };
 
-        match token {
-            // TODO: support tag.class and tag#id
-            CssToken::HashToken(value) => Selector::IdSelector(value[1..].to_string()),
-            CssToken::Delim(delim) => {
-                if delim == '.' {
-                    return Selector::ClassSelector(self.consume_ident());
+        loop {
+            // "White space is forbidden between the components of a compound selector", so
+            // whitespace before another compound selector is the descendant combinator.
+            let has_whitespace = self.t.peek() == Some(&CssToken::Whitespace);
+            self.consume_whitespace();
+
+            // <combinator> = '>' | '+' | '~' | [ '|' '|' ]
+            let combinator = match self.t.peek() {
+                Some(CssToken::Delim('>')) => Combinator::Child,
+                Some(CssToken::Delim('+')) => Combinator::NextSibling,
+                Some(CssToken::Delim('~')) => Combinator::SubsequentSibling,
+                Some(CssToken::Comma)
+                | Some(CssToken::OpenCurly)
+                | Some(CssToken::CloseParenthesis)
+                | None => return Some(selector),
+                Some(_) if has_whitespace => Combinator::Descendant,
+                Some(_) => return None,
+            };
+            if combinator != Combinator::Descendant {
+                self.t.next();
+                self.consume_whitespace();
+            }
+
+            selector.combinators.push(combinator);
+            selector.compounds.push(self.consume_compound_selector()?);
+        }
+    }
+
+    /// Consumes simple selectors as long as they continue. Returns None if no simple selector
+    /// is consumed or they are invalid.
+    /// https://www.w3.org/TR/selectors-4/#typedef-compound-selector
+    fn consume_compound_selector(&mut self) -> Option<CompoundSelector> {
+        // <compound-selector> = [ <type-selector>? <subclass-selector>* ]!
+        let mut compound = Vec::new();
+        loop {
+            let selector = match self.t.peek() {
+                // "If it contains a type selector or universal selector, that selector must come
+                // first in the sequence."
+                Some(CssToken::Ident(_)) | Some(CssToken::Delim('*')) if !compound.is_empty() => {
+                    return None
+                }
+                Some(CssToken::Ident(ident)) => {
+                    let ident = ident.to_string();
+                    self.t.next();
+                    Selector::TypeSelector(ident)
+                }
+                Some(CssToken::Delim('*')) => {
+                    self.t.next();
+                    Selector::UniversalSelector
+                }
+                // "An ID selector consists of a “number sign” (U+0023, #) immediately followed
+                // by the ID value, which must be a CSS identifier."
+                Some(CssToken::HashToken(value, HashType::Id)) => {
+                    let value = value.to_string();
+                    self.t.next();
+                    Selector::IdSelector(value)
+                }
+                Some(CssToken::Delim('.')) => {
+                    self.t.next();
+                    match self.t.next() {
+                        Some(CssToken::Ident(ident)) => Selector::ClassSelector(ident),
+                        _ => return None,
+                    }
+                }
+                Some(CssToken::OpenSquare) => {
+                    self.t.next();
+                    Selector::AttributeSelector(self.consume_attribute_selector()?)
+                }
+                Some(CssToken::Colon) => {
+                    self.t.next();
+                    Selector::PseudoClass(self.consume_pseudo_class()?)
+                }
+                _ => break,
+            };
+            compound.push(selector);
+        }
+
+        if compound.is_empty() {
+            None
+        } else {
+            Some(compound)
+        }
+    }
+
+    /// Consumes a pseudo-class after a ":". Returns None if it's not supported or invalid, e.g. a
+    /// pseudo-element that starts with "::".
+    /// https://www.w3.org/TR/selectors-4/#typedef-pseudo-class-selector
+    fn consume_pseudo_class(&mut self) -> Option<PseudoClass> {
+        // <pseudo-class-selector> = ':' <ident-token> |
+        //                           ':' <function-token> <any-value> ')'
+        // "Like other CSS keywords, pseudo-class names are ASCII case-insensitive."
+        match self.t.next() {
+            Some(CssToken::Ident(name)) => match name.to_ascii_lowercase().as_str() {
+                "first-child" => Some(PseudoClass::FirstChild),
+                "last-child" => Some(PseudoClass::LastChild),
+                "root" => Some(PseudoClass::Root),
+                "empty" => Some(PseudoClass::Empty),
+                "link" => Some(PseudoClass::Link),
+                "visited" => Some(PseudoClass::Visited),
+                "hover" => Some(PseudoClass::Hover),
+                "active" => Some(PseudoClass::Active),
+                "focus" => Some(PseudoClass::Focus),
+                _ => {
+                    self.warn(&format!("pseudo-class :{} is not supported yet", name));
+                    None
+                }
+            },
+            Some(CssToken::Function(name)) => {
+                let pseudo_class = match name.to_ascii_lowercase().as_str() {
+                    "nth-child" => {
+                        let (a, b) = self.consume_an_plus_b()?;
+                        PseudoClass::NthChild(a, b)
+                    }
+                    // TODO: :is() and :where() take a <forgiving-selector-list>, which ignores
+                    // invalid selectors in the list.
+                    "not" => PseudoClass::Not(self.consume_selector_list()?),
+                    "is" => PseudoClass::Is(self.consume_selector_list()?),
+                    "where" => PseudoClass::Where(self.consume_selector_list()?),
+                    _ => {
+                        self.warn(&format!("pseudo-class :{}() is not supported yet", name));
+                        return None;
+                    }
+                };
+                self.consume_whitespace();
+                match self.t.next() {
+                    Some(CssToken::CloseParenthesis) => Some(pseudo_class),
+                    _ => None,
                 }
-                panic!("Parse error: {:?} is an unexpected token.", token);
             }
-            CssToken::Ident(ident) => {
-                // TODO: fix this. Skip pseudo-classes such as :link and :visited
-                if self.t.peek() == Some(&CssToken::Colon) {
-                    while self.t.peek() != Some(&CssToken::OpenCurly) {
+            _ => None,
+        }
+    }
+
+    /// Consumes the An+B microsyntax, e.g. "2n+1", "odd" and "-n + 3", and returns A and B.
+    /// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+    fn consume_an_plus_b(&mut self) -> Option<(i64, i64)> {
+        self.consume_whitespace();
+
+        // Get A and the rest of the token that starts with "n", e.g. "n-1" of "2n-1".
+        let (a, rest) = match self.t.next()? {
+            // "odd: 2n+1", "even: 2n"
+            CssToken::Ident(ident) if ident.eq_ignore_ascii_case("odd") => return Some((2, 1)),
+            CssToken::Ident(ident) if ident.eq_ignore_ascii_case("even") => return Some((2, 0)),
+            // <integer>
+            CssToken::Number(b) => return Some((0, Self::to_integer(b)?)),
+            // <n-dimension>, <ndashdigit-dimension> and <ndash-dimension>
+            CssToken::Dimension(a, unit) => (Self::to_integer(a)?, unit),
+            // "-n", "-n-" and <dashndashdigit-ident>
+            CssToken::Ident(ident) if ident.starts_with('-') => (-1, ident[1..].to_string()),
+            // "n", "n-" and <ndashdigit-ident>
+            CssToken::Ident(ident) => (1, ident),
+            // "+n", "+n-" and "+" <ndashdigit-ident>. No whitespace is allowed after "+".
+            CssToken::Delim('+') => match self.t.next()? {
+                CssToken::Ident(ident) => (1, ident),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let rest = rest.to_ascii_lowercase();
+        if !rest.starts_with('n') {
+            return None;
+        }
+        let b = match &rest[1..] {
+            // A B may follow, e.g. "2n + 1" and "2n -1".
+            "" => {
+                self.consume_whitespace();
+                match self.t.peek() {
+                    // <signed-integer>
+                    // TODO: distinguish a signed integer from a signless integer such as "2n 1".
+                    Some(CssToken::Number(b)) => {
+                        let b = Self::to_integer(*b)?;
                         self.t.next();
+                        b
+                    }
+                    // ['+' | '-'] <signless-integer>
+                    Some(CssToken::Delim(sign)) if *sign == '+' || *sign == '-' => {
+                        let sign = if *sign == '+' { 1 } else { -1 };
+                        self.t.next();
+                        self.consume_whitespace();
+                        match self.t.next()? {
+                            CssToken::Number(b) if b >= 0.0 => sign * Self::to_integer(b)?,
+                            _ => return None,
+                        }
                     }
+                    _ => 0,
                 }
-                Selector::TypeSelector(ident.to_string())
             }
-            CssToken::AtKeyword(_keyword) => {
-                // skip until "{" comes
-                while self.t.peek() != Some(&CssToken::OpenCurly) {
-                    self.t.next();
+            // "n-" followed by <signless-integer>, e.g. "2n- 1".
+            "-" => {
+                self.consume_whitespace();
+                match self.t.next()? {
+                    CssToken::Number(b) if b >= 0.0 => -Self::to_integer(b)?,
+                    _ => return None,
                 }
-                Selector::UnknownSelector
             }
-            _ => {
-                panic!("warning: unexpected token {:?}", token);
+            // "n-" followed by digits, e.g. "2n-1".
+            digits => {
+                let digits = digits.strip_prefix('-')?;
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                -digits.parse::<i64>().ok()?
             }
+        };
+
+        self.consume_whitespace();
+        Some((a, b))
+    }
+
+    /// Returns `value` as an integer if it doesn't have a fractional part. A value out of the
+    /// range of i64 is clamped to it.
+    fn to_integer(value: f64) -> Option<i64> {
+        if value.fract() == 0.0 {
+            Some(value as i64)
+        } else {
+            None
+        }
+    }
+
+    /// Consumes the inside of "[" and "]" of an attribute selector, and the "]".
+    /// https://www.w3.org/TR/selectors-4/#typedef-attribute-selector
+    fn consume_attribute_selector(&mut self) -> Option<AttributeSelector> {
+        // <attribute-selector> = '[' <wq-name> ']' |
+        //                        '[' <wq-name> <attr-matcher> [ <string-token> | <ident-token> ]
+        //                        <attr-modifier>? ']'
+        // TODO: support namespace prefixes.
+        self.consume_whitespace();
+        let name = match self.t.next() {
+            Some(CssToken::Ident(ident)) => ident,
+            _ => return None,
+        };
+        self.consume_whitespace();
+
+        // <attr-matcher> = [ '~' | '|' | '^' | '$' | '*' ]? '='
+        let matcher = match self.t.next() {
+            Some(CssToken::CloseSquare) => {
+                return Some(AttributeSelector {
+                    name,
+                    matcher: None,
+                    case_insensitive: false,
+                })
+            }
+            Some(CssToken::Delim('=')) => AttributeMatcher::Equal,
+            Some(CssToken::Delim(c)) => {
+                if self.t.next() != Some(CssToken::Delim('=')) {
+                    return None;
+                }
+                match c {
+                    '~' => AttributeMatcher::Includes,
+                    '|' => AttributeMatcher::DashMatch,
+                    '^' => AttributeMatcher::Prefix,
+                    '$' => AttributeMatcher::Suffix,
+                    '*' => AttributeMatcher::Substring,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        self.consume_whitespace();
+
+        let value = match self.t.next() {
+            Some(CssToken::Ident(value)) | Some(CssToken::StringToken(value)) => value,
+            _ => return None,
+        };
+        self.consume_whitespace();
+
+        // <attr-modifier> = i | s
+        let mut case_insensitive = false;
+        if let Some(CssToken::Ident(modifier)) = self.t.peek() {
+            case_insensitive = match modifier.to_ascii_lowercase().as_str() {
+                "i" => true,
+                "s" => false,
+                _ => return None,
+            };
+            self.t.next();
+            self.consume_whitespace();
+        }
+
+        match self.t.next() {
+            Some(CssToken::CloseSquare) => Some(AttributeSelector {
+                name,
+                matcher: Some((matcher, value)),
+                case_insensitive,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Consumes <whitespace-token>s until the next input token is something else.
+    fn consume_whitespace(&mut self) {
+        while self.t.peek() == Some(&CssToken::Whitespace) {
+            self.t.next();
         }
     }
 
     /// https://www.w3.org/TR/css-syntax-3/#consume-a-declaration
-    fn consume_declaration(&mut self) -> Option<Declaration> {
+    /// A declaration of a shorthand property is expanded into the declarations of its longhand
+    /// properties, so this returns a list of declarations. It's empty if the declaration is
+    /// invalid.
+    fn consume_declaration(&mut self) -> Vec<Declaration> {
         if self.t.peek().is_none() {
-            return None;
+            return Vec::new();
         }
 
         // Create a new declaration with its name set to the value of the current input token.
-        let mut declaration = Declaration::new();
-        declaration.set_property(self.consume_ident());
+        let property = self.consume_ident();
+
+        // "1. While the next input token is a <whitespace-token>, consume the next input token."
+        self.consume_whitespace();
 
         // "2. If the next input token is anything other than a <colon-token>, this is a parse error.
         // Return nothing. Otherwise, consume the next input token."
         match self.t.next() {
             Some(token) => match token {
                 CssToken::Colon => {}
-                _ => return None,
+                _ => return Vec::new(),
             },
-            None => return None,
+            None => return Vec::new(),
         }
 
         // "3. While the next input token is a <whitespace-token>, consume the next input token."
+        self.consume_whitespace();
 
         // "4. As long as the next input token is anything other than an <EOF-token>, consume a
         // component value and append it to the declaration’s value."
-        declaration.set_value(self.consume_component_value());
+        // Whitespace between the component values is not significant for the supported
+        // properties, so it's not appended.
+        let mut values: Vec<ComponentValue> = Vec::new();
+        loop {
+            match self.t.peek() {
+                Some(CssToken::SemiColon) | Some(CssToken::CloseCurly) | None => break,
+                Some(CssToken::Whitespace) => {
+                    self.t.next();
+                }
+                Some(_) => values.push(self.consume_component_value()),
+            }
+        }
 
-        Some(declaration)
+        // "5. If the last two non-<whitespace-token>s in the declaration’s value are a
+        // <delim-token> with the value "!" followed by an <ident-token> with a value that is an
+        // ASCII case-insensitive match for "important", remove them from the declaration’s value
+        // and set the declaration’s important flag to true."
+        let mut important = false;
+        if let [.., ComponentValue::InputToken(CssToken::Delim('!')), ComponentValue::Keyword(ident)] =
+            values.as_slice()
+        {
+            if ident.eq_ignore_ascii_case("important") {
+                important = true;
+                values.truncate(values.len() - 2);
+            }
+        }
+
+        self.declarations_from_values(property, values, important)
+    }
+
+    /// Returns the declarations of `property` whose value is `values`. A shorthand property is
+    /// expanded into the declarations of its longhand properties. It's empty if the value is
+    /// invalid.
+    fn declarations_from_values(
+        &self,
+        property: String,
+        values: Vec<ComponentValue>,
+        important: bool,
+    ) -> Vec<Declaration> {
+        // A shorthand property sets all of its longhand properties. "If the value is invalid
+        // for the shorthand, the whole declaration is ignored."
+        // https://www.w3.org/TR/css-cascade-4/#shorthand
+        if longhands(&property).is_some() {
+            return match expand_shorthand(&property, &values) {
+                Some(expanded) => expanded
+                    .into_iter()
+                    .map(|(longhand, value)| {
+                        let mut declaration = Declaration::new();
+                        declaration.set_property(longhand);
+                        declaration.set_value(value);
+                        declaration.important = important;
+                        declaration
+                    })
+                    .collect(),
+                None => {
+                    self.warn(&format!("invalid value for css property {}", property));
+                    Vec::new()
+                }
+            };
+        }
+
+        // A declaration without a value is invalid.
+        // TODO: support multiple component values for longhand properties. Only the first one
+        // is kept.
+        let value = match values.into_iter().next() {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        let mut declaration = Declaration::new();
+        declaration.set_property(property);
+        declaration.set_value(value);
+        declaration.important = important;
+        vec![declaration]
+    }
+
+    /// https://drafts.csswg.org/cssom/#parse-a-css-value
+    /// Parses the whole input as the value of `property`, e.g. the value of setProperty(). It's
+    /// empty if the value is invalid.
+    pub fn parse_value(&mut self, property: &str, important: bool) -> Vec<Declaration> {
+        // "1. Let list be the value returned by invoking parse a list of component values from
+        // value."
+        let mut values = Vec::new();
+        loop {
+            match self.t.peek() {
+                Some(CssToken::Whitespace) => {
+                    self.t.next();
+                }
+                // A value can't end the declaration and start another one.
+                Some(CssToken::SemiColon) | Some(CssToken::CloseCurly) => return Vec::new(),
+                Some(_) => values.push(self.consume_component_value()),
+                None => break,
+            }
+        }
+
+        // "2. Match list against the grammar for the property property in the CSS
+        // specification."
+        // "3. If the above step failed, return null."
+        // A longhand property takes one component value, and "!important" isn't a part of a
+        // value.
+        if longhands(property).is_none() && values.len() != 1 {
+            return Vec::new();
+        }
+        self.declarations_from_values(property.to_string(), values, important)
     }
 
     /// https://www.w3.org/TR/css-syntax-3/#consume-simple-block
@@ -275,33 +1260,30 @@ impl CssParser {
                     assert_eq!(self.t.next(), Some(CssToken::CloseCurly));
                     return declarations;
                 }
-                CssToken::SemiColon => {
-                    assert_eq!(self.t.next(), Some(CssToken::SemiColon));
-                    // Do nothing.
+                // "<whitespace-token>, <semicolon-token>: Do nothing."
+                CssToken::Whitespace | CssToken::SemiColon => {
+                    self.t.next();
                 }
-                CssToken::Ident(ref _ident) => match self.consume_declaration() {
-                    Some(declaration) => {
-                        declarations.push(declaration);
-                        if self.t.peek() == Some(&CssToken::Delim(',')) {
-                            self.t.next();
-                        }
+                CssToken::Ident(ref _ident) => {
+                    declarations.extend(self.consume_declaration());
+                    if self.t.peek() == Some(&CssToken::Comma) {
+                        self.t.next();
                     }
-                    None => {}
-                },
+                }
                 CssToken::StringToken(_) => {
                     self.t.next();
-                    if self.t.peek() == Some(&CssToken::Delim(',')) {
+                    if self.t.peek() == Some(&CssToken::Comma) {
                         self.t.next();
                     }
                 }
                 CssToken::Number(_) => {
                     self.t.next();
-                    if self.t.peek() == Some(&CssToken::Delim(',')) {
+                    if self.t.peek() == Some(&CssToken::Comma) {
                         self.t.next();
                     }
                 }
                 _ => {
-                    println!("warning: unexpected token {:?}", token);
+                    self.warn(&format!("unexpected token {:?}", token));
                     self.t.next();
                 }
             }
@@ -309,27 +1291,219 @@ impl CssParser {
     }
 
     /// https://www.w3.org/TR/css-syntax-3/#consume-at-rule
-    fn consume_at_rule(&mut self) -> Option<AtRule> {
-        let rule = AtRule::new();
+    /// Returns None if the at-rule is not supported or invalid.
+    fn consume_at_rule(&mut self) -> Option<Rule> {
+        let name = match self.t.next() {
+            Some(CssToken::AtKeyword(keyword)) => keyword.to_ascii_lowercase(),
+            _ => return None,
+        };
+        let mut prelude = Vec::new();
 
         loop {
             let token = match self.t.next() {
                 Some(t) => t,
-                None => return None,
+                // "<EOF-token>: This is a parse error. Return the at-rule."
+                None => return self.statement_at_rule(&name, &prelude),
             };
 
             match token {
-                CssToken::OpenCurly => {
-                    //TODO: set rule to AtRule.
-                    let _qualified_rule = self.consume_qualified_rule();
-                    // consume the close curly for a AtRule block
-                    assert_eq!(self.t.next(), Some(CssToken::CloseCurly));
-                    return Some(rule);
+                // "<semicolon-token>: Return the at-rule."
+                CssToken::SemiColon => return self.statement_at_rule(&name, &prelude),
+                // "<{-token>: Consume a simple block and assign it to the at-rule's block.
+                // Return the at-rule."
+                CssToken::OpenCurly => match name.as_str() {
+                    "media" => {
+                        let mut rule = AtRule::new(name);
+                        rule.media_queries = parse_media_query_list(&prelude);
+                        rule.rules = self.consume_list_of_rules(false);
+                        return Some(Rule::AtRule(rule));
+                    }
+                    "supports" => {
+                        // "If the <supports-condition> ... doesn't match the grammar, the whole
+                        // rule is invalid."
+                        let condition = parse_supports_condition(&prelude);
+                        let rules = self.consume_list_of_rules(false);
+                        return match condition {
+                            Some(condition) => {
+                                Some(Rule::Supports(SupportsRule { condition, rules }))
+                            }
+                            None => {
+                                self.warn("invalid @supports condition");
+                                None
+                            }
+                        };
+                    }
+                    "font-face" => {
+                        // "The @font-face rule ... accepts no prelude."
+                        let rule = self.consume_font_face_block();
+                        if prelude.iter().any(|t| *t != CssToken::Whitespace) {
+                            return None;
+                        }
+                        return Some(Rule::FontFace(rule));
+                    }
+                    _ => {
+                        self.warn(&format!("at-rule @{} is not supported yet", name));
+                        self.consume_simple_block();
+                        return None;
+                    }
+                },
+                // "anything else: Reconsume the current input token. Consume a component value.
+                // Append the returned value to the at-rule's prelude."
+                _ => prelude.push(token),
+            }
+        }
+    }
+
+    /// Returns the at-rule that has no block. Only @import is supported.
+    fn statement_at_rule(&self, name: &str, prelude: &[CssToken]) -> Option<Rule> {
+        if name != "import" {
+            self.warn(&format!("at-rule @{} is not supported yet", name));
+            return None;
+        }
+
+        // @import [ <url> | <string> ] <media-query-list>?;
+        // https://www.w3.org/TR/css-cascade-4/#at-import
+        let mut tokens = prelude.iter().filter(|t| **t != CssToken::Whitespace);
+        let href = match tokens.next() {
+            Some(CssToken::Url(url)) | Some(CssToken::StringToken(url)) => url.to_string(),
+            // url("style.css")
+            Some(CssToken::Function(function)) if function.eq_ignore_ascii_case("url") => {
+                match (tokens.next(), tokens.next()) {
+                    (Some(CssToken::StringToken(url)), Some(CssToken::CloseParenthesis)) => {
+                        url.to_string()
+                    }
+                    _ => return None,
                 }
-                _ => {
-                    println!("consume_at_rule anything else: {:?}", token);
-                    // TODO: set prelude to AtRule
+            }
+            _ => return None,
+        };
+        let media_queries: Vec<CssToken> = tokens.cloned().collect();
+
+        Some(Rule::Import(ImportRule {
+            href,
+            media_queries: parse_media_query_list(&media_queries),
+            style_sheet: None,
+        }))
+    }
+
+    /// Consumes the block of an @font-face rule whose "{" is already consumed. The block is a
+    /// list of descriptors, which look like declarations.
+    /// https://www.w3.org/TR/css-fonts-4/#font-face-rule
+    fn consume_font_face_block(&mut self) -> FontFaceRule {
+        let mut rule = FontFaceRule::new();
+
+        loop {
+            let name = match self.t.next() {
+                None | Some(CssToken::CloseCurly) => return rule,
+                Some(CssToken::Ident(name)) => name.to_ascii_lowercase(),
+                Some(_) => continue,
+            };
+
+            self.consume_whitespace();
+            let has_colon = self.t.peek() == Some(&CssToken::Colon);
+            let mut value = Vec::new();
+            loop {
+                match self.t.peek() {
+                    Some(CssToken::SemiColon) | Some(CssToken::CloseCurly) | None => break,
+                    Some(CssToken::Whitespace) => {
+                        self.t.next();
+                    }
+                    Some(_) => {
+                        if let Some(token) = self.t.next() {
+                            value.push(token);
+                        }
+                    }
+                }
+            }
+            if !has_colon {
+                continue;
+            }
+            // Remove the ":".
+            value.remove(0);
+
+            match name.as_str() {
+                "font-family" => rule.family = Self::font_family_name(&value),
+                "src" => rule.sources = Self::font_face_sources(&value),
+                _ => self.warn(&format!(
+                    "descriptor {} in @font-face is not supported yet",
+                    name
+                )),
+            }
+        }
+    }
+
+    /// <family-name> = <string> | <custom-ident>+
+    /// https://www.w3.org/TR/css-fonts-4/#family-name-syntax
+    fn font_family_name(value: &[CssToken]) -> Option<String> {
+        match value {
+            [CssToken::StringToken(name)] => Some(name.to_string()),
+            [] => None,
+            _ => {
+                // "a sequence of identifiers ... are converted to a string by joining all the
+                // identifiers in the sequence by a single space."
+                let mut idents = Vec::new();
+                for token in value {
+                    match token {
+                        CssToken::Ident(ident) => idents.push(ident.to_string()),
+                        _ => return None,
+                    }
+                }
+                Some(idents.join(" "))
+            }
+        }
+    }
+
+    /// <url> [ format(<font-format>) ]? | local(<family-name>), separated by commas.
+    /// https://www.w3.org/TR/css-fonts-4/#src-desc
+    fn font_face_sources(value: &[CssToken]) -> Vec<FontFaceSource> {
+        let mut sources = Vec::new();
+
+        for source in value.split(|t| *t == CssToken::Comma) {
+            let (url, rest) = match source {
+                [CssToken::Url(url), rest @ ..] => (url, rest),
+                [CssToken::Function(function), CssToken::StringToken(url), CssToken::CloseParenthesis, rest @ ..]
+                    if function.eq_ignore_ascii_case("url") =>
+                {
+                    (url, rest)
+                }
+                // local() refers to a font that is installed, which Pango finds by the family
+                // name without the @font-face rule.
+                _ => continue,
+            };
+            let format = match rest {
+                [] => None,
+                [CssToken::Function(function), CssToken::StringToken(format) | CssToken::Ident(format), CssToken::CloseParenthesis]
+                    if function.eq_ignore_ascii_case("format") =>
+                {
+                    Some(format.to_ascii_lowercase())
+                }
+                // "If a component value is parsed that is not one of these ... the entire
+                // source is ignored."
+                _ => continue,
+            };
+            sources.push(FontFaceSource {
+                url: url.to_string(),
+                format,
+            });
+        }
+
+        sources
+    }
+
+    /// Consumes and discards the rest of a {}-block whose "{" is already consumed.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-simple-block
+    fn consume_simple_block(&mut self) {
+        let mut depth = 1;
+        for token in self.t.by_ref() {
+            match token {
+                CssToken::OpenCurly => depth += 1,
+                CssToken::CloseCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
                 }
+                _ => {}
             }
         }
     }
@@ -363,14 +1537,31 @@ impl CssParser {
 
                     // The prelude of the qualified rule is parsed as a <selector-list>.
                     // https://www.w3.org/TR/css-syntax-3/#css-parse-something-according-to-a-css-grammar
-                    rule.set_selector(self.consume_selector());
+                    match self.consume_selector_list() {
+                        Some(selectors) if self.t.peek() == Some(&CssToken::OpenCurly) => {
+                            rule.set_selectors(selectors)
+                        }
+                        _ => {
+                            // "If a selector list is invalid, the entire style rule is invalid"
+                            // and matches nothing. Skip the rest of the prelude.
+                            // https://www.w3.org/TR/selectors-4/#invalid
+                            self.warn("invalid selector list");
+                            while self.t.peek().is_some()
+                                && self.t.peek() != Some(&CssToken::OpenCurly)
+                            {
+                                self.t.next();
+                            }
+                        }
+                    }
                 }
             }
         }
     }
 
     /// https://www.w3.org/TR/css-syntax-3/#consume-a-list-of-rules
-    fn consume_list_of_rules(&mut self) -> Vec<QualifiedRule> {
+    /// If `top_level` is false, the rules are in the block of an at-rule, and they end at the "}"
+    /// of the block.
+    fn consume_list_of_rules(&mut self, top_level: bool) -> Vec<Rule> {
         // "Create an initially empty list of rules."
         let mut rules = Vec::new();
 
@@ -380,12 +1571,34 @@ impl CssParser {
                 None => return rules,
             };
             match token {
+                // "<whitespace-token>: Do nothing."
+                // "<CDO-token>, <CDC-token>: If the top-level flag is set, do nothing."
+                CssToken::Whitespace | CssToken::Cdo | CssToken::Cdc => {
+                    self.t.next();
+                }
+                // The end of the block of an at-rule.
+                CssToken::CloseCurly if !top_level => {
+                    self.t.next();
+                    return rules;
+                }
                 // <at-keyword-token>
                 // "Reconsume the current input token. Consume an at-rule, and append the returned value
                 // to the list of rules."
                 CssToken::AtKeyword(_keyword) => {
-                    let _rule = self.consume_at_rule();
-                    // TODO: we ignore media query for now. implement it properly.
+                    if let Some(rule) = self.consume_at_rule() {
+                        // "Any @import rules must precede all other valid at-rules and style
+                        // rules in a style sheet ... or else the @import rule is invalid."
+                        // https://www.w3.org/TR/css-cascade-4/#at-import
+                        if let Rule::Import(_) = rule {
+                            let precedes_others =
+                                top_level && rules.iter().all(|r| matches!(r, Rule::Import(_)));
+                            if !precedes_others {
+                                self.warn("@import must precede all other rules");
+                                continue;
+                            }
+                        }
+                        rules.push(rule);
+                    }
                 }
                 _ => {
                     // anything else
@@ -393,7 +1606,7 @@ impl CssParser {
                     // returned, append it to the list of rules."
                     let rule = self.consume_qualified_rule();
                     match rule {
-                        Some(r) => rules.push(r),
+                        Some(r) => rules.push(Rule::QualifiedRule(r)),
                         None => return rules,
                     }
                 }
@@ -401,6 +1614,41 @@ impl CssParser {
         }
     }
 
+    /// Parses all the input as a selector, e.g. the argument of `querySelector()`. Returns None
+    /// if the input is not a valid selector.
+    /// https://www.w3.org/TR/selectors-4/#parse-a-selector
+    pub fn parse_selector(&mut self) -> Option<SelectorList> {
+        let selectors = self.consume_selector_list()?;
+
+        // The rest of the input can't be a part of the selector.
+        if self.t.peek().is_some() {
+            return None;
+        }
+
+        Some(selectors)
+    }
+
+    /// Parses all the input as a list of component values, e.g. the value of a declaration.
+    /// Whitespace is not included.
+    /// https://www.w3.org/TR/css-syntax-3/#parse-list-of-component-values
+    pub fn parse_list_of_component_values(&mut self) -> Vec<ComponentValue> {
+        let mut values = Vec::new();
+        while let Some(token) = self.t.peek() {
+            if *token == CssToken::Whitespace {
+                self.t.next();
+            } else {
+                values.push(self.consume_component_value());
+            }
+        }
+        values
+    }
+
+    /// Parses all the input as a list of declarations, e.g. the value of a style attribute.
+    /// https://www.w3.org/TR/css-syntax-3/#parse-a-list-of-declarations
+    pub fn parse_list_of_declarations(&mut self) -> Vec<Declaration> {
+        self.consume_list_of_declarations()
+    }
+
     /// https://www.w3.org/TR/css-syntax-3/#parse-stylesheet
     pub fn parse_stylesheet(&mut self) -> StyleSheet {
         // 1. Create a new stylesheet.
@@ -409,9 +1657,35 @@ impl CssParser {
         // 2. Consume a list of rules from the stream of tokens, with the top-level flag set. Let
         // the return value be rules.
         // 3. Assign rules to the stylesheet’s value.
-        sheet.set_rules(self.consume_list_of_rules());
+        sheet.set_rules(self.consume_list_of_rules(true));
 
         // 4. Return the stylesheet.
         sheet
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn an_plus_b(css: &str) -> Option<(i64, i64)> {
+        CssParser::new(CssTokenizer::new(css.to_string())).consume_an_plus_b()
+    }
+
+    #[test]
+    fn consume_an_plus_b() {
+        assert_eq!(an_plus_b("odd"), Some((2, 1)));
+        assert_eq!(an_plus_b("EVEN"), Some((2, 0)));
+        assert_eq!(an_plus_b("3"), Some((0, 3)));
+        assert_eq!(an_plus_b("-n+3"), Some((-1, 3)));
+        assert_eq!(an_plus_b("+n"), Some((1, 0)));
+        assert_eq!(an_plus_b("2n-1"), Some((2, -1)));
+        assert_eq!(an_plus_b("2n- 1"), Some((2, -1)));
+        assert_eq!(an_plus_b("2n + 1"), Some((2, 1)));
+        assert_eq!(an_plus_b("-n- 2"), Some((-1, -2)));
+        assert_eq!(an_plus_b("2n - 9223372036854775808"), Some((2, -i64::MAX)));
+        assert_eq!(an_plus_b("1.5n"), None);
+        assert_eq!(an_plus_b("2n - -1"), None);
+        assert_eq!(an_plus_b("foo"), None);
+    }
+}
@@ -0,0 +1,614 @@
+//! This is a part of "Media Queries Level 5".
+//! https://www.w3.org/TR/mediaqueries-5/
+//!
+//! A media query list is parsed from the prelude of an @media rule and evaluated against the
+//! viewport of the browser window.
+
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::values::{ColorScheme, Length, LengthContext, Viewport};
+use std::string::String;
+use std::vec::Vec;
+
+/// "Relative length units in media queries are based on the initial value, which means that
+/// units are never based on results of declarations."
+/// https://www.w3.org/TR/mediaqueries-5/#units
+const INITIAL_FONT_SIZE: f64 = 16.0;
+
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-media-query-list
+pub type MediaQueryList = Vec<MediaQuery>;
+
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-media-query
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    /// True if the media query is preceded by "not".
+    negated: bool,
+    media_type: MediaType,
+    condition: Option<MediaCondition>,
+}
+
+impl MediaQuery {
+    /// "A media query that does not match the grammar in the previous section must be replaced
+    /// by not all during parsing."
+    /// https://www.w3.org/TR/mediaqueries-5/#error-handling
+    fn not_all() -> Self {
+        Self {
+            negated: true,
+            media_type: MediaType::All,
+            condition: None,
+        }
+    }
+}
+
+/// https://www.w3.org/TR/mediaqueries-5/#media-types
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    /// "Unknown media types must be treated as not matching."
+    Unknown(String),
+}
+
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-media-condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    /// "The <general-enclosed> production ... evaluates to unknown."
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-general-enclosed
+    GeneralEnclosed,
+}
+
+/// A media feature in the boolean context if `test` is None, e.g. "(orientation)", or a
+/// comparison of the feature with a value, e.g. "(width >= 600px)". "(min-width: 600px)" is
+/// parsed as the same as the latter.
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-media-feature
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaFeature {
+    name: String,
+    test: Option<(Comparison, MediaValue)>,
+}
+
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-mf-comparison
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    /// Returns the comparison with the operands swapped, e.g. ">" for "<".
+    fn flip(self) -> Self {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Ge => Comparison::Le,
+            Comparison::Gt => Comparison::Lt,
+        }
+    }
+
+    fn compare(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Eq => left == right,
+            Comparison::Ge => left >= right,
+            Comparison::Gt => left > right,
+        }
+    }
+}
+
+/// https://www.w3.org/TR/mediaqueries-5/#typedef-mf-value
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+    Number(f64),
+    Length(Length),
+    /// A <resolution> in dppx.
+    /// https://www.w3.org/TR/css-values-4/#resolution
+    Resolution(f64),
+    /// The value is in ASCII lowercase.
+    Ident(String),
+}
+
+impl MediaValue {
+    fn from_token(token: &CssToken) -> Option<Self> {
+        match token {
+            CssToken::Number(value) => Some(MediaValue::Number(*value)),
+            // "dpi: Dots per inch", "dpcm: Dots per centimeter", "dppx, x: Dots per px unit"
+            // https://www.w3.org/TR/css-values-4/#resolution
+            CssToken::Dimension(value, unit) => match unit.to_ascii_lowercase().as_str() {
+                "dpi" => Some(MediaValue::Resolution(value / 96.0)),
+                "dpcm" => Some(MediaValue::Resolution(value * 2.54 / 96.0)),
+                "dppx" | "x" => Some(MediaValue::Resolution(*value)),
+                _ => Length::from_token(token).map(MediaValue::Length),
+            },
+            CssToken::Ident(ident) => Some(MediaValue::Ident(ident.to_ascii_lowercase())),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if `media_queries` matches `viewport`.
+/// "A media query list is true if any of its component media queries are true, and false only
+/// if all of its component media queries are false."
+/// "An empty media query list evaluates to true."
+/// https://www.w3.org/TR/mediaqueries-5/#mq-list
+pub fn matches_media_query_list(media_queries: &MediaQueryList, viewport: &Viewport) -> bool {
+    media_queries.is_empty()
+        || media_queries
+            .iter()
+            .any(|query| matches_media_query(query, viewport))
+}
+
+/// https://www.w3.org/TR/mediaqueries-5/#mq-syntax
+fn matches_media_query(query: &MediaQuery, viewport: &Viewport) -> bool {
+    let media_type = match query.media_type {
+        // This browser shows pages on a screen.
+        MediaType::All | MediaType::Screen => true,
+        MediaType::Print | MediaType::Unknown(_) => false,
+    };
+    let result = match &query.condition {
+        Some(condition) => match evaluate_condition(condition, viewport) {
+            Some(c) => media_type && c,
+            // "If the result of any of the above is unknown, the media query is false"
+            // regardless of "not".
+            None => return false,
+        },
+        None => media_type,
+    };
+
+    // "The not keyword ... negates the result of the entire media query"
+    result != query.negated
+}
+
+/// Evaluates `condition` in the three-valued logic, where None is unknown.
+/// https://www.w3.org/TR/mediaqueries-5/#evaluating
+fn evaluate_condition(condition: &MediaCondition, viewport: &Viewport) -> Option<bool> {
+    match condition {
+        MediaCondition::Feature(feature) => evaluate_feature(feature, viewport),
+        // "The result is the negation of the <media-in-parens> term. The negation of unknown is
+        // unknown."
+        MediaCondition::Not(c) => evaluate_condition(c, viewport).map(|c| !c),
+        // "The result is true if all of the <media-in-parens> child terms are true, false if at
+        // least one of the <media-in-parens> is false, and unknown otherwise."
+        MediaCondition::And(conditions) => {
+            let mut result = Some(true);
+            for c in conditions {
+                match evaluate_condition(c, viewport) {
+                    Some(false) => return Some(false),
+                    Some(true) => {}
+                    None => result = None,
+                }
+            }
+            result
+        }
+        // "The result is false if all of the <media-in-parens> child terms are false, true if
+        // at least one of the <media-in-parens> is true, and unknown otherwise."
+        MediaCondition::Or(conditions) => {
+            let mut result = Some(false);
+            for c in conditions {
+                match evaluate_condition(c, viewport) {
+                    Some(true) => return Some(true),
+                    Some(false) => {}
+                    None => result = None,
+                }
+            }
+            result
+        }
+        MediaCondition::GeneralEnclosed => None,
+    }
+}
+
+/// Evaluates `feature` against `viewport`. Returns None for an unknown media feature or a value
+/// that isn't valid for the feature.
+/// https://www.w3.org/TR/mediaqueries-5/#media-descriptor-table
+fn evaluate_feature(feature: &MediaFeature, viewport: &Viewport) -> Option<bool> {
+    let context = LengthContext {
+        font_size: INITIAL_FONT_SIZE,
+        root_font_size: INITIAL_FONT_SIZE,
+        viewport: *viewport,
+    };
+
+    match feature.name.as_str() {
+        // "The width media feature describes the width of the targeted display area of the
+        // output device."
+        // https://www.w3.org/TR/mediaqueries-5/#width
+        // https://www.w3.org/TR/mediaqueries-5/#height
+        "width" | "height" => {
+            let size = if feature.name == "width" {
+                viewport.width
+            } else {
+                viewport.height
+            };
+            match &feature.test {
+                // "If the feature would be true for any value other than the number 0 (or a
+                // dimension with the value 0), the feature evaluates to true."
+                // https://www.w3.org/TR/mediaqueries-5/#mq-boolean-context
+                None => Some(size != 0.0),
+                Some((comparison, MediaValue::Length(length))) => match length {
+                    Length::Percentage(_) => None,
+                    _ => Some(comparison.compare(size, length.to_px(&context, 0.0))),
+                },
+                Some((comparison, MediaValue::Number(value))) if *value == 0.0 => {
+                    Some(comparison.compare(size, 0.0))
+                }
+                Some(_) => None,
+            }
+        }
+        // "portrait: The orientation media feature is portrait when the value of the height
+        // media feature is greater than or equal to the value of the width media feature.
+        // landscape: Otherwise orientation is landscape."
+        // https://www.w3.org/TR/mediaqueries-5/#orientation
+        "orientation" => {
+            let portrait = viewport.height >= viewport.width;
+            match &feature.test {
+                None => Some(true),
+                Some((Comparison::Eq, MediaValue::Ident(value))) => match value.as_str() {
+                    "portrait" => Some(portrait),
+                    "landscape" => Some(!portrait),
+                    _ => None,
+                },
+                Some(_) => None,
+            }
+        }
+        // "The prefers-color-scheme media feature reflects the user's desire that the page use
+        // a light or dark color theme."
+        // https://www.w3.org/TR/mediaqueries-5/#prefers-color-scheme
+        "prefers-color-scheme" => match &feature.test {
+            None => Some(true),
+            Some((Comparison::Eq, MediaValue::Ident(value))) => match value.as_str() {
+                "light" => Some(viewport.color_scheme == ColorScheme::Light),
+                "dark" => Some(viewport.color_scheme == ColorScheme::Dark),
+                _ => None,
+            },
+            Some(_) => None,
+        },
+        // "The resolution media feature describes the resolution of the output device, i.e. the
+        // density of the pixels, taking into account the page zoom but assuming a pinch zoom of
+        // 1.0."
+        // https://www.w3.org/TR/mediaqueries-5/#resolution
+        "resolution" => match &feature.test {
+            None => Some(viewport.resolution != 0.0),
+            Some((comparison, MediaValue::Resolution(value))) => {
+                Some(comparison.compare(viewport.resolution, *value))
+            }
+            Some(_) => None,
+        },
+        _ => {
+            println!(
+                "warning: media feature {} is not supported yet",
+                feature.name
+            );
+            None
+        }
+    }
+}
+
+/// Parses `tokens`, e.g. the prelude of an @media rule, as a media query list.
+/// https://www.w3.org/TR/mediaqueries-5/#mq-list
+pub fn parse_media_query_list(tokens: &[CssToken]) -> MediaQueryList {
+    // Whitespace is not significant between the parts of a media query.
+    let tokens: Vec<CssToken> = tokens
+        .iter()
+        .filter(|t| **t != CssToken::Whitespace)
+        .cloned()
+        .collect();
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    // "the list ... is split at the top-level <comma-token>s, and each piece is parsed as a
+    // <media-query>". An invalid media query in the list doesn't make the others invalid.
+    let mut media_queries = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            CssToken::OpenParenthesis | CssToken::Function(_) => depth += 1,
+            CssToken::CloseParenthesis if depth > 0 => depth -= 1,
+            CssToken::Comma if depth == 0 => {
+                media_queries.push(parse_media_query(&tokens[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    media_queries.push(parse_media_query(&tokens[start..]));
+    media_queries
+}
+
+/// Parses `tokens` as a media query, or returns "not all" if it's invalid.
+fn parse_media_query(tokens: &[CssToken]) -> MediaQuery {
+    let mut parser = MediaQueryParser {
+        tokens,
+        position: 0,
+    };
+    match parser.consume_media_query() {
+        Some(query) if parser.position == tokens.len() => query,
+        _ => {
+            println!("warning: invalid media query {:?}", tokens);
+            MediaQuery::not_all()
+        }
+    }
+}
+
+/// A parser of a media query whose tokens don't have whitespace.
+struct MediaQueryParser<'a> {
+    tokens: &'a [CssToken],
+    position: usize,
+}
+
+impl<'a> MediaQueryParser<'a> {
+    fn peek(&self) -> Option<&'a CssToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&'a CssToken> {
+        self.tokens.get(self.position + n)
+    }
+
+    fn next(&mut self) -> Option<&'a CssToken> {
+        let token = self.tokens.get(self.position);
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Returns true and consumes the next token if it's the keyword `keyword`.
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// <media-query> = <media-condition>
+    ///               | [ not | only ]? <media-type> [ and <media-condition-without-or> ]?
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-media-query
+    fn consume_media_query(&mut self) -> Option<MediaQuery> {
+        // "not (...)" is a <media-condition>, and "not screen" is a negated media type.
+        let is_condition = match self.peek()? {
+            CssToken::Ident(ident) if ident.eq_ignore_ascii_case("not") => {
+                self.peek_nth(1) == Some(&CssToken::OpenParenthesis)
+            }
+            CssToken::Ident(_) => false,
+            _ => true,
+        };
+        if is_condition {
+            return Some(MediaQuery {
+                negated: false,
+                media_type: MediaType::All,
+                condition: Some(self.consume_media_condition(true)?),
+            });
+        }
+
+        let negated = self.consume_keyword("not");
+        // "The only keyword has no effect on the media query's result"
+        if !negated {
+            self.consume_keyword("only");
+        }
+
+        // "The <media-type> production does not include the keywords only, not, and, or, and
+        // layer."
+        let media_type = match self.next()? {
+            CssToken::Ident(ident) => match ident.to_ascii_lowercase().as_str() {
+                "only" | "not" | "and" | "or" | "layer" => return None,
+                "all" => MediaType::All,
+                "screen" => MediaType::Screen,
+                "print" => MediaType::Print,
+                other => MediaType::Unknown(other.to_string()),
+            },
+            _ => return None,
+        };
+
+        let condition = if self.consume_keyword("and") {
+            Some(self.consume_media_condition(false)?)
+        } else {
+            None
+        };
+
+        Some(MediaQuery {
+            negated,
+            media_type,
+            condition,
+        })
+    }
+
+    /// <media-condition> = <media-not> | <media-in-parens> [ <media-and>* | <media-or>* ]
+    /// <media-condition-without-or> = <media-not> | <media-in-parens> <media-and>*
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-media-condition
+    fn consume_media_condition(&mut self, allow_or: bool) -> Option<MediaCondition> {
+        // <media-not> = not <media-in-parens>
+        if self.consume_keyword("not") {
+            return Some(MediaCondition::Not(Box::new(
+                self.consume_media_in_parens()?,
+            )));
+        }
+
+        let first = self.consume_media_in_parens()?;
+        let is_and = match self.peek() {
+            Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case("and") => true,
+            Some(CssToken::Ident(ident)) if allow_or && ident.eq_ignore_ascii_case("or") => false,
+            _ => return Some(first),
+        };
+
+        // "and" and "or" can't be mixed at the same level without parentheses.
+        let keyword = if is_and { "and" } else { "or" };
+        let mut conditions = vec![first];
+        while self.consume_keyword(keyword) {
+            conditions.push(self.consume_media_in_parens()?);
+        }
+
+        if is_and {
+            Some(MediaCondition::And(conditions))
+        } else {
+            Some(MediaCondition::Or(conditions))
+        }
+    }
+
+    /// <media-in-parens> = ( <media-condition> ) | <media-feature> | <general-enclosed>
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-media-in-parens
+    fn consume_media_in_parens(&mut self) -> Option<MediaCondition> {
+        match self.peek()? {
+            CssToken::OpenParenthesis => {}
+            // <general-enclosed> = [ <function-token> <any-value>? ) ]
+            CssToken::Function(_) => {
+                self.consume_general_enclosed();
+                return Some(MediaCondition::GeneralEnclosed);
+            }
+            _ => return None,
+        }
+
+        let start = self.position;
+        self.next();
+        let nested_condition = match self.peek() {
+            Some(CssToken::OpenParenthesis) => true,
+            Some(CssToken::Ident(ident)) => ident.eq_ignore_ascii_case("not"),
+            _ => false,
+        };
+        let condition = if nested_condition {
+            self.consume_media_condition(true)
+        } else {
+            self.consume_media_feature()
+        };
+
+        match condition {
+            Some(c) if self.peek() == Some(&CssToken::CloseParenthesis) => {
+                self.next();
+                Some(c)
+            }
+            // <general-enclosed> = [ ( <any-value>? ) ]
+            _ => {
+                self.position = start;
+                self.consume_general_enclosed();
+                Some(MediaCondition::GeneralEnclosed)
+            }
+        }
+    }
+
+    /// Consumes the tokens up to and including the ")" that matches the next "(" or function.
+    fn consume_general_enclosed(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                CssToken::OpenParenthesis | CssToken::Function(_) => depth += 1,
+                CssToken::CloseParenthesis => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Consumes the content of the parentheses of a media feature.
+    /// <media-feature> = ( [ <mf-plain> | <mf-boolean> | <mf-range> ] )
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-media-feature
+    fn consume_media_feature(&mut self) -> Option<MediaCondition> {
+        if let Some(CssToken::Ident(name)) = self.peek() {
+            let name = name.to_ascii_lowercase();
+            self.next();
+            return match self.peek() {
+                // <mf-boolean> = <mf-name>
+                Some(CssToken::CloseParenthesis) => {
+                    Some(MediaCondition::Feature(MediaFeature { name, test: None }))
+                }
+                // <mf-plain> = <mf-name> : <mf-value>
+                Some(CssToken::Colon) => {
+                    self.next();
+                    let value = MediaValue::from_token(self.next()?)?;
+                    // "min-" and "max-" prefixes mean "greater or equal to" and "smaller or
+                    // equal to" respectively.
+                    // https://www.w3.org/TR/mediaqueries-5/#mq-min-max
+                    let (name, comparison) = if let Some(name) = name.strip_prefix("min-") {
+                        (name.to_string(), Comparison::Ge)
+                    } else if let Some(name) = name.strip_prefix("max-") {
+                        (name.to_string(), Comparison::Le)
+                    } else {
+                        (name, Comparison::Eq)
+                    };
+                    Some(MediaCondition::Feature(MediaFeature {
+                        name,
+                        test: Some((comparison, value)),
+                    }))
+                }
+                // <mf-range> = <mf-name> <mf-comparison> <mf-value>
+                _ => {
+                    let comparison = self.consume_comparison()?;
+                    let value = MediaValue::from_token(self.next()?)?;
+                    Some(MediaCondition::Feature(MediaFeature {
+                        name,
+                        test: Some((comparison, value)),
+                    }))
+                }
+            };
+        }
+
+        // <mf-range> = <mf-value> <mf-comparison> <mf-name>
+        //            | <mf-value> <mf-lt> <mf-name> <mf-lt> <mf-value>
+        //            | <mf-value> <mf-gt> <mf-name> <mf-gt> <mf-value>
+        let left = MediaValue::from_token(self.next()?)?;
+        let left_comparison = self.consume_comparison()?;
+        let name = match self.next()? {
+            CssToken::Ident(name) => name.to_ascii_lowercase(),
+            _ => return None,
+        };
+        // "value < name" is the same as "name > value".
+        let left_feature = MediaCondition::Feature(MediaFeature {
+            name: name.clone(),
+            test: Some((left_comparison.flip(), left)),
+        });
+        if self.peek() == Some(&CssToken::CloseParenthesis) {
+            return Some(left_feature);
+        }
+
+        let right_comparison = self.consume_comparison()?;
+        let is_lt = |c: Comparison| c == Comparison::Lt || c == Comparison::Le;
+        let is_gt = |c: Comparison| c == Comparison::Gt || c == Comparison::Ge;
+        if !(is_lt(left_comparison) && is_lt(right_comparison)
+            || is_gt(left_comparison) && is_gt(right_comparison))
+        {
+            return None;
+        }
+        let right = MediaValue::from_token(self.next()?)?;
+        Some(MediaCondition::And(vec![
+            left_feature,
+            MediaCondition::Feature(MediaFeature {
+                name,
+                test: Some((right_comparison, right)),
+            }),
+        ]))
+    }
+
+    /// <mf-comparison> = <mf-lt> | <mf-gt> | <mf-eq>
+    /// <mf-lt> = '<' '='?, <mf-gt> = '>' '='?, <mf-eq> = '='
+    /// https://www.w3.org/TR/mediaqueries-5/#typedef-mf-comparison
+    fn consume_comparison(&mut self) -> Option<Comparison> {
+        let comparison = match self.next()? {
+            CssToken::Delim('=') => return Some(Comparison::Eq),
+            CssToken::Delim('<') => Comparison::Lt,
+            CssToken::Delim('>') => Comparison::Gt,
+            _ => return None,
+        };
+        if self.peek() != Some(&CssToken::Delim('=')) {
+            return Some(comparison);
+        }
+        self.next();
+        match comparison {
+            Comparison::Lt => Some(Comparison::Le),
+            _ => Some(Comparison::Ge),
+        }
+    }
+}
@@ -1,2 +1,10 @@
+pub mod cascade;
 pub mod cssom;
+pub mod media;
+pub mod properties;
+pub mod selector;
+pub mod shorthand;
+pub mod supports;
 pub mod token;
+pub mod user_agent;
+pub mod values;
@@ -0,0 +1,294 @@
+//! The CSS properties that the renderer supports: their definitions, which decide how their
+//! computed values are found, and the values that they accept.
+//! https://www.w3.org/TR/css-cascade-4/#value-stages
+//! https://www.w3.org/TR/css-conditional-3/#support-definition
+
+use crate::renderer::css::cssom::CssParser;
+use crate::renderer::css::shorthand::{expand_shorthand, is_css_wide_keyword, longhands};
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::values::Length;
+
+/// How the computed value of a property is found from its specified value.
+/// https://www.w3.org/TR/css-cascade-4/#computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputedValue {
+    /// "as specified"
+    AsSpecified,
+    /// "absolute length" or "as specified, but with lengths made absolute". Font-relative
+    /// lengths are converted to px, so that a child inherits the lengths in px. Percentages are
+    /// kept, because they're resolved against the containing block in layout.
+    AbsoluteLength,
+}
+
+/// The definition of a property in the property definition table of its spec.
+/// https://www.w3.org/TR/css-values-4/#property-definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PropertyDefinition {
+    pub name: &'static str,
+    /// "Inherited: yes" means that a property without a cascaded value takes the computed value
+    /// of the parent.
+    /// https://www.w3.org/TR/css-cascade-4/#inheriting
+    pub inherited: bool,
+    /// The initial value in CSS syntax. An empty string is the default of the renderer that
+    /// can't be written in CSS, e.g. the default font.
+    /// https://www.w3.org/TR/css-cascade-4/#initial-values
+    pub initial: &'static str,
+    pub computed_value: ComputedValue,
+}
+
+/// The longhand properties that the renderer supports. The computed values are found in this
+/// order, so font-size comes first for the font-relative lengths of the other properties.
+pub const PROPERTIES: [PropertyDefinition; 18] = [
+    // https://www.w3.org/TR/css-fonts-4/#font-size-prop
+    PropertyDefinition {
+        name: "font-size",
+        inherited: true,
+        initial: "medium",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    // https://www.w3.org/TR/css-fonts-4/#font-weight-prop
+    PropertyDefinition {
+        name: "font-weight",
+        inherited: true,
+        initial: "normal",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // "Initial: depends on user agent"
+    // https://www.w3.org/TR/css-fonts-4/#font-family-prop
+    PropertyDefinition {
+        name: "font-family",
+        inherited: true,
+        initial: "",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // "Initial: CanvasText", which is black in the light color scheme.
+    // https://www.w3.org/TR/css-color-4/#the-color-property
+    PropertyDefinition {
+        name: "color",
+        inherited: true,
+        initial: "black",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // https://www.w3.org/TR/css-lists-3/#text-markers
+    PropertyDefinition {
+        name: "list-style-type",
+        inherited: true,
+        initial: "disc",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // "Computed value: the specified keyword, a number, or a computed <length-percentage>
+    // value"
+    // https://www.w3.org/TR/css-inline-3/#line-height-property
+    PropertyDefinition {
+        name: "line-height",
+        inherited: true,
+        initial: "normal",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    // https://www.w3.org/TR/css-display-3/#the-display-properties
+    PropertyDefinition {
+        name: "display",
+        inherited: false,
+        initial: "inline",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // https://www.w3.org/TR/css-backgrounds-3/#background-color
+    PropertyDefinition {
+        name: "background-color",
+        inherited: false,
+        initial: "transparent",
+        computed_value: ComputedValue::AsSpecified,
+    },
+    // https://www.w3.org/TR/css-sizing-3/#preferred-size-properties
+    PropertyDefinition {
+        name: "width",
+        inherited: false,
+        initial: "auto",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "height",
+        inherited: false,
+        initial: "auto",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    // https://www.w3.org/TR/css-box-4/#margin-physical
+    PropertyDefinition {
+        name: "margin-top",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "margin-right",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "margin-bottom",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "margin-left",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    // https://www.w3.org/TR/css-box-4/#padding-physical
+    PropertyDefinition {
+        name: "padding-top",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "padding-right",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "padding-bottom",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+    PropertyDefinition {
+        name: "padding-left",
+        inherited: false,
+        initial: "0",
+        computed_value: ComputedValue::AbsoluteLength,
+    },
+];
+
+/// Returns the definition of the longhand property `name`, or None if it's not supported.
+pub fn property_definition(name: &str) -> Option<&'static PropertyDefinition> {
+    PROPERTIES.iter().find(|p| p.name == name)
+}
+
+/// https://www.w3.org/TR/css-color-4/#named-colors
+/// Only the colors that `Color::from_name` knows are listed.
+const NAMED_COLORS: [&str; 18] = [
+    "black",
+    "silver",
+    "gray",
+    "white",
+    "maroon",
+    "red",
+    "purple",
+    "fuchsia",
+    "green",
+    "lime",
+    "olive",
+    "yellow",
+    "navy",
+    "blue",
+    "teal",
+    "aqua",
+    "orange",
+    "lightgray",
+];
+
+/// Returns true if the renderer supports the declaration of `property` whose value is `value`.
+/// "A CSS processor is considered to support a declaration (consisting of a property and value)
+/// if it accepts that declaration (rather than discarding it as a parse error)."
+/// https://www.w3.org/TR/css-conditional-3/#support-definition
+pub fn is_supported_declaration(property: &str, value: &[CssToken]) -> bool {
+    // A shorthand property is supported if the value is valid for it, even if the renderer
+    // doesn't support some of its longhand properties, because the declaration is accepted.
+    if longhands(property).is_some() {
+        let values = CssParser::from_tokens(value.to_vec()).parse_list_of_component_values();
+        return expand_shorthand(property, &values).is_some();
+    }
+
+    let value: Vec<&CssToken> = value
+        .iter()
+        .filter(|t| **t != CssToken::Whitespace)
+        .collect();
+
+    // "font-family" takes a list of font family names.
+    // https://www.w3.org/TR/css-fonts-4/#font-family-prop
+    if property == "font-family" {
+        return !value.is_empty()
+            && value.iter().all(|t| {
+                matches!(
+                    t,
+                    CssToken::Ident(_) | CssToken::StringToken(_) | CssToken::Comma
+                )
+            });
+    }
+
+    // The other properties take a single component value.
+    let value = match value.as_slice() {
+        [value] => *value,
+        _ => return false,
+    };
+
+    // "all properties accept the CSS-wide keywords"
+    // https://www.w3.org/TR/css-values-4/#common-keywords
+    if let CssToken::Ident(keyword) = value {
+        if property_definition(property).is_some() && is_css_wide_keyword(keyword) {
+            return true;
+        }
+    }
+
+    match property {
+        "color" | "background-color" => match value {
+            CssToken::Ident(name) => {
+                NAMED_COLORS.iter().any(|c| c.eq_ignore_ascii_case(name))
+                    || name.eq_ignore_ascii_case("transparent")
+            }
+            CssToken::HashToken(code, _) => code.len() == 6,
+            _ => false,
+        },
+        "display" => is_keyword(value, &["block", "inline", "list-item", "none"]),
+        "font-size" => {
+            is_keyword(
+                value,
+                &[
+                    "xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large",
+                ],
+            ) || is_non_negative_length(value)
+        }
+        "font-weight" => match value {
+            CssToken::Number(weight) => (1.0..=1000.0).contains(weight),
+            _ => is_keyword(value, &["normal", "bold"]),
+        },
+        "list-style-type" => is_keyword(value, &["disc", "circle", "square", "decimal", "none"]),
+        "line-height" => is_keyword(value, &["normal"]) || is_non_negative_length(value),
+        "width" | "height" => {
+            is_keyword(value, &["auto"])
+                || matches!(value, CssToken::Number(_))
+                || Length::from_token(value).is_some()
+        }
+        "margin-top" | "margin-right" | "margin-bottom" | "margin-left" | "padding-top"
+        | "padding-right" | "padding-bottom" | "padding-left" => {
+            matches!(value, CssToken::Number(_)) || Length::from_token(value).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `value` is a length or a percentage that isn't negative. A number is treated
+/// as a length in px as the renderer does.
+fn is_non_negative_length(value: &CssToken) -> bool {
+    match value {
+        CssToken::Number(number) => *number >= 0.0,
+        CssToken::Dimension(number, _) | CssToken::Percentage(number) => {
+            *number >= 0.0 && Length::from_token(value).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `value` is one of `keywords`. "CSS keywords are ASCII case-insensitive."
+/// https://www.w3.org/TR/css-values-4/#keywords
+fn is_keyword(value: &CssToken, keywords: &[&str]) -> bool {
+    match value {
+        CssToken::Ident(ident) => keywords.iter().any(|k| k.eq_ignore_ascii_case(ident)),
+        _ => false,
+    }
+}
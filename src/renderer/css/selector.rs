@@ -0,0 +1,307 @@
+//! This is a part of "Selectors Level 4".
+//! https://www.w3.org/TR/selectors-4/#matching
+//!
+//! The selector-matching engine shared by the cascade and DOM queries such as `querySelector`.
+
+use crate::history;
+use crate::renderer::css::cssom::{
+    AttributeMatcher, AttributeSelector, Combinator, ComplexSelector, CompoundSelector, CssParser,
+    PseudoClass, Selector, SelectorList,
+};
+use crate::renderer::css::token::CssTokenizer;
+use crate::renderer::html::dom::{Document, Element, ElementKind, NodeId, NodeKind};
+use crate::url::ParsedUrl;
+use std::string::String;
+
+/// Returns true if any of `selectors` matches `node` in `document`.
+/// https://www.w3.org/TR/selectors-4/#match-a-selector-against-an-element
+pub fn matches(selectors: &SelectorList, document: &Document, node: NodeId) -> bool {
+    selectors
+        .iter()
+        .any(|selector| matches_complex(selector, document, node))
+}
+
+/// Returns true if `selector` matches `node`. A complex selector is matched from right to left,
+/// walking up ancestors and back previous siblings of `node` according to the combinators.
+/// https://www.w3.org/TR/selectors-4/#complex
+pub fn matches_complex(selector: &ComplexSelector, document: &Document, node: NodeId) -> bool {
+    match selector.compounds.len() {
+        0 => false,
+        n => matches_complex_from(selector, n - 1, document, node),
+    }
+}
+
+/// Returns true if `selector.compounds[index]` matches `node` and the compound selectors on its
+/// left match the elements that the combinators represent.
+fn matches_complex_from(
+    selector: &ComplexSelector,
+    index: usize,
+    document: &Document,
+    node: NodeId,
+) -> bool {
+    if !matches_compound(&selector.compounds[index], document, node) {
+        return false;
+    }
+    if index == 0 {
+        return true;
+    }
+
+    let left = index - 1;
+    match selector.combinators[left] {
+        // "A descendant combinator is whitespace that separates two compound selectors. A
+        // selector of the form A B represents an element B that is an arbitrary descendant of
+        // some ancestor element A."
+        Combinator::Descendant => {
+            let mut ancestor = parent_element(document, node);
+            while let Some(a) = ancestor {
+                if matches_complex_from(selector, left, document, a) {
+                    return true;
+                }
+                ancestor = parent_element(document, a);
+            }
+            false
+        }
+        // "A child combinator describes a childhood relationship between two elements."
+        Combinator::Child => match parent_element(document, node) {
+            Some(parent) => matches_complex_from(selector, left, document, parent),
+            None => false,
+        },
+        // "the elements represented by the two compound selectors share the same parent in the
+        // document tree and the element represented by the first compound selector immediately
+        // precedes the element represented by the second one."
+        Combinator::NextSibling => match previous_element_sibling(document, node) {
+            Some(sibling) => matches_complex_from(selector, left, document, sibling),
+            None => false,
+        },
+        // "the element represented by the first compound selector precedes (not necessarily
+        // immediately) the element represented by the second one."
+        Combinator::SubsequentSibling => {
+            let mut sibling = previous_element_sibling(document, node);
+            while let Some(s) = sibling {
+                if matches_complex_from(selector, left, document, s) {
+                    return true;
+                }
+                sibling = previous_element_sibling(document, s);
+            }
+            false
+        }
+    }
+}
+
+/// Returns true if all of the simple selectors in `compound` match `node`. No element matches
+/// an empty compound selector.
+/// https://www.w3.org/TR/selectors-4/#compound
+pub fn matches_compound(compound: &CompoundSelector, document: &Document, node: NodeId) -> bool {
+    !compound.is_empty()
+        && compound
+            .iter()
+            .all(|selector| matches_simple(selector, document, node))
+}
+
+/// Returns true if the simple selector `selector` matches `node`.
+fn matches_simple(selector: &Selector, document: &Document, node: NodeId) -> bool {
+    let element = match document.node(node).element() {
+        Some(e) => e,
+        None => return false,
+    };
+
+    match selector {
+        // "The type selector is written as a CSS qualified name ... Type selectors are ASCII
+        // case-insensitive for elements in the HTML namespace."
+        Selector::TypeSelector(type_name) => element.local_name() == type_name.to_ascii_lowercase(),
+        // "The universal selector ... represents an element of any element type"
+        Selector::UniversalSelector => true,
+        // https://www.w3.org/TR/selectors-4/#class-html
+        Selector::ClassSelector(class_name) => element.classes().contains(class_name),
+        // https://www.w3.org/TR/selectors-4/#id-selectors
+        Selector::IdSelector(id_name) => element.id() == *id_name,
+        Selector::AttributeSelector(attribute) => matches_attribute(attribute, element),
+        Selector::PseudoClass(pseudo_class) => matches_pseudo_class(pseudo_class, document, node),
+    }
+}
+
+/// https://www.w3.org/TR/selectors-4/#pseudo-classes
+fn matches_pseudo_class(pseudo_class: &PseudoClass, document: &Document, node: NodeId) -> bool {
+    match pseudo_class {
+        // "Same as :nth-child(1). The :first-child pseudo-class represents an element that is
+        // first among its inclusive siblings."
+        PseudoClass::FirstChild => previous_element_sibling(document, node).is_none(),
+        // "Same as :nth-last-child(1). The :last-child pseudo-class represents an element that is
+        // last among its inclusive siblings."
+        PseudoClass::LastChild => next_element_sibling(document, node).is_none(),
+        // "The :nth-child(An+B) pseudo-class notation represents elements that are among An+Bth
+        // elements from the list composed of their inclusive siblings, ... for all positive
+        // integer or zero values of n"
+        PseudoClass::NthChild(a, b) => {
+            let mut index: i64 = 1;
+            let mut sibling = previous_element_sibling(document, node);
+            while let Some(s) = sibling {
+                index += 1;
+                sibling = previous_element_sibling(document, s);
+            }
+
+            // Find n >= 0 such that a * n + b == index. A and B can be as large as an i64, so an
+            // overflow is treated as no match.
+            match index.checked_sub(*b) {
+                Some(diff) if *a == 0 => diff == 0,
+                Some(diff) => {
+                    diff.checked_rem(*a) == Some(0) && diff.checked_div(*a).is_some_and(|n| n >= 0)
+                }
+                None => false,
+            }
+        }
+        // "The negation pseudo-class, :not(), is a functional pseudo-class taking a selector list
+        // as an argument. It represents an element that is not represented by its argument."
+        PseudoClass::Not(selectors) => !matches(selectors, document, node),
+        // "The matches-any pseudo-class, :is(), is a functional pseudo-class taking a
+        // <forgiving-selector-list> as its sole argument."
+        // ":where() ... has the same syntax and functionality as :is()" but its specificity is
+        // always zero.
+        PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => {
+            matches(selectors, document, node)
+        }
+        // "The :root pseudo-class represents an element that is the root of the document."
+        PseudoClass::Root => document.document_element() == Some(node),
+        // "The :empty pseudo-class represents an element that has no children except, optionally,
+        // document white space characters."
+        PseudoClass::Empty => {
+            let mut child = document.node(node).first_child();
+            while let Some(c) = child {
+                match document.node(c).kind() {
+                    NodeKind::Text(text) if text.chars().all(|c| c.is_ascii_whitespace()) => {}
+                    _ => return false,
+                }
+                child = document.node(c).next_sibling();
+            }
+            true
+        }
+        // "All a elements that have an href attribute, and all area elements that have an href
+        // attribute, must match one of :link and :visited."
+        // https://html.spec.whatwg.org/multipage/semantics-other.html#selector-link
+        PseudoClass::Link => match link_url(document, node) {
+            Some(url) => !history::is_visited(&url),
+            None => false,
+        },
+        PseudoClass::Visited => match link_url(document, node) {
+            Some(url) => history::is_visited(&url),
+            None => false,
+        },
+        // "The :hover pseudo-class must match any element falling into one of the following
+        // categories: The designated hovered element ... An element that has a descendant that
+        // the user indicates using a pointing device."
+        // https://html.spec.whatwg.org/multipage/semantics-other.html#selector-hover
+        PseudoClass::Hover => is_inclusive_ancestor(document, node, document.hovered_element()),
+        // "An element that has a descendant that is currently matching the :active
+        // pseudo-class" also matches it.
+        // https://html.spec.whatwg.org/multipage/semantics-other.html#selector-active
+        PseudoClass::Active => is_inclusive_ancestor(document, node, document.active_element()),
+        // "For the purposes of the CSS :focus pseudo-class, an element has the focus when ... it
+        // is one of the elements listed in the current focus chain of the top-level traversable".
+        // Note: the focus chain has only the focused element because we don't have frames.
+        // https://html.spec.whatwg.org/multipage/semantics-other.html#selector-focus
+        PseudoClass::Focus => document.focused_element() == Some(node),
+    }
+}
+
+/// Returns the URL that the href attribute of a link points to, resolved against the document
+/// URL. Returns None if `node` is not a link.
+fn link_url(document: &Document, node: NodeId) -> Option<String> {
+    let element = document.node(node).element()?;
+    if element.kind() != ElementKind::A {
+        return None;
+    }
+    let href = element.get_attribute("href")?;
+    Some(ParsedUrl::new(document.url()).join(&href))
+}
+
+/// Returns true if `node` is `descendant` or one of its ancestors.
+fn is_inclusive_ancestor(document: &Document, node: NodeId, descendant: Option<NodeId>) -> bool {
+    let mut current = descendant;
+    while let Some(c) = current {
+        if c == node {
+            return true;
+        }
+        current = document.node(c).parent();
+    }
+    false
+}
+
+/// https://www.w3.org/TR/selectors-4/#attribute-selectors
+fn matches_attribute(selector: &AttributeSelector, element: &Element) -> bool {
+    // The attribute name is compared ASCII case-insensitively in `get_attribute` for HTML
+    // elements.
+    let value = match element.get_attribute(&selector.name) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let (matcher, expected) = match &selector.matcher {
+        // "[att] Represents an element with the att attribute, whatever the value of the
+        // attribute."
+        None => return true,
+        Some((m, e)) => (m, e),
+    };
+
+    let (value, expected) = if selector.case_insensitive {
+        (value.to_ascii_lowercase(), expected.to_ascii_lowercase())
+    } else {
+        (value, expected.to_string())
+    };
+
+    match matcher {
+        // "Represents an element with the att attribute whose value is exactly "val"."
+        AttributeMatcher::Equal => value == expected,
+        // "Represents an element with the att attribute whose value is a whitespace-separated
+        // list of words, one of which is exactly "val". If "val" contains whitespace, it will
+        // never represent anything (since the words are separated by spaces). Also if "val" is
+        // the empty string, it will never represent anything."
+        AttributeMatcher::Includes => value.split_ascii_whitespace().any(|w| w == expected),
+        // "Represents an element with the att attribute, its value either being exactly "val"
+        // or beginning with "val" immediately followed by "-" (U+002D)."
+        AttributeMatcher::DashMatch => {
+            value == expected || value.starts_with(&(expected.to_string() + "-"))
+        }
+        // "If "val" is the empty string then the selector does not represent anything."
+        AttributeMatcher::Prefix => !expected.is_empty() && value.starts_with(&expected),
+        AttributeMatcher::Suffix => !expected.is_empty() && value.ends_with(&expected),
+        AttributeMatcher::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+}
+
+/// Returns the parent of `node` if it's an element.
+fn parent_element(document: &Document, node: NodeId) -> Option<NodeId> {
+    document
+        .node(node)
+        .parent()
+        .filter(|p| document.node(*p).element().is_some())
+}
+
+/// Returns the nearest previous sibling of `node` that is an element.
+fn previous_element_sibling(document: &Document, node: NodeId) -> Option<NodeId> {
+    let mut sibling = document.node(node).previous_sibling();
+    while let Some(s) = sibling {
+        if document.node(s).element().is_some() {
+            return Some(s);
+        }
+        sibling = document.node(s).previous_sibling();
+    }
+    None
+}
+
+/// Returns the nearest next sibling of `node` that is an element.
+fn next_element_sibling(document: &Document, node: NodeId) -> Option<NodeId> {
+    let mut sibling = document.node(node).next_sibling();
+    while let Some(s) = sibling {
+        if document.node(s).element().is_some() {
+            return Some(s);
+        }
+        sibling = document.node(s).next_sibling();
+    }
+    None
+}
+
+/// Parses `selectors` as a selector list. Returns None if it's not a valid selector.
+/// https://www.w3.org/TR/selectors-4/#parse-a-selector
+pub fn parse_selector(selectors: &str) -> Option<SelectorList> {
+    CssParser::new(CssTokenizer::new(String::from(selectors))).parse_selector()
+}
@@ -0,0 +1,531 @@
+//! Shorthand properties, which set several longhand properties at once. A declaration of a
+//! shorthand property is expanded into the declarations of its longhand properties when it's
+//! parsed, so that the cascade only sees longhand properties.
+//! https://www.w3.org/TR/css-cascade-4/#shorthand
+
+use crate::renderer::css::cssom::ComponentValue;
+use crate::renderer::css::token::CssToken;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// The longhand properties of a shorthand property for the four sides of a box, in the order of
+/// top, right, bottom and left.
+const MARGIN: [&str; 4] = ["margin-top", "margin-right", "margin-bottom", "margin-left"];
+const PADDING: [&str; 4] = [
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+];
+const BORDER_WIDTH: [&str; 4] = [
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+];
+const BORDER_STYLE: [&str; 4] = [
+    "border-top-style",
+    "border-right-style",
+    "border-bottom-style",
+    "border-left-style",
+];
+const BORDER_COLOR: [&str; 4] = [
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+];
+
+/// https://www.w3.org/TR/css-fonts-4/#font-prop
+const FONT: [&str; 7] = [
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "font-stretch",
+    "font-size",
+    "line-height",
+    "font-family",
+];
+
+/// https://www.w3.org/TR/css-backgrounds-3/#background
+/// TODO: support background-position, background-size, background-origin and background-clip.
+const BACKGROUND: [&str; 4] = [
+    "background-color",
+    "background-image",
+    "background-repeat",
+    "background-attachment",
+];
+
+/// https://www.w3.org/TR/css-lists-3/#list-style-property
+const LIST_STYLE: [&str; 3] = ["list-style-position", "list-style-image", "list-style-type"];
+
+/// Returns the longhand properties of `property`, or None if it's not a shorthand property.
+pub fn longhands(property: &str) -> Option<Vec<&'static str>> {
+    let longhands = match property {
+        "margin" => MARGIN.to_vec(),
+        "padding" => PADDING.to_vec(),
+        "border-width" => BORDER_WIDTH.to_vec(),
+        "border-style" => BORDER_STYLE.to_vec(),
+        "border-color" => BORDER_COLOR.to_vec(),
+        "border" => (0..4)
+            .flat_map(|side| [BORDER_WIDTH[side], BORDER_STYLE[side], BORDER_COLOR[side]])
+            .collect(),
+        "font" => FONT.to_vec(),
+        "background" => BACKGROUND.to_vec(),
+        "list-style" => LIST_STYLE.to_vec(),
+        _ => {
+            let side = border_side(property)?;
+            vec![BORDER_WIDTH[side], BORDER_STYLE[side], BORDER_COLOR[side]]
+        }
+    };
+    Some(longhands)
+}
+
+/// Returns the index of the side of "border-top", "border-right", "border-bottom" or
+/// "border-left".
+fn border_side(property: &str) -> Option<usize> {
+    ["border-top", "border-right", "border-bottom", "border-left"]
+        .iter()
+        .position(|p| *p == property)
+}
+
+/// Expands the declaration of the shorthand property `property` whose value is `values` into
+/// the pairs of its longhand properties and their values. The values don't have whitespace.
+/// Returns None if the value is invalid for the shorthand property, so that the declaration is
+/// ignored.
+pub fn expand_shorthand(
+    property: &str,
+    values: &[ComponentValue],
+) -> Option<Vec<(String, ComponentValue)>> {
+    let longhands = longhands(property)?;
+
+    // "the CSS-wide keywords ... can be used as the value of a shorthand, which sets all of its
+    // longhands to that keyword."
+    // https://www.w3.org/TR/css-cascade-4/#defaulting-keywords
+    if let [ComponentValue::Keyword(keyword)] = values {
+        if is_css_wide_keyword(keyword) {
+            return Some(
+                longhands
+                    .iter()
+                    .map(|l| (l.to_string(), values[0].clone()))
+                    .collect(),
+            );
+        }
+    }
+
+    let expanded = match property {
+        "margin" => expand_box(values, |v| {
+            is_length(v)
+                || matches!(v, ComponentValue::Keyword(k) if k.eq_ignore_ascii_case("auto"))
+        })?,
+        "padding" => expand_box(values, is_length)?,
+        "border-width" => expand_box(values, is_line_width)?,
+        "border-style" => expand_box(values, is_line_style)?,
+        "border-color" => expand_box(values, is_color)?,
+        "border" => {
+            let [width, style, color] = expand_border_side(values)?;
+            let mut expanded = Vec::new();
+            for _ in 0..4 {
+                expanded.push(width.clone());
+                expanded.push(style.clone());
+                expanded.push(color.clone());
+            }
+            expanded
+        }
+        "font" => expand_font(values)?,
+        "background" => expand_background(values)?,
+        "list-style" => expand_list_style(values)?,
+        _ => expand_border_side(values)?.to_vec(),
+    };
+
+    Some(
+        longhands
+            .iter()
+            .map(|l| l.to_string())
+            .zip(expanded)
+            .collect(),
+    )
+}
+
+/// https://www.w3.org/TR/css-cascade-4/#css-wide-keywords
+pub fn is_css_wide_keyword(keyword: &str) -> bool {
+    ["inherit", "initial", "unset"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(keyword))
+}
+
+fn keyword(keyword: &str) -> ComponentValue {
+    ComponentValue::Keyword(keyword.to_string())
+}
+
+fn is_keyword(value: &ComponentValue, keywords: &[&str]) -> bool {
+    match value {
+        ComponentValue::Keyword(k) => keywords
+            .iter()
+            .any(|keyword| keyword.eq_ignore_ascii_case(k)),
+        _ => false,
+    }
+}
+
+/// A number is a length in px as well as `RenderObject::to_length`.
+fn is_length(value: &ComponentValue) -> bool {
+    matches!(value, ComponentValue::Length(_) | ComponentValue::Number(_))
+}
+
+/// https://www.w3.org/TR/css-backgrounds-3/#typedef-line-width
+fn is_line_width(value: &ComponentValue) -> bool {
+    is_length(value) || is_keyword(value, &["thin", "medium", "thick"])
+}
+
+/// https://www.w3.org/TR/css-backgrounds-3/#typedef-line-style
+fn is_line_style(value: &ComponentValue) -> bool {
+    is_keyword(
+        value,
+        &[
+            "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset",
+            "outset",
+        ],
+    )
+}
+
+/// Returns true if `value` can be a <color>. Any keyword is accepted as a named color here,
+/// because the other keywords of a shorthand are checked before colors.
+/// https://www.w3.org/TR/css-color-4/#typedef-color
+fn is_color(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::Keyword(_) => true,
+        ComponentValue::InputToken(CssToken::HashToken(..)) => true,
+        ComponentValue::Function(name, _) => {
+            matches!(name.as_str(), "rgb" | "rgba" | "hsl" | "hsla")
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `value` is a <url> or a url() function.
+/// https://www.w3.org/TR/css-values-4/#urls
+fn is_url(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::InputToken(CssToken::Url(_)) => true,
+        ComponentValue::Function(name, _) => name.eq_ignore_ascii_case("url"),
+        _ => false,
+    }
+}
+
+/// Expands 1 to 4 values for the sides of a box into the values in the order of top, right,
+/// bottom and left.
+/// "If there is only one component value, it applies to all sides. If there are two values, the
+/// top and bottom are set to the first value and the right and left are set to the second. If
+/// there are three values, the top is set to the first value, the left and right are set to the
+/// second, and the bottom is set to the third. If there are four values they apply to the top,
+/// right, bottom, and left, respectively."
+/// https://www.w3.org/TR/css-box-4/#margin-shorthand
+fn expand_box(
+    values: &[ComponentValue],
+    is_valid: impl Fn(&ComponentValue) -> bool,
+) -> Option<Vec<ComponentValue>> {
+    if !values.iter().all(is_valid) {
+        return None;
+    }
+    let (top, right, bottom, left) = match values {
+        [a] => (a, a, a, a),
+        [a, b] => (a, b, a, b),
+        [a, b, c] => (a, b, c, b),
+        [a, b, c, d] => (a, b, c, d),
+        _ => return None,
+    };
+    Some(vec![
+        top.clone(),
+        right.clone(),
+        bottom.clone(),
+        left.clone(),
+    ])
+}
+
+/// <line-width> || <line-style> || <color>
+/// Returns the values of the width, the style and the color of a side. "Omitted values are set
+/// to their initial values."
+/// https://www.w3.org/TR/css-backgrounds-3/#border-shorthands
+fn expand_border_side(values: &[ComponentValue]) -> Option<[ComponentValue; 3]> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for value in values {
+        if width.is_none() && is_line_width(value) {
+            width = Some(value.clone());
+        } else if style.is_none() && is_line_style(value) {
+            style = Some(value.clone());
+        } else if color.is_none() && is_color(value) {
+            color = Some(value.clone());
+        } else {
+            return None;
+        }
+    }
+    if values.is_empty() {
+        return None;
+    }
+
+    Some([
+        width.unwrap_or_else(|| keyword("medium")),
+        style.unwrap_or_else(|| keyword("none")),
+        color.unwrap_or_else(|| keyword("currentcolor")),
+    ])
+}
+
+/// [ <'font-style'> || <font-variant-css2> || <'font-weight'> || <font-stretch-css3> ]?
+/// <'font-size'> [ / <'line-height'> ]? <'font-family'>
+/// https://www.w3.org/TR/css-fonts-4/#font-prop
+fn expand_font(values: &[ComponentValue]) -> Option<Vec<ComponentValue>> {
+    // TODO: support the system font keywords such as "caption".
+    let mut style = None;
+    let mut variant = None;
+    let mut weight = None;
+    let mut stretch = None;
+
+    // The values before the font size. "normal" can be any of them.
+    let mut i = 0;
+    while i < values.len() && i < 4 {
+        let value = &values[i];
+        if is_font_size(value) {
+            break;
+        }
+        if is_keyword(value, &["normal"]) {
+            // "normal" resets the value that is not set by the other values.
+        } else if style.is_none() && is_keyword(value, &["italic", "oblique"]) {
+            style = Some(value.clone());
+        } else if variant.is_none() && is_keyword(value, &["small-caps"]) {
+            variant = Some(value.clone());
+        } else if weight.is_none() && is_font_weight(value) {
+            weight = Some(value.clone());
+        } else if stretch.is_none() && is_font_stretch(value) {
+            stretch = Some(value.clone());
+        } else {
+            return None;
+        }
+        i += 1;
+    }
+
+    // The font size is required.
+    let size = values.get(i).filter(|v| is_font_size(v))?.clone();
+    i += 1;
+
+    let mut line_height = keyword("normal");
+    if values.get(i) == Some(&ComponentValue::InputToken(CssToken::Delim('/'))) {
+        line_height = values
+            .get(i + 1)
+            .filter(|v| is_length(v) || is_keyword(v, &["normal"]))?
+            .clone();
+        i += 2;
+    }
+
+    // The font family is required.
+    let family = first_font_family(&values[i..])?;
+
+    Some(vec![
+        style.unwrap_or_else(|| keyword("normal")),
+        variant.unwrap_or_else(|| keyword("normal")),
+        weight.unwrap_or_else(|| keyword("normal")),
+        stretch.unwrap_or_else(|| keyword("normal")),
+        size,
+        line_height,
+        family,
+    ])
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-size-prop
+fn is_font_size(value: &ComponentValue) -> bool {
+    matches!(value, ComponentValue::Length(_))
+        || is_keyword(
+            value,
+            &[
+                "xx-small",
+                "x-small",
+                "small",
+                "medium",
+                "large",
+                "x-large",
+                "xx-large",
+                "xxx-large",
+                "larger",
+                "smaller",
+            ],
+        )
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-weight-prop
+fn is_font_weight(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::Number(weight) => (1.0..=1000.0).contains(weight),
+        _ => is_keyword(value, &["bold", "bolder", "lighter"]),
+    }
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-stretch-prop
+fn is_font_stretch(value: &ComponentValue) -> bool {
+    is_keyword(
+        value,
+        &[
+            "ultra-condensed",
+            "extra-condensed",
+            "condensed",
+            "semi-condensed",
+            "semi-expanded",
+            "expanded",
+            "extra-expanded",
+            "ultra-expanded",
+        ],
+    )
+}
+
+/// Returns the first font family in the comma-separated list of font families. A family name
+/// of identifiers is joined by spaces.
+/// TODO: keep all the font families as the value of font-family.
+/// https://www.w3.org/TR/css-fonts-4/#family-name-syntax
+fn first_font_family(values: &[ComponentValue]) -> Option<ComponentValue> {
+    let first = values
+        .split(|v| *v == ComponentValue::InputToken(CssToken::Comma))
+        .next()?;
+    match first {
+        [] => None,
+        [ComponentValue::InputToken(CssToken::StringToken(name))] => Some(
+            ComponentValue::InputToken(CssToken::StringToken(name.to_string())),
+        ),
+        _ => {
+            let mut idents = Vec::new();
+            for value in first {
+                match value {
+                    ComponentValue::Keyword(ident) => idents.push(ident.to_string()),
+                    _ => return None,
+                }
+            }
+            Some(keyword(&idents.join(" ")))
+        }
+    }
+}
+
+/// <bg-layer> = <bg-image> || <bg-position> [ / <bg-size> ]? || <repeat-style> || <attachment>
+/// || <box> || <box>
+/// <final-bg-layer> = <bg-layer> || <'background-color'>
+/// https://www.w3.org/TR/css-backgrounds-3/#background
+fn expand_background(values: &[ComponentValue]) -> Option<Vec<ComponentValue>> {
+    // TODO: support multiple background layers.
+    let mut color = None;
+    let mut image = None;
+    let mut repeat = None;
+    let mut attachment = None;
+
+    let mut i = 0;
+    while i < values.len() {
+        let value = &values[i];
+        if image.is_none() && (is_url(value) || is_keyword(value, &["none"])) {
+            image = Some(value.clone());
+        } else if repeat.is_none()
+            && is_keyword(
+                value,
+                &[
+                    "repeat-x",
+                    "repeat-y",
+                    "repeat",
+                    "space",
+                    "round",
+                    "no-repeat",
+                ],
+            )
+        {
+            repeat = Some(value.clone());
+            // The second value of <repeat-style> for the vertical direction.
+            // TODO: keep both of the values.
+            if values
+                .get(i + 1)
+                .is_some_and(|v| is_keyword(v, &["repeat", "space", "round", "no-repeat"]))
+                && !is_keyword(value, &["repeat-x", "repeat-y"])
+            {
+                i += 1;
+            }
+        } else if attachment.is_none() && is_keyword(value, &["scroll", "fixed", "local"]) {
+            attachment = Some(value.clone());
+        } else if is_length(value)
+            || is_keyword(value, &["left", "center", "right", "top", "bottom"])
+        {
+            // <bg-position> is accepted but ignored.
+        } else if *value == ComponentValue::InputToken(CssToken::Delim('/')) {
+            // <bg-size> after "/" is accepted but ignored.
+            match values.get(i + 1) {
+                Some(v) if is_length(v) || is_keyword(v, &["auto", "cover", "contain"]) => i += 1,
+                _ => return None,
+            }
+            while values
+                .get(i + 1)
+                .is_some_and(|v| is_length(v) || is_keyword(v, &["auto"]))
+            {
+                i += 1;
+            }
+        } else if is_keyword(value, &["border-box", "padding-box", "content-box"]) {
+            // <box> for background-origin and background-clip is accepted but ignored.
+        } else if color.is_none() && is_color(value) {
+            color = Some(value.clone());
+        } else {
+            return None;
+        }
+        i += 1;
+    }
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(vec![
+        color.unwrap_or_else(|| keyword("transparent")),
+        image.unwrap_or_else(|| keyword("none")),
+        repeat.unwrap_or_else(|| keyword("repeat")),
+        attachment.unwrap_or_else(|| keyword("scroll")),
+    ])
+}
+
+/// <'list-style-position'> || <'list-style-image'> || <'list-style-type'>
+/// https://www.w3.org/TR/css-lists-3/#list-style-property
+fn expand_list_style(values: &[ComponentValue]) -> Option<Vec<ComponentValue>> {
+    let mut position = None;
+    let mut image = None;
+    let mut list_type = None;
+    let mut nones = 0;
+
+    for value in values {
+        if is_keyword(value, &["none"]) {
+            // "none" is resolved after the other values because it can be either of the image
+            // and the type.
+            nones += 1;
+        } else if position.is_none() && is_keyword(value, &["inside", "outside"]) {
+            position = Some(value.clone());
+        } else if image.is_none() && is_url(value) {
+            image = Some(value.clone());
+        } else if list_type.is_none()
+            && matches!(
+                value,
+                ComponentValue::Keyword(_) | ComponentValue::InputToken(CssToken::StringToken(_))
+            )
+        {
+            list_type = Some(value.clone());
+        } else {
+            return None;
+        }
+    }
+
+    // "Using a value of none in the shorthand is potentially ambiguous, as none is a valid value
+    // for both list-style-image and list-style-type. To resolve this ambiguity, a value of none
+    // in the shorthand must be applied to whichever of the two properties aren't otherwise set
+    // by the shorthand."
+    let unset = image.is_none() as usize + list_type.is_none() as usize;
+    if values.is_empty() || nones > unset {
+        return None;
+    }
+    if nones > 0 {
+        image = image.or_else(|| Some(keyword("none")));
+        list_type = list_type.or_else(|| Some(keyword("none")));
+    }
+
+    Some(vec![
+        position.unwrap_or_else(|| keyword("outside")),
+        image.unwrap_or_else(|| keyword("none")),
+        list_type.unwrap_or_else(|| keyword("disc")),
+    ])
+}
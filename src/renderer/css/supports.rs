@@ -0,0 +1,237 @@
+//! This is a part of "CSS Conditional Rules Module Level 3".
+//! https://www.w3.org/TR/css-conditional-3/#at-supports
+//!
+//! A supports condition is parsed from the prelude of an @supports rule and evaluated against
+//! the properties that the renderer supports.
+
+use crate::renderer::css::properties::is_supported_declaration;
+use crate::renderer::css::token::CssToken;
+use std::string::String;
+use std::vec::Vec;
+
+/// https://www.w3.org/TR/css-conditional-3/#typedef-supports-condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsCondition {
+    /// <supports-decl> = ( <declaration> )
+    /// The property is in ASCII lowercase.
+    Declaration(String, Vec<CssToken>),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// "<general-enclosed> ... evaluates to false."
+    /// https://www.w3.org/TR/css-conditional-3/#typedef-general-enclosed
+    GeneralEnclosed,
+}
+
+/// Returns true if `condition` is true.
+/// https://www.w3.org/TR/css-conditional-3/#evaluating-a-supports-condition
+pub fn evaluate_supports_condition(condition: &SupportsCondition) -> bool {
+    match condition {
+        // "The result is true if the UA supports the declaration within the parentheses."
+        SupportsCondition::Declaration(property, value) => {
+            is_supported_declaration(property, value)
+        }
+        // "The result is the negation of the <supports-in-parens> term."
+        SupportsCondition::Not(c) => !evaluate_supports_condition(c),
+        // "The result is true if all of the <supports-in-parens> child terms are true"
+        SupportsCondition::And(conditions) => conditions.iter().all(evaluate_supports_condition),
+        // "The result is true if any of the <supports-in-parens> child terms are true"
+        SupportsCondition::Or(conditions) => conditions.iter().any(evaluate_supports_condition),
+        SupportsCondition::GeneralEnclosed => false,
+    }
+}
+
+/// Parses `tokens`, e.g. the prelude of an @supports rule, as a supports condition. Returns
+/// None if it's invalid, which makes the whole rule invalid.
+/// https://www.w3.org/TR/css-conditional-3/#at-supports
+pub fn parse_supports_condition(tokens: &[CssToken]) -> Option<SupportsCondition> {
+    let mut parser = SupportsConditionParser {
+        tokens,
+        position: 0,
+    };
+    parser.consume_whitespace();
+    let condition = parser.consume_supports_condition()?;
+    parser.consume_whitespace();
+    if parser.position == tokens.len() {
+        Some(condition)
+    } else {
+        None
+    }
+}
+
+struct SupportsConditionParser<'a> {
+    tokens: &'a [CssToken],
+    position: usize,
+}
+
+impl<'a> SupportsConditionParser<'a> {
+    fn peek(&self) -> Option<&'a CssToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&'a CssToken> {
+        let token = self.tokens.get(self.position);
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek() == Some(&CssToken::Whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Returns true and consumes the next token if it's the keyword `keyword`.
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// <supports-condition> = not <supports-in-parens>
+    ///                      | <supports-in-parens> [ and <supports-in-parens> ]*
+    ///                      | <supports-in-parens> [ or <supports-in-parens> ]*
+    /// "whitespace is required after a not and on both sides of an and or or."
+    fn consume_supports_condition(&mut self) -> Option<SupportsCondition> {
+        if self.consume_keyword("not") {
+            if self.next() != Some(&CssToken::Whitespace) {
+                return None;
+            }
+            self.consume_whitespace();
+            return Some(SupportsCondition::Not(Box::new(
+                self.consume_supports_in_parens()?,
+            )));
+        }
+
+        let first = self.consume_supports_in_parens()?;
+        let mut conditions = vec![first];
+        let mut keyword: Option<&str> = None;
+        loop {
+            // Look ahead for " and " or " or " after the previous term.
+            let start = self.position;
+            if self.next() != Some(&CssToken::Whitespace) {
+                self.position = start;
+                break;
+            }
+            self.consume_whitespace();
+            let next_keyword = match self.peek() {
+                Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case("and") => "and",
+                Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case("or") => "or",
+                _ => {
+                    self.position = start;
+                    break;
+                }
+            };
+            // "and" and "or" can't be mixed at the same level without parentheses.
+            if keyword.is_some_and(|k| k != next_keyword) {
+                return None;
+            }
+            keyword = Some(next_keyword);
+            self.next();
+            if self.next() != Some(&CssToken::Whitespace) {
+                return None;
+            }
+            self.consume_whitespace();
+            conditions.push(self.consume_supports_in_parens()?);
+        }
+
+        match keyword {
+            None => conditions.pop(),
+            Some("and") => Some(SupportsCondition::And(conditions)),
+            Some(_) => Some(SupportsCondition::Or(conditions)),
+        }
+    }
+
+    /// <supports-in-parens> = ( <supports-condition> ) | <supports-feature> | <general-enclosed>
+    /// https://www.w3.org/TR/css-conditional-3/#typedef-supports-in-parens
+    fn consume_supports_in_parens(&mut self) -> Option<SupportsCondition> {
+        match self.peek()? {
+            CssToken::OpenParenthesis => {}
+            // <general-enclosed> = [ <function-token> <any-value>? ) ]
+            // TODO: support selector().
+            CssToken::Function(_) => {
+                self.consume_general_enclosed();
+                return Some(SupportsCondition::GeneralEnclosed);
+            }
+            _ => return None,
+        }
+
+        let start = self.position;
+        self.next();
+        self.consume_whitespace();
+        let condition = match self.peek() {
+            Some(CssToken::OpenParenthesis) | Some(CssToken::Function(_)) => {
+                self.consume_supports_condition()
+            }
+            Some(CssToken::Ident(ident)) if ident.eq_ignore_ascii_case("not") => {
+                self.consume_supports_condition()
+            }
+            _ => self.consume_supports_declaration(),
+        };
+        self.consume_whitespace();
+
+        match condition {
+            Some(c) if self.peek() == Some(&CssToken::CloseParenthesis) => {
+                self.next();
+                Some(c)
+            }
+            // <general-enclosed> = [ ( <any-value>? ) ]
+            _ => {
+                self.position = start;
+                self.consume_general_enclosed();
+                Some(SupportsCondition::GeneralEnclosed)
+            }
+        }
+    }
+
+    /// Consumes a <declaration> in the parentheses of a <supports-decl>, leaving the ")".
+    /// https://www.w3.org/TR/css-conditional-3/#typedef-supports-decl
+    fn consume_supports_declaration(&mut self) -> Option<SupportsCondition> {
+        let property = match self.next()? {
+            CssToken::Ident(ident) => ident.to_ascii_lowercase(),
+            _ => return None,
+        };
+        self.consume_whitespace();
+        if self.next() != Some(&CssToken::Colon) {
+            return None;
+        }
+
+        // The value ends at the ")" of the <supports-decl>. A value that has parentheses is not
+        // supported.
+        let mut value = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                CssToken::CloseParenthesis => break,
+                CssToken::OpenParenthesis | CssToken::Function(_) => return None,
+                _ => {
+                    value.push(token.clone());
+                    self.next();
+                }
+            }
+        }
+        Some(SupportsCondition::Declaration(property, value))
+    }
+
+    /// Consumes the tokens up to and including the ")" that matches the next "(" or function.
+    fn consume_general_enclosed(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                CssToken::OpenParenthesis | CssToken::Function(_) => depth += 1,
+                CssToken::CloseParenthesis => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -7,20 +7,69 @@
 use std::string::String;
 use std::vec::Vec;
 
+/// https://www.w3.org/TR/css-syntax-3/#maximum-allowed-code-point
+const MAXIMUM_ALLOWED_CODE_POINT: u32 = 0x10ffff;
+
+/// https://www.w3.org/TR/css-syntax-3/#typedef-hash-token
+/// "<hash-token>s additionally have a type flag set to either "id" or "unrestricted". The type
+/// flag defaults to "unrestricted" if not otherwise set."
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashType {
+    /// The value would be a valid identifier, e.g. "#main". Only this type can be an ID selector.
+    Id,
+    /// e.g. "#123".
+    Unrestricted,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// https://www.w3.org/TR/css-syntax-3/#consume-token
 /// https://www.w3.org/TR/css-syntax-3/#tokenization
 pub enum CssToken {
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-ident-token
+    Ident(String),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-function-token
+    /// The value is the name of the function without "(".
+    Function(String),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-at-keyword-token
+    /// The value doesn't have "@".
+    AtKeyword(String),
     /// https://www.w3.org/TR/css-syntax-3/#typedef-hash-token
-    HashToken(String),
+    /// The value doesn't have "#".
+    HashToken(String, HashType),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-string-token
+    StringToken(String),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-bad-string-token
+    BadString,
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-url-token
+    Url(String),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-bad-url-token
+    BadUrl,
     /// https://www.w3.org/TR/css-syntax-3/#typedef-delim-token
     Delim(char),
     /// https://www.w3.org/TR/css-syntax-3/#typedef-number-token
+    /// Note: we don't keep the type flag. A number is an integer if its fractional part is zero.
     Number(f64),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-percentage-token
+    Percentage(f64),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-dimension-token
+    /// A number and its unit, e.g. "10px".
+    Dimension(f64, String),
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-whitespace-token
+    Whitespace,
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-cdo-token
+    Cdo,
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-cdc-token
+    Cdc,
     /// https://www.w3.org/TR/css-syntax-3/#typedef-colon-token
     Colon,
     /// https://www.w3.org/TR/css-syntax-3/#typedef-semicolon-token
     SemiColon,
+    /// https://www.w3.org/TR/css-syntax-3/#typedef-comma-token
+    Comma,
+    /// https://www.w3.org/TR/css-syntax-3/#tokendef-open-square
+    OpenSquare,
+    /// https://www.w3.org/TR/css-syntax-3/#tokendef-close-square
+    CloseSquare,
     /// https://www.w3.org/TR/css-syntax-3/#tokendef-open-paren
     OpenParenthesis,
     /// https://www.w3.org/TR/css-syntax-3/#tokendef-close-paren
@@ -29,199 +78,764 @@ pub enum CssToken {
     OpenCurly,
     /// https://www.w3.org/TR/css-syntax-3/#tokendef-close-curly
     CloseCurly,
-    /// https://www.w3.org/TR/css-syntax-3/#typedef-ident-token
-    Ident(String),
-    /// https://www.w3.org/TR/css-syntax-3/#typedef-string-token
-    StringToken(String),
-    /// https://www.w3.org/TR/css-syntax-3/#typedef-at-keyword-token
-    AtKeyword(String),
+}
+
+/// The position of a code point in the input. Both of the line and the column start from 1, and
+/// the column counts code points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#whitespace
+fn is_whitespace(c: char) -> bool {
+    c == '\n' || c == '\t' || c == ' '
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#ident-start-code-point
+fn is_ident_start_code_point(c: char) -> bool {
+    c.is_ascii_alphabetic() || c as u32 >= 0x80 || c == '_'
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#ident-code-point
+fn is_ident_code_point(c: char) -> bool {
+    is_ident_start_code_point(c) || c.is_ascii_digit() || c == '-'
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#non-printable-code-point
+fn is_non_printable_code_point(c: char) -> bool {
+    matches!(c, '\u{0}'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+fn preprocess(css: &str) -> Vec<char> {
+    let mut input = Vec::new();
+    let mut chars = css.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // "Replace any U+000D CARRIAGE RETURN (CR) code points, U+000C FORM FEED (FF) code
+            // points, or pairs of U+000D CARRIAGE RETURN (CR) followed by U+000A LINE FEED (LF)
+            // in input by a single U+000A LINE FEED (LF) code point."
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                input.push('\n');
+            }
+            '\u{c}' => input.push('\n'),
+            // "Replace any U+0000 NULL or surrogate code points in input with U+FFFD REPLACEMENT
+            // CHARACTER (�)."
+            '\u{0}' => input.push('\u{fffd}'),
+            _ => input.push(c),
+        }
+    }
+    input
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CssTokenizer {
     pos: usize,
     input: Vec<char>,
-    cache: Option<CssToken>,
+    /// The positions in `input` where lines start.
+    line_starts: Vec<usize>,
 }
 
 impl CssTokenizer {
     pub fn new(css: String) -> Self {
+        let input = preprocess(&css);
+        let mut line_starts = vec![0];
+        for (i, c) in input.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
         Self {
             pos: 0,
-            input: css.chars().collect(),
-            cache: None,
+            input,
+            line_starts,
         }
     }
 
-    /// https://www.w3.org/TR/css-syntax-3/#consume-name
-    fn consume_ident_token(&mut self) -> String {
-        let mut s = String::new();
-        s.push(self.input[self.pos]);
+    /// Returns the line and the column of the code point at `pos`.
+    fn source_position(&self, pos: usize) -> SourcePosition {
+        let line = match self.line_starts.binary_search(&pos) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        SourcePosition {
+            line: line + 1,
+            column: pos - self.line_starts[line] + 1,
+        }
+    }
 
-        loop {
+    /// Returns the code point `n` code points after the next input code point, or None for EOF.
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input.get(self.pos + n).copied()
+    }
+
+    /// Consumes the next input code point.
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+    fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+        // "1. If the first code point is not U+005C REVERSE SOLIDUS (\), return false."
+        // "2. Otherwise, if the second code point is a newline, return false."
+        // "3. Otherwise, return true."
+        first == Some('\\') && second != Some('\n')
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn would_start_ident_sequence(
+        first: Option<char>,
+        second: Option<char>,
+        third: Option<char>,
+    ) -> bool {
+        match first {
+            // "U+002D HYPHEN-MINUS: If the second code point is an ident-start code point or a
+            // U+002D HYPHEN-MINUS, or the second and third code points are a valid escape, return
+            // true. Otherwise, return false."
+            Some('-') => match second {
+                Some(c) if is_ident_start_code_point(c) || c == '-' => true,
+                _ => Self::is_valid_escape(second, third),
+            },
+            // "ident-start code point: Return true."
+            Some(c) if is_ident_start_code_point(c) => true,
+            // "U+005C REVERSE SOLIDUS (\): If the first and second code points are a valid
+            // escape, return true. Otherwise, return false."
+            Some('\\') => Self::is_valid_escape(first, second),
+            // "anything else: Return false."
+            _ => false,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+    fn would_start_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        match first {
+            // "U+002B PLUS SIGN (+), U+002D HYPHEN-MINUS (-): If the second code point is a
+            // digit, return true. Otherwise, if the second code point is a U+002E FULL STOP (.)
+            // and the third code point is a digit, return true. Otherwise, return false."
+            Some('+') | Some('-') => is_digit(second) || (second == Some('.') && is_digit(third)),
+            // "U+002E FULL STOP (.): If the second code point is a digit, return true. Otherwise,
+            // return false."
+            Some('.') => is_digit(second),
+            // "digit: Return true."
+            // "anything else: Return false."
+            c => is_digit(c),
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-comment
+    fn consume_comments(&mut self) {
+        // "If the next two input code point are U+002F SOLIDUS (/) followed by a U+002A ASTERISK
+        // (*), consume them and all following code points up to and including the first U+002A
+        // ASTERISK (*) followed by a U+002F SOLIDUS (/), or up to an EOF code point. Return to
+        // the start of this step."
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.pos += 2;
+            loop {
+                match self.consume() {
+                    Some('*') if self.peek(0) == Some('/') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    Some(_) => {}
+                    // "If the preceding paragraph ended by consuming an EOF code point, this is a
+                    // parse error."
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// Consumes as much whitespace as possible.
+    fn consume_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
             self.pos += 1;
-            let c = self.input[self.pos];
-            match c {
-                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => {
-                    s.push(c);
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    /// This assumes that the U+005C REVERSE SOLIDUS (\) has already been consumed.
+    fn consume_escaped_code_point(&mut self) -> char {
+        match self.consume() {
+            // "hex digit: Consume as many hex digits as possible, but no more than 5. Note that
+            // this means 1-6 hex digits have been consumed in total. If the next input code point
+            // is whitespace, consume it as well. Interpret the hex digits as a hexadecimal
+            // number. If this number is zero, or is for a surrogate, or is greater than the
+            // maximum allowed code point, return U+FFFD REPLACEMENT CHARACTER (�). Otherwise,
+            // return the code point with that value."
+            Some(c) if c.is_ascii_hexdigit() => {
+                let mut hex = String::from(c);
+                while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+                    hex.push(self.consume().expect("a hex digit should exist"));
+                }
+                if self.peek(0).is_some_and(is_whitespace) {
+                    self.pos += 1;
                 }
-                _ => break,
+
+                let number = u32::from_str_radix(&hex, 16).expect("hex should be hex digits");
+                if number == 0 || number > MAXIMUM_ALLOWED_CODE_POINT {
+                    return '\u{fffd}';
+                }
+                // `char::from_u32` fails for a surrogate.
+                char::from_u32(number).unwrap_or('\u{fffd}')
             }
+            // "EOF: This is a parse error. Return U+FFFD REPLACEMENT CHARACTER (�)."
+            None => '\u{fffd}',
+            // "anything else: Return the current input code point."
+            Some(c) => c,
         }
+    }
 
-        return s;
+    /// https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_ident_sequence(&mut self) -> String {
+        // "Let result initially be an empty string."
+        let mut result = String::new();
+
+        // "Repeatedly consume the next input code point from the stream:"
+        loop {
+            match self.peek(0) {
+                // "ident code point: Append the code point to result."
+                Some(c) if is_ident_code_point(c) => {
+                    self.pos += 1;
+                    result.push(c);
+                }
+                // "the stream starts with a valid escape: Consume an escaped code point. Append
+                // the returned code point to result."
+                c if Self::is_valid_escape(c, self.peek(1)) => {
+                    self.pos += 1;
+                    result.push(self.consume_escaped_code_point());
+                }
+                // "anything else: Reconsume the current input code point. Return result."
+                _ => return result,
+            }
+        }
     }
 
     /// https://www.w3.org/TR/css-syntax-3/#consume-a-string-token
-    fn consume_string_token(&mut self) -> String {
+    /// This assumes that the starting code point has already been consumed.
+    fn consume_string_token(&mut self, ending: char) -> CssToken {
+        // "Initially create a <string-token> with its value set to the empty string."
         let mut s = String::new();
 
+        // "Repeatedly consume the next input code point from the stream:"
         loop {
-            if self.pos >= self.input.len() {
-                return s;
+            match self.consume() {
+                // "ending code point: Return the <string-token>."
+                Some(c) if c == ending => return CssToken::StringToken(s),
+                // "EOF: This is a parse error. Return the <string-token>."
+                None => return CssToken::StringToken(s),
+                // "newline: This is a parse error. Reconsume the current input code point, create
+                // a <bad-string-token>, and return it."
+                Some('\n') => {
+                    self.pos -= 1;
+                    return CssToken::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    // "If the next input code point is EOF, do nothing."
+                    None => {}
+                    // "Otherwise, if the next input code point is a newline, consume it."
+                    Some('\n') => self.pos += 1,
+                    // "Otherwise, (the stream starts with a valid escape) consume an escaped code
+                    // point and append the returned code point to the <string-token>'s value."
+                    Some(_) => s.push(self.consume_escaped_code_point()),
+                },
+                // "anything else: Append the current input code point to the <string-token>'s
+                // value."
+                Some(c) => s.push(c),
             }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-number
+    fn consume_number(&mut self) -> f64 {
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
 
+        // "1. Initially set type to "integer". Let repr be the empty string."
+        let mut repr = String::new();
+
+        // "2. If the next input code point is U+002B PLUS SIGN (+) or U+002D HYPHEN-MINUS (-),
+        // consume it and append it to repr."
+        if let Some(c @ ('+' | '-')) = self.peek(0) {
+            self.pos += 1;
+            repr.push(c);
+        }
+
+        // "3. While the next input code point is a digit, consume it and append it to repr."
+        while is_digit(self.peek(0)) {
+            repr.push(self.consume().expect("a digit should exist"));
+        }
+
+        // "4. If the next 2 input code points are U+002E FULL STOP (.) followed by a digit,
+        // then: 1. Consume them. 2. Append them to repr. 3. Set type to "number". 4. While the
+        // next input code point is a digit, consume it and append it to repr."
+        if self.peek(0) == Some('.') && is_digit(self.peek(1)) {
+            repr.push('.');
             self.pos += 1;
-            let c = self.input[self.pos];
-            match c {
-                '"' => break,
-                _ => s.push(c),
+            while is_digit(self.peek(0)) {
+                repr.push(self.consume().expect("a digit should exist"));
+            }
+        }
+
+        // "5. If the next 2 or 3 input code points are U+0045 LATIN CAPITAL LETTER E (E) or
+        // U+0065 LATIN SMALL LETTER E (e), optionally followed by U+002D HYPHEN-MINUS (-) or
+        // U+002B PLUS SIGN (+), followed by a digit, then: 1. Consume them. 2. Append them to
+        // repr. 3. Set type to "number". 4. While the next input code point is a digit, consume
+        // it and append it to repr."
+        if let Some('e' | 'E') = self.peek(0) {
+            let has_sign = matches!(self.peek(1), Some('+' | '-'));
+            let digit_offset = if has_sign { 2 } else { 1 };
+            if is_digit(self.peek(digit_offset)) {
+                for _ in 0..digit_offset {
+                    repr.push(self.consume().expect("an exponent should exist"));
+                }
+                while is_digit(self.peek(0)) {
+                    repr.push(self.consume().expect("a digit should exist"));
+                }
             }
         }
 
-        return s;
+        // "6. Convert repr to a number, and set the value to the returned value."
+        // "7. Return value and type."
+        repr.parse::<f64>()
+            .expect("repr should be a valid representation of a number")
     }
 
-    /// https://www.w3.org/TR/css-syntax-3/#consume-number
     /// https://www.w3.org/TR/css-syntax-3/#consume-a-numeric-token
-    fn consume_numeric_token(&mut self) -> f64 {
-        let mut num = 0f64;
-        let mut floating = false;
-        let mut floating_count = 0;
+    fn consume_numeric_token(&mut self) -> CssToken {
+        // "Consume a number and let number be the result."
+        let number = self.consume_number();
 
-        loop {
-            if self.pos >= self.input.len() {
-                return num;
-            }
+        // "If the next 3 input code points would start an ident sequence, then: 1. Create a
+        // <dimension-token> with the same value and type flag as number, and a unit set
+        // initially to the empty string. 2. Consume an ident sequence. Set the
+        // <dimension-token>'s unit to the returned value. 3. Return the <dimension-token>."
+        if Self::would_start_ident_sequence(self.peek(0), self.peek(1), self.peek(2)) {
+            return CssToken::Dimension(number, self.consume_ident_sequence());
+        }
+
+        // "Otherwise, if the next input code point is U+0025 PERCENTAGE SIGN (%), consume it.
+        // Create a <percentage-token> with the same value as number, and return it."
+        if self.peek(0) == Some('%') {
+            self.pos += 1;
+            return CssToken::Percentage(number);
+        }
 
-            let c = self.input[self.pos];
+        // "Otherwise, create a <number-token> with the same value and type flag as number, and
+        // return it."
+        CssToken::Number(number)
+    }
 
-            match c {
-                '0'..='9' => {
-                    if floating {
-                        floating_count += 1;
-                        num = num
-                            + ((c.to_digit(10).unwrap() as f64)
-                                * ((1f64 / 10f64).powi(floating_count)));
-                    } else {
-                        num = num * 10.0 + (c.to_digit(10).unwrap() as f64);
-                    }
+    /// https://www.w3.org/TR/css-syntax-3/#consume-remnants-of-bad-url
+    fn consume_remnants_of_bad_url(&mut self) {
+        // "Repeatedly consume the next input code point from the stream:"
+        loop {
+            match self.peek(0) {
+                // "U+0029 RIGHT PARENTHESIS ()), EOF: Return."
+                Some(')') => {
                     self.pos += 1;
+                    return;
                 }
-                '.' => {
-                    floating = true;
+                None => return,
+                // "the input stream starts with a valid escape: Consume an escaped code point."
+                c if Self::is_valid_escape(c, self.peek(1)) => {
                     self.pos += 1;
+                    self.consume_escaped_code_point();
                 }
-                _ => break,
+                // "anything else: Do nothing."
+                _ => self.pos += 1,
             }
         }
-
-        return num;
     }
-}
 
-impl Iterator for CssTokenizer {
-    type Item = CssToken;
+    /// https://www.w3.org/TR/css-syntax-3/#consume-url-token
+    /// This assumes that the initial "url(" has already been consumed.
+    fn consume_url_token(&mut self) -> CssToken {
+        // "1. Initially create a <url-token> with its value set to the empty string."
+        let mut url = String::new();
 
-    /// https://www.w3.org/TR/css-syntax-3/#consume-token
-    fn next(&mut self) -> Option<Self::Item> {
+        // "2. Consume as much whitespace as possible."
+        self.consume_whitespace();
+
+        // "3. Repeatedly consume the next input code point from the stream:"
         loop {
-            if self.pos >= self.input.len() {
-                return None;
+            match self.consume() {
+                // "U+0029 RIGHT PARENTHESIS ()): Return the <url-token>."
+                Some(')') => return CssToken::Url(url),
+                // "EOF: This is a parse error. Return the <url-token>."
+                None => return CssToken::Url(url),
+                // "whitespace: Consume as much whitespace as possible. If the next input code
+                // point is U+0029 RIGHT PARENTHESIS ()) or EOF, consume it and return the
+                // <url-token> (if EOF was encountered, this is a parse error); otherwise, consume
+                // the remnants of a bad url, create a <bad-url-token>, and return it."
+                Some(c) if is_whitespace(c) => {
+                    self.consume_whitespace();
+                    match self.peek(0) {
+                        Some(')') => {
+                            self.pos += 1;
+                            return CssToken::Url(url);
+                        }
+                        None => return CssToken::Url(url),
+                        Some(_) => {
+                            self.consume_remnants_of_bad_url();
+                            return CssToken::BadUrl;
+                        }
+                    }
+                }
+                // "U+0022 QUOTATION MARK ("), U+0027 APOSTROPHE ('), U+0028 LEFT PARENTHESIS
+                // ((), non-printable code point: This is a parse error. Consume the remnants of a
+                // bad url, create a <bad-url-token>, and return it."
+                Some(c) if c == '"' || c == '\'' || c == '(' || is_non_printable_code_point(c) => {
+                    self.consume_remnants_of_bad_url();
+                    return CssToken::BadUrl;
+                }
+                // "U+005C REVERSE SOLIDUS (\): If the stream starts with a valid escape, consume
+                // an escaped code point and append the returned code point to the <url-token>'s
+                // value. Otherwise, this is a parse error. Consume the remnants of a bad url,
+                // create a <bad-url-token>, and return it."
+                Some('\\') => {
+                    if Self::is_valid_escape(Some('\\'), self.peek(0)) {
+                        url.push(self.consume_escaped_code_point());
+                    } else {
+                        self.consume_remnants_of_bad_url();
+                        return CssToken::BadUrl;
+                    }
+                }
+                // "anything else: Append the current input code point to the <url-token>'s
+                // value."
+                Some(c) => url.push(c),
             }
+        }
+    }
 
-            let c = self.input[self.pos];
+    /// https://www.w3.org/TR/css-syntax-3/#consume-ident-like-token
+    fn consume_ident_like_token(&mut self) -> CssToken {
+        // "Consume an ident sequence, and let string be the result."
+        let string = self.consume_ident_sequence();
 
-            let token = match c {
-                '"' | '\'' => {
-                    let value = self.consume_string_token();
-                    CssToken::StringToken(value)
-                }
-                '#' => {
-                    // TODO: support the case if the next token is not ident code point
+        // "If string's value is an ASCII case-insensitive match for "url", and the next input
+        // code point is U+0028 LEFT PARENTHESIS ((), consume it."
+        if string.eq_ignore_ascii_case("url") && self.peek(0) == Some('(') {
+            self.pos += 1;
+
+            // "While the next two input code points are whitespace, consume the next input code
+            // point."
+            while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace)
+            {
+                self.pos += 1;
+            }
+
+            // "If the next one or two input code points are U+0022 QUOTATION MARK ("), U+0027
+            // APOSTROPHE ('), or whitespace followed by U+0022 QUOTATION MARK (") or U+0027
+            // APOSTROPHE ('), then create a <function-token> with its value set to string and
+            // return it."
+            let is_quote = |c: Option<char>| c == Some('"') || c == Some('\'');
+            if is_quote(self.peek(0))
+                || (self.peek(0).is_some_and(is_whitespace) && is_quote(self.peek(1)))
+            {
+                return CssToken::Function(string);
+            }
+
+            // "Otherwise, consume a url token, and return it."
+            return self.consume_url_token();
+        }
+
+        // "Otherwise, if the next input code point is U+0028 LEFT PARENTHESIS ((), consume it.
+        // Create a <function-token> with its value set to string and return it."
+        if self.peek(0) == Some('(') {
+            self.pos += 1;
+            return CssToken::Function(string);
+        }
+
+        // "Otherwise, create an <ident-token> with its value set to string and return it."
+        CssToken::Ident(string)
+    }
+
+    /// Consumes a token and returns it with the position where it starts. Returns None at the end
+    /// of the input.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-token
+    pub fn next_token(&mut self) -> Option<(CssToken, SourcePosition)> {
+        // "Consume comments."
+        self.consume_comments();
+
+        let position = self.source_position(self.pos);
+
+        // "Consume the next input code point."
+        let c = self.consume()?;
+
+        let token = match c {
+            // "whitespace: Consume as much whitespace as possible. Return a <whitespace-token>."
+            c if is_whitespace(c) => {
+                self.consume_whitespace();
+                CssToken::Whitespace
+            }
+            // "U+0022 QUOTATION MARK ("): Consume a string token and return it."
+            // "U+0027 APOSTROPHE ('): Consume a string token and return it."
+            '"' | '\'' => self.consume_string_token(c),
+            '#' => {
+                // "If the next input code point is an ident code point or the next two input code
+                // points are a valid escape, then:"
+                if self.peek(0).is_some_and(is_ident_code_point)
+                    || Self::is_valid_escape(self.peek(0), self.peek(1))
+                {
+                    // "2. If the next 3 input code points would start an ident sequence, set the
+                    // <hash-token>'s type flag to "id"."
+                    let hash_type = if Self::would_start_ident_sequence(
+                        self.peek(0),
+                        self.peek(1),
+                        self.peek(2),
+                    ) {
+                        HashType::Id
+                    } else {
+                        HashType::Unrestricted
+                    };
+                    // "3. Consume an ident sequence, and set the <hash-token>'s value to the
+                    // returned string."
+                    CssToken::HashToken(self.consume_ident_sequence(), hash_type)
+                } else {
                     // "Otherwise, return a <delim-token> with its value set to the current input
                     // code point."
-                    // https://www.w3.org/TR/css-syntax-3/#consume-token
-                    let value = self.consume_ident_token();
-                    self.pos -= 1;
-                    CssToken::HashToken(value)
+                    CssToken::Delim('#')
                 }
-                '(' => CssToken::OpenParenthesis,
-                ')' => CssToken::CloseParenthesis,
-                ',' => CssToken::Delim(','),
-                // TODO: support minus number with hyphen.
-                // "If the input stream starts with a number, reconsume the current input code
-                // point, consume a numeric token, and return it."
-                // https://www.w3.org/TR/css-syntax-3/#consume-a-token
-                '-' => {
-                    let t = CssToken::Ident(self.consume_ident_token());
+            }
+            '(' => CssToken::OpenParenthesis,
+            ')' => CssToken::CloseParenthesis,
+            // "U+002B PLUS SIGN (+): If the input stream starts with a number, reconsume the
+            // current input code point, consume a numeric token, and return it. Otherwise, return
+            // a <delim-token> with its value set to the current input code point."
+            // "U+002E FULL STOP (.): ditto."
+            '+' | '.' => {
+                if Self::would_start_number(Some(c), self.peek(0), self.peek(1)) {
                     self.pos -= 1;
-                    t
+                    self.consume_numeric_token()
+                } else {
+                    CssToken::Delim(c)
                 }
-                // TODO: support floating number case.
+            }
+            ',' => CssToken::Comma,
+            '-' => {
                 // "If the input stream starts with a number, reconsume the current input code
                 // point, consume a numeric token, and return it."
-                // https://www.w3.org/TR/css-syntax-3/#consume-a-token
-                '.' => CssToken::Delim('.'),
-                ':' => CssToken::Colon,
-                ';' => CssToken::SemiColon,
-                '@' => {
-                    // If the next 3 input code points would start an ident sequence, consume an
-                    // ident sequence, create an <at-keyword-token> with its value set to the
-                    // returned value, and return it.
-                    if self.input[self.pos + 1].is_ascii_alphabetic()
-                        && self.input[self.pos + 2].is_alphanumeric()
-                    {
-                        // skip '@'
-                        self.pos += 1;
-                        let t = CssToken::AtKeyword(self.consume_ident_token());
-                        self.pos -= 1;
-                        t
-                    } else {
-                        CssToken::Delim('@')
-                    }
-                }
-                '{' => CssToken::OpenCurly,
-                '}' => CssToken::CloseCurly,
-                // digit
-                // Reconsume the current input code point, consume a numeric token, and return it.
-                '0'..='9' => {
-                    let t = CssToken::Number(self.consume_numeric_token());
+                if Self::would_start_number(Some(c), self.peek(0), self.peek(1)) {
                     self.pos -= 1;
-                    t
-                }
-                // ident-start code point
-                // Reconsume the current input code point, consume an ident-like token, and return
-                // it.
-                'a'..='z' | 'A'..='Z' | '_' => {
-                    let t = CssToken::Ident(self.consume_ident_token());
+                    self.consume_numeric_token()
+                // "Otherwise, if the next 2 input code points are U+002D HYPHEN-MINUS U+003E
+                // GREATER-THAN SIGN (->), consume them and return a <CDC-token>."
+                } else if self.peek(0) == Some('-') && self.peek(1) == Some('>') {
+                    self.pos += 2;
+                    CssToken::Cdc
+                // "Otherwise, if the input stream starts with an ident sequence, reconsume the
+                // current input code point, consume an ident-like token, and return it."
+                } else if Self::would_start_ident_sequence(Some(c), self.peek(0), self.peek(1)) {
                     self.pos -= 1;
-                    t
+                    self.consume_ident_like_token()
+                } else {
+                    CssToken::Delim(c)
                 }
-                // TODO: handle white spaces property
-                // "Consume as much whitespace as possible. Return a <whitespace-token>."
-                // https://www.w3.org/TR/css-syntax-3/#consume-token
-                ' ' | '\n' => {
-                    self.pos += 1;
-                    continue;
+            }
+            ':' => CssToken::Colon,
+            ';' => CssToken::SemiColon,
+            // "U+003C LESS-THAN SIGN (<): If the next 3 input code points are U+0021 EXCLAMATION
+            // MARK U+002D HYPHEN-MINUS U+002D HYPHEN-MINUS (!--), consume them and return a
+            // <CDO-token>."
+            '<' => {
+                if self.peek(0) == Some('!')
+                    && self.peek(1) == Some('-')
+                    && self.peek(2) == Some('-')
+                {
+                    self.pos += 3;
+                    CssToken::Cdo
+                } else {
+                    CssToken::Delim(c)
                 }
-                _ => {
-                    unimplemented!("char {} is not supported yet", c);
+            }
+            // "U+0040 COMMERCIAL AT (@): If the next 3 input code points would start an ident
+            // sequence, consume an ident sequence, create an <at-keyword-token> with its value
+            // set to the returned value, and return it."
+            '@' => {
+                if Self::would_start_ident_sequence(self.peek(0), self.peek(1), self.peek(2)) {
+                    CssToken::AtKeyword(self.consume_ident_sequence())
+                } else {
+                    CssToken::Delim(c)
                 }
-            };
+            }
+            '[' => CssToken::OpenSquare,
+            ']' => CssToken::CloseSquare,
+            // "U+005C REVERSE SOLIDUS (\): If the input stream starts with a valid escape,
+            // reconsume the current input code point, consume an ident-like token, and return it.
+            // Otherwise, this is a parse error. Return a <delim-token> with its value set to the
+            // current input code point."
+            '\\' => {
+                if Self::is_valid_escape(Some(c), self.peek(0)) {
+                    self.pos -= 1;
+                    self.consume_ident_like_token()
+                } else {
+                    CssToken::Delim(c)
+                }
+            }
+            '{' => CssToken::OpenCurly,
+            '}' => CssToken::CloseCurly,
+            // "digit: Reconsume the current input code point, consume a numeric token, and return
+            // it."
+            '0'..='9' => {
+                self.pos -= 1;
+                self.consume_numeric_token()
+            }
+            // "ident-start code point: Reconsume the current input code point, consume an
+            // ident-like token, and return it."
+            c if is_ident_start_code_point(c) => {
+                self.pos -= 1;
+                self.consume_ident_like_token()
+            }
+            // "anything else: Return a <delim-token> with its value set to the current input code
+            // point."
+            _ => CssToken::Delim(c),
+        };
 
-            self.pos += 1;
-            return Some(token);
+        Some((token, position))
+    }
+}
+
+impl Iterator for CssTokenizer {
+    type Item = CssToken;
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-token
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().map(|(token, _)| token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(css: &str) -> Vec<CssToken> {
+        CssTokenizer::new(css.to_string()).collect()
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokenize("/* a */p/**/{/* unterminated"),
+            vec![CssToken::Ident("p".to_string()), CssToken::OpenCurly]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            tokenize("\\66 oo \\@x #\\31 23 \\\n\\"),
+            vec![
+                CssToken::Ident("foo".to_string()),
+                CssToken::Whitespace,
+                CssToken::Ident("@x".to_string()),
+                CssToken::Whitespace,
+                CssToken::HashToken("123".to_string(), HashType::Id),
+                CssToken::Whitespace,
+                // A newline can't be escaped.
+                CssToken::Delim('\\'),
+                CssToken::Whitespace,
+                // An escape at EOF is U+FFFD.
+                CssToken::Ident("\u{fffd}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            tokenize("url( a.png ) url(\"b.png\") url(c d) url(e\"f)"),
+            vec![
+                CssToken::Url("a.png".to_string()),
+                CssToken::Whitespace,
+                CssToken::Function("url".to_string()),
+                CssToken::StringToken("b.png".to_string()),
+                CssToken::CloseParenthesis,
+                CssToken::Whitespace,
+                CssToken::BadUrl,
+                CssToken::Whitespace,
+                CssToken::BadUrl,
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            tokenize("'a\\'b' \"c\nd"),
+            vec![
+                CssToken::StringToken("a'b".to_string()),
+                CssToken::Whitespace,
+                CssToken::BadString,
+                CssToken::Whitespace,
+                CssToken::Ident("d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cdo_and_cdc() {
+        assert_eq!(
+            tokenize("<!-- --> <!- -->"),
+            vec![
+                CssToken::Cdo,
+                CssToken::Whitespace,
+                CssToken::Cdc,
+                CssToken::Whitespace,
+                CssToken::Delim('<'),
+                CssToken::Delim('!'),
+                CssToken::Delim('-'),
+                CssToken::Whitespace,
+                CssToken::Cdc,
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            tokenize("10px 1.5em 50% -2 .5 -.5e1 +3 1e2px"),
+            vec![
+                CssToken::Dimension(10.0, "px".to_string()),
+                CssToken::Whitespace,
+                CssToken::Dimension(1.5, "em".to_string()),
+                CssToken::Whitespace,
+                CssToken::Percentage(50.0),
+                CssToken::Whitespace,
+                CssToken::Number(-2.0),
+                CssToken::Whitespace,
+                CssToken::Number(0.5),
+                CssToken::Whitespace,
+                CssToken::Number(-5.0),
+                CssToken::Whitespace,
+                CssToken::Number(3.0),
+                CssToken::Whitespace,
+                CssToken::Dimension(100.0, "px".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let mut t = CssTokenizer::new("p {\n  color: red;\r\n}".to_string());
+        let mut positions = Vec::new();
+        while let Some((token, position)) = t.next_token() {
+            if token != CssToken::Whitespace {
+                positions.push((position.line, position.column));
+            }
         }
+        assert_eq!(
+            positions,
+            vec![(1, 1), (1, 3), (2, 3), (2, 8), (2, 10), (2, 13), (3, 1)]
+        );
     }
 }
@@ -0,0 +1,28 @@
+//! The user agent style sheet, which has the default styles of HTML elements and is the lowest
+//! origin in the cascade.
+//! https://www.w3.org/TR/css-cascade-4/#cascade-origin-ua
+//! https://html.spec.whatwg.org/multipage/rendering.html
+
+use crate::renderer::css::cssom::{CssParser, Origin, StyleSheet};
+use crate::renderer::css::token::CssTokenizer;
+use std::rc::Rc;
+use std::string::String;
+
+/// The source of the user agent style sheet.
+const HTML_CSS: &str = include_str!("html.css");
+
+thread_local! {
+    static UA_STYLE_SHEET: Rc<StyleSheet> = Rc::new(parse_ua_style_sheet());
+}
+
+fn parse_ua_style_sheet() -> StyleSheet {
+    let mut style_sheet =
+        CssParser::new(CssTokenizer::new(String::from(HTML_CSS))).parse_stylesheet();
+    style_sheet.set_origin(Origin::UserAgent);
+    style_sheet
+}
+
+/// Returns the user agent style sheet. It's parsed only once, when it's used for the first time.
+pub fn ua_style_sheet() -> Rc<StyleSheet> {
+    UA_STYLE_SHEET.with(|style_sheet| style_sheet.clone())
+}
@@ -0,0 +1,136 @@
+//! This is a part of "CSS Values and Units Module Level 4".
+//! https://www.w3.org/TR/css-values-4/
+
+use crate::renderer::css::token::CssToken;
+
+/// The size of the viewport in px, and the other features of the output device that media
+/// queries test.
+/// https://www.w3.org/TR/css-values-4/#viewport-relative-lengths
+/// https://www.w3.org/TR/mediaqueries-5/#mq-features
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub width: f64,
+    pub height: f64,
+    /// The number of device pixels per px.
+    /// https://www.w3.org/TR/mediaqueries-5/#resolution
+    pub resolution: f64,
+    /// https://www.w3.org/TR/mediaqueries-5/#prefers-color-scheme
+    pub color_scheme: ColorScheme,
+}
+
+impl Viewport {
+    /// Creates a viewport of a device with 1dppx that prefers the light color scheme.
+    pub fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+/// The color theme that the user prefers.
+/// https://www.w3.org/TR/mediaqueries-5/#prefers-color-scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// The values that relative lengths are resolved against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// The computed font size of the element in px.
+    pub font_size: f64,
+    /// The computed font size of the root element in px.
+    pub root_font_size: f64,
+    pub viewport: Viewport,
+}
+
+/// A <length> or a <percentage>.
+/// https://www.w3.org/TR/css-values-4/#lengths
+/// https://www.w3.org/TR/css-values-4/#percentages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    /// https://www.w3.org/TR/css-values-4/#px
+    Px(f64),
+    /// https://www.w3.org/TR/css-values-4/#pt
+    Pt(f64),
+    /// https://www.w3.org/TR/css-values-4/#em
+    Em(f64),
+    /// https://www.w3.org/TR/css-values-4/#rem
+    Rem(f64),
+    /// https://www.w3.org/TR/css-values-4/#ch
+    Ch(f64),
+    /// https://www.w3.org/TR/css-values-4/#vw
+    Vw(f64),
+    /// https://www.w3.org/TR/css-values-4/#vh
+    Vh(f64),
+    /// https://www.w3.org/TR/css-values-4/#percentage-value
+    Percentage(f64),
+}
+
+impl Length {
+    /// Returns the length that `token` represents. Returns None if `token` is not a length or a
+    /// percentage, or if the unit is not supported.
+    pub fn from_token(token: &CssToken) -> Option<Self> {
+        match token {
+            // "Property values are ASCII case-insensitive" and so are the units.
+            CssToken::Dimension(value, unit) => match unit.to_ascii_lowercase().as_str() {
+                "px" => Some(Length::Px(*value)),
+                "pt" => Some(Length::Pt(*value)),
+                "em" => Some(Length::Em(*value)),
+                "rem" => Some(Length::Rem(*value)),
+                "ch" => Some(Length::Ch(*value)),
+                "vw" => Some(Length::Vw(*value)),
+                "vh" => Some(Length::Vh(*value)),
+                _ => None,
+            },
+            CssToken::Percentage(value) => Some(Length::Percentage(*value)),
+            // "for zero lengths the unit identifier is optional (i.e. can be syntactically
+            // represented as the <number> 0)."
+            // https://www.w3.org/TR/css-values-4/#lengths
+            CssToken::Number(value) if *value == 0.0 => Some(Length::Px(0.0)),
+            _ => None,
+        }
+    }
+
+    /// Returns the computed value. A length in an absolute unit or a font-relative unit is
+    /// converted to px, while a viewport-relative length and a percentage are kept as they are
+    /// until layout.
+    /// https://www.w3.org/TR/css-values-4/#absolute-lengths
+    /// https://www.w3.org/TR/css-values-4/#font-relative-lengths
+    pub fn compute(&self, context: &LengthContext) -> Self {
+        match self {
+            Length::Pt(_) | Length::Em(_) | Length::Rem(_) | Length::Ch(_) => {
+                Length::Px(self.to_px(context, 0.0))
+            }
+            _ => *self,
+        }
+    }
+
+    /// Returns the length in px. A percentage is resolved against `percentage_basis`, e.g. the
+    /// width of the containing block.
+    pub fn to_px(&self, context: &LengthContext, percentage_basis: f64) -> f64 {
+        match self {
+            Length::Px(value) => *value,
+            // "1pt = 1/72th of 1in" and "1in = 96px".
+            Length::Pt(value) => value * 96.0 / 72.0,
+            // "Equal to the computed value of the font-size property of the element on which it
+            // is used."
+            Length::Em(value) => value * context.font_size,
+            // "Equal to the computed value of font-size on the root element."
+            Length::Rem(value) => value * context.root_font_size,
+            // "In the cases where it is impossible or impractical to determine the measure of the
+            // “0” glyph, it must be assumed to be 0.5em wide by 0.5em tall."
+            // Note: we don't measure the glyph.
+            Length::Ch(value) => value * context.font_size * 0.5,
+            // "Equal to 1% of the width of current viewport."
+            Length::Vw(value) => value * context.viewport.width / 100.0,
+            // "Equal to 1% of the height of current viewport."
+            Length::Vh(value) => value * context.viewport.height / 100.0,
+            Length::Percentage(value) => value * percentage_basis / 100.0,
+        }
+    }
+}
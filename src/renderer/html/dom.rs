@@ -1,65 +1,990 @@
 //! This is a part of "13.2.6 Tree construction" in the HTML spec.
 //! https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
 
+use crate::renderer::css::cssom::{CompoundSelector, Selector};
+use crate::renderer::css::selector::{matches, matches_compound, parse_selector};
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::event::{dispatch, Event, EventListener, EventListenerCallback};
+use crate::renderer::html::mutation_observer::{
+    add_transient_observers, is_observed, queue_mutation_record, queue_tree_mutation_record,
+    MutationRecord, MutationRecordType, RegisteredObserver,
+};
 use crate::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
 use std::cell::RefCell;
-use std::rc::{Rc, Weak};
+use std::rc::Rc;
 use std::string::String;
 use std::string::ToString;
 use std::vec::Vec;
 
+/// The index of a node in the arena of its document. A NodeId is valid only for the document
+/// that created the node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
 #[derive(Debug, Clone)]
 /// https://dom.spec.whatwg.org/#interface-node
 pub struct Node {
     kind: NodeKind,
-    parent: Option<Weak<RefCell<Node>>>,
-    first_child: Option<Rc<RefCell<Node>>>,
-    last_child: Option<Weak<RefCell<Node>>>,
-    previous_sibling: Option<Weak<RefCell<Node>>>,
-    next_sibling: Option<Rc<RefCell<Node>>>,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    /// https://dom.spec.whatwg.org/#eventtarget-event-listener-list
+    event_listeners: Vec<EventListener>,
+    /// https://dom.spec.whatwg.org/#registered-observer-list
+    registered_observers: Vec<RegisteredObserver>,
 }
 
 ///dom.spec.whatwg.org/#interface-node
 impl Node {
-    pub fn new(kind: NodeKind) -> Self {
-        Self {
+    fn new(kind: NodeKind) -> Self {
+        let mut node = Self {
             kind,
             parent: None,
             first_child: None,
             last_child: None,
             previous_sibling: None,
             next_sibling: None,
+            event_listeners: Vec::new(),
+            registered_observers: Vec::new(),
+        };
+        node.activate_event_handlers();
+        node
+    }
+
+    pub fn kind(&self) -> &NodeKind {
+        &self.kind
+    }
+
+    /// Returns the element if this node is an element.
+    pub fn element(&self) -> Option<&Element> {
+        match &self.kind {
+            NodeKind::Element(e) => Some(e),
+            _ => None,
         }
     }
 
-    pub fn kind(&self) -> NodeKind {
-        self.kind.clone()
+    pub fn element_mut(&mut self) -> Option<&mut Element> {
+        match &mut self.kind {
+            NodeKind::Element(e) => Some(e),
+            _ => None,
+        }
     }
 
-    pub fn update_first_child(&mut self, first_child: Option<Rc<RefCell<Node>>>) {
-        self.first_child = first_child;
+    pub fn first_child(&self) -> Option<NodeId> {
+        self.first_child
     }
 
-    pub fn first_child(&self) -> Option<Rc<RefCell<Node>>> {
-        self.first_child.as_ref().map(|n| n.clone())
+    pub fn last_child(&self) -> Option<NodeId> {
+        self.last_child
     }
 
-    #[allow(dead_code)]
-    pub fn last_child(&self) -> Option<Weak<RefCell<Node>>> {
-        self.last_child.as_ref().map(|n| n.clone())
+    pub fn previous_sibling(&self) -> Option<NodeId> {
+        self.previous_sibling
     }
 
-    #[allow(dead_code)]
-    pub fn previous_sibling(&self) -> Option<Weak<RefCell<Node>>> {
-        self.previous_sibling.as_ref().map(|n| n.clone())
+    pub fn next_sibling(&self) -> Option<NodeId> {
+        self.next_sibling
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// Returns the data of a Text node.
+    /// https://dom.spec.whatwg.org/#concept-cd-data
+    pub fn data(&self) -> Option<String> {
+        match &self.kind {
+            NodeKind::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn registered_observers(&self) -> Vec<RegisteredObserver> {
+        self.registered_observers.clone()
+    }
+
+    pub fn registered_observers_mut(&mut self) -> &mut Vec<RegisteredObserver> {
+        &mut self.registered_observers
+    }
+
+    pub fn event_listeners(&self) -> Vec<EventListener> {
+        self.event_listeners.clone()
     }
 
-    pub fn next_sibling(&self) -> Option<Rc<RefCell<Node>>> {
-        self.next_sibling.as_ref().map(|n| n.clone())
+    /// https://dom.spec.whatwg.org/#add-an-event-listener
+    fn append_event_listener(&mut self, listener: EventListener) {
+        // "4. If eventTarget's event listener list does not contain an event listener whose type
+        // is listener's type, callback is listener's callback, and capture is listener's capture,
+        // then append listener to eventTarget's event listener list."
+        if !self.event_listeners.iter().any(|l| l.is_same(&listener)) {
+            self.event_listeners.push(listener);
+        }
+    }
+
+    /// Adds an event listener for each event handler content attribute of this element, such as
+    /// `onclick`. This has to be called when an attribute is set. The listener of an event
+    /// handler is added only once, and it reads the current attribute value when the event fires.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#event-handler-attributes:concept-element-attributes-change-ext
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#activate-an-event-handler
+    pub fn activate_event_handlers(&mut self) {
+        let names: Vec<String> = match self.element() {
+            Some(e) => e
+                .attributes()
+                .into_iter()
+                .map(|attr| attr.name)
+                .filter(|name| EVENT_HANDLER_NAMES.contains(&name.as_str()))
+                .collect(),
+            None => return,
+        };
+
+        for name in names {
+            // "5. Let listener be a new event listener whose type is the event handler event type
+            // corresponding to eventHandler and callback is callback."
+            // The event type is the name of the event handler without "on".
+            let listener = EventListener::new(
+                &name[2..],
+                EventListenerCallback::EventHandler(name.clone()),
+                false,
+                false,
+            );
+            // "6. Add an event listener with eventTarget and listener."
+            self.append_event_listener(listener);
+        }
     }
 }
 
+/// https://dom.spec.whatwg.org/#interface-document
+/// A document owns all the nodes created for it in an arena, and the nodes refer to their
+/// parent, children and siblings by NodeIds. A node removed from the tree stays in the arena, so
+/// that a script can insert it again.
+#[derive(Debug, Clone)]
+pub struct Document {
+    nodes: Vec<Node>,
+    /// https://dom.spec.whatwg.org/#concept-document-url
+    url: String,
+    /// The element that the user designates with a pointing device.
+    /// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-hover
+    hovered_element: Option<NodeId>,
+    /// The element that the user is activating, e.g. while a mouse button is pressed on it.
+    /// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-active
+    active_element: Option<NodeId>,
+    /// https://html.spec.whatwg.org/multipage/interaction.html#focused-area-of-the-document
+    focused_element: Option<NodeId>,
+    /// The html element that the HTML fragment parsing algorithm uses as its root. A node can't
+    /// be freed from the arena, so the element is created once and reused by every fragment.
+    fragment_root: Option<NodeId>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    /// Creates a document whose Document node is the root of the tree.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::new(NodeKind::Document)],
+            url: String::new(),
+            hovered_element: None,
+            active_element: None,
+            focused_element: None,
+            fragment_root: None,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    pub fn set_url(&mut self, url: String) {
+        self.url = url;
+    }
+
+    pub fn hovered_element(&self) -> Option<NodeId> {
+        self.hovered_element
+    }
+
+    pub fn set_hovered_element(&mut self, element: Option<NodeId>) {
+        self.hovered_element = element;
+    }
+
+    pub fn active_element(&self) -> Option<NodeId> {
+        self.active_element
+    }
+
+    pub fn set_active_element(&mut self, element: Option<NodeId>) {
+        self.active_element = element;
+    }
+
+    pub fn focused_element(&self) -> Option<NodeId> {
+        self.focused_element
+    }
+
+    pub fn set_focused_element(&mut self, element: Option<NodeId>) {
+        self.focused_element = element;
+    }
+
+    /// Returns the Document node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Returns the html element that the HTML fragment parsing algorithm appends the parsed
+    /// nodes to. It's created when a fragment is parsed for the first time.
+    fn fragment_root(&mut self) -> NodeId {
+        match self.fragment_root {
+            Some(root) => root,
+            None => {
+                let root = self.create_node(NodeKind::Element(Element::new("html", Vec::new())));
+                self.fragment_root = Some(root);
+                root
+            }
+        }
+    }
+
+    /// Creates a node that doesn't have a parent yet.
+    pub fn create_node(&mut self, kind: NodeKind) -> NodeId {
+        self.nodes.push(Node::new(kind));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    /// Returns the element kind if `node` is an element.
+    pub fn element_kind(&self, node: NodeId) -> Option<ElementKind> {
+        self.node(node).element().map(|e| e.kind())
+    }
+
+    /// Returns true if `node` is an inclusive ancestor of `of`.
+    /// https://dom.spec.whatwg.org/#concept-tree-inclusive-ancestor
+    fn is_inclusive_ancestor(&self, node: NodeId, of: NodeId) -> bool {
+        let mut current = Some(of);
+        while let Some(c) = current {
+            if c == node {
+                return true;
+            }
+            current = self.node(c).parent();
+        }
+        false
+    }
+
+    /// Returns true if `parent` is the parent of `child`.
+    fn is_parent_of(&self, parent: NodeId, child: NodeId) -> bool {
+        self.node(child).parent() == Some(parent)
+    }
+
+    /// Returns true if `parent` has an element child other than `except`.
+    fn has_element_child(&self, parent: NodeId, except: Option<NodeId>) -> bool {
+        let mut child = self.node(parent).first_child();
+        while let Some(c) = child {
+            if self.node(c).element().is_some() && Some(c) != except {
+                return true;
+            }
+            child = self.node(c).next_sibling();
+        }
+        false
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+    fn ensure_pre_insertion_validity(
+        &self,
+        node: NodeId,
+        parent: NodeId,
+        child: Option<NodeId>,
+    ) -> Result<(), DomException> {
+        // "1. If parent is not a Document, DocumentFragment, or Element node, then throw a
+        // "HierarchyRequestError" DOMException."
+        if let NodeKind::Text(_) = self.node(parent).kind {
+            return Err(DomException::HierarchyRequestError);
+        }
+
+        // "2. If node is a host-including inclusive ancestor of parent, then throw a
+        // "HierarchyRequestError" DOMException."
+        if self.is_inclusive_ancestor(node, parent) {
+            return Err(DomException::HierarchyRequestError);
+        }
+
+        // "3. If child is non-null and its parent is not parent, then throw a "NotFoundError"
+        // DOMException."
+        if let Some(c) = child {
+            if !self.is_parent_of(parent, c) {
+                return Err(DomException::NotFoundError);
+            }
+        }
+
+        self.ensure_node_kind_validity(node, parent, child)
+    }
+
+    /// The steps 4 to 6 shared by "ensure pre-insertion validity" and "replace". `child` is the
+    /// node to be replaced in the "replace" case, or the reference child in the "pre-insert" case.
+    /// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+    /// https://dom.spec.whatwg.org/#concept-node-replace
+    fn ensure_node_kind_validity(
+        &self,
+        node: NodeId,
+        parent: NodeId,
+        replaced: Option<NodeId>,
+    ) -> Result<(), DomException> {
+        let parent_is_document = self.node(parent).kind == NodeKind::Document;
+
+        match self.node(node).kind {
+            // "4. If node is not a DocumentFragment, DocumentType, Element, or CharacterData node,
+            // then throw a "HierarchyRequestError" DOMException."
+            NodeKind::Document => Err(DomException::HierarchyRequestError),
+            // "5. If either node is a Text node and parent is a document, ... then throw a
+            // "HierarchyRequestError" DOMException."
+            NodeKind::Text(_) if parent_is_document => Err(DomException::HierarchyRequestError),
+            // "6. If parent is a document, and ... node is an Element: parent has an element
+            // child (that is not child), ... throw a "HierarchyRequestError" DOMException."
+            NodeKind::Element(_) if parent_is_document => {
+                if self.has_element_child(parent, replaced) {
+                    Err(DomException::HierarchyRequestError)
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Inserts `node` into `parent` before `child`, or at the end if `child` is None.
+    /// https://dom.spec.whatwg.org/#concept-node-insert
+    fn insert(
+        &mut self,
+        node: NodeId,
+        parent: NodeId,
+        child: Option<NodeId>,
+        suppress_observers: bool,
+    ) {
+        // "Adopt node into parent's node document." removes node from its old parent.
+        self.remove(node, false);
+
+        let previous = match child {
+            Some(c) => self.node(c).previous_sibling(),
+            None => self.node(parent).last_child(),
+        };
+
+        match previous {
+            Some(p) => self.node_mut(p).next_sibling = Some(node),
+            None => self.node_mut(parent).first_child = Some(node),
+        }
+        match child {
+            Some(c) => self.node_mut(c).previous_sibling = Some(node),
+            None => self.node_mut(parent).last_child = Some(node),
+        }
+
+        {
+            let n = self.node_mut(node);
+            n.parent = Some(parent);
+            n.previous_sibling = previous;
+            n.next_sibling = child;
+        }
+
+        // "10. If suppress observers flag is unset, then queue a tree mutation record for parent
+        // with nodes, « », previousSibling, and child."
+        if !suppress_observers {
+            queue_tree_mutation_record(self, parent, vec![node], Vec::new(), previous, child);
+        }
+    }
+
+    /// Removes `node` from its parent and reconnects the siblings around it.
+    /// https://dom.spec.whatwg.org/#concept-node-remove
+    fn remove(&mut self, node: NodeId, suppress_observers: bool) {
+        let parent = match self.node(node).parent() {
+            Some(p) => p,
+            None => return,
+        };
+        let previous = self.node(node).previous_sibling();
+        let next = self.node(node).next_sibling();
+
+        match previous {
+            Some(p) => self.node_mut(p).next_sibling = next,
+            None => self.node_mut(parent).first_child = next,
+        }
+        match next {
+            Some(n) => self.node_mut(n).previous_sibling = previous,
+            None => self.node_mut(parent).last_child = previous,
+        }
+
+        {
+            let n = self.node_mut(node);
+            n.parent = None;
+            n.previous_sibling = None;
+            n.next_sibling = None;
+        }
+
+        add_transient_observers(self, node, parent);
+
+        // "16. If suppress observers flag is unset, then queue a tree mutation record for parent
+        // with « », « node », oldPreviousSibling, and oldNextSibling."
+        if !suppress_observers {
+            queue_tree_mutation_record(self, parent, Vec::new(), vec![node], previous, next);
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-node-pre-insert
+    fn pre_insert(
+        &mut self,
+        node: NodeId,
+        parent: NodeId,
+        child: Option<NodeId>,
+    ) -> Result<NodeId, DomException> {
+        // "1. Ensure pre-insertion validity of node into parent before child."
+        self.ensure_pre_insertion_validity(node, parent, child)?;
+
+        // "2. Let referenceChild be child."
+        // "3. If referenceChild is node, then set referenceChild to node's next sibling."
+        let reference_child = match child {
+            Some(c) if c == node => self.node(node).next_sibling(),
+            _ => child,
+        };
+
+        // "4. Insert node into parent before referenceChild."
+        self.insert(node, parent, reference_child, false);
+
+        // "5. Return node."
+        Ok(node)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-appendchild
+    pub fn append_child(&mut self, parent: NodeId, node: NodeId) -> Result<NodeId, DomException> {
+        // "The appendChild(node) method steps are to return the result of appending node to this."
+        self.pre_insert(node, parent, None)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-insertbefore
+    pub fn insert_before(
+        &mut self,
+        parent: NodeId,
+        node: NodeId,
+        child: Option<NodeId>,
+    ) -> Result<NodeId, DomException> {
+        // "The insertBefore(node, child) method steps are to return the result of pre-inserting
+        // node into this before child."
+        self.pre_insert(node, parent, child)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-removechild
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) -> Result<NodeId, DomException> {
+        // https://dom.spec.whatwg.org/#concept-node-pre-remove
+        // "1. If child's parent is not parent, then throw a "NotFoundError" DOMException."
+        if !self.is_parent_of(parent, child) {
+            return Err(DomException::NotFoundError);
+        }
+
+        // "2. Remove child."
+        self.remove(child, false);
+
+        // "3. Return child."
+        Ok(child)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-replacechild
+    /// https://dom.spec.whatwg.org/#concept-node-replace
+    pub fn replace_child(
+        &mut self,
+        parent: NodeId,
+        node: NodeId,
+        child: NodeId,
+    ) -> Result<NodeId, DomException> {
+        // "1. If parent is not a Document, DocumentFragment, or Element node, then throw a
+        // "HierarchyRequestError" DOMException."
+        if let NodeKind::Text(_) = self.node(parent).kind {
+            return Err(DomException::HierarchyRequestError);
+        }
+
+        // "2. If node is a host-including inclusive ancestor of parent, then throw a
+        // "HierarchyRequestError" DOMException."
+        if self.is_inclusive_ancestor(node, parent) {
+            return Err(DomException::HierarchyRequestError);
+        }
+
+        // "3. If child's parent is not parent, then throw a "NotFoundError" DOMException."
+        if !self.is_parent_of(parent, child) {
+            return Err(DomException::NotFoundError);
+        }
+
+        // "4. - 6."
+        self.ensure_node_kind_validity(node, parent, Some(child))?;
+
+        // "7. Let referenceChild be child's next sibling."
+        // "8. If referenceChild is node, then set referenceChild to node's next sibling."
+        let mut reference_child = self.node(child).next_sibling();
+        if reference_child == Some(node) {
+            reference_child = self.node(node).next_sibling();
+        }
+
+        // "9. Let previousSibling be child's previous sibling."
+        let mut previous_sibling = self.node(child).previous_sibling();
+        if previous_sibling == Some(node) {
+            previous_sibling = self.node(node).previous_sibling();
+        }
+
+        // "11. If child's parent is non-null, then: ... 3. Remove child with the suppress
+        // observers flag set."
+        self.remove(child, true);
+
+        // "13. Insert node into parent before referenceChild with the suppress observers flag
+        // set."
+        self.insert(node, parent, reference_child, true);
+
+        // "14. Queue a tree mutation record for parent with nodes, removedNodes, previousSibling,
+        // and referenceChild."
+        queue_tree_mutation_record(
+            self,
+            parent,
+            vec![node],
+            vec![child],
+            previous_sibling,
+            reference_child,
+        );
+
+        // "15. Return child."
+        Ok(child)
+    }
+
+    /// Replaces all children of `parent` with `nodes`.
+    /// https://dom.spec.whatwg.org/#concept-node-replace-all
+    pub fn replace_all(&mut self, parent: NodeId, nodes: Vec<NodeId>) {
+        // "Remove all parent's children, in tree order, with the suppress observers flag set."
+        let mut removed_nodes = Vec::new();
+        let mut child = self.node(parent).first_child();
+        while let Some(c) = child {
+            child = self.node(c).next_sibling();
+            self.remove(c, true);
+            removed_nodes.push(c);
+        }
+
+        // "If node is non-null, then insert node into parent before null with the suppress
+        // observers flag set."
+        for node in &nodes {
+            self.insert(*node, parent, None, true);
+        }
+
+        // "If either addedNodes or removedNodes is not empty, then queue a tree mutation record
+        // for parent with addedNodes, removedNodes, null, and null."
+        if !nodes.is_empty() || !removed_nodes.is_empty() {
+            queue_tree_mutation_record(self, parent, nodes, removed_nodes, None, None);
+        }
+    }
+
+    /// Appends the descendants of `node` to `result` in tree order.
+    fn collect_descendants(&self, node: NodeId, result: &mut Vec<NodeId>) {
+        let mut child = self.node(node).first_child();
+        while let Some(c) = child {
+            result.push(c);
+            self.collect_descendants(c, result);
+            child = self.node(c).next_sibling();
+        }
+    }
+
+    /// Returns the first descendant element of `node` that matches `selectors`.
+    /// https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+    pub fn query_selector(
+        &self,
+        node: NodeId,
+        selectors: &str,
+    ) -> Result<Option<NodeId>, DomException> {
+        // "The querySelector(selectors) method steps are to return the first result of running
+        // scope-match a selectors string selectors against this, if the result is not an empty
+        // list; otherwise null."
+        Ok(self.query_selector_all(node, selectors)?.into_iter().next())
+    }
+
+    /// Returns all descendant elements of `node` that match `selectors`, in tree order. The
+    /// result is a static list, so it doesn't reflect later changes of the tree.
+    /// https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+    /// https://dom.spec.whatwg.org/#scope-match-a-selectors-string
+    pub fn query_selector_all(
+        &self,
+        node: NodeId,
+        selectors: &str,
+    ) -> Result<Vec<NodeId>, DomException> {
+        // "1. Let s be the result of parse a selector selectors."
+        // "2. If s is failure, then throw a "SyntaxError" DOMException."
+        let selector = match parse_selector(selectors) {
+            Some(s) => s,
+            None => return Err(DomException::SyntaxError),
+        };
+
+        // "3. Return the result of match a selector against a tree with s and node's root using
+        // scoping root node."
+        let mut descendants = Vec::new();
+        self.collect_descendants(node, &mut descendants);
+        Ok(descendants
+            .into_iter()
+            .filter(|n| matches(&selector, self, *n))
+            .collect())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-document-getelementsbytagname
+    /// https://dom.spec.whatwg.org/#concept-getelementsbytagname
+    pub fn get_elements_by_tag_name(node: NodeId, qualified_name: &str) -> HtmlCollection {
+        // "1. If qualifiedName is "*" (U+002A), return a HTMLCollection rooted at root, whose
+        // filter matches only descendant elements."
+        if qualified_name == "*" {
+            return HtmlCollection::new(node, HtmlCollectionFilter::All);
+        }
+
+        // "2. Otherwise, if root's node document is an HTML document, return a HTMLCollection
+        // rooted at root, whose filter matches the following descendant elements: whose
+        // namespace is the HTML namespace and whose qualified name is qualifiedName, in ASCII
+        // lowercase."
+        HtmlCollection::new(
+            node,
+            HtmlCollectionFilter::Selectors(vec![Selector::TypeSelector(
+                qualified_name.to_string(),
+            )]),
+        )
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-document-getelementsbyclassname
+    /// https://dom.spec.whatwg.org/#concept-getelementsbyclassname
+    pub fn get_elements_by_class_name(node: NodeId, class_names: &str) -> HtmlCollection {
+        // "1. Let classes be the result of running the ordered set parser on classNames."
+        // "2. If classes is the empty set, return an empty HTMLCollection."
+        // "3. Return a HTMLCollection rooted at root, whose filter matches descendant elements
+        // that have all their classes in classes."
+        // An empty list of selectors matches no element, so the empty set is handled here as
+        // well.
+        HtmlCollection::new(
+            node,
+            HtmlCollectionFilter::Selectors(
+                class_names
+                    .split_ascii_whitespace()
+                    .map(|c| Selector::ClassSelector(c.to_string()))
+                    .collect(),
+            ),
+        )
+    }
+
+    /// Returns the first element child of the document, which is the html element.
+    /// https://dom.spec.whatwg.org/#document-element
+    pub fn document_element(&self) -> Option<NodeId> {
+        let mut child = self.node(self.root()).first_child();
+        while let Some(c) = child {
+            if self.node(c).element().is_some() {
+                return Some(c);
+            }
+            child = self.node(c).next_sibling();
+        }
+        None
+    }
+
+    /// "The head element of a document is the first head element that is a child of the html
+    /// element, if there is one, or null otherwise."
+    /// https://html.spec.whatwg.org/multipage/dom.html#the-head-element-2
+    pub fn head_element(&self) -> Option<NodeId> {
+        let mut child = self.node(self.document_element()?).first_child();
+        while let Some(c) = child {
+            if self.element_kind(c) == Some(ElementKind::Head) {
+                return Some(c);
+            }
+            child = self.node(c).next_sibling();
+        }
+        None
+    }
+
+    /// "The title element of a document is the first title element in the document (in tree
+    /// order), if there is one, or null otherwise."
+    /// https://html.spec.whatwg.org/multipage/dom.html#the-title-element-2
+    pub fn title_element(&self) -> Option<NodeId> {
+        Self::get_elements_by_tag_name(self.root(), "title").item(self, 0)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#document.title
+    pub fn title(&self) -> String {
+        // "2. Otherwise, let value be the child text content of the title element, or the empty
+        // string if the title element is null."
+        let value = match self.title_element() {
+            Some(title) => child_text_content(self, title),
+            None => String::new(),
+        };
+
+        // "3. Strip and collapse ASCII whitespace in value."
+        // "4. Return value."
+        value
+            .split_ascii_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#document.title
+    pub fn set_title(&mut self, value: &str) {
+        // "1. If the title element is null and the head element is null, then return."
+        // "2. If the title element is non-null, let element be the title element."
+        // "3. Otherwise: 1. Let element be the result of creating an element given the document
+        // element's node document, "title", and the HTML namespace. 2. Append element to the
+        // head element."
+        let element = match (self.title_element(), self.head_element()) {
+            (Some(title), _) => title,
+            (None, Some(head)) => {
+                let title = self.create_node(NodeKind::Element(Element::new("title", Vec::new())));
+                self.append_child(head, title)
+                    .expect("a title element can be appended to a head element");
+                title
+            }
+            (None, None) => return,
+        };
+
+        // "4. String replace all with the given value within element."
+        // https://dom.spec.whatwg.org/#string-replace-all
+        let mut nodes = Vec::new();
+        if !value.is_empty() {
+            nodes.push(self.create_node(NodeKind::Text(value.to_string())));
+        }
+        self.replace_all(element, nodes);
+    }
+
+    /// Sets an attribute of the element `node`, queues a mutation record and runs the attribute
+    /// change steps. Use this instead of `Element::set_attribute` to change an element in the
+    /// document tree.
+    /// https://dom.spec.whatwg.org/#dom-element-setattribute
+    pub fn set_attribute(
+        &mut self,
+        node: NodeId,
+        qualified_name: &str,
+        value: &str,
+    ) -> Result<(), DomException> {
+        let old_value = match self.node_mut(node).element_mut() {
+            Some(e) => {
+                let old_value = e.get_attribute(qualified_name);
+                e.set_attribute(qualified_name, value)?;
+                old_value
+            }
+            None => return Ok(()),
+        };
+
+        // https://dom.spec.whatwg.org/#handle-attribute-changes
+        // "1. Queue a mutation record of "attributes" for element with attribute's local name,
+        // attribute's namespace, oldValue, « », « », null, and null."
+        queue_mutation_record(
+            self,
+            MutationRecord::new(
+                MutationRecordType::Attributes,
+                node,
+                Some(qualified_name.to_ascii_lowercase()),
+                old_value,
+            ),
+        );
+
+        // "3. Run the attribute change steps with element, attribute's local name, oldValue,
+        // newValue, and attribute's namespace."
+        self.node_mut(node).activate_event_handlers();
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-removeattribute
+    pub fn remove_attribute(&mut self, node: NodeId, qualified_name: &str) {
+        let old_value = match self.node_mut(node).element_mut() {
+            Some(e) => match e.get_attribute(qualified_name) {
+                Some(old_value) => {
+                    e.remove_attribute(qualified_name);
+                    old_value
+                }
+                None => return,
+            },
+            None => return,
+        };
+
+        // https://dom.spec.whatwg.org/#concept-element-attributes-remove
+        // "1. Handle attribute changes for attribute with attribute's element, attribute's value,
+        // and null."
+        queue_mutation_record(
+            self,
+            MutationRecord::new(
+                MutationRecordType::Attributes,
+                node,
+                Some(qualified_name.to_ascii_lowercase()),
+                Some(old_value),
+            ),
+        );
+    }
+
+    /// Replaces the data of the Text node `node` with `data`.
+    /// https://dom.spec.whatwg.org/#dom-characterdata-data
+    pub fn set_data(&mut self, node: NodeId, data: &str) {
+        self.replace_data(node, |s| *s = data.to_string());
+    }
+
+    /// Appends `data` to the data of the Text node `node`.
+    /// https://dom.spec.whatwg.org/#dom-characterdata-appenddata
+    pub fn append_data(&mut self, node: NodeId, data: &str) {
+        self.replace_data(node, |s| s.push_str(data));
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-cd-replace
+    fn replace_data<F: FnOnce(&mut String)>(&mut self, node: NodeId, replace: F) {
+        // Copying the old value is skipped if nobody observes the node, since the parser appends
+        // characters to a Text node one by one.
+        let observed = is_observed(self, node);
+        let old_value = match self.node_mut(node).kind {
+            NodeKind::Text(ref mut s) => {
+                let old_value = if observed { Some(s.clone()) } else { None };
+                replace(s);
+                old_value
+            }
+            _ => return,
+        };
+
+        // "4. Queue a mutation record of "characterData" for node with null, null, node's data,
+        // « », « », null, and null."
+        if observed {
+            queue_mutation_record(
+                self,
+                MutationRecord::new(MutationRecordType::CharacterData, node, None, old_value),
+            );
+        }
+    }
+
+    /// Returns the DOMTokenList of the class attribute of `node`.
+    /// https://dom.spec.whatwg.org/#dom-element-classlist
+    pub fn class_list(node: NodeId) -> DomTokenList {
+        DomTokenList::new(node, "class")
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-addeventlistener
+    pub fn add_event_listener(&mut self, node: NodeId, listener: EventListener) {
+        self.node_mut(node).append_event_listener(listener);
+    }
+
+    /// Removes the listener that has the same type, callback and capture as `listener`.
+    /// https://dom.spec.whatwg.org/#remove-an-event-listener
+    pub fn remove_event_listener(&mut self, node: NodeId, listener: &EventListener) {
+        // "2. Set listener's removed to true and remove listener from eventTarget's event
+        // listener list."
+        self.node_mut(node).event_listeners.retain(|l| {
+            if l.is_same(listener) {
+                l.set_removed();
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Dispatches `event` to `node`. `call` runs the callback of each invoked listener. The
+    /// document isn't borrowed while `call` runs, so a callback can change the tree. Returns
+    /// false if the event was canceled.
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-dispatchevent
+    pub fn dispatch_event<F>(
+        document: &Rc<RefCell<Document>>,
+        node: NodeId,
+        event: &Rc<RefCell<Event>>,
+        call: &mut F,
+    ) -> Result<bool, DomException>
+    where
+        F: FnMut(&EventListener, &Rc<RefCell<Event>>),
+    {
+        dispatch(document, event, node, call)
+    }
+}
+
+/// The names of the event handler content attributes that we support.
+/// https://html.spec.whatwg.org/multipage/webappapis.html#globaleventhandlers
+static EVENT_HANDLER_NAMES: [&str; 24] = [
+    "onabort",
+    "onblur",
+    "onchange",
+    "onclick",
+    "oncontextmenu",
+    "ondblclick",
+    "onerror",
+    "onfocus",
+    "oninput",
+    "onkeydown",
+    "onkeypress",
+    "onkeyup",
+    "onload",
+    "onmousedown",
+    "onmouseenter",
+    "onmouseleave",
+    "onmousemove",
+    "onmouseout",
+    "onmouseover",
+    "onmouseup",
+    "onreset",
+    "onresize",
+    "onscroll",
+    "onsubmit",
+];
+
+#[derive(Debug, Clone)]
+enum HtmlCollectionFilter {
+    /// Matches all descendant elements.
+    All,
+    /// Matches descendant elements that match all of the selectors. No element matches an empty
+    /// list.
+    Selectors(CompoundSelector),
+}
+
+/// https://dom.spec.whatwg.org/#interface-htmlcollection
+/// An HTMLCollection is live. The elements are looked up every time the collection is accessed,
+/// so it always reflects the current state of the tree.
+#[derive(Debug, Clone)]
+pub struct HtmlCollection {
+    root: NodeId,
+    filter: HtmlCollectionFilter,
+}
+
+impl HtmlCollection {
+    fn new(root: NodeId, filter: HtmlCollectionFilter) -> Self {
+        Self { root, filter }
+    }
+
+    /// Returns the elements currently represented by the collection, in tree order.
+    /// https://dom.spec.whatwg.org/#represented-by-the-collection
+    pub fn elements(&self, document: &Document) -> Vec<NodeId> {
+        let mut descendants = Vec::new();
+        document.collect_descendants(self.root, &mut descendants);
+        descendants
+            .into_iter()
+            .filter(|n| match &self.filter {
+                HtmlCollectionFilter::All => document.element_kind(*n).is_some(),
+                HtmlCollectionFilter::Selectors(selectors) => {
+                    matches_compound(selectors, document, *n)
+                }
+            })
+            .collect()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-htmlcollection-length
+    pub fn length(&self, document: &Document) -> usize {
+        self.elements(document).len()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-htmlcollection-item
+    pub fn item(&self, document: &Document, index: usize) -> Option<NodeId> {
+        self.elements(document).get(index).cloned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// https://webidl.spec.whatwg.org/#idl-DOMException
+pub enum DomException {
+    /// "The operation would yield an incorrect node tree."
+    HierarchyRequestError,
+    /// "The object can not be found here."
+    NotFoundError,
+    /// "The string did not match the expected pattern."
+    SyntaxError,
+    /// "The string contains invalid characters."
+    InvalidCharacterError,
+    /// "The object is in an invalid state."
+    InvalidStateError,
+    /// "The operation is not supported."
+    NotSupportedError,
+}
+
 #[derive(Debug, Clone)]
 pub enum NodeKind {
     /// https://dom.spec.whatwg.org/#interface-document
@@ -87,90 +1012,359 @@ impl PartialEq for NodeKind {
             },
         }
     }
-}
+}
+
+impl Eq for NodeKind {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// https://dom.spec.whatwg.org/#interface-element
+pub struct Element {
+    kind: ElementKind,
+    /// https://dom.spec.whatwg.org/#concept-element-local-name
+    local_name: String,
+    attributes: Vec<Attribute>,
+}
+
+impl Element {
+    pub fn new(local_name: &str, attributes: Vec<Attribute>) -> Self {
+        Self {
+            kind: Self::str_to_element_kind(local_name),
+            local_name: local_name.to_string(),
+            attributes,
+        }
+    }
+
+    // associated function
+    pub fn str_to_element_kind(name: &str) -> ElementKind {
+        if name == "html" {
+            ElementKind::Html
+        } else if name == "head" {
+            ElementKind::Head
+        } else if name == "title" {
+            ElementKind::Title
+        } else if name == "link" {
+            ElementKind::Link
+        } else if name == "meta" {
+            ElementKind::Meta
+        } else if name == "style" {
+            ElementKind::Style
+        } else if name == "script" {
+            ElementKind::Script
+        } else if name == "body" {
+            ElementKind::Body
+        } else if name == "h1" {
+            ElementKind::H1
+        } else if name == "h2" {
+            ElementKind::H2
+        } else if name == "h3" {
+            ElementKind::H3
+        } else if name == "h4" {
+            ElementKind::H4
+        } else if name == "h5" {
+            ElementKind::H5
+        } else if name == "h6" {
+            ElementKind::H6
+        } else if name == "p" {
+            ElementKind::P
+        } else if name == "ul" {
+            ElementKind::Ul
+        } else if name == "ol" {
+            ElementKind::Ol
+        } else if name == "li" {
+            ElementKind::Li
+        } else if name == "dl" {
+            ElementKind::Dl
+        } else if name == "dt" {
+            ElementKind::Dt
+        } else if name == "dd" {
+            ElementKind::Dd
+        } else if name == "div" {
+            ElementKind::Div
+        } else if name == "hr" {
+            ElementKind::Hr
+        } else if name == "table" {
+            ElementKind::Table
+        } else if name == "button" {
+            ElementKind::Button
+        } else if name == "select" {
+            ElementKind::Select
+        } else if name == "optgroup" {
+            ElementKind::Optgroup
+        } else if name == "option" {
+            ElementKind::Option
+        } else if name == "a" {
+            ElementKind::A
+        } else if name == "b" {
+            ElementKind::B
+        } else if name == "big" {
+            ElementKind::Big
+        } else if name == "br" {
+            ElementKind::Br
+        } else if name == "code" {
+            ElementKind::Code
+        } else if name == "em" {
+            ElementKind::Em
+        } else if name == "font" {
+            ElementKind::Font
+        } else if name == "i" {
+            ElementKind::I
+        } else if name == "img" {
+            ElementKind::Img
+        } else if name == "nobr" {
+            ElementKind::Nobr
+        } else if name == "s" {
+            ElementKind::S
+        } else if name == "small" {
+            ElementKind::Small
+        } else if name == "strike" {
+            ElementKind::Strike
+        } else if name == "strong" {
+            ElementKind::Strong
+        } else if name == "tt" {
+            ElementKind::Tt
+        } else if name == "u" {
+            ElementKind::U
+        } else {
+            ElementKind::Unknown
+        }
+    }
+
+    pub fn kind(&self) -> ElementKind {
+        self.kind
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-localname
+    pub fn local_name(&self) -> String {
+        self.local_name.clone()
+    }
+
+    pub fn attributes(&self) -> Vec<Attribute> {
+        self.attributes.clone()
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-element-attributes-get-by-name
+    fn find_attribute(&self, qualified_name: &str) -> Option<usize> {
+        // "1. If element is in the HTML namespace and its node document is an HTML document,
+        // then set qualifiedName to qualifiedName in ASCII lowercase."
+        let name = qualified_name.to_ascii_lowercase();
+        // "2. Return the first attribute in element's attribute list whose qualified name is
+        // qualifiedName; otherwise null."
+        self.attributes.iter().position(|attr| attr.name == name)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-getattribute
+    pub fn get_attribute(&self, qualified_name: &str) -> Option<String> {
+        self.find_attribute(qualified_name)
+            .map(|i| self.attributes[i].value.clone())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-setattribute
+    pub fn set_attribute(&mut self, qualified_name: &str, value: &str) -> Result<(), DomException> {
+        // "1. If qualifiedName is not a valid attribute local name, then throw an
+        // "InvalidCharacterError" DOMException."
+        // https://dom.spec.whatwg.org/#valid-attribute-local-name
+        if qualified_name.is_empty()
+            || qualified_name
+                .chars()
+                .any(|c| c.is_ascii_whitespace() || c == '\0' || c == '/' || c == '=' || c == '>')
+        {
+            return Err(DomException::InvalidCharacterError);
+        }
+
+        // "3. Let attribute be the first attribute in this's attribute list whose qualified name
+        // is qualifiedName, and null otherwise."
+        match self.find_attribute(qualified_name) {
+            // "5. Change attribute to value."
+            Some(i) => self.attributes[i].value = value.to_string(),
+            // "4. If attribute is null, create an attribute whose local name is qualifiedName,
+            // value is value, and node document is this's node document, then append this
+            // attribute to this, and then return."
+            None => {
+                let mut attr = Attribute::new();
+                attr.name = qualified_name.to_ascii_lowercase();
+                attr.value = value.to_string();
+                self.attributes.push(attr);
+            }
+        }
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-removeattribute
+    pub fn remove_attribute(&mut self, qualified_name: &str) {
+        if let Some(i) = self.find_attribute(qualified_name) {
+            self.attributes.remove(i);
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-hasattribute
+    pub fn has_attribute(&self, qualified_name: &str) -> bool {
+        self.find_attribute(qualified_name).is_some()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-id
+    pub fn id(&self) -> String {
+        self.get_attribute("id").unwrap_or_default()
+    }
+
+    /// Returns the classes of the element, which are the tokens of the class attribute.
+    /// https://dom.spec.whatwg.org/#concept-class
+    pub fn classes(&self) -> Vec<String> {
+        parse_ordered_set(&self.get_attribute("class").unwrap_or_default())
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-ordered-set-parser
+fn parse_ordered_set(input: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for token in input.split_ascii_whitespace() {
+        if !tokens.iter().any(|t| t == token) {
+            tokens.push(token.to_string());
+        }
+    }
+    tokens
+}
+
+/// https://dom.spec.whatwg.org/#interface-domtokenlist
+/// A DOMTokenList is live. The tokens are parsed from the associated attribute every time the
+/// list is accessed, and every change is written back to the attribute.
+#[derive(Debug, Clone)]
+pub struct DomTokenList {
+    element: NodeId,
+    local_name: String,
+}
+
+impl DomTokenList {
+    fn new(element: NodeId, local_name: &str) -> Self {
+        Self {
+            element,
+            local_name: local_name.to_string(),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-dtl-tokens
+    fn tokens(&self, document: &Document) -> Vec<String> {
+        parse_ordered_set(&self.value(document))
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-dtl-update
+    fn update(&self, document: &mut Document, tokens: Vec<String>) {
+        let has_attribute = match document.node(self.element).element() {
+            Some(e) => e.has_attribute(&self.local_name),
+            None => return,
+        };
+        // "1. If the associated element does not have an associated attribute and token set is
+        // empty, then return."
+        if !has_attribute && tokens.is_empty() {
+            return;
+        }
+        // "2. Set an attribute value for the associated element using associated attribute's
+        // local name and the result of running the ordered set serializer for token set."
+        document
+            .set_attribute(self.element, &self.local_name, &tokens.join(" "))
+            .expect("the local name of the associated attribute should be valid");
+    }
+
+    /// Checks that `token` can be added to or removed from the list.
+    fn validate(token: &str) -> Result<(), DomException> {
+        // "1. If token is the empty string, then throw a "SyntaxError" DOMException."
+        if token.is_empty() {
+            return Err(DomException::SyntaxError);
+        }
+        // "2. If token contains any ASCII whitespace, then throw an "InvalidCharacterError"
+        // DOMException."
+        if token.chars().any(|c| c.is_ascii_whitespace()) {
+            return Err(DomException::InvalidCharacterError);
+        }
+        Ok(())
+    }
 
-impl Eq for NodeKind {}
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-value
+    pub fn value(&self, document: &Document) -> String {
+        match document.node(self.element).element() {
+            Some(e) => e.get_attribute(&self.local_name).unwrap_or_default(),
+            None => String::new(),
+        }
+    }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-/// https://dom.spec.whatwg.org/#interface-element
-pub struct Element {
-    kind: ElementKind,
-    attributes: Vec<Attribute>,
-}
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-length
+    pub fn length(&self, document: &Document) -> usize {
+        self.tokens(document).len()
+    }
 
-impl Element {
-    pub fn new(kind: ElementKind, attributes: Vec<Attribute>) -> Self {
-        Self { kind, attributes }
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-item
+    pub fn item(&self, document: &Document, index: usize) -> Option<String> {
+        self.tokens(document).get(index).cloned()
     }
 
-    // associated function
-    fn str_to_element_kind(name: &str) -> ElementKind {
-        if name == "html" {
-            ElementKind::Html
-        } else if name == "head" {
-            ElementKind::Head
-        } else if name == "style" {
-            ElementKind::Style
-        } else if name == "script" {
-            ElementKind::Script
-        } else if name == "body" {
-            ElementKind::Body
-        } else if name == "h1" {
-            ElementKind::H1
-        } else if name == "h2" {
-            ElementKind::H2
-        } else if name == "p" {
-            ElementKind::P
-        } else if name == "ul" {
-            ElementKind::Ul
-        } else if name == "li" {
-            ElementKind::Li
-        } else if name == "div" {
-            ElementKind::Div
-        } else if name == "a" {
-            ElementKind::A
-        } else {
-            unimplemented!("not supported this tag name: {}", name);
-        }
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-contains
+    pub fn contains(&self, document: &Document, token: &str) -> bool {
+        self.tokens(document).iter().any(|t| t == token)
     }
 
-    // associated function
-    pub fn element_kind_to_string(kind: ElementKind) -> String {
-        if kind == ElementKind::Html {
-            "html".to_string()
-        } else if kind == ElementKind::Head {
-            "head".to_string()
-        } else if kind == ElementKind::Style {
-            "style".to_string()
-        } else if kind == ElementKind::Script {
-            "script".to_string()
-        } else if kind == ElementKind::Body {
-            "body".to_string()
-        } else if kind == ElementKind::H1 {
-            "h1".to_string()
-        } else if kind == ElementKind::H2 {
-            "h2".to_string()
-        } else if kind == ElementKind::P {
-            "p".to_string()
-        } else if kind == ElementKind::Ul {
-            "ul".to_string()
-        } else if kind == ElementKind::Li {
-            "li".to_string()
-        } else if kind == ElementKind::Div {
-            "div".to_string()
-        } else if kind == ElementKind::A {
-            "a".to_string()
-        } else {
-            unimplemented!("not supported this element kind: {:?}", kind);
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-add
+    pub fn add(&self, document: &mut Document, tokens: &[&str]) -> Result<(), DomException> {
+        // "1. For each token in tokens: ..."
+        for token in tokens {
+            Self::validate(token)?;
+        }
+        // "2. For each token in tokens, append token to this's token set."
+        let mut token_set = self.tokens(document);
+        for token in tokens {
+            if !token_set.iter().any(|t| t == token) {
+                token_set.push(token.to_string());
+            }
         }
+        // "3. Run the update steps."
+        self.update(document, token_set);
+        Ok(())
     }
 
-    pub fn kind(&self) -> ElementKind {
-        self.kind
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-remove
+    pub fn remove(&self, document: &mut Document, tokens: &[&str]) -> Result<(), DomException> {
+        for token in tokens {
+            Self::validate(token)?;
+        }
+        // "2. For each token in tokens, remove token from this's token set."
+        let mut token_set = self.tokens(document);
+        token_set.retain(|t| !tokens.contains(&t.as_str()));
+        self.update(document, token_set);
+        Ok(())
     }
 
-    pub fn attributes(&self) -> Vec<Attribute> {
-        self.attributes.clone()
+    /// Returns true if `token` is in the list after the call.
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-toggle
+    pub fn toggle(
+        &self,
+        document: &mut Document,
+        token: &str,
+        force: Option<bool>,
+    ) -> Result<bool, DomException> {
+        Self::validate(token)?;
+
+        let mut token_set = self.tokens(document);
+        // "3. If this's token set[token] exists, then:"
+        if token_set.iter().any(|t| t == token) {
+            // "1. If force is either not given or is false, then remove token from this's token
+            // set, run the update steps and return false."
+            if force != Some(true) {
+                token_set.retain(|t| t != token);
+                self.update(document, token_set);
+                return Ok(false);
+            }
+            // "2. Return true."
+            return Ok(true);
+        }
+
+        // "4. Otherwise, if force not given or is true, append token to this's token set, run
+        // the update steps, and return true."
+        if force != Some(false) {
+            token_set.push(token.to_string());
+            self.update(document, token_set);
+            return Ok(true);
+        }
+
+        // "5. Return false."
+        Ok(false)
     }
 }
 
@@ -181,6 +1375,12 @@ pub enum ElementKind {
     Html,
     /// https://html.spec.whatwg.org/multipage/semantics.html#the-head-element
     Head,
+    /// https://html.spec.whatwg.org/multipage/semantics.html#the-title-element
+    Title,
+    /// https://html.spec.whatwg.org/multipage/semantics.html#the-link-element
+    Link,
+    /// https://html.spec.whatwg.org/multipage/semantics.html#the-meta-element
+    Meta,
     /// https://html.spec.whatwg.org/multipage/semantics.html#the-style-element
     Style,
     /// https://html.spec.whatwg.org/multipage/scripting.html#the-script-element
@@ -190,16 +1390,73 @@ pub enum ElementKind {
     /// https://html.spec.whatwg.org/multipage/sections.html#the-h1,-h2,-h3,-h4,-h5,-and-h6-elements
     H1,
     H2,
+    H3,
+    H4,
+    H5,
+    H6,
     /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-p-element
     P,
     /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-ul-element
     Ul,
+    /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-ol-element
+    Ol,
     /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-li-element
     Li,
+    /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-dl-element
+    Dl,
+    /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-dt-element
+    Dt,
+    /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-dd-element
+    Dd,
     /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-div-element
     Div,
+    /// https://html.spec.whatwg.org/multipage/grouping-content.html#the-hr-element
+    Hr,
+    /// https://html.spec.whatwg.org/multipage/tables.html#the-table-element
+    Table,
+    /// https://html.spec.whatwg.org/multipage/form-elements.html#the-button-element
+    Button,
+    /// https://html.spec.whatwg.org/multipage/form-elements.html#the-select-element
+    Select,
+    /// https://html.spec.whatwg.org/multipage/form-elements.html#the-optgroup-element
+    Optgroup,
+    /// https://html.spec.whatwg.org/multipage/form-elements.html#the-option-element
+    Option,
     /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-a-element
     A,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-b-element
+    B,
+    /// https://html.spec.whatwg.org/multipage/obsolete.html#big
+    Big,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-br-element
+    Br,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-code-element
+    Code,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-em-element
+    Em,
+    /// https://html.spec.whatwg.org/multipage/obsolete.html#font
+    Font,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-i-element
+    I,
+    /// https://html.spec.whatwg.org/multipage/embedded-content.html#the-img-element
+    Img,
+    /// https://html.spec.whatwg.org/multipage/obsolete.html#nobr
+    Nobr,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-s-element
+    S,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-small-element
+    Small,
+    /// https://html.spec.whatwg.org/multipage/obsolete.html#strike
+    Strike,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-strong-element
+    Strong,
+    /// https://html.spec.whatwg.org/multipage/obsolete.html#tt
+    Tt,
+    /// https://html.spec.whatwg.org/multipage/text-level-semantics.html#the-u-element
+    U,
+    /// An element whose tag name we don't know. Its tag name is kept in the element's local name.
+    /// https://html.spec.whatwg.org/multipage/dom.html#htmlunknownelement
+    Unknown,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -211,99 +1468,138 @@ pub enum InsertionMode {
     AfterHead,
     InBody,
     Text,
+    InSelect,
     AfterBody,
     AfterAfterBody,
 }
 
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
-    root: Rc<RefCell<Node>>,
+    /// The document that the parser is building. It's shared with the JavaScript runtime, which
+    /// changes the tree while the parser is paused for a script.
+    document: Rc<RefCell<Document>>,
+    /// The Document node that the parser inserts the html element into.
+    root: NodeId,
     mode: InsertionMode,
     t: HtmlTokenizer,
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-stack-of-open-elements
-    stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    stack_of_open_elements: Vec<NodeId>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#list-of-active-formatting-elements
+    /// We don't support any element that inserts a marker (applet, object, marquee, template,
+    /// td, th and caption), so the list never contains markers.
+    list_of_active_formatting_elements: Vec<NodeId>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#head-element-pointer
+    head_element: Option<NodeId>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-frag-parse-context
+    /// This is set only when the parser was created as part of the HTML fragment parsing
+    /// algorithm.
+    context_element: Option<NodeId>,
     /// https://html.spec.whatwg.org/multipage/parsing.html#original-insertion-mode
     original_insertion_mode: InsertionMode,
+    /// The token that the parser was about to process when it paused for a script.
+    pending_token: Option<HtmlToken>,
 }
 
 impl HtmlParser {
     pub fn new(t: HtmlTokenizer) -> Self {
+        let document = Document::new();
+        let root = document.root();
+        Self::with_document(t, Rc::new(RefCell::new(document)), root)
+    }
+
+    /// Creates a parser that inserts nodes into `root` of `document`.
+    fn with_document(t: HtmlTokenizer, document: Rc<RefCell<Document>>, root: NodeId) -> Self {
         Self {
-            root: Rc::new(RefCell::new(Node::new(NodeKind::Document))),
+            document,
+            root,
             mode: InsertionMode::Initial,
             t,
             stack_of_open_elements: Vec::new(),
+            list_of_active_formatting_elements: Vec::new(),
+            head_element: None,
+            context_element: None,
             original_insertion_mode: InsertionMode::Initial,
+            pending_token: None,
         }
     }
 
-    /// Creates a char node.
-    fn create_char(&self, c: char) -> Node {
-        let mut s = String::new();
-        s.push(c);
-        return Node::new(NodeKind::Text(s));
+    /// Returns the document that the parser is building.
+    pub fn document(&self) -> Rc<RefCell<Document>> {
+        self.document.clone()
+    }
+
+    /// Returns the element kind if `node` is an element.
+    fn element_kind(&self, node: NodeId) -> Option<ElementKind> {
+        self.document.borrow().element_kind(node)
+    }
+
+    /// Creates an element node for `kind`.
+    fn create_element_node(&self, kind: NodeKind) -> NodeId {
+        self.document.borrow_mut().create_node(kind)
     }
 
     /// Creates an element node.
-    fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> Node {
-        let kind = Element::str_to_element_kind(tag);
+    fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> NodeId {
+        self.create_element_node(NodeKind::Element(Element::new(tag, attributes)))
+    }
+
+    /// Creates an element for the token for which `node` was created, so the new element has the
+    /// same tag name and attributes as `node`.
+    fn clone_element(&self, node: NodeId) -> NodeId {
+        let kind = self.document.borrow().node(node).kind().clone();
+        self.create_element_node(kind)
+    }
 
-        return Node::new(NodeKind::Element(Element::new(kind, attributes)));
+    /// Returns the current node, or the root if the stack of open elements is empty.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#current-node
+    fn current_node(&self) -> NodeId {
+        match self.stack_of_open_elements.last() {
+            Some(n) => *n,
+            None => self.root,
+        }
     }
 
     /// Creates an element node for the token and insert it to the appropriate place for inserting
     /// a node. Put the new node in the stack of open elements.
     /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
-    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n,
-            None => &self.root,
-        };
-
-        let node = Rc::new(RefCell::new(self.create_element(tag, attributes)));
-
-        if current.borrow().first_child().is_some() {
-            let mut last_sibiling = current.borrow().first_child();
-            loop {
-                last_sibiling = match last_sibiling {
-                    Some(ref node) => {
-                        if node.borrow().next_sibling().is_some() {
-                            node.borrow().next_sibling()
-                        } else {
-                            break;
-                        }
-                    }
-                    None => unimplemented!("last_sibiling should be Some"),
-                };
-            }
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> NodeId {
+        let node = self.create_element(tag, attributes);
+        self.insert_element_node(node);
+        node
+    }
 
-            last_sibiling.unwrap().borrow_mut().next_sibling = Some(node.clone());
-            node.borrow_mut().previous_sibling =
-                Some(Rc::downgrade(&current.borrow().first_child().unwrap()));
-        } else {
-            current.borrow_mut().first_child = Some(node.clone());
+    /// Appends `node` to `parent`. A parse error never stops the parser, so a node which can't be
+    /// inserted is just dropped with a warning.
+    fn append_node(&self, parent: NodeId, node: NodeId) {
+        let mut document = self.document.borrow_mut();
+        if let Err(e) = document.append_child(parent, node) {
+            println!(
+                "warning: failed to insert a node {:?}: {:?}",
+                document.node(node).kind(),
+                e
+            );
         }
+    }
 
-        current.borrow_mut().last_child = Some(Rc::downgrade(&node));
-        node.borrow_mut().parent = Some(Rc::downgrade(&current));
-
+    /// Inserts an already created element node to the appropriate place for inserting a node and
+    /// puts it in the stack of open elements.
+    fn insert_element_node(&mut self, node: NodeId) {
+        self.append_node(self.current_node(), node);
         self.stack_of_open_elements.push(node);
     }
 
     /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character
     fn insert_char(&mut self, c: char) {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n,
-            None => &self.root,
-        };
-
-        // When the current node is Text, add a character to the current node.
-        match current.borrow_mut().kind {
-            NodeKind::Text(ref mut s) => {
-                s.push(c);
+        let current = self.current_node();
+        let mut document = self.document.borrow_mut();
+
+        // "If there is a Text node immediately before the adjusted insertion location, then
+        // append data to that Text node's data."
+        if let Some(last) = document.node(current).last_child() {
+            if document.node(last).data().is_some() {
+                document.append_data(last, &c.to_string());
                 return;
             }
-            _ => {}
         }
 
         // do not create a Text node if new char is '\n' or ' '
@@ -311,73 +1607,699 @@ impl HtmlParser {
             return;
         }
 
-        let node = Rc::new(RefCell::new(self.create_char(c)));
+        let node = document.create_node(NodeKind::Text(c.to_string()));
+        drop(document);
+        self.append_node(current, node);
+    }
 
-        if current.borrow().first_child().is_some() {
-            current
-                .borrow()
-                .first_child()
-                .unwrap()
-                .borrow_mut()
-                .next_sibling = Some(node.clone());
-            node.borrow_mut().previous_sibling =
-                Some(Rc::downgrade(&current.borrow().first_child().unwrap()));
-        } else {
-            current.borrow_mut().first_child = Some(node.clone());
+    /// Pops nodes until a node with `element_kind` comes. Nothing is popped if the stack of open
+    /// elements doesn't have such a node.
+    fn pop_until(&mut self, element_kind: ElementKind) {
+        if !self.contain_in_stack(element_kind) {
+            return;
         }
 
-        current.borrow_mut().last_child = Some(Rc::downgrade(&node));
-        node.borrow_mut().parent = Some(Rc::downgrade(&current));
+        loop {
+            let current = match self.stack_of_open_elements.pop() {
+                Some(n) => n,
+                None => return,
+            };
+
+            if self.element_kind(current) == Some(element_kind) {
+                return;
+            }
+        }
+    }
 
-        self.stack_of_open_elements.push(node);
+    /// Returns true if the stack of open elements has NodeKind::Element::<element_kind> node.
+    fn contain_in_stack(&mut self, element_kind: ElementKind) -> bool {
+        for i in 0..self.stack_of_open_elements.len() {
+            if self.element_kind(self.stack_of_open_elements[i]) == Some(element_kind) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Returns the position of `node` in the stack of open elements.
+    fn position_in_stack(&self, node: NodeId) -> Option<usize> {
+        self.stack_of_open_elements.iter().position(|n| *n == node)
+    }
+
+    /// Returns the position of `node` in the list of active formatting elements.
+    fn position_in_active_formatting_elements(&self, node: NodeId) -> Option<usize> {
+        self.list_of_active_formatting_elements
+            .iter()
+            .position(|n| *n == node)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#special
+    fn is_special(kind: ElementKind) -> bool {
+        matches!(
+            kind,
+            ElementKind::Html
+                | ElementKind::Head
+                | ElementKind::Title
+                | ElementKind::Link
+                | ElementKind::Meta
+                | ElementKind::Style
+                | ElementKind::Script
+                | ElementKind::Body
+                | ElementKind::H1
+                | ElementKind::H2
+                | ElementKind::H3
+                | ElementKind::H4
+                | ElementKind::H5
+                | ElementKind::H6
+                | ElementKind::P
+                | ElementKind::Ul
+                | ElementKind::Ol
+                | ElementKind::Li
+                | ElementKind::Dl
+                | ElementKind::Dt
+                | ElementKind::Dd
+                | ElementKind::Div
+                | ElementKind::Hr
+                | ElementKind::Table
+                | ElementKind::Button
+                | ElementKind::Select
+                | ElementKind::Br
+                | ElementKind::Img
+        )
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    fn has_element_in_specific_scope(&self, target: ElementKind, list: &[ElementKind]) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = match self.element_kind(*node) {
+                Some(k) => k,
+                None => continue,
+            };
+            // "If node is the target node, terminate in a match state."
+            if kind == target {
+                return true;
+            }
+            // "Otherwise, if node is one of the element types in list, terminate in a failure
+            // state."
+            if list.contains(&kind) {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+    fn has_element_in_scope(&self, target: ElementKind) -> bool {
+        self.has_element_in_specific_scope(target, &[ElementKind::Html, ElementKind::Table])
+    }
+
+    /// Same as `has_element_in_scope` but the target is the node itself rather than any element
+    /// of its kind.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+    fn has_node_in_scope(&self, target: NodeId) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            // "If node is the target node, terminate in a match state."
+            if *node == target {
+                return true;
+            }
+            // "Otherwise, if node is one of the element types in list, terminate in a failure
+            // state."
+            if matches!(
+                self.element_kind(*node),
+                Some(ElementKind::Html) | Some(ElementKind::Table)
+            ) {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-list-item-scope
+    fn has_element_in_list_item_scope(&self, target: ElementKind) -> bool {
+        self.has_element_in_specific_scope(
+            target,
+            &[
+                ElementKind::Html,
+                ElementKind::Table,
+                ElementKind::Ol,
+                ElementKind::Ul,
+            ],
+        )
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-button-scope
+    fn has_element_in_button_scope(&self, target: ElementKind) -> bool {
+        self.has_element_in_specific_scope(
+            target,
+            &[ElementKind::Html, ElementKind::Table, ElementKind::Button],
+        )
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-table-scope
+    /// This is used only by the "in table" insertion modes, which we don't support yet.
+    #[allow(dead_code)]
+    fn has_element_in_table_scope(&self, target: ElementKind) -> bool {
+        self.has_element_in_specific_scope(target, &[ElementKind::Html, ElementKind::Table])
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-select-scope
+    fn has_element_in_select_scope(&self, target: ElementKind) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = match self.element_kind(*node) {
+                Some(k) => k,
+                None => continue,
+            };
+            if kind == target {
+                return true;
+            }
+            // "...with the list of element types consisting of all element types except the
+            // following: optgroup in the HTML namespace, option in the HTML namespace"
+            if kind != ElementKind::Optgroup && kind != ElementKind::Option {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// Returns true if the current node is an element whose kind is `kind`.
+    fn is_current_node(&self, kind: ElementKind) -> bool {
+        self.element_kind(self.current_node()) == Some(kind)
+    }
+
+    /// Returns true if `kind` is one of h1, h2, h3, h4, h5 and h6.
+    fn is_heading(kind: ElementKind) -> bool {
+        matches!(
+            kind,
+            ElementKind::H1
+                | ElementKind::H2
+                | ElementKind::H3
+                | ElementKind::H4
+                | ElementKind::H5
+                | ElementKind::H6
+        )
+    }
+
+    /// Returns true if the stack of open elements has any of h1, h2, h3, h4, h5 and h6 in scope.
+    fn has_heading_in_scope(&self) -> bool {
+        self.has_element_in_scope(ElementKind::H1)
+            || self.has_element_in_scope(ElementKind::H2)
+            || self.has_element_in_scope(ElementKind::H3)
+            || self.has_element_in_scope(ElementKind::H4)
+            || self.has_element_in_scope(ElementKind::H5)
+            || self.has_element_in_scope(ElementKind::H6)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        // "Generate implied end tags, except for p elements."
+        self.generate_implied_end_tags(Some(ElementKind::P));
+        // "Pop elements from the stack of open elements until a p element has been popped from
+        // the stack."
+        self.pop_until(ElementKind::P);
+    }
+
+    /// Closes a p element if the stack of open elements has a p element in button scope. Many
+    /// block-level start tags in the "in body" insertion mode begin with this step.
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_button_scope(ElementKind::P) {
+            self.close_p_element();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    fn reset_insertion_mode_appropriately(&mut self) {
+        for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+            // "If node is the first node in the stack of open elements, then set last to true,
+            // and, if the parser was created as part of the HTML fragment parsing algorithm
+            // (fragment case), set node to the context element passed to that algorithm."
+            let last = i == 0;
+            let node = match self.context_element {
+                Some(ref context) if last => context,
+                _ => node,
+            };
+            let kind = match self.element_kind(*node) {
+                Some(k) => k,
+                None => continue,
+            };
+
+            match kind {
+                ElementKind::Select => {
+                    self.mode = InsertionMode::InSelect;
+                    return;
+                }
+                ElementKind::Head if !last => {
+                    self.mode = InsertionMode::InHead;
+                    return;
+                }
+                ElementKind::Body => {
+                    self.mode = InsertionMode::InBody;
+                    return;
+                }
+                ElementKind::Html => {
+                    // "If the head element pointer is null, switch the insertion mode to
+                    // "before head" and return. Otherwise, the head element pointer is not null,
+                    // switch the insertion mode to "after head" and return."
+                    self.mode = match self.head_element {
+                        Some(_) => InsertionMode::AfterHead,
+                        None => InsertionMode::BeforeHead,
+                    };
+                    return;
+                }
+                _ => {}
+            }
+
+            if last {
+                break;
+            }
+        }
+
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, exception: Option<ElementKind>) {
+        loop {
+            let kind = match self.element_kind(self.current_node()) {
+                Some(k) => k,
+                None => return,
+            };
+            if Some(kind) == exception {
+                return;
+            }
+            // "while the current node is a dd element, a dt element, an li element, an optgroup
+            // element, an option element, a p element, an rb element, an rp element, an rt
+            // element, or an rtc element, the UA must pop the current node off the stack of open
+            // elements."
+            match kind {
+                ElementKind::Dd
+                | ElementKind::Dt
+                | ElementKind::Li
+                | ElementKind::Optgroup
+                | ElementKind::Option
+                | ElementKind::P => {
+                    self.stack_of_open_elements.pop();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Returns the last element whose kind is `kind` in the list of active formatting elements.
+    fn active_formatting_element(&self, kind: ElementKind) -> Option<NodeId> {
+        self.list_of_active_formatting_elements
+            .iter()
+            .rev()
+            .find(|n| self.element_kind(**n) == Some(kind))
+            .cloned()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements
+    fn push_active_formatting_element(&mut self, node: NodeId) {
+        let document = self.document.borrow();
+        let element = match document.node(node).element() {
+            Some(e) => e,
+            None => panic!("only elements can be pushed to the list of active formatting elements"),
+        };
+
+        // "If there are already three elements in the list of active formatting elements after
+        // the last marker, if any, or anywhere in the list if there are no markers, that have
+        // the same tag name, namespace, and attributes as element, then remove the earliest
+        // such element from the list of active formatting elements."
+        let same: Vec<usize> = self
+            .list_of_active_formatting_elements
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| match document.node(**n).element() {
+                Some(e) => {
+                    e.kind == element.kind
+                        && e.attributes.len() == element.attributes.len()
+                        && e.attributes.iter().all(|a| element.attributes.contains(a))
+                }
+                None => false,
+            })
+            .map(|(i, _)| i)
+            .collect();
+        drop(document);
+        if same.len() >= 3 {
+            self.list_of_active_formatting_elements.remove(same[0]);
+        }
+
+        self.list_of_active_formatting_elements.push(node);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        // "1. If there are no entries in the list of active formatting elements, then there is
+        // nothing to reconstruct; stop this algorithm."
+        // "2. If the last (most recently added) entry in the list of active formatting elements
+        // is a marker, or if it is an element that is in the stack of open elements, then there
+        // is nothing to reconstruct; stop this algorithm."
+        let last = match self.list_of_active_formatting_elements.last() {
+            Some(n) => *n,
+            None => return,
+        };
+        if self.position_in_stack(last).is_some() {
+            return;
+        }
+
+        // "3. - 6. Rewind"
+        // Find the earliest entry that is not in the stack of open elements, such that all
+        // entries after it are also not in the stack.
+        let mut i = self.list_of_active_formatting_elements.len() - 1;
+        while i > 0 {
+            let previous = self.list_of_active_formatting_elements[i - 1];
+            if self.position_in_stack(previous).is_some() {
+                break;
+            }
+            i -= 1;
+        }
+
+        // "7. - 10. Advance, Create"
+        // "Insert an HTML element for the token for which the element entry was created, to
+        // obtain new element." and "Replace the entry for entry in the list with an entry for
+        // new element."
+        while i < self.list_of_active_formatting_elements.len() {
+            let new_element = self.clone_element(self.list_of_active_formatting_elements[i]);
+            self.insert_element_node(new_element);
+            self.list_of_active_formatting_elements[i] = new_element;
+            i += 1;
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    /// "Any other end tag"
+    fn any_other_end_tag(&mut self, tag: &str) {
+        // "1. Initialize node to be the current node (the bottommost node of the stack)."
+        let mut i = self.stack_of_open_elements.len();
+        while i > 0 {
+            i -= 1;
+            let (kind, local_name) = match self
+                .document
+                .borrow()
+                .node(self.stack_of_open_elements[i])
+                .element()
+            {
+                Some(e) => (e.kind(), e.local_name()),
+                None => continue,
+            };
+
+            // "2. Loop: If node is an HTML element with the same tag name as the token, then:
+            // generate implied end tags, except for HTML elements with the same tag name as the
+            // token. Pop all the nodes from the current node up to node, including node, then
+            // stop these steps."
+            if local_name == tag {
+                self.generate_implied_end_tags(Some(kind));
+                self.stack_of_open_elements.truncate(i);
+                return;
+            }
+
+            // "3. Otherwise, if node is in the special category, then this is a parse error;
+            // ignore the token, and return."
+            if Self::is_special(kind) {
+                return;
+            }
+        }
     }
 
-    /// Returns true if the current node's kind is same as NodeKind::Element::<element_kind>.
-    fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n,
-            None => return false,
-        };
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    fn adoption_agency_algorithm(&mut self, tag: &str) {
+        // "1. Let subject be token's tag name."
+        let subject = Element::str_to_element_kind(tag);
+
+        // "2. If the current node is an HTML element whose tag name is subject, and the current
+        // node is not in the list of active formatting elements, then pop the current node off
+        // the stack of open elements and return."
+        let current = self.current_node();
+        if self.element_kind(current) == Some(subject)
+            && self
+                .position_in_active_formatting_elements(current)
+                .is_none()
+        {
+            self.stack_of_open_elements.pop();
+            return;
+        }
+
+        // "3. Let outerLoopCounter be 0."
+        // "4. While true: If outerLoopCounter is greater than or equal to 8, then return."
+        for _ in 0..8 {
+            // "4.3. Let formattingElement be the last element in the list of active formatting
+            // elements that has the tag name subject. If there is no such element, then return
+            // and instead act as described in the "any other end tag" entry above."
+            let formatting_element = match self.active_formatting_element(subject) {
+                Some(n) => n,
+                None => {
+                    self.any_other_end_tag(tag);
+                    return;
+                }
+            };
+
+            // "4.4. If formattingElement is not in the stack of open elements, then this is a
+            // parse error; remove the element from the list, and return."
+            let formatting_element_index = match self.position_in_stack(formatting_element) {
+                Some(i) => i,
+                None => {
+                    if let Some(i) = self.position_in_active_formatting_elements(formatting_element)
+                    {
+                        self.list_of_active_formatting_elements.remove(i);
+                    }
+                    return;
+                }
+            };
+
+            // "4.5. If formattingElement is in the stack of open elements, but the element is not
+            // in scope, then this is a parse error; return."
+            if !self.has_node_in_scope(formatting_element) {
+                return;
+            }
+
+            // "4.6. If formattingElement is not the current node, this is a parse error. (But do
+            // not return.)"
+
+            // "4.7. Let furthestBlock be the topmost node in the stack of open elements that is
+            // lower in the stack than formattingElement, and is an element in the special
+            // category. There might not be one."
+            let furthest_block_index =
+                (formatting_element_index + 1..self.stack_of_open_elements.len()).find(|i| {
+                    match self.element_kind(self.stack_of_open_elements[*i]) {
+                        Some(k) => Self::is_special(k),
+                        None => false,
+                    }
+                });
+
+            // "4.8. If there is no furthestBlock, then the UA must first pop all the nodes from
+            // the bottom of the stack of open elements, from the current node up to and
+            // including formattingElement, then remove formattingElement from the list of active
+            // formatting elements, and finally return."
+            let furthest_block_index = match furthest_block_index {
+                Some(i) => i,
+                None => {
+                    self.stack_of_open_elements
+                        .truncate(formatting_element_index);
+                    if let Some(i) = self.position_in_active_formatting_elements(formatting_element)
+                    {
+                        self.list_of_active_formatting_elements.remove(i);
+                    }
+                    return;
+                }
+            };
+            let furthest_block = self.stack_of_open_elements[furthest_block_index];
+
+            // "4.9. Let commonAncestor be the element immediately above formattingElement in the
+            // stack of open elements."
+            let common_ancestor = self.stack_of_open_elements[formatting_element_index - 1];
+
+            // "4.10. Let a bookmark note the position of formattingElement in the list of active
+            // formatting elements relative to the elements on either side of it in the list."
+            // The bookmark is the index where the new element is inserted before
+            // formattingElement is removed from the list.
+            let mut bookmark = self
+                .position_in_active_formatting_elements(formatting_element)
+                .expect("formatting element should be in the list");
+
+            // "4.11. Let node and lastNode be furthestBlock."
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+
+            // "4.12. Let innerLoopCounter be 0."
+            let mut inner_loop_counter = 0;
+
+            // "4.13. While true:"
+            loop {
+                inner_loop_counter += 1;
+
+                // "4.13.2. Let node be the element immediately above node in the stack of open
+                // elements, or if node is no longer in the stack of open elements (e.g. because
+                // it got removed by this algorithm), the element that was immediately above node
+                // in the stack of open elements before node was removed."
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index];
+
+                // "4.13.3. If node is formattingElement, then break."
+                if node == formatting_element {
+                    break;
+                }
+
+                // "4.13.4. If innerLoopCounter is greater than 3 and node is in the list of active
+                // formatting elements, then remove node from the list of active formatting
+                // elements."
+                let mut position = self.position_in_active_formatting_elements(node);
+                if inner_loop_counter > 3 {
+                    if let Some(p) = position {
+                        self.list_of_active_formatting_elements.remove(p);
+                        if p < bookmark {
+                            bookmark -= 1;
+                        }
+                        position = None;
+                    }
+                }
+
+                // "4.13.5. If node is not in the list of active formatting elements, then remove
+                // node from the stack of open elements and continue."
+                let position = match position {
+                    Some(p) => p,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                // "4.13.6. Create an element for the token for which the element node was
+                // created, in the HTML namespace, with commonAncestor as the intended parent;
+                // replace the entry for node in the list of active formatting elements with an
+                // entry for the new element, replace the entry for node in the stack of open
+                // elements with an entry for the new element, and let node be the new element."
+                let node = self.clone_element(node);
+                self.list_of_active_formatting_elements[position] = node;
+                self.stack_of_open_elements[node_index] = node;
+
+                // "4.13.7. If lastNode is furthestBlock, then move the aforementioned bookmark to
+                // be immediately after the new node in the list of active formatting elements."
+                if last_node == furthest_block {
+                    bookmark = position + 1;
+                }
+
+                // "4.13.8. Append lastNode to node."
+                self.append_node(node, last_node);
+
+                // "4.13.9. Set lastNode to node."
+                last_node = node;
+            }
+
+            // "4.14. Insert whatever lastNode ended up being in the appropriate place for
+            // inserting a node, but using commonAncestor as the override target."
+            self.append_node(common_ancestor, last_node);
 
-        if current.borrow().kind == NodeKind::Element(Element::new(element_kind, Vec::new())) {
-            self.stack_of_open_elements.pop();
-            return true;
-        }
+            // "4.15. Create an element for the token for which formattingElement was created, in
+            // the HTML namespace, with furthestBlock as the intended parent."
+            let new_element = self.clone_element(formatting_element);
 
-        false
-    }
+            // "4.16. Take all of the child nodes of furthestBlock and append them to the element
+            // created in the last step."
+            let mut child = self.document.borrow().node(furthest_block).first_child();
+            while let Some(c) = child {
+                child = self.document.borrow().node(c).next_sibling();
+                self.append_node(new_element, c);
+            }
 
-    /// Pops nodes until a node with `element_kind` comes.
-    fn pop_until(&mut self, element_kind: ElementKind) {
-        assert!(self.contain_in_stack(element_kind));
+            // "4.17. Append that new element to furthestBlock."
+            self.append_node(furthest_block, new_element);
 
-        loop {
-            let current = match self.stack_of_open_elements.pop() {
-                Some(n) => n,
-                None => return,
-            };
+            // "4.18. Remove formattingElement from the list of active formatting elements, and
+            // insert the new element into the list of active formatting elements at the position
+            // of the aforementioned bookmark."
+            self.list_of_active_formatting_elements
+                .insert(bookmark, new_element);
+            if let Some(i) = self.position_in_active_formatting_elements(formatting_element) {
+                self.list_of_active_formatting_elements.remove(i);
+            }
 
-            if current.borrow().kind == NodeKind::Element(Element::new(element_kind, Vec::new())) {
-                return;
+            // "4.19. Remove formattingElement from the stack of open elements, and insert the new
+            // element into the stack of open elements immediately below the position of
+            // furthestBlock in that stack."
+            if let Some(i) = self.position_in_stack(formatting_element) {
+                self.stack_of_open_elements.remove(i);
             }
+            let furthest_block_index = self
+                .position_in_stack(furthest_block)
+                .expect("furthest block should be in the stack");
+            self.stack_of_open_elements
+                .insert(furthest_block_index + 1, new_element);
         }
     }
 
-    /// Returns true if the stack of open elements has NodeKind::Element::<element_kind> node.
-    fn contain_in_stack(&mut self, element_kind: ElementKind) -> bool {
-        for i in 0..self.stack_of_open_elements.len() {
-            if self.stack_of_open_elements[i].borrow().kind
-                == NodeKind::Element(Element::new(element_kind, Vec::new()))
-            {
-                return true;
-            }
+    /// Parses `markup` as a fragment in the context of `context` and returns the created nodes.
+    /// The returned nodes don't have a parent yet.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
+    pub fn parse_fragment(
+        document: &Rc<RefCell<Document>>,
+        context: NodeId,
+        markup: String,
+    ) -> Vec<NodeId> {
+        // "4. Set the state of the HTML parser's tokenization stage as follows, switching on the
+        // context element:"
+        // We don't have the RCDATA, RAWTEXT and PLAINTEXT states yet, so only script is handled.
+        let mut t = HtmlTokenizer::new(markup);
+        if document.borrow().element_kind(context) == Some(ElementKind::Script) {
+            t.switch_context(State::ScriptData);
         }
 
-        false
+        // "1. Create a new Document node, and mark it as being an HTML document."
+        // "3. Create a new HTML parser, and associate it with the just created Document node."
+        // "5. Let root be a new html element with no attributes."
+        // "6. Append the element root to the Document node created above."
+        // The parser inserts nodes into the arena of the context element's document, so that the
+        // created nodes can be inserted into the context element as they are. Instead of a new
+        // Document node and a new html element, the document's fragment root is reused, which
+        // has no children and no attributes between fragments.
+        let root = document.borrow_mut().fragment_root();
+        let mut parser = HtmlParser::with_document(t, document.clone(), root);
+        parser.context_element = Some(context);
+
+        // "7. Set up the parser's stack of open elements so that it contains just the single
+        // element root."
+        parser.stack_of_open_elements.push(root);
+
+        // "10. Reset the parser's insertion mode appropriately."
+        parser.reset_insertion_mode_appropriately();
+
+        // "12. Start the parser and let it run until it has consumed all the characters just
+        // inserted into the input stream."
+        parser.construct_tree();
+
+        // "13. Return root's children, in tree order."
+        let mut document = document.borrow_mut();
+        let mut children = Vec::new();
+        let mut child = document.node(root).first_child();
+        while let Some(c) = child {
+            child = document.node(c).next_sibling();
+            document
+                .remove_child(root, c)
+                .expect("root should be the parent");
+            children.push(c);
+        }
+        children
+    }
+
+    /// Runs the parser to the end of the input without executing scripts, as if scripting was
+    /// disabled.
+    pub fn construct_tree(&mut self) -> Rc<RefCell<Document>> {
+        while self.parse_until_script().is_some() {}
+        self.document.clone()
     }
 
-    pub fn construct_tree(&mut self) -> Rc<RefCell<Node>> {
-        let mut token = self.t.next();
+    /// Runs the parser until a script element is ready to be executed, and returns the script
+    /// element. The caller is expected to execute the script and call this method again to
+    /// resume parsing. Returns None when the parser reaches the end of the input.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+    pub fn parse_until_script(&mut self) -> Option<NodeId> {
+        let mut token = match self.pending_token.take() {
+            Some(t) => Some(t),
+            None => self.t.next(),
+        };
 
         while token.is_some() {
             match self.mode {
@@ -388,6 +2310,9 @@ impl HtmlParser {
                 InsertionMode::BeforeHtml => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
+                            // "A character token that is one of U+0009 CHARACTER TABULATION,
+                            // U+000A LINE FEED (LF), U+000C FORM FEED (FF), U+000D CARRIAGE RETURN
+                            // (CR), or U+0020 SPACE: Ignore the token."
                             if c == ' ' || c == '\n' {
                                 token = self.t.next();
                                 continue;
@@ -415,25 +2340,27 @@ impl HtmlParser {
                         }) => {
                             // Any other end tag
                             // Parse error. Ignore the token.
-                            if tag != "head" || tag != "body" || tag != "html" || tag != "br" {
+                            if tag != "head" && tag != "body" && tag != "html" && tag != "br" {
                                 // Ignore the token.
                                 token = self.t.next();
                                 continue;
                             }
                         }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
-                        }
+                        Some(HtmlToken::Eof) | None => {}
                     }
-                    token = self.t.next();
-                    //self.insert_element("html", Vec::new());
-                    //self.mode = InsertionMode::BeforeHead;
+                    // "Anything else: Create an html element whose node document is the Document
+                    // object. Append it to the Document object. Put this element in the stack of
+                    // open elements. Switch the insertion mode to "before head", then reprocess
+                    // the token."
+                    self.insert_element("html", Vec::new());
+                    self.mode = InsertionMode::BeforeHead;
                 } // end of InsertionMode::BeforeHtml
 
                 // https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode
                 InsertionMode::BeforeHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
+                            // Ignore the whitespace.
                             if c == ' ' || c == '\n' {
                                 token = self.t.next();
                                 continue;
@@ -445,20 +2372,39 @@ impl HtmlParser {
                             ref attributes,
                         }) => {
                             if tag == "head" {
-                                self.insert_element(tag, attributes.to_vec());
+                                let head = self.insert_element(tag, attributes.to_vec());
+                                self.head_element = Some(head);
                                 self.mode = InsertionMode::InHead;
                                 token = self.t.next();
                                 continue;
                             }
+                            // "A start tag whose tag name is "html": Process the token using
+                            // the rules for the "in body" insertion mode."
+                            // Note: we don't add the attributes to the existing html element,
+                            // so the token is ignored as in the "in body" insertion mode.
+                            if tag == "html" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                        Some(HtmlToken::EndTag {
+                            ref tag,
+                            self_closing: _,
+                        }) => {
+                            // "Any other end tag: Parse error. Ignore the token."
+                            if tag != "head" && tag != "body" && tag != "html" && tag != "br" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
-                        _ => {}
+                        Some(HtmlToken::Eof) | None => {}
                     }
-                    token = self.t.next();
-                    //self.insert_element("head", Vec::new());
-                    //self.mode = InsertionMode::InHead;
+                    // "Anything else: Insert an HTML element for a "head" start tag token with no
+                    // attributes. Set the head element pointer to the newly created head element.
+                    // Switch the insertion mode to "in head". Reprocess the current token."
+                    let head = self.insert_element("head", Vec::new());
+                    self.head_element = Some(head);
+                    self.mode = InsertionMode::InHead;
                 } // end of InsertionMode::BeforeHead
 
                 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
@@ -483,13 +2429,47 @@ impl HtmlParser {
                                 token = self.t.next();
                                 continue;
                             }
+                            // https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+                            if tag == "title" {
+                                // Note: we don't support the RCDATA state, so the text in a title
+                                // element is tokenized in the data state like the text in a style
+                                // element.
+                                self.insert_element(tag, attributes.to_vec());
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::Text;
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "link" || tag == "meta" {
+                                // "Insert an HTML element for the token. Immediately pop the
+                                // current node off the stack of open elements."
+                                self.insert_element(tag, attributes.to_vec());
+                                self.stack_of_open_elements.pop();
+                                token = self.t.next();
+                                continue;
+                            }
+                            // https://html.spec.whatwg.org/multipage/parsing.html#scriptTag
                             if tag == "script" {
+                                // "Insert the newly created element at the adjusted insertion
+                                // location."
                                 self.insert_element(tag, attributes.to_vec());
+                                // "Switch the tokenizer to the script data state."
+                                self.t.switch_context(State::ScriptData);
+                                // "Let the original insertion mode be the current insertion mode."
                                 self.original_insertion_mode = self.mode;
+                                // "Switch the insertion mode to "text"."
                                 self.mode = InsertionMode::Text;
                                 token = self.t.next();
                                 continue;
                             }
+                            // "A start tag whose tag name is "head": Parse error. Ignore the
+                            // token."
+                            // The html start tag is ignored as in the "before head" insertion
+                            // mode.
+                            if tag == "head" || tag == "html" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
                         Some(HtmlToken::EndTag {
                             ref tag,
@@ -501,14 +2481,19 @@ impl HtmlParser {
                                 self.pop_until(ElementKind::Head);
                                 continue;
                             }
+                            // "Any other end tag: Parse error. Ignore the token."
+                            if tag != "body" && tag != "html" && tag != "br" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
-                        }
+                        Some(HtmlToken::Eof) | None => {}
                     }
-                    token = self.t.next();
-                    //self.mode = InsertionMode::AfterHead;
-                    //self.pop_until(ElementKind::Head);
+                    // "Anything else: Pop the current node (which will be the head element) off
+                    // the stack of open elements. Switch the insertion mode to "after head".
+                    // Reprocess the token."
+                    self.mode = InsertionMode::AfterHead;
+                    self.pop_until(ElementKind::Head);
                 } // end of InsertionMode::InHead
 
                 // https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode
@@ -532,15 +2517,51 @@ impl HtmlParser {
                                 self.mode = InsertionMode::InBody;
                                 continue;
                             }
+                            // "A start tag whose tag name is one of: "base", "basefont",
+                            // "bgsound", "link", "meta", "noframes", "script", "style",
+                            // "template", "title": Parse error. Push the node pointed to by the
+                            // head element pointer onto the stack of open elements. Process the
+                            // token using the rules for the "in head" insertion mode. Remove the
+                            // node pointed to by the head element pointer from the stack of open
+                            // elements."
+                            // Note: the head element is removed from the stack when the "in head"
+                            // insertion mode processes a token that doesn't belong to head.
+                            if tag == "link"
+                                || tag == "meta"
+                                || tag == "script"
+                                || tag == "style"
+                                || tag == "title"
+                            {
+                                if let Some(head) = self.head_element {
+                                    self.stack_of_open_elements.push(head);
+                                    self.mode = InsertionMode::InHead;
+                                    continue;
+                                }
+                            }
+                            // "A start tag whose tag name is "head": Parse error. Ignore the
+                            // token."
+                            if tag == "head" || tag == "html" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                        Some(HtmlToken::EndTag {
+                            ref tag,
+                            self_closing: _,
+                        }) => {
+                            // "Any other end tag: Parse error. Ignore the token."
+                            if tag != "body" && tag != "html" && tag != "br" {
+                                token = self.t.next();
+                                continue;
+                            }
                         }
-                        _ => {}
+                        Some(HtmlToken::Eof) | None => {}
                     }
-                    token = self.t.next();
-                    //self.insert_element("body", Vec::new());
-                    //self.mode = InsertionMode::InBody;
+                    // "Anything else: Insert an HTML element for a "body" start tag token with no
+                    // attributes. Switch the insertion mode to "in body". Reprocess the current
+                    // token."
+                    self.insert_element("body", Vec::new());
+                    self.mode = InsertionMode::InBody;
                 } // end of InsertionMode::AfterHead
 
                 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
@@ -552,123 +2573,394 @@ impl HtmlParser {
                             ref attributes,
                         }) => {
                             // "Process the token using the rules for the "in head" insertion mode."
+                            // The original insertion mode has to be "in body" so that the
+                            // parser comes back here after the end tag.
+                            if tag == "style" {
+                                self.insert_element(tag, attributes.to_vec());
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::Text;
+                                token = self.t.next();
+                                continue;
+                            }
                             if tag == "script" {
-                                // https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
-                                // "script
-                                // Switch the tokenizer to the script data state."
+                                self.insert_element(tag, attributes.to_vec());
                                 self.t.switch_context(State::ScriptData);
-
-                                self.mode = InsertionMode::InHead;
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::Text;
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "title" {
+                                self.insert_element(tag, attributes.to_vec());
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::Text;
+                                token = self.t.next();
                                 continue;
                             }
-                            if tag == "ul" {
+                            if tag == "link" || tag == "meta" {
                                 self.insert_element(tag, attributes.to_vec());
+                                self.stack_of_open_elements.pop();
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "li" {
+                            if tag == "div"
+                                || tag == "dl"
+                                || tag == "ol"
+                                || tag == "p"
+                                || tag == "table"
+                                || tag == "ul"
+                            {
+                                // "If the stack of open elements has a p element in button scope,
+                                // then close a p element."
+                                // Note: we don't support the "in table" insertion mode yet, so a
+                                // table element is treated like other block elements.
+                                self.close_p_element_in_button_scope();
+                                self.insert_element(tag, attributes.to_vec());
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "h1"
+                                || tag == "h2"
+                                || tag == "h3"
+                                || tag == "h4"
+                                || tag == "h5"
+                                || tag == "h6"
+                            {
+                                self.close_p_element_in_button_scope();
+                                // "If the current node is an HTML element whose tag name is one of
+                                // "h1", "h2", "h3", "h4", "h5", or "h6", then this is a parse
+                                // error; pop the current node off the stack of open elements."
+                                if let Some(kind) = self.element_kind(self.current_node()) {
+                                    if Self::is_heading(kind) {
+                                        self.stack_of_open_elements.pop();
+                                    }
+                                }
                                 self.insert_element(tag, attributes.to_vec());
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "div" {
+                            if tag == "li" || tag == "dd" || tag == "dt" {
+                                // "1. Set the frameset-ok flag to "not ok"."
+                                // "2. Initialize node to be the current node (the bottommost node
+                                // of the stack)."
+                                // "3. Loop: If node is an li element, then run these substeps:
+                                // generate implied end tags, except for li elements. Pop elements
+                                // from the stack of open elements until an li element has been
+                                // popped from the stack. Jump to the step labeled done below."
+                                // For "dd" and "dt", both of dd and dt elements are closed.
+                                let kind = Element::str_to_element_kind(tag);
+                                let mut i = self.stack_of_open_elements.len();
+                                while i > 0 {
+                                    i -= 1;
+                                    let node_kind =
+                                        match self.element_kind(self.stack_of_open_elements[i]) {
+                                            Some(k) => k,
+                                            None => continue,
+                                        };
+
+                                    let closing = if kind == ElementKind::Li {
+                                        node_kind == ElementKind::Li
+                                    } else {
+                                        node_kind == ElementKind::Dd || node_kind == ElementKind::Dt
+                                    };
+                                    if closing {
+                                        self.generate_implied_end_tags(Some(node_kind));
+                                        self.pop_until(node_kind);
+                                        break;
+                                    }
+
+                                    // "4. If node is in the special category, but is not an
+                                    // address, div, or p element, then jump to the step labeled
+                                    // done below."
+                                    if Self::is_special(node_kind)
+                                        && node_kind != ElementKind::Div
+                                        && node_kind != ElementKind::P
+                                    {
+                                        break;
+                                    }
+
+                                    // "5. Otherwise, set node to the previous entry in the stack
+                                    // of open elements and return to the step labeled loop."
+                                }
+
+                                // "6. Done: If the stack of open elements has a p element in
+                                // button scope, then close a p element."
+                                self.close_p_element_in_button_scope();
+                                // "7. Finally, insert an HTML element for the token."
                                 self.insert_element(tag, attributes.to_vec());
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "h1" {
+                            if tag == "button" {
+                                // "If the stack of open elements has a button element in scope,
+                                // then run these substeps: parse error. Generate implied end tags.
+                                // Pop elements from the stack of open elements until a button
+                                // element has been popped from the stack."
+                                if self.has_element_in_scope(ElementKind::Button) {
+                                    self.generate_implied_end_tags(None);
+                                    self.pop_until(ElementKind::Button);
+                                }
+                                self.reconstruct_active_formatting_elements();
                                 self.insert_element(tag, attributes.to_vec());
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "h2" {
+                            if tag == "select" {
+                                self.reconstruct_active_formatting_elements();
                                 self.insert_element(tag, attributes.to_vec());
+                                self.mode = InsertionMode::InSelect;
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "p" {
+                            if tag == "optgroup" || tag == "option" {
+                                // "If the current node is an option element, then pop the current
+                                // node off the stack of open elements."
+                                if self.is_current_node(ElementKind::Option) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                self.reconstruct_active_formatting_elements();
                                 self.insert_element(tag, attributes.to_vec());
                                 token = self.t.next();
                                 continue;
                             }
                             if tag == "a" {
+                                // "If the list of active formatting elements contains an a
+                                // element between the end of the list and the last marker on the
+                                // list (or the start of the list if there is no marker on the
+                                // list), then this is a parse error; run the adoption agency
+                                // algorithm for the token, then remove that element from the list
+                                // of active formatting elements and the stack of open elements if
+                                // the adoption agency algorithm didn't already remove it."
+                                if let Some(a) = self.active_formatting_element(ElementKind::A) {
+                                    self.adoption_agency_algorithm("a");
+                                    if let Some(i) = self.position_in_active_formatting_elements(a)
+                                    {
+                                        self.list_of_active_formatting_elements.remove(i);
+                                    }
+                                    if let Some(i) = self.position_in_stack(a) {
+                                        self.stack_of_open_elements.remove(i);
+                                    }
+                                }
+                                self.reconstruct_active_formatting_elements();
+                                let node = self.insert_element(tag, attributes.to_vec());
+                                self.push_active_formatting_element(node);
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "b"
+                                || tag == "big"
+                                || tag == "code"
+                                || tag == "em"
+                                || tag == "font"
+                                || tag == "i"
+                                || tag == "s"
+                                || tag == "small"
+                                || tag == "strike"
+                                || tag == "strong"
+                                || tag == "tt"
+                                || tag == "u"
+                            {
+                                self.reconstruct_active_formatting_elements();
+                                let node = self.insert_element(tag, attributes.to_vec());
+                                self.push_active_formatting_element(node);
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "nobr" {
+                                // "If the stack of open elements has a nobr element in scope,
+                                // then this is a parse error; run the adoption agency algorithm
+                                // for the token, then once again reconstruct the active
+                                // formatting elements, if any."
+                                self.reconstruct_active_formatting_elements();
+                                if self.has_element_in_scope(ElementKind::Nobr) {
+                                    self.adoption_agency_algorithm("nobr");
+                                    self.reconstruct_active_formatting_elements();
+                                }
+                                let node = self.insert_element(tag, attributes.to_vec());
+                                self.push_active_formatting_element(node);
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "br" || tag == "img" {
+                                // "Reconstruct the active formatting elements, if any. Insert an
+                                // HTML element for the token. Immediately pop the current node
+                                // off the stack of open elements."
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_element(tag, attributes.to_vec());
+                                self.stack_of_open_elements.pop();
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "hr" {
+                                // "If the stack of open elements has a p element in button scope,
+                                // then close a p element. Insert an HTML element for the token.
+                                // Immediately pop the current node off the stack of open
+                                // elements."
+                                self.close_p_element_in_button_scope();
                                 self.insert_element(tag, attributes.to_vec());
+                                self.stack_of_open_elements.pop();
                                 token = self.t.next();
                                 continue;
                             }
-                            println!("warning: unknown tag {:?}", tag);
+                            // "Any other start tag"
+                            // "Reconstruct the active formatting elements, if any. Insert an HTML
+                            // element for the token."
+                            self.reconstruct_active_formatting_elements();
+                            self.insert_element(tag, attributes.to_vec());
                             token = self.t.next();
                         }
                         Some(HtmlToken::EndTag {
                             ref tag,
                             self_closing: _,
                         }) => {
+                            if tag == "br" {
+                                // "Parse error. Drop the attributes from the token, and act as
+                                // described in the next entry; i.e. act as if this was a "br"
+                                // start tag token with no attributes, rather than the end tag
+                                // token that it actually is."
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_element(tag, Vec::new());
+                                self.stack_of_open_elements.pop();
+                                token = self.t.next();
+                                continue;
+                            }
                             if tag == "body" {
-                                self.mode = InsertionMode::AfterBody;
                                 token = self.t.next();
-                                if !self.contain_in_stack(ElementKind::Body) {
+                                if !self.has_element_in_scope(ElementKind::Body) {
                                     // Parse error. Ignore the token.
                                     continue;
                                 }
-                                self.pop_until(ElementKind::Body);
+                                // "Switch the insertion mode to "after body"."
+                                // The body and html elements stay in the stack of open elements
+                                // so that content after </body> is still inserted into body.
+                                self.mode = InsertionMode::AfterBody;
                                 continue;
                             }
                             if tag == "html" {
                                 // If the stack of open elements does not have a body element in
                                 // scope, this is a parse error; ignore the token.
-                                if self.pop_current_node(ElementKind::Body) {
-                                    self.mode = InsertionMode::AfterBody;
-                                    assert!(self.pop_current_node(ElementKind::Html));
-                                } else {
+                                if !self.has_element_in_scope(ElementKind::Body) {
                                     token = self.t.next();
+                                    continue;
                                 }
+                                // "Switch the insertion mode to "after body". Reprocess the
+                                // token."
+                                self.mode = InsertionMode::AfterBody;
                                 continue;
                             }
-                            if tag == "ul" {
-                                token = self.t.next();
-                                self.pop_until(ElementKind::Ul);
-                                continue;
-                            }
-                            if tag == "li" {
+                            if tag == "button"
+                                || tag == "div"
+                                || tag == "dl"
+                                || tag == "ol"
+                                || tag == "table"
+                                || tag == "ul"
+                            {
+                                // "If the stack of open elements does not have an element in
+                                // scope that is an HTML element with the same tag name as that of
+                                // the token, then this is a parse error; ignore the token."
+                                // "Otherwise: generate implied end tags. Pop elements from the
+                                // stack of open elements until an HTML element with the same tag
+                                // name as the token has been popped from the stack."
+                                let kind = Element::str_to_element_kind(tag);
                                 token = self.t.next();
-                                self.pop_until(ElementKind::Li);
+                                if self.has_element_in_scope(kind) {
+                                    self.generate_implied_end_tags(None);
+                                    self.pop_until(kind);
+                                }
                                 continue;
                             }
-                            if tag == "div" {
+                            if tag == "p" {
+                                // "If the stack of open elements does not have a p element in
+                                // button scope, then this is a parse error; insert an HTML element
+                                // for a "p" start tag token with no attributes."
+                                if !self.has_element_in_button_scope(ElementKind::P) {
+                                    self.insert_element(tag, Vec::new());
+                                }
+                                // "Close a p element."
+                                self.close_p_element();
                                 token = self.t.next();
-                                self.pop_until(ElementKind::Div);
                                 continue;
                             }
-                            if tag == "h1" {
+                            if tag == "li" {
+                                // "If the stack of open elements does not have an li element in
+                                // list item scope, then this is a parse error; ignore the token."
                                 token = self.t.next();
-                                self.pop_until(ElementKind::H1);
+                                if self.has_element_in_list_item_scope(ElementKind::Li) {
+                                    self.generate_implied_end_tags(Some(ElementKind::Li));
+                                    self.pop_until(ElementKind::Li);
+                                }
                                 continue;
                             }
-                            if tag == "h2" {
+                            if tag == "dd" || tag == "dt" {
+                                let kind = Element::str_to_element_kind(tag);
                                 token = self.t.next();
-                                self.pop_until(ElementKind::H2);
+                                if self.has_element_in_scope(kind) {
+                                    self.generate_implied_end_tags(Some(kind));
+                                    self.pop_until(kind);
+                                }
                                 continue;
                             }
-                            if tag == "p" {
+                            if tag == "h1"
+                                || tag == "h2"
+                                || tag == "h3"
+                                || tag == "h4"
+                                || tag == "h5"
+                                || tag == "h6"
+                            {
+                                // "If the stack of open elements does not have an element in
+                                // scope that is an HTML element and whose tag name is one of
+                                // "h1", "h2", "h3", "h4", "h5", or "h6", then this is a parse
+                                // error; ignore the token."
                                 token = self.t.next();
-                                self.pop_until(ElementKind::P);
+                                if !self.has_heading_in_scope() {
+                                    continue;
+                                }
+                                self.generate_implied_end_tags(None);
+                                // "Pop elements from the stack of open elements until an HTML
+                                // element whose tag name is one of "h1", "h2", "h3", "h4", "h5",
+                                // or "h6" has been popped from the stack."
+                                while let Some(node) = self.stack_of_open_elements.pop() {
+                                    if let Some(kind) = self.element_kind(node) {
+                                        if Self::is_heading(kind) {
+                                            break;
+                                        }
+                                    }
+                                }
                                 continue;
                             }
-                            if tag == "a" {
+                            if tag == "a"
+                                || tag == "b"
+                                || tag == "big"
+                                || tag == "code"
+                                || tag == "em"
+                                || tag == "font"
+                                || tag == "i"
+                                || tag == "nobr"
+                                || tag == "s"
+                                || tag == "small"
+                                || tag == "strike"
+                                || tag == "strong"
+                                || tag == "tt"
+                                || tag == "u"
+                            {
+                                // "Run the adoption agency algorithm for the token."
+                                self.adoption_agency_algorithm(tag);
                                 token = self.t.next();
-                                self.pop_until(ElementKind::A);
                                 continue;
                             }
-                            println!("warning: unknown tag {:?}", tag);
+                            // "Any other end tag"
+                            self.any_other_end_tag(tag);
                             token = self.t.next();
                         }
                         Some(HtmlToken::Char(c)) => {
+                            // "Reconstruct the active formatting elements, if any."
+                            self.reconstruct_active_formatting_elements();
                             self.insert_char(c);
                             token = self.t.next();
                             continue;
                         }
                         Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                            return None;
                         }
                     }
                 } // end of InsertionMode::InBody
@@ -677,7 +2969,7 @@ impl HtmlParser {
                 InsertionMode::Text => {
                     match token {
                         Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                            return None;
                         }
                         Some(HtmlToken::EndTag {
                             ref tag,
@@ -689,12 +2981,26 @@ impl HtmlParser {
                                 token = self.t.next();
                                 continue;
                             }
-                            if tag == "script" {
-                                self.pop_until(ElementKind::Script);
+                            if tag == "title" {
+                                self.pop_until(ElementKind::Title);
                                 self.mode = self.original_insertion_mode;
                                 token = self.t.next();
                                 continue;
                             }
+                            // https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+                            if tag == "script" {
+                                // "Let script be the current node (which will be a script
+                                // element)."
+                                let script = self.current_node();
+                                // "Pop the current node off the stack of open elements."
+                                self.pop_until(ElementKind::Script);
+                                // "Switch the insertion mode to the original insertion mode."
+                                self.mode = self.original_insertion_mode;
+                                // The parser pauses here and the caller executes the script.
+                                // Parsing resumes from the next token.
+                                self.pending_token = self.t.next();
+                                return Some(script);
+                            }
                         }
                         Some(HtmlToken::Char(c)) => {
                             self.insert_char(c);
@@ -707,14 +3013,136 @@ impl HtmlParser {
                     self.mode = self.original_insertion_mode;
                 } // end of InsertionMode::Text
 
+                // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
+                InsertionMode::InSelect => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            self.insert_char(c);
+                            token = self.t.next();
+                            continue;
+                        }
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing: _,
+                            ref attributes,
+                        }) => {
+                            if tag == "option" {
+                                // "If the current node is an option element, pop that node from
+                                // the stack of open elements."
+                                if self.is_current_node(ElementKind::Option) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                self.insert_element(tag, attributes.to_vec());
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "optgroup" {
+                                // "If the current node is an option element, pop that node from
+                                // the stack of open elements."
+                                // "If the current node is an optgroup element, pop that node from
+                                // the stack of open elements."
+                                if self.is_current_node(ElementKind::Option) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                if self.is_current_node(ElementKind::Optgroup) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                self.insert_element(tag, attributes.to_vec());
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "select" {
+                                // "Parse error. If the stack of open elements does not have a
+                                // select element in select scope, ignore the token. Otherwise:
+                                // Pop elements from the stack of open elements until a select
+                                // element has been popped from the stack. Reset the insertion mode
+                                // appropriately."
+                                token = self.t.next();
+                                if self.has_element_in_select_scope(ElementKind::Select) {
+                                    self.pop_until(ElementKind::Select);
+                                    self.reset_insertion_mode_appropriately();
+                                }
+                                continue;
+                            }
+                            if tag == "script" {
+                                // "Process the token using the rules for the "in head" insertion
+                                // mode."
+                                self.t.switch_context(State::ScriptData);
+                                self.insert_element(tag, attributes.to_vec());
+                                self.original_insertion_mode = self.mode;
+                                self.mode = InsertionMode::Text;
+                                token = self.t.next();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::EndTag {
+                            ref tag,
+                            self_closing: _,
+                        }) => {
+                            if tag == "optgroup" {
+                                // "First, if the current node is an option element, and the node
+                                // immediately before it in the stack of open elements is an
+                                // optgroup element, then pop the current node from the stack of
+                                // open elements."
+                                let len = self.stack_of_open_elements.len();
+                                if self.is_current_node(ElementKind::Option)
+                                    && len >= 2
+                                    && self.element_kind(self.stack_of_open_elements[len - 2])
+                                        == Some(ElementKind::Optgroup)
+                                {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                // "If the current node is an optgroup element, then pop that node
+                                // from the stack of open elements. Otherwise, this is a parse
+                                // error; ignore the token."
+                                if self.is_current_node(ElementKind::Optgroup) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "option" {
+                                if self.is_current_node(ElementKind::Option) {
+                                    self.stack_of_open_elements.pop();
+                                }
+                                token = self.t.next();
+                                continue;
+                            }
+                            if tag == "select" {
+                                token = self.t.next();
+                                if self.has_element_in_select_scope(ElementKind::Select) {
+                                    self.pop_until(ElementKind::Select);
+                                    self.reset_insertion_mode_appropriately();
+                                }
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return None;
+                        }
+                    }
+
+                    // "Anything else: Parse error. Ignore the token."
+                    token = self.t.next();
+                } // end of InsertionMode::InSelect
+
                 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody
                 InsertionMode::AfterBody => {
                     match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            // Not align with the spec.
-                            // TODO: Process the token using the rules for the "in body" insertion
-                            // mode.
-                            token = self.t.next();
+                        Some(HtmlToken::Char(c)) => {
+                            // "A character token that is one of U+0009 CHARACTER TABULATION,
+                            // U+000A LINE FEED (LF), U+000C FORM FEED (FF), U+000D CARRIAGE RETURN
+                            // (CR), or U+0020 SPACE: Process the token using the rules for the
+                            // "in body" insertion mode."
+                            if c == ' ' || c == '\n' {
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_char(c);
+                                token = self.t.next();
+                                continue;
+                            }
+                            // "Anything else: Parse error. Switch the insertion mode to "in body"
+                            // and reprocess the token."
+                            self.mode = InsertionMode::InBody;
                             continue;
                         }
                         Some(HtmlToken::EndTag {
@@ -728,7 +3156,7 @@ impl HtmlParser {
                             }
                         }
                         Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                            return None;
                         }
                         _ => {}
                     }
@@ -739,11 +3167,20 @@ impl HtmlParser {
                 // https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode
                 InsertionMode::AfterAfterBody => {
                     match token {
-                        Some(HtmlToken::Char(_c)) => {
-                            // Not align with the spec.
-                            // TODO: Process the token using the rules for the "in body" insertion
-                            // mode.
-                            token = self.t.next();
+                        Some(HtmlToken::Char(c)) => {
+                            // "A character token that is one of U+0009 CHARACTER TABULATION,
+                            // U+000A LINE FEED (LF), U+000C FORM FEED (FF), U+000D CARRIAGE RETURN
+                            // (CR), or U+0020 SPACE: Process the token using the rules for the
+                            // "in body" insertion mode."
+                            if c == ' ' || c == '\n' {
+                                self.reconstruct_active_formatting_elements();
+                                self.insert_char(c);
+                                token = self.t.next();
+                                continue;
+                            }
+                            // "Anything else: Parse error. Switch the insertion mode to "in body"
+                            // and reprocess the token."
+                            self.mode = InsertionMode::InBody;
                             continue;
                         }
                         Some(HtmlToken::EndTag {
@@ -757,7 +3194,7 @@ impl HtmlParser {
                             }
                         }
                         Some(HtmlToken::Eof) | None => {
-                            return self.root.clone();
+                            return None;
                         }
                         _ => {}
                     }
@@ -767,29 +3204,42 @@ impl HtmlParser {
             } // end of match self.mode {}
         } // end of while token.is_some {}
 
-        self.root.clone()
+        // The tokenizer doesn't emit an end-of-file token if the input ends with a tag. The html,
+        // head and body elements that are implied at the end of the input are inserted by
+        // processing an end-of-file token here.
+        if matches!(
+            self.mode,
+            InsertionMode::Initial
+                | InsertionMode::BeforeHtml
+                | InsertionMode::BeforeHead
+                | InsertionMode::InHead
+                | InsertionMode::AfterHead
+        ) {
+            self.pending_token = Some(HtmlToken::Eof);
+            return self.parse_until_script();
+        }
+
+        None
     }
 }
 
 pub fn get_element_by_id(
-    node: Option<Rc<RefCell<Node>>>,
+    document: &Document,
+    node: Option<NodeId>,
     id_name: &String,
-) -> Option<Rc<RefCell<Node>>> {
+) -> Option<NodeId> {
     match node {
         Some(n) => {
-            match n.borrow().kind() {
-                NodeKind::Element(e) => {
-                    for attr in &e.attributes() {
-                        if attr.name == "id" && attr.value == *id_name {
-                            return Some(n.clone());
-                        }
+            if let NodeKind::Element(e) = document.node(n).kind() {
+                for attr in &e.attributes() {
+                    if attr.name == "id" && attr.value == *id_name {
+                        return Some(n);
                     }
                 }
-                _ => {}
             }
 
-            let result1 = get_element_by_id(n.borrow().first_child(), id_name);
-            let result2 = get_element_by_id(n.borrow().next_sibling(), id_name);
+            let result1 = get_element_by_id(document, document.node(n).first_child(), id_name);
+            let result2 = get_element_by_id(document, document.node(n).next_sibling(), id_name);
             if result1.is_none() && result2.is_none() {
                 return None;
             }
@@ -803,57 +3253,66 @@ pub fn get_element_by_id(
     }
 }
 
-fn get_target_element_node(
-    node: Option<Rc<RefCell<Node>>>,
-    element_kind: ElementKind,
-) -> Option<Rc<RefCell<Node>>> {
-    match node {
-        Some(n) => {
-            if n.borrow().kind == NodeKind::Element(Element::new(element_kind, Vec::new())) {
-                return Some(n.clone());
-            }
-            let result1 = get_target_element_node(n.borrow().first_child(), element_kind);
-            let result2 = get_target_element_node(n.borrow().next_sibling(), element_kind);
-            if result1.is_none() && result2.is_none() {
-                return None;
-            }
-            if result1.is_none() {
-                return result2;
-            }
-            return result1;
+/// Returns the concatenation of the data of all the Text node children of `node`, in tree order.
+/// https://dom.spec.whatwg.org/#concept-child-text-content
+pub fn child_text_content(document: &Document, node: NodeId) -> String {
+    let mut content = String::new();
+    let mut child = document.node(node).first_child();
+    while let Some(c) = child {
+        if let NodeKind::Text(ref s) = document.node(c).kind {
+            content.push_str(s);
         }
-        None => return None,
+        child = document.node(c).next_sibling();
     }
+    content
 }
 
-pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
-    let style_node = match get_target_element_node(Some(root), ElementKind::Style) {
-        Some(node) => node,
-        None => return "".to_string(),
-    };
-    let text_node = match style_node.borrow().first_child() {
-        Some(node) => node,
-        None => return "".to_string(),
-    };
-    let content = match &text_node.borrow().kind {
-        NodeKind::Text(ref s) => s.clone(),
-        _ => "".to_string(),
-    };
-    content
+/// Where the contents of a style sheet come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StyleSheetSource {
+    /// The child text content of a style element.
+    /// https://html.spec.whatwg.org/multipage/semantics.html#the-style-element
+    Inline(String),
+    /// The URL in the href attribute of a link element whose rel attribute has the
+    /// "stylesheet" keyword. The URL may be relative to the document URL.
+    /// https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+    External(String),
 }
 
-pub fn get_js_content(root: Rc<RefCell<Node>>) -> String {
-    let js_node = match get_target_element_node(Some(root), ElementKind::Script) {
-        Some(node) => node,
-        None => return "".to_string(),
-    };
-    let text_node = match js_node.borrow().first_child() {
-        Some(node) => node,
-        None => return "".to_string(),
-    };
-    let content = match &text_node.borrow().kind {
-        NodeKind::Text(ref s) => s.clone(),
-        _ => "".to_string(),
-    };
-    content
+/// Returns the sources of the style sheets created by style and link elements in tree order, so
+/// that rules in a later style sheet come after rules in an earlier one.
+/// https://drafts.csswg.org/cssom/#documentorshadowroot-document-or-shadow-root-css-style-sheets
+pub fn get_style_sheet_sources(document: &Document) -> Vec<StyleSheetSource> {
+    let mut sources = Vec::new();
+    let elements = Document::get_elements_by_tag_name(document.root(), "*");
+    for node in elements.elements(document) {
+        let element = match document.node(node).element() {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if element.kind() == ElementKind::Style {
+            sources.push(StyleSheetSource::Inline(child_text_content(document, node)));
+            continue;
+        }
+
+        if element.kind() == ElementKind::Link {
+            // "The rel attribute's value must be a set of space-separated tokens", and the
+            // keywords are ASCII case-insensitive.
+            // https://html.spec.whatwg.org/multipage/links.html#linkTypes
+            let is_stylesheet = element
+                .get_attribute("rel")
+                .unwrap_or_default()
+                .split_ascii_whitespace()
+                .any(|t| t.eq_ignore_ascii_case("stylesheet"));
+            // "If the element has no href attribute, or if that attribute's value is the empty
+            // string, then ... return."
+            // https://html.spec.whatwg.org/multipage/semantics.html#concept-link-obtain
+            let href = element.get_attribute("href").unwrap_or_default();
+            if is_stylesheet && !href.is_empty() {
+                sources.push(StyleSheetSource::External(href));
+            }
+        }
+    }
+    sources
 }
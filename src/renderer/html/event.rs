@@ -0,0 +1,365 @@
+//! https://dom.spec.whatwg.org/#events
+
+use crate::renderer::html::dom::{Document, DomException, NodeId};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::string::{String, ToString};
+
+/// https://dom.spec.whatwg.org/#dom-event-eventphase
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventPhase {
+    None = 0,
+    CapturingPhase = 1,
+    AtTarget = 2,
+    BubblingPhase = 3,
+}
+
+/// https://dom.spec.whatwg.org/#interface-event
+#[derive(Debug, Clone)]
+pub struct Event {
+    event_type: String,
+    bubbles: bool,
+    cancelable: bool,
+    target: Option<NodeId>,
+    current_target: Option<NodeId>,
+    event_phase: EventPhase,
+    /// https://dom.spec.whatwg.org/#stop-propagation-flag
+    stop_propagation_flag: bool,
+    /// https://dom.spec.whatwg.org/#stop-immediate-propagation-flag
+    stop_immediate_propagation_flag: bool,
+    /// https://dom.spec.whatwg.org/#canceled-flag
+    canceled_flag: bool,
+    /// https://dom.spec.whatwg.org/#initialized-flag
+    initialized_flag: bool,
+    /// https://dom.spec.whatwg.org/#dispatch-flag
+    dispatch_flag: bool,
+}
+
+impl Event {
+    /// https://dom.spec.whatwg.org/#dom-event-event
+    pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+        let mut event = Self::create();
+        event.initialize(event_type, bubbles, cancelable);
+        event
+    }
+
+    /// Creates an event whose initialized flag is unset. `initEvent()` has to be called before
+    /// the event is dispatched.
+    /// https://dom.spec.whatwg.org/#dom-document-createevent
+    pub fn create() -> Self {
+        Self {
+            event_type: String::new(),
+            bubbles: false,
+            cancelable: false,
+            target: None,
+            current_target: None,
+            event_phase: EventPhase::None,
+            stop_propagation_flag: false,
+            stop_immediate_propagation_flag: false,
+            canceled_flag: false,
+            initialized_flag: false,
+            dispatch_flag: false,
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-event-initialize
+    fn initialize(&mut self, event_type: &str, bubbles: bool, cancelable: bool) {
+        // "1. Set event's initialized flag."
+        self.initialized_flag = true;
+        // "2. Unset event's stop propagation flag, stop immediate propagation flag, and canceled
+        // flag."
+        self.stop_propagation_flag = false;
+        self.stop_immediate_propagation_flag = false;
+        self.canceled_flag = false;
+        // "4. Set event's target to null."
+        self.target = None;
+        // "5. Set event's type attribute to type."
+        self.event_type = event_type.to_string();
+        // "6. Set event's bubbles attribute to bubbles."
+        self.bubbles = bubbles;
+        // "7. Set event's cancelable attribute to cancelable."
+        self.cancelable = cancelable;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-initevent
+    pub fn init_event(&mut self, event_type: &str, bubbles: bool, cancelable: bool) {
+        // "1. If this's dispatch flag is set, then return."
+        if self.dispatch_flag {
+            return;
+        }
+        // "2. Initialize this with type, bubbles, and cancelable."
+        self.initialize(event_type, bubbles, cancelable);
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-type
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-bubbles
+    pub fn bubbles(&self) -> bool {
+        self.bubbles
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-cancelable
+    pub fn cancelable(&self) -> bool {
+        self.cancelable
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-target
+    pub fn target(&self) -> Option<NodeId> {
+        self.target
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-currenttarget
+    pub fn current_target(&self) -> Option<NodeId> {
+        self.current_target
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-eventphase
+    pub fn event_phase(&self) -> EventPhase {
+        self.event_phase
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-defaultprevented
+    pub fn default_prevented(&self) -> bool {
+        // "The defaultPrevented getter steps are to return true if this's canceled flag is set;
+        // otherwise false."
+        self.canceled_flag
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-stoppropagation
+    pub fn stop_propagation(&mut self) {
+        // "The stopPropagation() method steps are to set this's stop propagation flag."
+        self.stop_propagation_flag = true;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-stopimmediatepropagation
+    pub fn stop_immediate_propagation(&mut self) {
+        // "The stopImmediatePropagation() method steps are to set this's stop propagation flag
+        // and this's stop immediate propagation flag."
+        self.stop_propagation_flag = true;
+        self.stop_immediate_propagation_flag = true;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-preventdefault
+    pub fn prevent_default(&mut self) {
+        // https://dom.spec.whatwg.org/#set-the-canceled-flag
+        // "To set the canceled flag, given an event event, if event's cancelable attribute value
+        // is true and event's in passive listener flag is unset, then set event's canceled flag,
+        // and do nothing otherwise."
+        // Note: we don't support passive listeners.
+        if self.cancelable {
+            self.canceled_flag = true;
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#callbackdef-eventlistener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventListenerCallback {
+    /// A function declared in JavaScript, identified by its name.
+    Function(String),
+    /// The event handler processing algorithm of an event handler such as "onclick". The handler
+    /// is compiled from the content attribute of the same name when the event fires.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#the-event-handler-processing-algorithm
+    EventHandler(String),
+}
+
+/// https://dom.spec.whatwg.org/#concept-event-listener
+#[derive(Debug, Clone)]
+pub struct EventListener {
+    event_type: String,
+    callback: EventListenerCallback,
+    capture: bool,
+    once: bool,
+    /// The removed flag is shared with the clones of this listener, so that a listener removed
+    /// while an event is being dispatched is not invoked.
+    removed: Rc<Cell<bool>>,
+}
+
+impl EventListener {
+    pub fn new(
+        event_type: &str,
+        callback: EventListenerCallback,
+        capture: bool,
+        once: bool,
+    ) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            callback,
+            capture,
+            once,
+            removed: Rc::new(Cell::new(false)),
+        }
+    }
+
+    pub fn callback(&self) -> EventListenerCallback {
+        self.callback.clone()
+    }
+
+    /// Returns true if this listener has the same type, callback and capture as `other`. An
+    /// event target never holds two such listeners.
+    pub fn is_same(&self, other: &EventListener) -> bool {
+        self.event_type == other.event_type
+            && self.callback == other.callback
+            && self.capture == other.capture
+    }
+
+    /// Sets the removed flag. The caller is responsible for removing this listener from the
+    /// event listener list.
+    pub fn set_removed(&self) {
+        self.removed.set(true);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum InvokePhase {
+    Capturing,
+    Bubbling,
+}
+
+/// Dispatches `event` to `target`. `call` is called with each listener to invoke, and is
+/// responsible for running its callback. The document is borrowed only between the calls, so
+/// that listeners can change the tree. Returns false if the event was canceled.
+/// https://dom.spec.whatwg.org/#concept-event-dispatch
+pub fn dispatch<F>(
+    document: &Rc<RefCell<Document>>,
+    event: &Rc<RefCell<Event>>,
+    target: NodeId,
+    call: &mut F,
+) -> Result<bool, DomException>
+where
+    F: FnMut(&EventListener, &Rc<RefCell<Event>>),
+{
+    // https://dom.spec.whatwg.org/#dom-eventtarget-dispatchevent
+    // "1. If event's dispatch flag is set, or if its initialized flag is not set, then throw an
+    // "InvalidStateError" DOMException."
+    if event.borrow().dispatch_flag || !event.borrow().initialized_flag {
+        return Err(DomException::InvalidStateError);
+    }
+
+    // "1. Set event's dispatch flag."
+    event.borrow_mut().dispatch_flag = true;
+    event.borrow_mut().target = Some(target);
+
+    // "5.4. Append to an event path with event, target, targetOverride, relatedTarget,
+    // touchTargets, and false."
+    // "5.9. While parent is non-null:"
+    // The parent of a node is its parent node, and a document has no parent because we don't
+    // have a Window object.
+    let mut path = vec![target];
+    {
+        let document = document.borrow();
+        let mut parent = document.node(target).parent();
+        while let Some(p) = parent {
+            parent = document.node(p).parent();
+            path.push(p);
+        }
+    }
+
+    // "5.13. For each struct in event's path, in reverse order:"
+    for node in path.iter().rev() {
+        // "1. If struct's shadow-adjusted target is non-null, then set event's eventPhase
+        // attribute to AT_TARGET."
+        // "2. Otherwise, set event's eventPhase attribute to CAPTURING_PHASE."
+        event.borrow_mut().event_phase = if *node == target {
+            EventPhase::AtTarget
+        } else {
+            EventPhase::CapturingPhase
+        };
+        // "3. Invoke with struct, event, "capturing", and legacyOutputDidListenersThrowFlag if
+        // given."
+        invoke(document, *node, event, InvokePhase::Capturing, call);
+    }
+
+    // "5.14. For each struct in event's path:"
+    for node in path.iter() {
+        if *node == target {
+            // "1. If struct's shadow-adjusted target is non-null, then set event's eventPhase
+            // attribute to AT_TARGET."
+            event.borrow_mut().event_phase = EventPhase::AtTarget;
+        } else {
+            // "2.1. If event's bubbles attribute is false, then continue."
+            if !event.borrow().bubbles {
+                continue;
+            }
+            // "2.2. Set event's eventPhase attribute to BUBBLING_PHASE."
+            event.borrow_mut().event_phase = EventPhase::BubblingPhase;
+        }
+        // "3. Invoke with struct, event, "bubbling", and legacyOutputDidListenersThrowFlag if
+        // given."
+        invoke(document, *node, event, InvokePhase::Bubbling, call);
+    }
+
+    let mut e = event.borrow_mut();
+    // "6. Set event's eventPhase attribute to NONE."
+    e.event_phase = EventPhase::None;
+    // "7. Set event's currentTarget attribute to null."
+    e.current_target = None;
+    // "9. Unset event's dispatch flag, stop propagation flag, and stop immediate propagation
+    // flag."
+    e.dispatch_flag = false;
+    e.stop_propagation_flag = false;
+    e.stop_immediate_propagation_flag = false;
+
+    // "12. Return false if event's canceled flag is set; otherwise true."
+    Ok(!e.canceled_flag)
+}
+
+/// https://dom.spec.whatwg.org/#concept-event-listener-invoke
+fn invoke<F>(
+    document: &Rc<RefCell<Document>>,
+    node: NodeId,
+    event: &Rc<RefCell<Event>>,
+    phase: InvokePhase,
+    call: &mut F,
+) where
+    F: FnMut(&EventListener, &Rc<RefCell<Event>>),
+{
+    // "3. If event's stop propagation flag is set, then return."
+    if event.borrow().stop_propagation_flag {
+        return;
+    }
+
+    // "4. Initialize event's currentTarget attribute to struct's invocation target."
+    event.borrow_mut().current_target = Some(node);
+
+    // "5. Let listeners be a clone of event's currentTarget attribute value's event listener
+    // list."
+    // "NOTE: This avoids event listeners added after this point from being run. Note that
+    // removal still has an effect due to the removed field."
+    let listeners = document.borrow().node(node).event_listeners();
+
+    // https://dom.spec.whatwg.org/#concept-event-listener-inner-invoke
+    // "2. For each listener of listeners, whose removed is false:"
+    for listener in listeners {
+        if listener.removed.get() {
+            continue;
+        }
+        // "1. If event's type attribute value is not listener's type, then continue."
+        if event.borrow().event_type != listener.event_type {
+            continue;
+        }
+        // "3. If phase is "capturing" and listener's capture is false, then continue."
+        if phase == InvokePhase::Capturing && !listener.capture {
+            continue;
+        }
+        // "4. If phase is "bubbling" and listener's capture is true, then continue."
+        if phase == InvokePhase::Bubbling && listener.capture {
+            continue;
+        }
+        // "5. If listener's once is true, then remove an event listener given event's
+        // currentTarget attribute value and listener."
+        if listener.once {
+            document.borrow_mut().remove_event_listener(node, &listener);
+        }
+        // "10. Call a user object's operation with listener's callback, "handleEvent", « event »,
+        // and event's currentTarget attribute value."
+        call(&listener, event);
+        // "13. If event's stop immediate propagation flag is set, then break."
+        if event.borrow().stop_immediate_propagation_flag {
+            break;
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! A test runner for html5lib-tests, the tokenizer and tree construction tests shared by HTML
+//! parsers.
+//! https://github.com/html5lib/html5lib-tests
+//!
+//! The test files are vendored in `tests/html5lib-tests`. Each case is reported as PASS, FAIL or
+//! SKIP. Most cases don't pass yet, so a test fails only if a case listed in
+//! `tests/html5lib-baseline` doesn't pass anymore. Run
+//! `HTML5LIB_UPDATE_BASELINE=1 cargo test html5lib` to record the cases passing now as the new
+//! baseline.
+
+use crate::renderer::html::dom::{Document, Element, HtmlParser, NodeId, NodeKind};
+use crate::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// A case that doesn't finish in this time fails, because the parser may loop forever on input
+/// that it doesn't support.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(&'static str),
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Returns the files in `tests/html5lib-tests/<dir>` with `extension`, sorted by name.
+fn test_files(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let dir = manifest_dir()
+        .join("tests")
+        .join("html5lib-tests")
+        .join(dir);
+    let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map(|e| e == extension).unwrap_or(false))
+            .collect(),
+        Err(e) => panic!("failed to read {:?}: {:?}", dir, e),
+    };
+    files.sort();
+    files
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Runs `f` on another thread, so that a panic or an infinite loop in the tokenizer or the parser
+/// fails only the case instead of the whole run.
+fn run_isolated<F>(f: F) -> Outcome
+where
+    F: FnOnce() -> Outcome + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(outcome) => outcome,
+        Err(mpsc::RecvTimeoutError::Timeout) => Outcome::Fail("timed out".to_string()),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Outcome::Fail("panicked".to_string()),
+    }
+}
+
+/// Prints the outcome of each case and a summary, and panics if a case in the baseline of
+/// `suite` doesn't pass.
+fn report(suite: &str, results: &[(String, Outcome)]) {
+    let baseline_path = manifest_dir()
+        .join("tests")
+        .join("html5lib-baseline")
+        .join(format!("{}.txt", suite));
+    let baseline: BTreeSet<String> = fs::read_to_string(&baseline_path)
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let mut passed = BTreeSet::new();
+    let mut failed = 0;
+    let mut skipped = 0;
+    for (id, outcome) in results {
+        match outcome {
+            Outcome::Pass => {
+                if baseline.contains(id) {
+                    println!("PASS {}", id);
+                } else {
+                    println!("PASS {} (not in the baseline)", id);
+                }
+                passed.insert(id.clone());
+            }
+            Outcome::Fail(reason) => {
+                println!("FAIL {}\n{}", id, reason);
+                failed += 1;
+            }
+            Outcome::Skip(reason) => {
+                println!("SKIP {} ({})", id, reason);
+                skipped += 1;
+            }
+        }
+    }
+    println!(
+        "html5lib {}: {} passed, {} failed, {} skipped",
+        suite,
+        passed.len(),
+        failed,
+        skipped
+    );
+
+    if std::env::var("HTML5LIB_UPDATE_BASELINE").is_ok() {
+        let mut content = String::new();
+        for id in &passed {
+            content.push_str(id);
+            content.push('\n');
+        }
+        fs::write(&baseline_path, content).expect("failed to write the baseline");
+        return;
+    }
+
+    let regressions: Vec<&String> = baseline.difference(&passed).collect();
+    assert!(
+        regressions.is_empty(),
+        "cases in the baseline don't pass anymore: {:?}",
+        regressions
+    );
+}
+
+/// A case in the tree construction format.
+/// https://github.com/html5lib/html5lib-tests/blob/master/tree-construction/README.md
+#[derive(Debug, Clone, Default)]
+struct TreeConstructionTest {
+    data: String,
+    document_fragment: Option<String>,
+    script_off: bool,
+    document: String,
+}
+
+/// Splits the content of a `.dat` file into cases. Each case starts with a `#data` line, and the
+/// other sections are introduced by lines such as `#errors` and `#document`.
+fn parse_dat(content: &str) -> Vec<TreeConstructionTest> {
+    let mut tests = Vec::new();
+    let mut test: Option<TreeConstructionTest> = None;
+    let mut section = String::new();
+    let mut lines: Vec<&str> = Vec::new();
+
+    let finish_section = |test: &mut Option<TreeConstructionTest>,
+                          section: &str,
+                          lines: &mut Vec<&str>| {
+        if let Some(t) = test.as_mut() {
+            match section {
+                "#data" => t.data = lines.join("\n"),
+                "#document-fragment" => t.document_fragment = lines.first().map(|l| l.to_string()),
+                "#script-off" => t.script_off = true,
+                "#document" => {
+                    // The cases are separated by an empty line.
+                    while lines.last() == Some(&"") {
+                        lines.pop();
+                    }
+                    t.document = lines.join("\n");
+                }
+                _ => {}
+            }
+        }
+        lines.clear();
+    };
+
+    let mut previous_line = "";
+    for line in content.split('\n') {
+        let is_header = line == "#data"
+            || line == "#errors"
+            || line == "#new-errors"
+            || line == "#document-fragment"
+            || line == "#script-off"
+            || line == "#script-on"
+            || line == "#document";
+        // A text node in `#document` may contain a line such as "#data", but a new case always
+        // follows an empty line.
+        if is_header && (section != "#document" || (line == "#data" && previous_line.is_empty())) {
+            finish_section(&mut test, &section, &mut lines);
+            if line == "#data" {
+                if let Some(t) = test.take() {
+                    tests.push(t);
+                }
+                test = Some(TreeConstructionTest::default());
+            }
+            section = line.to_string();
+        } else {
+            lines.push(line);
+        }
+        previous_line = line;
+    }
+    finish_section(&mut test, &section, &mut lines);
+    if let Some(t) = test {
+        tests.push(t);
+    }
+    tests
+}
+
+/// Appends the lines of `node` and its descendants in the format of the `#document` section,
+/// where each line starts with "| " and the children are indented by two spaces.
+fn dump_node(document: &Document, node: NodeId, depth: usize, out: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    match document.node(node).kind() {
+        NodeKind::Document => {}
+        NodeKind::Element(element) => {
+            out.push(format!("| {}<{}>", indent, element.local_name()));
+            // "Attribute name-value pairs are sorted lexicographically by name"
+            let mut attributes = element.attributes();
+            attributes.sort_by(|a, b| a.name.cmp(&b.name));
+            for attr in attributes {
+                out.push(format!("| {}  {}=\"{}\"", indent, attr.name, attr.value));
+            }
+        }
+        NodeKind::Text(text) => out.push(format!("| {}\"{}\"", indent, text)),
+    }
+
+    let mut child = document.node(node).first_child();
+    while let Some(c) = child {
+        dump_node(document, c, depth + 1, out);
+        child = document.node(c).next_sibling();
+    }
+}
+
+fn run_tree_construction_test(test: TreeConstructionTest) -> Outcome {
+    // We run scripts, so the scripting flag is always enabled.
+    if test.script_off {
+        return Outcome::Skip("scripting disabled");
+    }
+    if let Some(context) = &test.document_fragment {
+        // e.g. "svg path"
+        if context.contains(' ') {
+            return Outcome::Skip("foreign context element");
+        }
+    }
+
+    run_isolated(move || {
+        let mut lines = Vec::new();
+        match &test.document_fragment {
+            Some(context) => {
+                let document = Rc::new(RefCell::new(Document::new()));
+                let element = Element::new(context, Vec::new());
+                let context = document
+                    .borrow_mut()
+                    .create_node(NodeKind::Element(element));
+                let nodes = HtmlParser::parse_fragment(&document, context, test.data.clone());
+                let document = document.borrow();
+                for node in nodes {
+                    dump_node(&document, node, 0, &mut lines);
+                }
+            }
+            None => {
+                let document =
+                    HtmlParser::new(HtmlTokenizer::new(test.data.clone())).construct_tree();
+                let document = document.borrow();
+                // The document node itself is not dumped.
+                let mut child = document.node(document.root()).first_child();
+                while let Some(c) = child {
+                    dump_node(&document, c, 0, &mut lines);
+                    child = document.node(c).next_sibling();
+                }
+            }
+        }
+
+        let actual = lines.join("\n");
+        if actual == test.document {
+            Outcome::Pass
+        } else {
+            Outcome::Fail(format!(
+                "#data\n{}\n#expected\n{}\n#actual\n{}",
+                test.data, test.document, actual
+            ))
+        }
+    })
+}
+
+#[test]
+fn html5lib_tree_construction() {
+    let mut results = Vec::new();
+    for path in test_files("tree-construction", "dat") {
+        let content = fs::read_to_string(&path).expect("failed to read a test file");
+        for (i, test) in parse_dat(&content).into_iter().enumerate() {
+            let id = format!("{}:{}", file_name(&path), i + 1);
+            results.push((id, run_tree_construction_test(test)));
+        }
+    }
+    report("tree-construction", &results);
+}
+
+/// A JSON value, which is enough to read the tokenizer tests.
+/// https://www.json.org/json-en.html
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// The members in the order of appearance.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser {
+    input: Vec<char>,
+    pos: usize,
+}
+
+impl JsonParser {
+    fn parse(input: &str) -> Json {
+        let mut parser = Self {
+            input: input.chars().collect(),
+            pos: 0,
+        };
+        parser.value()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.input.len() && self.input[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) {
+        self.skip_whitespace();
+        if self.input.get(self.pos) != Some(&c) {
+            panic!("expected {:?} at {} in JSON", c, self.pos);
+        }
+        self.pos += 1;
+    }
+
+    /// Consumes `word` such as "true" if the input continues with it.
+    fn consume_word(&mut self, word: &str) -> bool {
+        let end = self.pos + word.len();
+        if end <= self.input.len() && self.input[self.pos..end].iter().copied().eq(word.chars()) {
+            self.pos = end;
+            return true;
+        }
+        false
+    }
+
+    fn value(&mut self) -> Json {
+        self.skip_whitespace();
+        match self.input.get(self.pos) {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Json::String(self.string()),
+            _ => {
+                if self.consume_word("true") {
+                    return Json::Bool(true);
+                }
+                if self.consume_word("false") {
+                    return Json::Bool(false);
+                }
+                if self.consume_word("null") {
+                    return Json::Null;
+                }
+                self.number()
+            }
+        }
+    }
+
+    fn object(&mut self) -> Json {
+        self.expect('{');
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.input.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Json::Object(members);
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string();
+            self.expect(':');
+            members.push((key, self.value()));
+            self.skip_whitespace();
+            match self.input.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Json::Object(members);
+                }
+                c => panic!("unexpected {:?} at {} in JSON object", c, self.pos),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Json {
+        self.expect('[');
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.input.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Json::Array(elements);
+        }
+        loop {
+            elements.push(self.value());
+            self.skip_whitespace();
+            match self.input.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Json::Array(elements);
+                }
+                c => panic!("unexpected {:?} at {} in JSON array", c, self.pos),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> u32 {
+        let end = self.pos + 4;
+        let hex: String = self.input[self.pos..end].iter().collect();
+        self.pos = end;
+        u32::from_str_radix(&hex, 16).expect("invalid \\u escape in JSON")
+    }
+
+    fn string(&mut self) -> String {
+        self.expect('"');
+        let mut s = String::new();
+        loop {
+            let c = self.input[self.pos];
+            self.pos += 1;
+            match c {
+                '"' => return s,
+                '\\' => {
+                    let escaped = self.input[self.pos];
+                    self.pos += 1;
+                    match escaped {
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            let mut code = self.hex4();
+                            // A character outside the BMP is escaped as a surrogate pair.
+                            if (0xd800..0xdc00).contains(&code) && self.consume_word("\\u") {
+                                let low = self.hex4();
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        c => s.push(c),
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Json {
+        let start = self.pos;
+        while self.pos < self.input.len()
+            && (self.input[self.pos].is_ascii_digit() || "+-.eE".contains(self.input[self.pos]))
+        {
+            self.pos += 1;
+        }
+        let number: String = self.input[start..self.pos].iter().collect();
+        match number.parse::<f64>() {
+            Ok(n) => Json::Number(n),
+            Err(_) => panic!("unexpected {:?} at {} in JSON", number, start),
+        }
+    }
+}
+
+/// Sorts the members of objects, because the order of attributes doesn't matter, and coalesces
+/// adjacent Character tokens as the expected output does.
+fn normalize_tokens(tokens: &[Json]) -> Vec<Json> {
+    let mut normalized: Vec<Json> = Vec::new();
+    for token in tokens {
+        let mut token = token.clone();
+        if let Json::Array(elements) = &mut token {
+            if elements.first() == Some(&Json::String("Character".to_string())) {
+                if let Some(Json::Array(last)) = normalized.last_mut() {
+                    if last.first() == Some(&Json::String("Character".to_string())) {
+                        if let (Some(Json::String(data)), Some(Json::String(more))) =
+                            (last.get_mut(1), elements.get(1))
+                        {
+                            data.push_str(more);
+                            continue;
+                        }
+                    }
+                }
+            }
+            for element in elements.iter_mut() {
+                if let Json::Object(members) = element {
+                    members.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+            }
+        }
+        normalized.push(token);
+    }
+    normalized
+}
+
+/// Converts our tokens to the output format of the tokenizer tests.
+/// https://github.com/html5lib/html5lib-tests/blob/master/tokenizer/README.md
+fn tokens_to_json(tokenizer: HtmlTokenizer) -> Vec<Json> {
+    let mut tokens = Vec::new();
+    for token in tokenizer {
+        match token {
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                let mut fields = vec![
+                    Json::String("StartTag".to_string()),
+                    Json::String(tag),
+                    Json::Object(
+                        attributes
+                            .into_iter()
+                            .map(|a| (a.name, Json::String(a.value)))
+                            .collect(),
+                    ),
+                ];
+                if self_closing {
+                    fields.push(Json::Bool(true));
+                }
+                tokens.push(Json::Array(fields));
+            }
+            HtmlToken::EndTag { tag, .. } => tokens.push(Json::Array(vec![
+                Json::String("EndTag".to_string()),
+                Json::String(tag),
+            ])),
+            HtmlToken::Char(c) => tokens.push(Json::Array(vec![
+                Json::String("Character".to_string()),
+                Json::String(c.to_string()),
+            ])),
+            HtmlToken::Eof => break,
+        }
+    }
+    tokens
+}
+
+fn run_tokenizer_test(test: &Json, initial_state: &str) -> Outcome {
+    let state = match initial_state {
+        "Data state" => State::Data,
+        "Script data state" => State::ScriptData,
+        _ => return Outcome::Skip("unsupported initial state"),
+    };
+    if test.get("doubleEscaped") == Some(&Json::Bool(true)) {
+        return Outcome::Skip("double escaped");
+    }
+    if let Some(tag) = test.get("lastStartTag").and_then(|t| t.as_str()) {
+        if tag != "script" {
+            return Outcome::Skip("unsupported last start tag");
+        }
+    }
+
+    let input = test
+        .get("input")
+        .and_then(|i| i.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let expected = normalize_tokens(
+        test.get("output")
+            .and_then(|o| o.as_array())
+            .unwrap_or(&Vec::new()),
+    );
+
+    run_isolated(move || {
+        let mut tokenizer = HtmlTokenizer::new(input.clone());
+        tokenizer.switch_context(state);
+        let actual = normalize_tokens(&tokens_to_json(tokenizer));
+        if actual == expected {
+            Outcome::Pass
+        } else {
+            Outcome::Fail(format!(
+                "input: {:?}\nexpected: {:?}\nactual: {:?}",
+                input, expected, actual
+            ))
+        }
+    })
+}
+
+#[test]
+fn html5lib_tokenizer() {
+    let mut results = Vec::new();
+    for path in test_files("tokenizer", "test") {
+        let content = fs::read_to_string(&path).expect("failed to read a test file");
+        let json = JsonParser::parse(&content);
+        let tests = json
+            .get("tests")
+            .and_then(|t| t.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for (i, test) in tests.iter().enumerate() {
+            let id = format!("{}:{}", file_name(&path), i + 1);
+            match test.get("initialStates").and_then(|s| s.as_array()) {
+                Some(states) => {
+                    for state in states {
+                        let state = state.as_str().unwrap_or_default();
+                        let id = format!("{} ({})", id, state);
+                        results.push((id, run_tokenizer_test(test, state)));
+                    }
+                }
+                None => results.push((id, run_tokenizer_test(test, "Data state"))),
+            }
+        }
+    }
+    report("tokenizer", &results);
+}
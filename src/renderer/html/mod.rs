@@ -1,3 +1,8 @@
 pub mod attribute;
 pub mod dom;
+pub mod event;
+#[cfg(test)]
+mod html5lib_tests;
+pub mod mutation_observer;
+pub mod serializer;
 pub mod token;
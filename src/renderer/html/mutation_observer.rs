@@ -0,0 +1,397 @@
+//! https://dom.spec.whatwg.org/#mutation-observers
+
+use crate::renderer::html::dom::{Document, NodeId};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::string::String;
+use std::vec::Vec;
+
+/// https://dom.spec.whatwg.org/#dom-mutationrecord-type
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MutationRecordType {
+    ChildList,
+    Attributes,
+    CharacterData,
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationrecord
+#[derive(Debug, Clone)]
+pub struct MutationRecord {
+    pub record_type: MutationRecordType,
+    pub target: NodeId,
+    pub added_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    pub previous_sibling: Option<NodeId>,
+    pub next_sibling: Option<NodeId>,
+    pub attribute_name: Option<String>,
+    pub old_value: Option<String>,
+}
+
+impl MutationRecord {
+    /// Creates a record of `record_type` for `target` with `name` and `old_value`, whose node
+    /// lists are empty.
+    pub fn new(
+        record_type: MutationRecordType,
+        target: NodeId,
+        name: Option<String>,
+        old_value: Option<String>,
+    ) -> Self {
+        Self {
+            record_type,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: name,
+            old_value,
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#dictdef-mutationobserverinit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+    pub child_list: bool,
+    pub attributes: Option<bool>,
+    pub character_data: Option<bool>,
+    pub subtree: bool,
+    pub attribute_old_value: Option<bool>,
+    pub character_data_old_value: Option<bool>,
+    pub attribute_filter: Option<Vec<String>>,
+}
+
+/// https://dom.spec.whatwg.org/#registered-observer
+#[derive(Debug, Clone)]
+pub struct RegisteredObserver {
+    observer: Rc<RefCell<MutationObserver>>,
+    options: MutationObserverInit,
+    /// True if this is a transient registered observer, which is added to a removed node so
+    /// that a subtree observer keeps observing it until the next notification.
+    /// https://dom.spec.whatwg.org/#transient-registered-observer
+    transient: bool,
+}
+
+/// A MutationObserver collects mutation records of the nodes it observes. The callback of an
+/// observer created by JavaScript is owned by the runtime, which takes the records at a
+/// microtask checkpoint.
+/// https://dom.spec.whatwg.org/#interface-mutationobserver
+#[derive(Debug, Clone, Default)]
+pub struct MutationObserver {
+    /// https://dom.spec.whatwg.org/#mutationobserver-node-list
+    node_list: Vec<NodeId>,
+    /// https://dom.spec.whatwg.org/#concept-mo-queue
+    record_queue: Vec<MutationRecord>,
+}
+
+impl MutationObserver {
+    pub fn new() -> Self {
+        Self {
+            node_list: Vec::new(),
+            record_queue: Vec::new(),
+        }
+    }
+
+    /// Returns an error with the message of a TypeError if `options` is invalid.
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-observe
+    pub fn observe(
+        observer: &Rc<RefCell<MutationObserver>>,
+        document: &mut Document,
+        target: NodeId,
+        options: MutationObserverInit,
+    ) -> Result<(), &'static str> {
+        let mut options = options;
+
+        // "1. If either options["attributeOldValue"] or options["attributeFilter"] exists, and
+        // options["attributes"] does not exist, then set options["attributes"] to true."
+        if (options.attribute_old_value.is_some() || options.attribute_filter.is_some())
+            && options.attributes.is_none()
+        {
+            options.attributes = Some(true);
+        }
+
+        // "2. If options["characterDataOldValue"] exists and options["characterData"] does not
+        // exist, then set options["characterData"] to true."
+        if options.character_data_old_value.is_some() && options.character_data.is_none() {
+            options.character_data = Some(true);
+        }
+
+        let attributes = options.attributes.unwrap_or(false);
+        let character_data = options.character_data.unwrap_or(false);
+
+        // "3. If none of options["childList"], options["attributes"], and
+        // options["characterData"] is true, then throw a TypeError."
+        if !options.child_list && !attributes && !character_data {
+            return Err("one of childList, attributes and characterData must be true");
+        }
+
+        // "4. If options["attributeOldValue"] is true and options["attributes"] is false, then
+        // throw a TypeError."
+        // "5. If options["attributeFilter"] is present and options["attributes"] is false, then
+        // throw a TypeError."
+        if (options.attribute_old_value == Some(true) || options.attribute_filter.is_some())
+            && !attributes
+        {
+            return Err("attributeOldValue and attributeFilter require attributes");
+        }
+
+        // "6. If options["characterDataOldValue"] is true and options["characterData"] is false,
+        // then throw a TypeError."
+        if options.character_data_old_value == Some(true) && !character_data {
+            return Err("characterDataOldValue requires characterData");
+        }
+
+        // "7. For each registered of target's registered observer list, if registered's observer
+        // is this:"
+        let mut registered_before = false;
+        for registered in document
+            .node_mut(target)
+            .registered_observers_mut()
+            .iter_mut()
+        {
+            if registered.transient || !Rc::ptr_eq(&registered.observer, observer) {
+                continue;
+            }
+            // "2. Set registered's options to options."
+            registered.options = options.clone();
+            registered_before = true;
+        }
+
+        if registered_before {
+            // "1. For each node of this's node list, remove all transient registered observers
+            // whose source is registered from node's registered observer list."
+            Self::remove_transient_observers(observer, document);
+            return Ok(());
+        }
+
+        // "8. Otherwise: 1. Append a new registered observer whose observer is this and options
+        // is options to target's registered observer list. 2. Append a weak reference to target
+        // to this's node list."
+        document
+            .node_mut(target)
+            .registered_observers_mut()
+            .push(RegisteredObserver {
+                observer: observer.clone(),
+                options,
+                transient: false,
+            });
+        observer.borrow_mut().node_list.push(target);
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-disconnect
+    pub fn disconnect(observer: &Rc<RefCell<MutationObserver>>, document: &mut Document) {
+        // "1. For each node of this's node list, remove any registered observer from node's
+        // registered observer list for which this is the observer."
+        let nodes = observer.borrow().node_list.clone();
+        for node in nodes {
+            document
+                .node_mut(node)
+                .registered_observers_mut()
+                .retain(|r| !Rc::ptr_eq(&r.observer, observer));
+        }
+        observer.borrow_mut().node_list.clear();
+
+        // "2. Empty this's record queue."
+        observer.borrow_mut().record_queue.clear();
+    }
+
+    pub fn has_records(&self) -> bool {
+        !self.record_queue.is_empty()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-takerecords
+    pub fn take_records(&mut self) -> Vec<MutationRecord> {
+        // "1. Let records be a clone of this's record queue."
+        // "2. Empty this's record queue."
+        // "3. Return records."
+        self.record_queue.drain(..).collect()
+    }
+
+    /// Removes all transient registered observers whose observer is `observer` from the nodes
+    /// in its node list. This is called when the records are delivered.
+    /// https://dom.spec.whatwg.org/#notify-mutation-observers
+    pub fn remove_transient_observers(
+        observer: &Rc<RefCell<MutationObserver>>,
+        document: &mut Document,
+    ) {
+        let nodes = observer.borrow().node_list.clone();
+        for node in nodes {
+            document
+                .node_mut(node)
+                .registered_observers_mut()
+                .retain(|r| !(r.transient && Rc::ptr_eq(&r.observer, observer)));
+        }
+    }
+}
+
+/// Adds transient registered observers to `node`, which is being removed from `parent`, for
+/// each observer of an inclusive ancestor of `parent` that observes the subtree.
+/// https://dom.spec.whatwg.org/#concept-node-remove
+pub fn add_transient_observers(document: &mut Document, node: NodeId, parent: NodeId) {
+    // "14. For each inclusive ancestor ancestor of parent: if ancestor's registered observer
+    // list is not empty, for each registered of ancestor's registered observer list: if
+    // registered's options["subtree"] is true, then append a new transient registered observer
+    // whose observer is registered's observer, options is registered's options, and source is
+    // registered to node's registered observer list."
+    let mut ancestor = Some(parent);
+    while let Some(a) = ancestor {
+        for registered in document.node(a).registered_observers() {
+            if !registered.options.subtree {
+                continue;
+            }
+            document
+                .node_mut(node)
+                .registered_observers_mut()
+                .push(RegisteredObserver {
+                    observer: registered.observer.clone(),
+                    options: registered.options.clone(),
+                    transient: true,
+                });
+            registered.observer.borrow_mut().node_list.push(node);
+        }
+        ancestor = document.node(a).parent();
+    }
+}
+
+/// Returns true if `target` or one of its ancestors has a registered observer. This is used to
+/// avoid computing an old value that nobody needs.
+pub fn is_observed(document: &Document, target: NodeId) -> bool {
+    let mut node = Some(target);
+    while let Some(n) = node {
+        if !document.node(n).registered_observers().is_empty() {
+            return true;
+        }
+        node = document.node(n).parent();
+    }
+    false
+}
+
+/// Queues a copy of `record` to each interested observer. The old value of `record` is the
+/// oldValue argument, which is set to the copy only if the observer asked for it.
+/// https://dom.spec.whatwg.org/#queueing-a-mutation-record
+pub fn queue_mutation_record(document: &Document, record: MutationRecord) {
+    let record_type = record.record_type;
+    let target = record.target;
+
+    // "1. Let interestedObservers be an empty map."
+    let mut interested_observers: Vec<(Rc<RefCell<MutationObserver>>, Option<String>)> = Vec::new();
+
+    // "2. Let nodes be the inclusive ancestors of target."
+    // "3. For each node in nodes, and then for each registered of node's registered observer
+    // list:"
+    let mut node = Some(target);
+    while let Some(n) = node {
+        for registered in document.node(n).registered_observers() {
+            // "1. Let options be registered's options."
+            let options = &registered.options;
+
+            // "2. If none of the following are true ... then:"
+            // "node is not target and options["subtree"] is false"
+            if n != target && !options.subtree {
+                continue;
+            }
+            match record_type {
+                MutationRecordType::Attributes => {
+                    // "type is "attributes" and options["attributes"] either does not exist or
+                    // is false"
+                    if options.attributes != Some(true) {
+                        continue;
+                    }
+                    // "type is "attributes", options["attributeFilter"] exists, and
+                    // options["attributeFilter"] does not contain name or namespace is non-null"
+                    if let (Some(filter), Some(name)) =
+                        (&options.attribute_filter, &record.attribute_name)
+                    {
+                        if !filter.contains(name) {
+                            continue;
+                        }
+                    }
+                }
+                // "type is "characterData" and options["characterData"] either does not exist
+                // or is false"
+                MutationRecordType::CharacterData => {
+                    if options.character_data != Some(true) {
+                        continue;
+                    }
+                }
+                // "type is "childList" and options["childList"] is false"
+                MutationRecordType::ChildList => {
+                    if !options.child_list {
+                        continue;
+                    }
+                }
+            }
+
+            // "1. Let mo be registered's observer."
+            // "2. If interestedObservers[mo] does not exist, then set interestedObservers[mo] to
+            // null."
+            let index = match interested_observers
+                .iter()
+                .position(|(mo, _)| Rc::ptr_eq(mo, &registered.observer))
+            {
+                Some(i) => i,
+                None => {
+                    interested_observers.push((registered.observer.clone(), None));
+                    interested_observers.len() - 1
+                }
+            };
+
+            // "3. If either type is "attributes" and options["attributeOldValue"] is true, or type
+            // is "characterData" and options["characterDataOldValue"] is true, then set
+            // interestedObservers[mo] to oldValue."
+            if (record_type == MutationRecordType::Attributes
+                && options.attribute_old_value == Some(true))
+                || (record_type == MutationRecordType::CharacterData
+                    && options.character_data_old_value == Some(true))
+            {
+                interested_observers[index].1 = record.old_value.clone();
+            }
+        }
+        node = document.node(n).parent();
+    }
+
+    // "4. For each observer → mappedOldValue of interestedObservers:"
+    for (observer, mapped_old_value) in interested_observers {
+        // "1. Let record be a new MutationRecord object with its type set to type, target set to
+        // target, attributeName set to name, attributeNamespace set to namespace, oldValue set
+        // to mappedOldValue, addedNodes set to addedNodes, removedNodes set to removedNodes,
+        // previousSibling set to previousSibling, and nextSibling set to nextSibling."
+        let record = MutationRecord {
+            old_value: mapped_old_value,
+            ..record.clone()
+        };
+        // "2. Enqueue record to observer's record queue."
+        observer.borrow_mut().record_queue.push(record);
+    }
+
+    // "5. Queue a mutation observer microtask."
+    // The runtime delivers the records of its observers at every microtask checkpoint, so
+    // nothing has to be queued here.
+}
+
+/// https://dom.spec.whatwg.org/#queue-a-tree-mutation-record
+pub fn queue_tree_mutation_record(
+    document: &Document,
+    target: NodeId,
+    added_nodes: Vec<NodeId>,
+    removed_nodes: Vec<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+) {
+    // "1. Assert: either addedNodes or removedNodes is not empty."
+    assert!(!added_nodes.is_empty() || !removed_nodes.is_empty());
+
+    // "2. Queue a mutation record of "childList" for target with null, null, null, addedNodes,
+    // removedNodes, previousSibling, and nextSibling."
+    queue_mutation_record(
+        document,
+        MutationRecord {
+            added_nodes,
+            removed_nodes,
+            previous_sibling,
+            next_sibling,
+            ..MutationRecord::new(MutationRecordType::ChildList, target, None, None)
+        },
+    );
+}
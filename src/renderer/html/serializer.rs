@@ -0,0 +1,148 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+
+use crate::renderer::html::dom::{Document, ElementKind, NodeId, NodeKind};
+use std::string::String;
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#serializes-as-void
+fn serializes_as_void(kind: ElementKind) -> bool {
+    matches!(
+        kind,
+        ElementKind::Br
+            | ElementKind::Hr
+            | ElementKind::Img
+            | ElementKind::Link
+            | ElementKind::Meta
+    )
+}
+
+/// Returns true if the text inside an element of `kind` is written without escaping.
+/// Note: we don't support xmp, iframe, noembed, noframes, plaintext and noscript elements.
+fn is_raw_text_element(kind: ElementKind) -> bool {
+    matches!(kind, ElementKind::Style | ElementKind::Script)
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape_string(s: &str, attribute_mode: bool) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            // "1. Replace any occurrence of the "&" character by the string "&amp;"."
+            '&' => result.push_str("&amp;"),
+            // "2. Replace any occurrences of the U+00A0 NO-BREAK SPACE character by the string
+            // "&nbsp;"."
+            '\u{a0}' => result.push_str("&nbsp;"),
+            // "3. Replace any occurrences of the "<" character by the string "&lt;"."
+            '<' => result.push_str("&lt;"),
+            // "4. Replace any occurrences of the ">" character by the string "&gt;"."
+            '>' => result.push_str("&gt;"),
+            // "5. If the algorithm was invoked in the attribute mode, then replace any
+            // occurrences of the """ character by the string "&quot;"."
+            '"' if attribute_mode => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Appends the serialization of `current_node`, a child of a node whose element kind is
+/// `parent_kind`, to `s`.
+fn serialize_child(
+    document: &Document,
+    current_node: NodeId,
+    parent_kind: Option<ElementKind>,
+    s: &mut String,
+) {
+    match document.node(current_node).kind() {
+        // A document is never a child node, but serializing it is useful to save a page.
+        NodeKind::Document => s.push_str(&serialize_children(document, current_node)),
+        NodeKind::Element(element) => {
+            // "If current node is an element in the HTML namespace, the MathML namespace, or the
+            // SVG namespace, then let tagname be current node's local name."
+            let tagname = element.local_name();
+
+            // "Append a U+003C LESS-THAN SIGN character (<), followed by tagname."
+            s.push('<');
+            s.push_str(&tagname);
+
+            // "For each attribute that the element has, append a U+0020 SPACE character, the
+            // attribute's serialized name, a U+003D EQUALS SIGN character (=), a U+0022 QUOTATION
+            // MARK character ("), the attribute's value, escaped as described below in attribute
+            // mode, and a second U+0022 QUOTATION MARK character (")."
+            for attr in element.attributes() {
+                s.push(' ');
+                s.push_str(&attr.name);
+                s.push_str("=\"");
+                s.push_str(&escape_string(&attr.value, true));
+                s.push('"');
+            }
+
+            // "Append a U+003E GREATER-THAN SIGN character (>)."
+            s.push('>');
+
+            // "If current node serializes as void, then continue on to the next child node at
+            // this point."
+            if serializes_as_void(element.kind()) {
+                return;
+            }
+
+            // "Append the value of running the HTML fragment serialization algorithm on the
+            // current node element, followed by a U+003C LESS-THAN SIGN character (<), a
+            // U+002F SOLIDUS character (/), tagname again, and finally a U+003E GREATER-THAN SIGN
+            // character (>)."
+            s.push_str(&serialize_children(document, current_node));
+            s.push_str("</");
+            s.push_str(&tagname);
+            s.push('>');
+        }
+        NodeKind::Text(data) => {
+            // "If the parent of current node is a style, script, xmp, iframe, noembed, noframes,
+            // or plaintext element, or if the parent of current node is a noscript element and
+            // scripting is enabled for the node, then append the value of current node's data IDL
+            // attribute literally."
+            if let Some(kind) = parent_kind {
+                if is_raw_text_element(kind) {
+                    s.push_str(data);
+                    return;
+                }
+            }
+            // "Otherwise, append the value of current node's data IDL attribute, escaped as
+            // described below."
+            s.push_str(&escape_string(data, false));
+        }
+    }
+}
+
+/// Returns the serialization of the children of `node`. This is the getter of `innerHTML`.
+/// https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-serialisation-algorithm
+pub fn serialize_children(document: &Document, node: NodeId) -> String {
+    let parent_kind = document.element_kind(node);
+
+    // "1. If the node serializes as void, then return the empty string."
+    if let Some(kind) = parent_kind {
+        if serializes_as_void(kind) {
+            return String::new();
+        }
+    }
+
+    // "2. Let s be a string, and initialize it to the empty string."
+    let mut s = String::new();
+
+    // "4. For each child node of the node, in tree order, run the following steps:"
+    let mut child = document.node(node).first_child();
+    while let Some(c) = child {
+        serialize_child(document, c, parent_kind, &mut s);
+        child = document.node(c).next_sibling();
+    }
+
+    // "5. Return s."
+    s
+}
+
+/// Returns the serialization of `node` itself, as if it were the only child of a fictional node.
+/// This is the getter of `outerHTML`.
+/// https://w3c.github.io/DOM-Parsing/#dom-element-outerhtml
+pub fn serialize_node(document: &Document, node: NodeId) -> String {
+    let mut s = String::new();
+    serialize_child(document, node, None, &mut s);
+    s
+}
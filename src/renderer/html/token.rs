@@ -213,6 +213,64 @@ impl HtmlTokenizer {
         self.pos > self.input.len()
     }
 
+    /// Consumes a character reference right after "&" and returns the character it stands for.
+    /// Returns None and consumes nothing if the following characters are not a character
+    /// reference, so "&" is treated as a normal character.
+    /// This is a simplified version of the character reference state. Only numeric character
+    /// references and a few named character references ending with ";" are supported.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+    fn consume_character_reference(&mut self) -> Option<char> {
+        let end = match self.input[self.pos..].iter().position(|c| *c == ';') {
+            Some(i) => self.pos + i,
+            None => return None,
+        };
+        let name: String = self.input[self.pos..end].iter().collect();
+
+        let c = if let Some(number) = name.strip_prefix('#') {
+            // https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-state
+            let code = if let Some(hex) = number
+                .strip_prefix('x')
+                .or_else(|| number.strip_prefix('X'))
+            {
+                if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                u32::from_str_radix(hex, 16).unwrap_or(u32::MAX)
+            } else {
+                if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                number.parse::<u32>().unwrap_or(u32::MAX)
+            };
+            // https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+            // "If the number is 0x00, then this is a null-character-reference parse error. Set
+            // the character reference code to 0xFFFD."
+            // "If the number is greater than 0x10FFFF, then this is a
+            // character-reference-outside-unicode-range parse error. Set the character reference
+            // code to 0xFFFD."
+            // "If the number is a surrogate, then this is a surrogate-character-reference parse
+            // error. Set the character reference code to 0xFFFD."
+            match code {
+                0 => '\u{fffd}',
+                _ => char::from_u32(code).unwrap_or('\u{fffd}'),
+            }
+        } else {
+            // https://html.spec.whatwg.org/multipage/named-characters.html#named-character-references
+            match name.as_str() {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                _ => return None,
+            }
+        };
+
+        self.pos = end + 1;
+        Some(c)
+    }
+
     /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
     pub fn switch_context(&mut self, state: State) {
         self.state = state;
@@ -236,6 +294,12 @@ impl Iterator for HtmlTokenizer {
             match self.state {
                 // https://html.spec.whatwg.org/multipage/parsing.html#data-state
                 State::Data => {
+                    if c == '&' {
+                        if let Some(reference) = self.consume_character_reference() {
+                            return Some(HtmlToken::Char(reference));
+                        }
+                    }
+
                     if c == '<' {
                         self.state = State::TagOpen;
                         continue;
@@ -400,6 +464,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        if let Some(reference) = self.consume_character_reference() {
+                            self.append_attribute(reference, /*is_name*/ false);
+                            continue;
+                        }
+                    }
+
                     if self.is_eof() {
                         return Some(HtmlToken::Eof);
                     }
@@ -413,6 +484,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        if let Some(reference) = self.consume_character_reference() {
+                            self.append_attribute(reference, /*is_name*/ false);
+                            continue;
+                        }
+                    }
+
                     if self.is_eof() {
                         return Some(HtmlToken::Eof);
                     }
@@ -426,6 +504,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        if let Some(reference) = self.consume_character_reference() {
+                            self.append_attribute(reference, /*is_name*/ false);
+                            continue;
+                        }
+                    }
+
                     if c == '>' {
                         self.state = State::Data;
                         return self.take_latest_token();
@@ -69,6 +69,21 @@ pub enum Node {
         callee: Option<Rc<Node>>,
         arguments: Vec<Option<Rc<Node>>>,
     },
+    /// https://github.com/estree/estree/blob/master/es5.md#newexpression
+    NewExpression {
+        callee: Option<Rc<Node>>,
+        arguments: Vec<Option<Rc<Node>>>,
+    },
+    /// https://github.com/estree/estree/blob/master/es5.md#objectexpression
+    ObjectExpression { properties: Vec<Option<Rc<Node>>> },
+    /// https://github.com/estree/estree/blob/master/es5.md#property
+    /// `key` is an Identifier or a StringLiteral.
+    Property {
+        key: Option<Rc<Node>>,
+        value: Option<Rc<Node>>,
+    },
+    /// https://github.com/estree/estree/blob/master/es5.md#arrayexpression
+    ArrayExpression { elements: Vec<Option<Rc<Node>>> },
     /// https://github.com/estree/estree/blob/master/es5.md#identifier
     /// https://262.ecma-international.org/12.0/#prod-Identifier
     Identifier(String),
@@ -78,6 +93,9 @@ pub enum Node {
     /// https://github.com/estree/estree/blob/master/es5.md#literal
     /// https://262.ecma-international.org/12.0/#prod-StringLiteral
     StringLiteral(String),
+    /// https://github.com/estree/estree/blob/master/es5.md#literal
+    /// https://262.ecma-international.org/12.0/#prod-BooleanLiteral
+    BooleanLiteral(bool),
 }
 
 impl Node {
@@ -150,6 +168,25 @@ impl Node {
         Some(Rc::new(Node::CallExpression { callee, arguments }))
     }
 
+    pub fn new_new_expression(
+        callee: Option<Rc<Self>>,
+        arguments: Vec<Option<Rc<Self>>>,
+    ) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::NewExpression { callee, arguments }))
+    }
+
+    pub fn new_object_expression(properties: Vec<Option<Rc<Self>>>) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::ObjectExpression { properties }))
+    }
+
+    pub fn new_property(key: Option<Rc<Self>>, value: Option<Rc<Self>>) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::Property { key, value }))
+    }
+
+    pub fn new_array_expression(elements: Vec<Option<Rc<Self>>>) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::ArrayExpression { elements }))
+    }
+
     pub fn new_identifier(name: String) -> Option<Rc<Self>> {
         Some(Rc::new(Node::Identifier(name)))
     }
@@ -161,6 +198,10 @@ impl Node {
     pub fn new_string_literal(value: String) -> Option<Rc<Self>> {
         Some(Rc::new(Node::StringLiteral(value)))
     }
+
+    pub fn new_boolean_literal(value: bool) -> Option<Rc<Self>> {
+        Some(Rc::new(Node::BooleanLiteral(value)))
+    }
 }
 
 pub struct JsParser {
@@ -188,10 +229,82 @@ impl JsParser {
         };
 
         match t {
+            // BooleanLiteral ::= "true" | "false"
+            Token::Identifier(value) if value == "true" => Node::new_boolean_literal(true),
+            Token::Identifier(value) if value == "false" => Node::new_boolean_literal(false),
             Token::Identifier(value) => Node::new_identifier(value),
             // Literal
             Token::Number(value) => Node::new_numeric_literal(value),
             Token::StringLiteral(value) => Node::new_string_literal(value),
+            Token::Punctuator('{') => self.object_literal(),
+            Token::Punctuator('[') => self.array_literal(),
+            _ => None,
+        }
+    }
+
+    /// Parses an object literal after '{' is consumed.
+    ///
+    /// ObjectLiteral ::= "{" ( PropertyNameAndValueList )? "}"
+    /// PropertyNameAndValueList ::= PropertyNameAndValue ( "," PropertyNameAndValue
+    ///                                                   | "," )*
+    /// PropertyNameAndValue ::= PropertyName ":" AssignmentExpression
+    /// PropertyName ::= Identifier | <STRING_LITERAL> | <DECIMAL_LITERAL>
+    fn object_literal(&mut self) -> Option<Rc<Node>> {
+        let mut properties = Vec::new();
+
+        loop {
+            let key = match self.t.next() {
+                Some(Token::Punctuator('}')) => return Node::new_object_expression(properties),
+                Some(Token::Punctuator(',')) => continue,
+                Some(Token::Identifier(name)) => Node::new_identifier(name),
+                Some(Token::StringLiteral(value)) => Node::new_string_literal(value),
+                Some(Token::Number(value)) => Node::new_string_literal(value.to_string()),
+                _ => return None,
+            };
+
+            // consume ':'
+            match self.t.next() {
+                Some(Token::Punctuator(':')) => {}
+                _ => return None,
+            }
+
+            properties.push(Node::new_property(key, self.assignment_expression()));
+        }
+    }
+
+    /// Parses an array literal after '[' is consumed.
+    ///
+    /// ArrayLiteral ::= "[" ( ( Elision )? "]"
+    ///                  | ElementList Elision "]"
+    ///                  | ( ElementList )? "]" )
+    /// ElementList ::= ( Elision )? AssignmentExpression ( Elision AssignmentExpression )*
+    fn array_literal(&mut self) -> Option<Rc<Node>> {
+        let mut elements = Vec::new();
+
+        loop {
+            match self.t.peek() {
+                Some(Token::Punctuator(']')) => {
+                    // consume ']'
+                    assert!(self.t.next().is_some());
+                    return Node::new_array_expression(elements);
+                }
+                Some(Token::Punctuator(',')) => {
+                    // consume ','
+                    assert!(self.t.next().is_some());
+                }
+                Some(_) => elements.push(self.assignment_expression()),
+                None => return None,
+            }
+        }
+    }
+
+    /// Parses an expression in brackets after '[' is consumed.
+    fn computed_property(&mut self) -> Option<Rc<Node>> {
+        let property = self.expression();
+
+        // consume ']'
+        match self.t.next() {
+            Some(Token::Punctuator(']')) => property,
             _ => None,
         }
     }
@@ -200,7 +313,25 @@ impl JsParser {
     ///
     /// MemberExpression ::= ( ( FunctionExpression | PrimaryExpression ) ( MemberExpressionPart)* )
     ///                    | AllocationExpression
+    ///
+    /// AllocationExpression ::= ( "new" MemberExpression ( ( Arguments ( MemberExpressionPart )* )* ) )
     fn member_expression(&mut self) -> Option<Rc<Node>> {
+        if let Some(Token::Keyword(keyword)) = self.t.peek() {
+            if keyword == "new" {
+                // consume "new"
+                assert!(self.t.next().is_some());
+                let callee = self.member_expression();
+
+                // `new Foo` without arguments is the same as `new Foo()`.
+                if let Some(Token::Punctuator('(')) = self.t.peek() {
+                    // consume '('
+                    assert!(self.t.next().is_some());
+                    return Node::new_new_expression(callee, self.arguments());
+                }
+                return Node::new_new_expression(callee, Vec::new());
+            }
+        }
+
         let expr = self.primary_expression();
 
         let t = match self.t.peek() {
@@ -216,6 +347,12 @@ impl JsParser {
                     return Node::new_member_expression(expr, self.identifier());
                 }
 
+                if c == '[' {
+                    // consume '['
+                    assert!(self.t.next().is_some());
+                    return Node::new_member_expression(expr, self.computed_property());
+                }
+
                 expr
             }
             _ => expr,
@@ -228,27 +365,48 @@ impl JsParser {
     /// Arguments ::= "(" ( ArgumentList )? ")"
     /// CallExpression ::= MemberExpression Arguments ( CallExpressionPart )*
     ///
+    /// CallExpressionPart ::= Arguments | ( "[" Expression "]" ) | ( "." Identifier )
+    ///
     /// LeftHandSideExpression ::= CallExpression | MemberExpression
     fn left_hand_side_expression(&mut self) -> Option<Rc<Node>> {
-        let expr = self.member_expression();
+        let mut expr = self.member_expression();
 
-        let t = match self.t.peek() {
-            Some(token) => token,
-            None => return expr,
-        };
+        loop {
+            let t = match self.t.peek() {
+                Some(token) => token,
+                None => return expr,
+            };
+
+            match t {
+                Token::Punctuator(c) => {
+                    if c == '(' {
+                        // consume '('
+                        assert!(self.t.next().is_some());
+                        expr = Node::new_call_expression(expr, self.arguments());
+                        continue;
+                    }
 
-        match t {
-            Token::Punctuator(c) => {
-                if c == '(' {
-                    // consume '('
-                    assert!(self.t.next().is_some());
-                    return Node::new_call_expression(expr, self.arguments());
-                }
+                    // e.g. `document.getElementById("id").innerHTML`
+                    if c == '.' {
+                        // consume '.'
+                        assert!(self.t.next().is_some());
+                        expr = Node::new_member_expression(expr, self.identifier());
+                        continue;
+                    }
 
-                // return MemberExpression
-                expr
+                    // e.g. `records[0].target`
+                    if c == '[' {
+                        // consume '['
+                        assert!(self.t.next().is_some());
+                        expr = Node::new_member_expression(expr, self.computed_property());
+                        continue;
+                    }
+
+                    // return CallExpression or MemberExpression
+                    return expr;
+                }
+                _ => return expr,
             }
-            _ => expr,
         }
     }
 
@@ -267,33 +425,37 @@ impl JsParser {
     ///
     /// ConditionalExpression ::= LogicalORExpression ( "?" AssignmentExpression ":" AssignmentExpression )?
     fn conditional_expression(&mut self) -> Option<Rc<Node>> {
-        let left = self.left_hand_side_expression();
+        let mut left = self.left_hand_side_expression();
 
-        let t = match self.t.peek() {
-            Some(token) => token,
-            None => return left,
-        };
-
-        // TODO: support MultiplicativeExpression ('*' and '/')
-        match t {
-            Token::Punctuator(c) => match c {
-                // AdditiveExpression
-                '+' | '-' => {
-                    // consume '+' or '-'
-                    assert!(self.t.next().is_some());
-                    Node::new_binary_expression(c, left, self.left_hand_side_expression())
-                }
-                // end of expression
-                ';' => {
-                    // consume ';'
-                    assert!(self.t.next().is_some());
-                    left
-                }
-                // end of expression wihtout consuming next token
-                ',' | ')' => left,
-                _ => left,
-            },
-            _ => left,
+        // AdditiveExpression is left-associative, so `a + b + c` is parsed as `(a + b) + c`.
+        loop {
+            let t = match self.t.peek() {
+                Some(token) => token,
+                None => return left,
+            };
+
+            // TODO: support MultiplicativeExpression ('*' and '/')
+            match t {
+                Token::Punctuator(c) => match c {
+                    // AdditiveExpression
+                    '+' | '-' => {
+                        // consume '+' or '-'
+                        assert!(self.t.next().is_some());
+                        left =
+                            Node::new_binary_expression(c, left, self.left_hand_side_expression());
+                    }
+                    // end of expression
+                    ';' => {
+                        // consume ';'
+                        assert!(self.t.next().is_some());
+                        return left;
+                    }
+                    // end of expression wihtout consuming next token
+                    ',' | ')' => return left,
+                    _ => return left,
+                },
+                _ => return left,
+            }
         }
     }
 
@@ -473,6 +635,7 @@ impl JsParser {
     }
 
     /// Arguments ::= "(" ( ArgumentList )? ")"
+    /// ArgumentList ::= AssignmentExpression ( "," AssignmentExpression )*
     fn arguments(&mut self) -> Vec<Option<Rc<Node>>> {
         let mut arguments = Vec::new();
 
@@ -480,18 +643,21 @@ impl JsParser {
             // push identifier to `arguments` until hits ')'
             match self.t.peek() {
                 Some(t) => match t {
-                    Token::Punctuator(c) => {
-                        if c == ')' {
-                            // consume ')'
-                            assert!(self.t.next().is_some());
-                            return arguments;
-                        }
-                        if c == ',' {
-                            // consume ','
-                            assert!(self.t.next().is_some());
-                        }
+                    Token::Punctuator(')') => {
+                        // consume ')'
+                        assert!(self.t.next().is_some());
+                        return arguments;
+                    }
+                    Token::Punctuator(',') => {
+                        // consume ','
+                        assert!(self.t.next().is_some());
                     }
-                    _ => arguments.push(self.expression()),
+                    // an argument can start with a punctuator such as `{` of an object literal
+                    _ => match self.assignment_expression() {
+                        Some(argument) => arguments.push(Some(argument)),
+                        // avoid looping forever on a token that can't start an expression
+                        None => return arguments,
+                    },
                 },
                 None => return arguments,
             }
@@ -563,6 +729,28 @@ impl JsParser {
         }
     }
 
+    /// Parses the value of an event handler content attribute such as `onclick`. Unlike a
+    /// program, the body of a function can have a return statement. Returns None if the source
+    /// text has a part that can't be parsed.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#getting-the-current-value-of-the-event-handler
+    ///
+    /// FunctionBody ::= ( SourceElements )? <EOF>
+    pub fn parse_function_body(&mut self) -> Option<Rc<Node>> {
+        let mut body = Vec::new();
+
+        while let Some(node) = self.source_element() {
+            body.push(Some(node));
+        }
+
+        // "If body is not parsable as FunctionBody or if parsing detects an early error, then
+        // follow these substeps: ... Return null."
+        if self.t.peek().is_some() {
+            return None;
+        }
+
+        Node::new_block_statement(body)
+    }
+
     /// SourceElements ::= ( SourceElement )+
     ///
     /// Program ::= ( SourceElements )? <EOF>
@@ -1,11 +1,23 @@
+use crate::renderer::css::cssom::{idl_attribute_to_css_property, CssStyleDeclaration};
 use crate::renderer::html::dom::get_element_by_id;
-use crate::renderer::html::dom::Node as DomNode;
+use crate::renderer::html::dom::Document;
+use crate::renderer::html::dom::DomException;
+use crate::renderer::html::dom::DomTokenList;
+use crate::renderer::html::dom::HtmlCollection;
+use crate::renderer::html::dom::HtmlParser;
+use crate::renderer::html::dom::NodeId;
 use crate::renderer::html::dom::NodeKind as DomNodeKind;
+use crate::renderer::html::event::{Event, EventListener, EventListenerCallback};
+use crate::renderer::html::mutation_observer::{
+    MutationObserver, MutationObserverInit, MutationRecord, MutationRecordType,
+};
+use crate::renderer::html::serializer::{serialize_children, serialize_node};
+use crate::renderer::js::ast::JsParser;
 use crate::renderer::js::ast::Node;
 use crate::renderer::js::ast::Program;
+use crate::renderer::js::token::JsLexer;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::ops::Add;
 use std::rc::Rc;
 use std::string::{String, ToString};
 use std::vec::Vec;
@@ -19,27 +31,52 @@ pub enum RuntimeValue {
     /// https://262.ecma-international.org/13.0/#sec-terms-and-definitions-string-value
     /// https://262.ecma-international.org/13.0/#sec-ecmascript-language-types-string-type
     StringLiteral(String),
+    /// https://262.ecma-international.org/13.0/#sec-ecmascript-language-types-boolean-type
+    Boolean(bool),
     /// https://dom.spec.whatwg.org/#interface-htmlcollection
     /// https://dom.spec.whatwg.org/#element
     HtmlElement {
-        object: Rc<RefCell<DomNode>>,
+        object: NodeId,
         property: Option<String>,
     },
-}
-
-impl RuntimeValue {
-    fn to_string(&self) -> String {
-        match self {
-            RuntimeValue::Number(value) => format!("{}", value),
-            RuntimeValue::StringLiteral(value) => value.to_string(),
-            RuntimeValue::HtmlElement {
-                object,
-                property: _,
-            } => {
-                format!("{:?}", object.borrow().kind())
-            }
-        }
-    }
+    /// https://dom.spec.whatwg.org/#interface-nodelist
+    /// A static list of nodes such as the result of `querySelectorAll()`.
+    NodeList {
+        nodes: Vec<NodeId>,
+        property: Option<String>,
+    },
+    /// https://dom.spec.whatwg.org/#interface-htmlcollection
+    HtmlCollection {
+        object: HtmlCollection,
+        property: Option<String>,
+    },
+    /// https://dom.spec.whatwg.org/#interface-domtokenlist
+    DomTokenList {
+        object: DomTokenList,
+        property: Option<String>,
+    },
+    /// https://drafts.csswg.org/cssom/#the-cssstyledeclaration-interface
+    CssStyleDeclaration {
+        object: CssStyleDeclaration,
+        property: Option<String>,
+    },
+    /// https://dom.spec.whatwg.org/#interface-event
+    Event {
+        object: Rc<RefCell<Event>>,
+        property: Option<String>,
+    },
+    /// https://dom.spec.whatwg.org/#interface-mutationobserver
+    MutationObserver {
+        object: Rc<RefCell<MutationObserver>>,
+        property: Option<String>,
+    },
+    /// https://dom.spec.whatwg.org/#interface-mutationrecord
+    MutationRecord(MutationRecord),
+    /// https://262.ecma-international.org/13.0/#sec-object-type
+    /// An object created by an object literal, which has only data properties.
+    Object(HashMap<String, Option<RuntimeValue>>),
+    /// https://262.ecma-international.org/13.0/#sec-array-exotic-objects
+    Array(Vec<Option<RuntimeValue>>),
 }
 
 impl PartialEq for RuntimeValue {
@@ -53,24 +90,62 @@ impl PartialEq for RuntimeValue {
                 RuntimeValue::StringLiteral(v2) => v1 == v2,
                 _ => false,
             },
+            RuntimeValue::Boolean(v1) => match other {
+                RuntimeValue::Boolean(v2) => v1 == v2,
+                _ => false,
+            },
             RuntimeValue::HtmlElement {
                 object: _,
                 property: _,
             } => false,
+            RuntimeValue::NodeList { .. }
+            | RuntimeValue::HtmlCollection { .. }
+            | RuntimeValue::DomTokenList { .. }
+            | RuntimeValue::CssStyleDeclaration { .. }
+            | RuntimeValue::Event { .. }
+            | RuntimeValue::MutationObserver { .. }
+            | RuntimeValue::MutationRecord(_)
+            | RuntimeValue::Object(_)
+            | RuntimeValue::Array(_) => false,
         }
     }
 }
 
-impl Add<RuntimeValue> for RuntimeValue {
-    type Output = RuntimeValue;
+/// Returns an error if `property` is set, i.e. the value is a property of an object such as
+/// `element.id`. The properties of such a value are not supported yet.
+fn unsupported_property(property: &Option<String>) -> Result<(), JsError> {
+    match property {
+        Some(p) => Err(JsError::TypeError(format!(
+            "the properties of {} are not supported yet",
+            p
+        ))),
+        None => Ok(()),
+    }
+}
 
-    fn add(self, rhs: RuntimeValue) -> RuntimeValue {
-        // https://tc39.es/ecma262/multipage/ecmascript-language-expressions.html#sec-applystringornumericbinaryoperator
-        if let (RuntimeValue::Number(left_num), RuntimeValue::Number(right_num)) = (&self, &rhs) {
-            return RuntimeValue::Number(left_num + right_num);
-        }
+/// An exception thrown while running a script. We don't have Error objects, so an exception
+/// only keeps the kind of the error and its message.
+/// https://262.ecma-international.org/13.0/#sec-native-error-types-used-in-this-standard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsError {
+    /// https://262.ecma-international.org/13.0/#sec-native-error-types-used-in-this-standard-syntaxerror
+    SyntaxError(String),
+    /// https://262.ecma-international.org/13.0/#sec-native-error-types-used-in-this-standard-typeerror
+    TypeError(String),
+    /// https://262.ecma-international.org/13.0/#sec-native-error-types-used-in-this-standard-referenceerror
+    ReferenceError(String),
+    /// https://webidl.spec.whatwg.org/#idl-DOMException
+    DomException(DomException),
+}
 
-        return RuntimeValue::StringLiteral(self.to_string() + &rhs.to_string());
+impl core::fmt::Display for JsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            JsError::SyntaxError(message) => write!(f, "SyntaxError: {}", message),
+            JsError::TypeError(message) => write!(f, "TypeError: {}", message),
+            JsError::ReferenceError(message) => write!(f, "ReferenceError: {}", message),
+            JsError::DomException(e) => write!(f, "DOMException: {:?}", e),
+        }
     }
 }
 
@@ -142,32 +217,107 @@ impl Function {
 
 #[derive(Debug, Clone)]
 pub struct JsRuntime {
-    dom_root: Option<Rc<RefCell<DomNode>>>,
-    dom_modified: bool,
+    document: Rc<RefCell<Document>>,
     url: String,
+    /// The mutation observers created by scripts and the names of their callbacks. They are
+    /// notified in the order of creation.
+    /// https://dom.spec.whatwg.org/#mutation-observer-list
+    mutation_observers: Vec<(Rc<RefCell<MutationObserver>>, String)>,
+    /// The number of scripts and callbacks that are running, which is the depth of the
+    /// JavaScript execution context stack.
+    execution_context_depth: usize,
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#performing-a-microtask-checkpoint
+    performing_microtask_checkpoint: bool,
     pub global_variables: HashMap<String, Option<RuntimeValue>>,
     pub functions: Vec<Function>,
     pub env: Rc<RefCell<Environment>>,
 }
 
 impl JsRuntime {
-    pub fn new(dom_root: Rc<RefCell<DomNode>>, url: String) -> Self {
+    pub fn new(document: Rc<RefCell<Document>>, url: String) -> Self {
         Self {
-            dom_root: Some(dom_root),
-            dom_modified: false,
+            document,
             url,
+            mutation_observers: Vec::new(),
+            execution_context_depth: 0,
+            performing_microtask_checkpoint: false,
             global_variables: HashMap::new(),
             functions: Vec::new(),
             env: Rc::new(RefCell::new(Environment::new(None))),
         }
     }
 
-    pub fn dom_root(&self) -> Option<Rc<RefCell<DomNode>>> {
-        self.dom_root.clone()
+    /// Converts `value` to a string. The properties of nodes are looked up in the document.
+    fn to_string(&self, value: &RuntimeValue) -> String {
+        match value {
+            RuntimeValue::Number(value) => format!("{}", value),
+            RuntimeValue::StringLiteral(value) => value.to_string(),
+            RuntimeValue::Boolean(value) => format!("{}", value),
+            RuntimeValue::HtmlElement { object, property } => {
+                let document = self.document.borrow();
+                match property.as_deref() {
+                    // https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+                    Some("innerHTML") => serialize_children(&document, *object),
+                    // https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml
+                    Some("outerHTML") => serialize_node(&document, *object),
+                    // https://dom.spec.whatwg.org/#dom-element-id
+                    Some("id") => match document.node(*object).element() {
+                        Some(e) => e.id(),
+                        None => String::new(),
+                    },
+                    // https://dom.spec.whatwg.org/#dom-element-classname
+                    Some("className") => match document.node(*object).element() {
+                        Some(e) => e.get_attribute("class").unwrap_or_default(),
+                        None => String::new(),
+                    },
+                    // https://dom.spec.whatwg.org/#dom-characterdata-data
+                    // https://dom.spec.whatwg.org/#dom-node-nodevalue
+                    Some("data") | Some("nodeValue") => {
+                        document.node(*object).data().unwrap_or_default()
+                    }
+                    // https://html.spec.whatwg.org/multipage/dom.html#document.title
+                    Some("title") if *object == document.root() => document.title(),
+                    _ => format!("{:?}", document.node(*object).kind()),
+                }
+            }
+            RuntimeValue::NodeList { .. } => "[object NodeList]".to_string(),
+            RuntimeValue::HtmlCollection { .. } => "[object HTMLCollection]".to_string(),
+            // https://dom.spec.whatwg.org/#dom-domtokenlist-value
+            RuntimeValue::DomTokenList { object, .. } => object.value(&self.document.borrow()),
+            RuntimeValue::CssStyleDeclaration { object, property } => {
+                let document = self.document.borrow();
+                match property.as_deref() {
+                    Some("cssText") => object.css_text(&document),
+                    // https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-camel-cased-attribute
+                    // "Getting the camel-cased attribute attribute must invoke getPropertyValue()
+                    // with the argument being the result of running the IDL attribute to CSS
+                    // property algorithm for camel-cased attribute."
+                    Some(p) => {
+                        object.get_property_value(&document, &idl_attribute_to_css_property(p))
+                    }
+                    None => "[object CSSStyleDeclaration]".to_string(),
+                }
+            }
+            RuntimeValue::Event { .. } => "[object Event]".to_string(),
+            RuntimeValue::MutationObserver { .. } => "[object MutationObserver]".to_string(),
+            RuntimeValue::MutationRecord(_) => "[object MutationRecord]".to_string(),
+            RuntimeValue::Object(_) => "[object Object]".to_string(),
+            // https://262.ecma-international.org/13.0/#sec-array.prototype.join
+            RuntimeValue::Array(elements) => elements
+                .iter()
+                .map(|e| e.as_ref().map(|v| self.to_string(v)).unwrap_or_default())
+                .collect::<Vec<String>>()
+                .join(","),
+        }
     }
 
-    pub fn dom_modified(&self) -> bool {
-        self.dom_modified
+    /// https://tc39.es/ecma262/multipage/ecmascript-language-expressions.html#sec-applystringornumericbinaryoperator
+    fn add(&self, left: RuntimeValue, right: RuntimeValue) -> RuntimeValue {
+        if let (RuntimeValue::Number(left_num), RuntimeValue::Number(right_num)) = (&left, &right) {
+            return RuntimeValue::Number(left_num + right_num);
+        }
+
+        RuntimeValue::StringLiteral(self.to_string(&left) + &self.to_string(&right))
     }
 
     /// https://developer.mozilla.org/en-US/docs/Web/API
@@ -180,67 +330,792 @@ impl JsRuntime {
         func: &RuntimeValue,
         arguments: &Vec<Option<Rc<Node>>>,
         env: Rc<RefCell<Environment>>,
-    ) -> (bool, Option<RuntimeValue>) {
+    ) -> Result<(bool, Option<RuntimeValue>), JsError> {
         if func == &RuntimeValue::StringLiteral("console.log".to_string()) {
-            match self.eval(&arguments[0], env.clone()) {
+            match self.eval_argument(arguments, 0, env.clone())? {
                 Some(arg) => {
-                    println!("[console.log] {:?}", arg.to_string());
-                    return (true, None);
+                    println!("[console.log] {:?}", self.to_string(&arg));
+                    return Ok((true, None));
+                }
+                None => {
+                    println!("[console.log] undefined");
+                    return Ok((true, None));
                 }
-                None => return (false, None),
             }
         }
 
         if func == &RuntimeValue::StringLiteral("document.getElementById".to_string()) {
-            let arg = match self.eval(&arguments[0], env.clone()) {
+            let arg = match self.eval_argument(arguments, 0, env.clone())? {
                 Some(a) => a,
-                None => return (true, None),
+                None => return Ok((true, None)),
             };
-            let target = match get_element_by_id(self.dom_root.clone(), &arg.to_string()) {
+            let id = self.to_string(&arg);
+            let document = self.document.borrow();
+            let target = match get_element_by_id(&document, Some(document.root()), &id) {
                 Some(n) => n,
-                None => return (true, None),
+                None => return Ok((true, None)),
             };
             println!(
                 "[document.getElementById] {:?}\n{:?}",
-                arg.to_string(),
-                target
+                id,
+                document.node(target)
             );
-            return (
+            return Ok((
                 true,
                 Some(RuntimeValue::HtmlElement {
                     object: target,
                     property: None,
                 }),
-            );
+            ));
+        }
+
+        if let RuntimeValue::StringLiteral(name) = func {
+            if let Some(method) = name.strip_prefix("document.") {
+                let document = self.document.borrow().root();
+                if let Some(result) =
+                    self.call_query_api(document, method, arguments, env.clone())?
+                {
+                    return Ok((true, result));
+                }
+            }
+        }
+
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if let Some(result) = self.call_query_api(*object, method, arguments, env.clone())? {
+                return Ok((true, result));
+            }
+        }
+
+        // https://dom.spec.whatwg.org/#dom-nodelist-item
+        // https://dom.spec.whatwg.org/#dom-htmlcollection-item
+        if let RuntimeValue::NodeList {
+            nodes: _,
+            property: Some(method),
+        }
+        | RuntimeValue::HtmlCollection {
+            object: _,
+            property: Some(method),
+        } = func
+        {
+            if method != "item" {
+                return Ok((false, None));
+            }
+
+            let index = match self.eval_argument(arguments, 0, env.clone())? {
+                Some(RuntimeValue::Number(n)) => n as usize,
+                _ => return Ok((true, None)),
+            };
+            let node = match func {
+                RuntimeValue::NodeList { nodes, .. } => nodes.get(index).copied(),
+                RuntimeValue::HtmlCollection { object, .. } => {
+                    object.item(&self.document.borrow(), index)
+                }
+                _ => None,
+            };
+            return Ok((
+                true,
+                node.map(|n| RuntimeValue::HtmlElement {
+                    object: n,
+                    property: None,
+                }),
+            ));
+        }
+
+        if func == &RuntimeValue::StringLiteral("document.createTextNode".to_string()) {
+            let data = match self.eval_argument(arguments, 0, env.clone())? {
+                Some(a) => self.to_string(&a),
+                None => return Ok((true, None)),
+            };
+            let node = self
+                .document
+                .borrow_mut()
+                .create_node(DomNodeKind::Text(data));
+            return Ok((
+                true,
+                Some(RuntimeValue::HtmlElement {
+                    object: node,
+                    property: None,
+                }),
+            ));
+        }
+
+        // https://dom.spec.whatwg.org/#interface-element
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if method == "getAttribute"
+                || method == "setAttribute"
+                || method == "removeAttribute"
+                || method == "hasAttribute"
+            {
+                let args = self.eval_arguments_to_strings(arguments, env.clone())?;
+                let required = if method == "setAttribute" { 2 } else { 1 };
+                if args.len() < required {
+                    return Err(JsError::TypeError(format!(
+                        "Element.{}: not enough arguments",
+                        method
+                    )));
+                }
+
+                let result = match self.document.borrow().node(*object).element() {
+                    Some(element) => match method.as_str() {
+                        "getAttribute" => element
+                            .get_attribute(&args[0])
+                            .map(RuntimeValue::StringLiteral),
+                        "hasAttribute" => {
+                            Some(RuntimeValue::Boolean(element.has_attribute(&args[0])))
+                        }
+                        _ => None,
+                    },
+                    None => {
+                        return Err(JsError::TypeError(format!(
+                            "Element.{}: this is not an Element",
+                            method
+                        )));
+                    }
+                };
+
+                if method == "setAttribute" {
+                    let result = self
+                        .document
+                        .borrow_mut()
+                        .set_attribute(*object, &args[0], &args[1]);
+                    if let Err(e) = result {
+                        return Err(JsError::DomException(e));
+                    }
+                }
+                if method == "removeAttribute" {
+                    self.document
+                        .borrow_mut()
+                        .remove_attribute(*object, &args[0]);
+                }
+                return Ok((true, result));
+            }
+        }
+
+        // https://dom.spec.whatwg.org/#interface-domtokenlist
+        if let RuntimeValue::DomTokenList {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if method == "item" {
+                let index = match self.eval_argument(arguments, 0, env.clone())? {
+                    Some(RuntimeValue::Number(n)) => n as usize,
+                    _ => return Ok((true, None)),
+                };
+                let token = object.item(&self.document.borrow(), index);
+                return Ok((true, token.map(RuntimeValue::StringLiteral)));
+            }
+
+            if method == "toggle" {
+                let token = match self.eval_argument(arguments, 0, env.clone())? {
+                    Some(t) => self.to_string(&t),
+                    None => return Ok((true, None)),
+                };
+                let force = match arguments.get(1) {
+                    Some(arg) => match self.eval(arg, env.clone())? {
+                        Some(RuntimeValue::Boolean(b)) => Some(b),
+                        _ => None,
+                    },
+                    None => None,
+                };
+                let result = object.toggle(&mut self.document.borrow_mut(), &token, force);
+                match result {
+                    Ok(b) => return Ok((true, Some(RuntimeValue::Boolean(b)))),
+                    Err(e) => {
+                        return Err(JsError::DomException(e));
+                    }
+                }
+            }
+
+            if method != "add" && method != "remove" && method != "contains" {
+                return Ok((false, None));
+            }
+
+            let tokens = self.eval_arguments_to_strings(arguments, env.clone())?;
+            let tokens: Vec<&str> = tokens.iter().map(|t| t.as_str()).collect();
+            let result = match method.as_str() {
+                "contains" => {
+                    let token = tokens.first().cloned().unwrap_or_default();
+                    let contains = object.contains(&self.document.borrow(), token);
+                    return Ok((true, Some(RuntimeValue::Boolean(contains))));
+                }
+                "add" => object.add(&mut self.document.borrow_mut(), &tokens),
+                _ => object.remove(&mut self.document.borrow_mut(), &tokens),
+            };
+            if let Err(e) = result {
+                return Err(JsError::DomException(e));
+            }
+            return Ok((true, None));
+        }
+
+        // https://drafts.csswg.org/cssom/#the-cssstyledeclaration-interface
+        if let RuntimeValue::CssStyleDeclaration {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if method != "getPropertyValue" && method != "setProperty" && method != "removeProperty"
+            {
+                return Ok((false, None));
+            }
+
+            let args = self.eval_arguments_to_strings(arguments, env.clone())?;
+            let property = args.first().cloned().unwrap_or_default();
+            let result = match method.as_str() {
+                "getPropertyValue" => object.get_property_value(&self.document.borrow(), &property),
+                "setProperty" => {
+                    let value = args.get(1).cloned().unwrap_or_default();
+                    let priority = args.get(2).cloned().unwrap_or_default();
+                    object.set_property(
+                        &mut self.document.borrow_mut(),
+                        &property,
+                        &value,
+                        &priority,
+                    );
+                    return Ok((true, None));
+                }
+                _ => object.remove_property(&mut self.document.borrow_mut(), &property),
+            };
+            return Ok((true, Some(RuntimeValue::StringLiteral(result))));
+        }
+
+        // https://dom.spec.whatwg.org/#dom-document-createevent
+        if func == &RuntimeValue::StringLiteral("document.createEvent".to_string()) {
+            let interface = match self.eval_argument(arguments, 0, env.clone())? {
+                Some(a) => self.to_string(&a).to_ascii_lowercase(),
+                None => return Ok((true, None)),
+            };
+            // "2. For each row of the following table ... If interface is an ASCII
+            // case-insensitive match for any of the strings in the first column in that row, then
+            // set constructor to the interface in the second column on the same row as the
+            // matching string"
+            // "3. If constructor is null, then throw a "NotSupportedError" DOMException."
+            // Note: we support only the Event interface.
+            if interface != "event" && interface != "events" && interface != "htmlevents" {
+                return Err(JsError::DomException(DomException::NotSupportedError));
+            }
+            return Ok((
+                true,
+                Some(RuntimeValue::Event {
+                    object: Rc::new(RefCell::new(Event::create())),
+                    property: None,
+                }),
+            ));
+        }
+
+        // https://dom.spec.whatwg.org/#interface-event
+        if let RuntimeValue::Event {
+            object,
+            property: Some(method),
+        } = func
+        {
+            match method.as_str() {
+                "preventDefault" => object.borrow_mut().prevent_default(),
+                "stopPropagation" => object.borrow_mut().stop_propagation(),
+                "stopImmediatePropagation" => object.borrow_mut().stop_immediate_propagation(),
+                "initEvent" => {
+                    let event_type = match self.eval_argument(arguments, 0, env.clone())? {
+                        Some(t) => self.to_string(&t),
+                        None => return Ok((true, None)),
+                    };
+                    let mut flags = [false, false];
+                    for (i, flag) in flags.iter_mut().enumerate() {
+                        if let Some(arg) = arguments.get(i + 1) {
+                            if let Some(RuntimeValue::Boolean(b)) = self.eval(arg, env.clone())? {
+                                *flag = b;
+                            }
+                        }
+                    }
+                    object
+                        .borrow_mut()
+                        .init_event(&event_type, flags[0], flags[1]);
+                }
+                _ => return Ok((false, None)),
+            }
+            return Ok((true, None));
+        }
+
+        // https://dom.spec.whatwg.org/#interface-mutationobserver
+        if let RuntimeValue::MutationObserver {
+            object,
+            property: Some(method),
+        } = func
+        {
+            match method.as_str() {
+                "observe" => {
+                    let target = match arguments.first() {
+                        Some(argument) => self.eval(argument, env.clone())?,
+                        None => None,
+                    };
+                    let target = match target {
+                        Some(RuntimeValue::HtmlElement {
+                            object,
+                            property: None,
+                        }) => object,
+                        _ => {
+                            return Err(JsError::TypeError(
+                                "MutationObserver.observe: target is not a Node".to_string(),
+                            ));
+                        }
+                    };
+                    let options = match arguments.get(1) {
+                        Some(argument) => self.eval(argument, env.clone())?,
+                        None => None,
+                    };
+                    let options = match options {
+                        Some(RuntimeValue::Object(options)) => {
+                            self.to_mutation_observer_init(&options)
+                        }
+                        _ => MutationObserverInit::default(),
+                    };
+                    let result = MutationObserver::observe(
+                        object,
+                        &mut self.document.borrow_mut(),
+                        target,
+                        options,
+                    );
+                    if let Err(e) = result {
+                        return Err(JsError::TypeError(format!(
+                            "MutationObserver.observe: {}",
+                            e
+                        )));
+                    }
+                    return Ok((true, None));
+                }
+                "disconnect" => {
+                    MutationObserver::disconnect(object, &mut self.document.borrow_mut());
+                    return Ok((true, None));
+                }
+                "takeRecords" => {
+                    let records = object.borrow_mut().take_records();
+                    return Ok((true, Some(Self::records_to_array(records))));
+                }
+                _ => return Ok((false, None)),
+            }
+        }
+
+        // https://dom.spec.whatwg.org/#interface-eventtarget
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if method == "addEventListener" || method == "removeEventListener" {
+                let args = self.eval_arguments_to_strings(arguments, env.clone())?;
+                if args.len() < 2 {
+                    return Err(JsError::TypeError(format!(
+                        "EventTarget.{}: not enough arguments",
+                        method
+                    )));
+                }
+                // The options argument can only be a boolean for capture, because object literals
+                // are not supported.
+                let capture = args.get(2).map(|c| c == "true").unwrap_or(false);
+                let listener = EventListener::new(
+                    &args[0],
+                    EventListenerCallback::Function(args[1].clone()),
+                    capture,
+                    false,
+                );
+                if method == "addEventListener" {
+                    self.document
+                        .borrow_mut()
+                        .add_event_listener(*object, listener);
+                } else {
+                    self.document
+                        .borrow_mut()
+                        .remove_event_listener(*object, &listener);
+                }
+                return Ok((true, None));
+            }
+
+            if method == "dispatchEvent" {
+                let event = match self.eval_argument(arguments, 0, env.clone())? {
+                    Some(RuntimeValue::Event {
+                        object,
+                        property: None,
+                    }) => object,
+                    _ => {
+                        return Err(JsError::TypeError(
+                            "EventTarget.dispatchEvent: argument is not an Event".to_string(),
+                        ));
+                    }
+                };
+                match self.dispatch_event(*object, &event) {
+                    Ok(b) => return Ok((true, Some(RuntimeValue::Boolean(b)))),
+                    Err(e) => {
+                        return Err(JsError::DomException(e));
+                    }
+                }
+            }
+        }
+
+        // https://dom.spec.whatwg.org/#interface-node
+        if let RuntimeValue::HtmlElement {
+            object,
+            property: Some(method),
+        } = func
+        {
+            if method != "appendChild"
+                && method != "insertBefore"
+                && method != "removeChild"
+                && method != "replaceChild"
+            {
+                return Ok((false, None));
+            }
+
+            // Arguments other than nodes are treated as null.
+            let mut nodes = Vec::new();
+            for argument in arguments {
+                match self.eval(argument, env.clone())? {
+                    Some(RuntimeValue::HtmlElement {
+                        object,
+                        property: None,
+                    }) => nodes.push(Some(object)),
+                    _ => nodes.push(None),
+                }
+            }
+            let first = nodes.first().copied().flatten();
+            let second = nodes.get(1).copied().flatten();
+
+            let mut document = self.document.borrow_mut();
+            let result = match (method.as_str(), first, second) {
+                ("appendChild", Some(node), _) => document.append_child(*object, node),
+                ("insertBefore", Some(node), child) => document.insert_before(*object, node, child),
+                ("removeChild", Some(child), _) => document.remove_child(*object, child),
+                ("replaceChild", Some(node), Some(child)) => {
+                    document.replace_child(*object, node, child)
+                }
+                _ => {
+                    return Err(JsError::TypeError(format!(
+                        "Node.{}: argument is not a Node",
+                        method
+                    )));
+                }
+            };
+
+            match result {
+                Ok(node) => {
+                    return Ok((
+                        true,
+                        Some(RuntimeValue::HtmlElement {
+                            object: node,
+                            property: None,
+                        }),
+                    ));
+                }
+                Err(e) => {
+                    return Err(JsError::DomException(e));
+                }
+            }
         }
 
         /*
         if func == &RuntimeValue::StringLiteral("setTimeout".to_string()) {
-            return (true, None);
+            return Ok((true, None));
         }
         */
 
-        (false, None)
+        Ok((false, None))
+    }
+
+    /// Evaluates the argument at `index`. A missing argument is undefined, which is evaluated to
+    /// nothing.
+    fn eval_argument(
+        &mut self,
+        arguments: &[Option<Rc<Node>>],
+        index: usize,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Option<RuntimeValue>, JsError> {
+        match arguments.get(index) {
+            Some(argument) => self.eval(argument, env),
+            None => Ok(None),
+        }
+    }
+
+    /// Evaluates `arguments` and converts them to strings. Arguments that are evaluated to
+    /// nothing are skipped.
+    fn eval_arguments_to_strings(
+        &mut self,
+        arguments: &Vec<Option<Rc<Node>>>,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Vec<String>, JsError> {
+        let mut values = Vec::new();
+        for argument in arguments {
+            if let Some(value) = self.eval(argument, env.clone())? {
+                values.push(self.to_string(&value));
+            }
+        }
+        Ok(values)
+    }
+
+    /// Calls `method` of Document, Element or the ParentNode mixin that looks up elements under
+    /// `node`. Returns None if `method` is not such a method.
+    /// https://dom.spec.whatwg.org/#interface-parentnode
+    fn call_query_api(
+        &mut self,
+        node: NodeId,
+        method: &str,
+        arguments: &[Option<Rc<Node>>],
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Option<Option<RuntimeValue>>, JsError> {
+        if method != "querySelector"
+            && method != "querySelectorAll"
+            && method != "getElementsByTagName"
+            && method != "getElementsByClassName"
+        {
+            return Ok(None);
+        }
+
+        let arg = match self.eval_argument(arguments, 0, env.clone())? {
+            Some(a) => self.to_string(&a),
+            None => return Ok(Some(None)),
+        };
+        println!("[{}] {:?}", method, arg);
+
+        let result = match method {
+            "querySelector" => self.document.borrow().query_selector(node, &arg).map(|n| {
+                n.map(|n| RuntimeValue::HtmlElement {
+                    object: n,
+                    property: None,
+                })
+            }),
+            "querySelectorAll" => {
+                self.document
+                    .borrow()
+                    .query_selector_all(node, &arg)
+                    .map(|nodes| {
+                        Some(RuntimeValue::NodeList {
+                            nodes,
+                            property: None,
+                        })
+                    })
+            }
+            "getElementsByTagName" => Ok(Some(RuntimeValue::HtmlCollection {
+                object: Document::get_elements_by_tag_name(node, &arg),
+                property: None,
+            })),
+            _ => Ok(Some(RuntimeValue::HtmlCollection {
+                object: Document::get_elements_by_class_name(node, &arg),
+                property: None,
+            })),
+        };
+
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(JsError::DomException(e)),
+        }
+    }
+
+    /// Returns the function defined in the JS code whose name is `name`. When there are
+    /// functions with the same name, the last one wins.
+    fn find_function(&self, name: &RuntimeValue) -> Option<Function> {
+        self.functions
+            .iter()
+            .rev()
+            .find(|func| name == &RuntimeValue::StringLiteral(func.id.to_string()))
+            .cloned()
+    }
+
+    /// Calls `function` with `arguments`. The params are added to `env` as local variables, and a
+    /// param without an argument is undefined.
+    fn call_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Option<RuntimeValue>>,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Option<RuntimeValue>, JsError> {
+        // assign arguments to params as local variables
+        for (i, param) in function.params.iter().enumerate() {
+            let name = match param.as_deref() {
+                Some(Node::Identifier(name)) => name.to_string(),
+                param => {
+                    return Err(JsError::SyntaxError(format!(
+                        "unexpected parameter {:?}",
+                        param
+                    )))
+                }
+            };
+            env.borrow_mut()
+                .add_variable(name, arguments.get(i).cloned().flatten());
+        }
+
+        self.eval(&function.body, env)
+    }
+
+    /// Reports an exception that a script or a callback didn't catch to the console.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#report-the-exception
+    fn report_exception(&self, error: &JsError) {
+        println!("[console.error] Uncaught {}", error);
+    }
+
+    /// Dispatches `event` to `target` and runs the event listeners. Returns false if the event
+    /// was canceled.
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-dispatchevent
+    pub fn dispatch_event(
+        &mut self,
+        target: NodeId,
+        event: &Rc<RefCell<Event>>,
+    ) -> Result<bool, DomException> {
+        // Listeners are called without borrowing the document, so they can change it.
+        let document = self.document.clone();
+        Document::dispatch_event(&document, target, event, &mut |listener, event| {
+            self.call_event_listener(listener, event)
+        })
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-event-fire
+    pub fn fire_event(
+        &mut self,
+        target: NodeId,
+        event_type: &str,
+        bubbles: bool,
+        cancelable: bool,
+    ) -> bool {
+        let event = Rc::new(RefCell::new(Event::new(event_type, bubbles, cancelable)));
+        self.dispatch_event(target, &event)
+            .expect("a new event should be dispatchable")
+    }
+
+    /// Calls the callback of `listener` with `event` as the only argument.
+    /// https://dom.spec.whatwg.org/#concept-event-listener-inner-invoke
+    fn call_event_listener(&mut self, listener: &EventListener, event: &Rc<RefCell<Event>>) {
+        self.execution_context_depth += 1;
+        let result = match listener.callback() {
+            EventListenerCallback::Function(name) => self.call_listener_function(&name, event),
+            EventListenerCallback::EventHandler(name) => self.call_event_handler(&name, event),
+        };
+        // "If this throws an exception, then: Report the exception."
+        if let Err(e) = result {
+            self.report_exception(&e);
+        }
+        // https://html.spec.whatwg.org/multipage/webappapis.html#clean-up-after-running-a-callback
+        self.clean_up_after_running_script();
+    }
+
+    /// Calls the function named `name` with `event` as the only argument.
+    fn call_listener_function(
+        &mut self,
+        name: &str,
+        event: &Rc<RefCell<Event>>,
+    ) -> Result<(), JsError> {
+        let function = match self.find_function(&RuntimeValue::StringLiteral(name.to_string())) {
+            Some(f) => f,
+            None => return Err(JsError::ReferenceError(format!("{} is not defined", name))),
+        };
+
+        let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+        let argument = RuntimeValue::Event {
+            object: event.clone(),
+            property: None,
+        };
+        self.call_function(&function, vec![Some(argument)], env)?;
+        Ok(())
+    }
+
+    /// Compiles the event handler content attribute `name` of the current target of `event`, and
+    /// calls it with `event` as the argument and the element as `this`.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#the-event-handler-processing-algorithm
+    fn call_event_handler(
+        &mut self,
+        name: &str,
+        event: &Rc<RefCell<Event>>,
+    ) -> Result<(), JsError> {
+        let target = match event.borrow().current_target() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        // "1. Let callback be the result of getting the current value of the event handler given
+        // eventTarget and name."
+        // "2. If callback is null, then return."
+        let source = match self.document.borrow().node(target).element() {
+            Some(e) => match e.get_attribute(name) {
+                Some(s) => s,
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+
+        // https://html.spec.whatwg.org/multipage/webappapis.html#getting-the-current-value-of-the-event-handler
+        // "3.2. If body is not parsable as FunctionBody or if parsing detects an early error, then
+        // follow these substeps: 1. Set eventHandler's value to null. 2. Report the error for the
+        // appropriate script and with the appropriate position (line number and column number)
+        // given by location, using settings object's global object. If the error is still not
+        // handled after this, then the error may be reported to a developer console. 3. Return
+        // null."
+        let body = match JsParser::new(JsLexer::new(source)).parse_function_body() {
+            Some(b) => b,
+            None => {
+                return Err(JsError::SyntaxError(format!(
+                    "failed to parse the {} event handler",
+                    name
+                )))
+            }
+        };
+
+        // "3.12. Let function be the result of calling OrdinaryFunctionCreate, with arguments
+        // functionPrototype, sourceText, parameters, body, ..."
+        // "parameters: If eventHandler is an element's event handler, ... then let the function
+        // have a single argument called event"
+        let function = Function::new(
+            name.to_string(),
+            vec![Node::new_identifier("event".to_string())],
+            Some(body),
+        );
+
+        // "4. Let return value be the callback return value: ... Invoke callback with one
+        // argument, the value of which is the Event object event, with the callback this value
+        // set to event's currentTarget."
+        // `this` is bound as a local variable of the function.
+        let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+        env.borrow_mut().add_variable(
+            "this".to_string(),
+            Some(RuntimeValue::HtmlElement {
+                object: target,
+                property: None,
+            }),
+        );
+        let argument = RuntimeValue::Event {
+            object: event.clone(),
+            property: None,
+        };
+        let result = self.call_function(&function, vec![Some(argument)], env)?;
+
+        // "5. Process return value as follows: ... If return value is false, then set the
+        // canceled flag of event."
+        if result == Some(RuntimeValue::Boolean(false)) {
+            event.borrow_mut().prevent_default();
+        }
+        Ok(())
     }
 
     fn eval(
         &mut self,
         node: &Option<Rc<Node>>,
         env: Rc<RefCell<Environment>>,
-    ) -> Option<RuntimeValue> {
+    ) -> Result<Option<RuntimeValue>, JsError> {
         use std::borrow::Borrow;
 
         let node = match node {
             Some(n) => n,
-            None => return None,
+            None => return Ok(None),
         };
 
-        match node.borrow() {
+        let value = match node.borrow() {
             Node::ExpressionStatement(expr) => return self.eval(&expr, env.clone()),
             Node::BlockStatement { body } => {
                 let mut result: Option<RuntimeValue> = None;
                 for stmt in body {
-                    result = self.eval(&stmt, env.clone());
+                    result = self.eval(&stmt, env.clone())?;
                 }
                 result
             }
@@ -248,20 +1123,15 @@ impl JsRuntime {
                 return self.eval(&argument, env.clone());
             }
             Node::FunctionDeclaration { id, params, body } => {
-                let id = match self.eval(&id, env.clone()) {
-                    Some(value) => match value {
-                        RuntimeValue::Number(n) => {
-                            unimplemented!("id should be string but got {:?}", n)
-                        }
-                        RuntimeValue::StringLiteral(s) => s,
-                        RuntimeValue::HtmlElement {
-                            object: node,
-                            property: _,
-                        } => {
-                            panic!("unexpected runtime value {:?}", node)
-                        }
-                    },
-                    None => return None,
+                let id = match self.eval(&id, env.clone())? {
+                    Some(RuntimeValue::StringLiteral(s)) => s,
+                    Some(value) => {
+                        return Err(JsError::SyntaxError(format!(
+                            "unexpected function name {}",
+                            self.to_string(&value)
+                        )))
+                    }
+                    None => return Ok(None),
                 };
                 let cloned_body = match body {
                     Some(b) => Some(b.clone()),
@@ -273,14 +1143,14 @@ impl JsRuntime {
             }
             Node::VariableDeclaration { declarations } => {
                 for declaration in declarations {
-                    self.eval(&declaration, env.clone());
+                    self.eval(&declaration, env.clone())?;
                 }
                 None
             }
             Node::VariableDeclarator { id, init } => {
                 if let Some(node) = id {
                     if let Node::Identifier(id) = node.borrow() {
-                        let init = self.eval(&init, env.clone());
+                        let init = self.eval(&init, env.clone())?;
                         env.borrow_mut().add_variable(id.to_string(), init);
                         //self.global_variables.insert(id.to_string(), init);
                     }
@@ -292,20 +1162,20 @@ impl JsRuntime {
                 left,
                 right,
             } => {
-                let left_value = match self.eval(&left, env.clone()) {
+                let left_value = match self.eval(&left, env.clone())? {
                     Some(value) => value,
-                    None => return None,
+                    None => return Ok(None),
                 };
-                let right_value = match self.eval(&right, env.clone()) {
+                let right_value = match self.eval(&right, env.clone())? {
                     Some(value) => value,
-                    None => return None,
+                    None => return Ok(None),
                 };
 
                 // https://tc39.es/ecma262/multipage/ecmascript-language-expressions.html#sec-applystringornumericbinaryoperator
                 if operator == &'+' {
-                    Some(left_value + right_value)
+                    Some(self.add(left_value, right_value))
                 } else {
-                    return None;
+                    return Ok(None);
                 }
             }
             Node::AssignmentExpression {
@@ -314,87 +1184,351 @@ impl JsRuntime {
                 right,
             } => {
                 if operator == &'=' {
-                    let left_value = match self.eval(&left, env.clone()) {
+                    let left_value = match self.eval(&left, env.clone())? {
                         Some(value) => value,
-                        None => return None,
+                        None => return Ok(None),
                     };
-                    let right_value = match self.eval(&right, env.clone()) {
+                    let right_value = match self.eval(&right, env.clone())? {
                         Some(value) => value,
-                        None => return None,
+                        None => return Ok(None),
                     };
 
                     println!("AssignmentExpression {:?} = {:?}", left_value, right_value);
 
                     match left_value {
-                        RuntimeValue::Number(n) => panic!("unexpected value {:?}", n),
+                        RuntimeValue::Number(_) | RuntimeValue::Boolean(_) => {
+                            return Err(JsError::SyntaxError(
+                                "invalid left-hand side in assignment".to_string(),
+                            ))
+                        }
                         RuntimeValue::StringLiteral(_s) => {
                             // TODO: update variable here
                         }
                         RuntimeValue::HtmlElement { object, property } => {
                             if let Some(p) = property {
                                 // this is the implementation of
-                                // `document.getElementById("target").innerHTML = "foobar";`
-                                // Currently, an assignment value should be a text like "foobar".
+                                // `document.getElementById("target").innerHTML = "<b>foobar</b>";`
+                                // https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
                                 if p == "innerHTML" {
-                                    self.dom_modified = true;
-                                    object.borrow_mut().update_first_child(Some(Rc::new(
-                                        RefCell::new(DomNode::new(DomNodeKind::Text(
-                                            right_value.to_string(),
-                                        ))),
-                                    )));
+                                    // "Let fragment be the result of invoking the fragment parsing
+                                    // algorithm steps with context and compliantString."
+                                    let fragment = HtmlParser::parse_fragment(
+                                        &self.document,
+                                        object,
+                                        self.to_string(&right_value),
+                                    );
+                                    // "Replace all with fragment within context."
+                                    self.document.borrow_mut().replace_all(object, fragment);
+                                }
+
+                                // https://dom.spec.whatwg.org/#dom-element-id
+                                // https://dom.spec.whatwg.org/#dom-element-classname
+                                // "The id attribute must reflect the "id" content attribute and
+                                // the className attribute must reflect the "class" content
+                                // attribute."
+                                if p == "id" || p == "className" {
+                                    let name = if p == "id" { "id" } else { "class" };
+                                    let value = self.to_string(&right_value);
+                                    self.document
+                                        .borrow_mut()
+                                        .set_attribute(object, name, &value)
+                                        .expect("id and class are valid attribute names");
+                                }
+
+                                // https://dom.spec.whatwg.org/#dom-characterdata-data
+                                // https://dom.spec.whatwg.org/#dom-node-nodevalue
+                                if p == "data" || p == "nodeValue" {
+                                    let value = self.to_string(&right_value);
+                                    self.document.borrow_mut().set_data(object, &value);
+                                }
+
+                                // https://html.spec.whatwg.org/multipage/dom.html#document.title
+                                if p == "title" && object == RefCell::borrow(&self.document).root()
+                                {
+                                    let value = self.to_string(&right_value);
+                                    self.document.borrow_mut().set_title(&value);
                                 }
                             }
                         }
+                        RuntimeValue::CssStyleDeclaration {
+                            object,
+                            property: Some(p),
+                        } => {
+                            let value = self.to_string(&right_value);
+                            let mut document = self.document.borrow_mut();
+                            if p == "cssText" {
+                                object.set_css_text(&mut document, &value);
+                            } else {
+                                // https://drafts.csswg.org/cssom/#dom-cssstyledeclaration-camel-cased-attribute
+                                // "Setting the camel-cased attribute attribute must invoke
+                                // setProperty() with the first argument being the result of
+                                // running the IDL attribute to CSS property algorithm for
+                                // camel-cased attribute, as second argument the given value, and
+                                // no third argument."
+                                let property = idl_attribute_to_css_property(&p);
+                                object.set_property(&mut document, &property, &value, "");
+                            }
+                        }
+                        // The properties of NodeList and HTMLCollection are read-only.
+                        RuntimeValue::CssStyleDeclaration { .. }
+                        | RuntimeValue::NodeList { .. }
+                        | RuntimeValue::HtmlCollection { .. }
+                        | RuntimeValue::DomTokenList { .. }
+                        | RuntimeValue::Event { .. }
+                        | RuntimeValue::MutationObserver { .. }
+                        | RuntimeValue::MutationRecord(_)
+                        | RuntimeValue::Object(_)
+                        | RuntimeValue::Array(_) => {}
                     }
                 }
-                return None;
+                None
             }
             Node::MemberExpression { object, property } => {
-                let object_value = match self.eval(&object, env.clone()) {
+                let object_value = match self.eval(&object, env.clone())? {
                     Some(value) => value,
-                    None => return None,
+                    None => return Ok(None),
                 };
-                let property_value = match self.eval(&property, env.clone()) {
+                let property_value = match self.eval(&property, env.clone())? {
                     Some(value) => value,
                     // return RuntimeValue in `object` because of no `property`
-                    None => return Some(object_value),
+                    None => return Ok(Some(object_value)),
                 };
 
                 match object_value {
                     // return html element for DOM manipulation
                     RuntimeValue::HtmlElement { object, property } => {
-                        assert!(property.is_none());
+                        unsupported_property(&property)?;
+
+                        // https://dom.spec.whatwg.org/#dom-element-classlist
+                        if property_value == RuntimeValue::StringLiteral("classList".to_string()) {
+                            return Ok(Some(RuntimeValue::DomTokenList {
+                                object: Document::class_list(object),
+                                property: None,
+                            }));
+                        }
+
+                        // https://drafts.csswg.org/cssom/#dom-elementcssinlinestyle-style
+                        if property_value == RuntimeValue::StringLiteral("style".to_string()) {
+                            return Ok(Some(RuntimeValue::CssStyleDeclaration {
+                                object: CssStyleDeclaration::new(object),
+                                property: None,
+                            }));
+                        }
 
                         // set `property` to the HtmlElement value.
-                        return Some(RuntimeValue::HtmlElement {
+                        Some(RuntimeValue::HtmlElement {
+                            object,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::NodeList { nodes, property } => {
+                        unsupported_property(&property)?;
+
+                        // https://dom.spec.whatwg.org/#dom-nodelist-length
+                        if property_value == RuntimeValue::StringLiteral("length".to_string()) {
+                            return Ok(Some(RuntimeValue::Number(nodes.len() as u64)));
+                        }
+
+                        // https://dom.spec.whatwg.org/#ref-for-dfn-supported-property-indices
+                        if let RuntimeValue::Number(index) = property_value {
+                            return Ok(nodes.get(index as usize).map(|n| {
+                                RuntimeValue::HtmlElement {
+                                    object: *n,
+                                    property: None,
+                                }
+                            }));
+                        }
+
+                        Some(RuntimeValue::NodeList {
+                            nodes,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::HtmlCollection { object, property } => {
+                        unsupported_property(&property)?;
+
+                        // https://dom.spec.whatwg.org/#dom-htmlcollection-length
+                        if property_value == RuntimeValue::StringLiteral("length".to_string()) {
+                            let length = object.length(&RefCell::borrow(&self.document));
+                            return Ok(Some(RuntimeValue::Number(length as u64)));
+                        }
+
+                        // https://dom.spec.whatwg.org/#dom-htmlcollection-item
+                        if let RuntimeValue::Number(index) = property_value {
+                            let item =
+                                object.item(&RefCell::borrow(&self.document), index as usize);
+                            return Ok(item.map(|n| RuntimeValue::HtmlElement {
+                                object: n,
+                                property: None,
+                            }));
+                        }
+
+                        Some(RuntimeValue::HtmlCollection {
+                            object,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::DomTokenList { object, property } => {
+                        unsupported_property(&property)?;
+
+                        // https://dom.spec.whatwg.org/#dom-domtokenlist-length
+                        if property_value == RuntimeValue::StringLiteral("length".to_string()) {
+                            let length = object.length(&RefCell::borrow(&self.document));
+                            return Ok(Some(RuntimeValue::Number(length as u64)));
+                        }
+
+                        Some(RuntimeValue::DomTokenList {
+                            object,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::Event { object, property } => {
+                        unsupported_property(&property)?;
+
+                        // https://dom.spec.whatwg.org/#interface-event
+                        let value = {
+                            let event = RefCell::borrow(&object);
+                            let element = |node: Option<NodeId>| {
+                                node.map(|n| RuntimeValue::HtmlElement {
+                                    object: n,
+                                    property: None,
+                                })
+                            };
+                            match self.to_string(&property_value).as_str() {
+                                "type" => {
+                                    Some(Some(RuntimeValue::StringLiteral(event.event_type())))
+                                }
+                                "target" => Some(element(event.target())),
+                                "currentTarget" => Some(element(event.current_target())),
+                                "eventPhase" => {
+                                    Some(Some(RuntimeValue::Number(event.event_phase() as u64)))
+                                }
+                                "bubbles" => Some(Some(RuntimeValue::Boolean(event.bubbles()))),
+                                "cancelable" => {
+                                    Some(Some(RuntimeValue::Boolean(event.cancelable())))
+                                }
+                                "defaultPrevented" => {
+                                    Some(Some(RuntimeValue::Boolean(event.default_prevented())))
+                                }
+                                _ => None,
+                            }
+                        };
+                        if let Some(value) = value {
+                            return Ok(value);
+                        }
+
+                        Some(RuntimeValue::Event {
                             object,
-                            property: Some(property_value.to_string()),
-                        });
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::CssStyleDeclaration { object, property } => {
+                        unsupported_property(&property)?;
+
+                        Some(RuntimeValue::CssStyleDeclaration {
+                            object,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    RuntimeValue::MutationObserver { object, property } => {
+                        unsupported_property(&property)?;
+
+                        Some(RuntimeValue::MutationObserver {
+                            object,
+                            property: Some(self.to_string(&property_value)),
+                        })
+                    }
+                    // https://dom.spec.whatwg.org/#interface-mutationrecord
+                    RuntimeValue::MutationRecord(record) => {
+                        let node = |node: Option<NodeId>| {
+                            node.map(|n| RuntimeValue::HtmlElement {
+                                object: n,
+                                property: None,
+                            })
+                        };
+                        match self.to_string(&property_value).as_str() {
+                            "type" => Some(RuntimeValue::StringLiteral(
+                                match record.record_type {
+                                    MutationRecordType::ChildList => "childList",
+                                    MutationRecordType::Attributes => "attributes",
+                                    MutationRecordType::CharacterData => "characterData",
+                                }
+                                .to_string(),
+                            )),
+                            "target" => node(Some(record.target)),
+                            "addedNodes" => Some(RuntimeValue::NodeList {
+                                nodes: record.added_nodes,
+                                property: None,
+                            }),
+                            "removedNodes" => Some(RuntimeValue::NodeList {
+                                nodes: record.removed_nodes,
+                                property: None,
+                            }),
+                            "previousSibling" => node(record.previous_sibling),
+                            "nextSibling" => node(record.next_sibling),
+                            "attributeName" => {
+                                record.attribute_name.map(RuntimeValue::StringLiteral)
+                            }
+                            "oldValue" => record.old_value.map(RuntimeValue::StringLiteral),
+                            _ => None,
+                        }
+                    }
+                    RuntimeValue::Object(properties) => properties
+                        .get(&self.to_string(&property_value))
+                        .cloned()
+                        .flatten(),
+                    RuntimeValue::Array(elements) => {
+                        // https://262.ecma-international.org/13.0/#sec-properties-of-array-instances-length
+                        if property_value == RuntimeValue::StringLiteral("length".to_string()) {
+                            return Ok(Some(RuntimeValue::Number(elements.len() as u64)));
+                        }
+
+                        if let RuntimeValue::Number(index) = property_value {
+                            return Ok(elements.get(index as usize).cloned().flatten());
+                        }
+
+                        None
                     }
                     _ => {
                         if object_value == RuntimeValue::StringLiteral("document".to_string()) {
                             // TOOD: this is tricky to support member functions for document.*. find smarter way...
                             if property_value
                                 == RuntimeValue::StringLiteral("getElementById".to_string())
+                                || property_value
+                                    == RuntimeValue::StringLiteral("createTextNode".to_string())
+                                || property_value
+                                    == RuntimeValue::StringLiteral("querySelector".to_string())
+                                || property_value
+                                    == RuntimeValue::StringLiteral("querySelectorAll".to_string())
+                                || property_value
+                                    == RuntimeValue::StringLiteral(
+                                        "getElementsByTagName".to_string(),
+                                    )
+                                || property_value
+                                    == RuntimeValue::StringLiteral(
+                                        "getElementsByClassName".to_string(),
+                                    )
+                                || property_value
+                                    == RuntimeValue::StringLiteral("createEvent".to_string())
                             {
-                                return Some(
-                                    object_value
-                                        + RuntimeValue::StringLiteral(".".to_string())
-                                        + property_value,
+                                let object_value = self.add(
+                                    object_value,
+                                    RuntimeValue::StringLiteral(".".to_string()),
                                 );
+                                return Ok(Some(self.add(object_value, property_value)));
                             }
 
                             // set `property` to the HtmlElement value.
-                            return Some(RuntimeValue::HtmlElement {
-                                object: self.dom_root.clone().expect("failed to get root node"),
-                                property: Some(property_value.to_string()),
-                            });
+                            return Ok(Some(RuntimeValue::HtmlElement {
+                                object: RefCell::borrow(&self.document).root(),
+                                property: Some(self.to_string(&property_value)),
+                            }));
                         }
 
                         if object_value == RuntimeValue::StringLiteral("location".to_string()) {
                             if property_value == RuntimeValue::StringLiteral("href".to_string()) {
                                 println!("[location.href] {:?}", self.url);
-                                return Some(RuntimeValue::StringLiteral(self.url.clone()));
+                                return Ok(Some(RuntimeValue::StringLiteral(self.url.clone())));
                             }
 
                             if property_value == RuntimeValue::StringLiteral("hash".to_string()) {
@@ -403,105 +1537,79 @@ impl JsRuntime {
                                     None => "".to_string(),
                                 };
                                 println!("[location.hash] {:?}", hash);
-                                return Some(RuntimeValue::StringLiteral(hash.clone()));
+                                return Ok(Some(RuntimeValue::StringLiteral(hash.clone())));
                             }
                         }
 
                         // return a concatenated string such as "console.log"
-                        return Some(
-                            object_value
-                                + RuntimeValue::StringLiteral(".".to_string())
-                                + property_value,
-                        );
+                        let object_value =
+                            self.add(object_value, RuntimeValue::StringLiteral(".".to_string()));
+                        Some(self.add(object_value, property_value))
                     }
                 }
             }
             Node::CallExpression { callee, arguments } => {
                 let env = Rc::new(RefCell::new(Environment::new(Some(env))));
-                let callee_value = match self.eval(&callee, env.clone()) {
+                let callee_value = match self.eval(&callee, env.clone())? {
                     Some(value) => value,
-                    None => return None,
+                    None => return Ok(None),
                 };
 
                 // call a Web API
-                let web_api_result = self.call_web_api(&callee_value, arguments, env.clone());
+                let web_api_result = self.call_web_api(&callee_value, arguments, env.clone())?;
                 if web_api_result.0 {
-                    return web_api_result.1;
+                    return Ok(web_api_result.1);
                 }
 
                 /*
                 if callee_value
                     == RuntimeValue::StringLiteral("document.getElementById".to_string())
                 {
-                    let arg = match self.eval(&arguments[0], env.clone()) {
+                    let arg = match self.eval(&arguments[0], env.clone())? {
                         Some(a) => a,
-                        None => return None,
+                        None => return Ok(None),
                     };
                     let target = match get_element_by_id(self.dom_root.clone(), &arg.to_string()) {
                         Some(n) => n,
-                        None => return None,
+                        None => return Ok(None),
                     };
                     println!(
                         "[document.getElementById] {:?}\n{:?}",
                         arg.to_string(),
                         target
                     );
-                    return Some(RuntimeValue::HtmlElement {
+                    return Ok(Some(RuntimeValue::HtmlElement {
                         object: target,
                         property: None,
-                    });
+                    }));
                 }
                 */
 
-                let mut new_local_variables: VariableMap = VariableMap::new();
-
                 // find a function defined in the JS code
-                let function = {
-                    let mut f: Option<Function> = None;
-
-                    for func in &self.functions {
-                        if callee_value == RuntimeValue::StringLiteral(func.id.to_string()) {
-                            f = Some(func.clone());
-                        }
-                    }
-
-                    match f {
-                        Some(f) => f,
-                        None => unimplemented!("function {:?} doesn't exist", callee),
+                let function = match self.find_function(&callee_value) {
+                    Some(f) => f,
+                    None => {
+                        return Err(JsError::ReferenceError(format!(
+                            "{} is not defined",
+                            self.to_string(&callee_value)
+                        )))
                     }
                 };
 
-                // assign arguments to params as local variables
-                assert!(arguments.len() == function.params.len());
-                for i in 0..arguments.len() {
-                    let name = match self.eval(&function.params[i], env.clone()) {
-                        Some(value) => match value {
-                            RuntimeValue::Number(n) => {
-                                unimplemented!("id should be string but got {:?}", n)
-                            }
-                            RuntimeValue::StringLiteral(s) => s,
-                            RuntimeValue::HtmlElement {
-                                object,
-                                property: _,
-                            } => {
-                                panic!("unexpected runtime value {:?}", object)
-                            }
-                        },
-                        None => return None,
-                    };
-
-                    new_local_variables.insert(name, self.eval(&arguments[i], env.clone()));
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.eval(argument, env.clone())?);
                 }
 
                 // call function with arguments
-                self.eval(&function.body.clone(), env.clone())
+                self.call_function(&function, values, env)?
             }
             Node::Identifier(name) => {
                 /*
                 // find a value from global variables
                 for (var_name, var_value) in &self.global_variables {
                     if name == var_name && var_value.is_some() {
-                        return var_value.clone();
+                        return Ok(var_value.clone());
                     }
                 }
                 */
@@ -512,14 +1620,332 @@ impl JsRuntime {
                     None => Some(RuntimeValue::StringLiteral(name.to_string())),
                 }
             }
+            Node::NewExpression { callee, arguments } => {
+                let callee_value = match self.eval(callee, env.clone())? {
+                    Some(value) => value,
+                    None => return Ok(None),
+                };
+
+                // https://dom.spec.whatwg.org/#dom-mutationobserver-mutationobserver
+                if callee_value == RuntimeValue::StringLiteral("MutationObserver".to_string()) {
+                    // "1. Set this's callback to callback."
+                    // A callback is the name of a function declared in the JS code.
+                    let callback = match arguments.first() {
+                        Some(argument) => self.eval(argument, env.clone())?,
+                        None => None,
+                    };
+                    let callback = match callback {
+                        Some(RuntimeValue::StringLiteral(name))
+                            if self
+                                .find_function(&RuntimeValue::StringLiteral(name.clone()))
+                                .is_some() =>
+                        {
+                            name
+                        }
+                        _ => {
+                            return Err(JsError::TypeError(
+                                "MutationObserver callback is not a function".to_string(),
+                            ))
+                        }
+                    };
+                    let observer = Rc::new(RefCell::new(MutationObserver::new()));
+                    self.mutation_observers.push((observer.clone(), callback));
+                    return Ok(Some(RuntimeValue::MutationObserver {
+                        object: observer,
+                        property: None,
+                    }));
+                }
+
+                return Err(JsError::TypeError(format!(
+                    "{} is not a constructor",
+                    self.to_string(&callee_value)
+                )));
+            }
+            Node::ObjectExpression { properties } => {
+                let mut object = HashMap::new();
+                for property in properties {
+                    if let Some(Node::Property { key, value }) = property.as_deref() {
+                        let key = match key.as_deref() {
+                            Some(Node::Identifier(name)) | Some(Node::StringLiteral(name)) => {
+                                name.to_string()
+                            }
+                            _ => continue,
+                        };
+                        let value = self.eval(value, env.clone())?;
+                        object.insert(key, value);
+                    }
+                }
+                Some(RuntimeValue::Object(object))
+            }
+            // A property is evaluated as a part of ObjectExpression.
+            Node::Property { .. } => None,
+            Node::ArrayExpression { elements } => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.eval(element, env.clone())?);
+                }
+                Some(RuntimeValue::Array(values))
+            }
             Node::NumericLiteral(value) => Some(RuntimeValue::Number(*value)),
             Node::StringLiteral(value) => Some(RuntimeValue::StringLiteral(value.to_string())),
-        }
+            Node::BooleanLiteral(value) => Some(RuntimeValue::Boolean(*value)),
+        };
+        Ok(value)
     }
 
     pub fn execute(&mut self, program: &Program) {
+        self.execution_context_depth += 1;
         for node in program.body() {
-            self.eval(&Some(node.clone()), self.env.clone());
+            // https://html.spec.whatwg.org/multipage/webappapis.html#run-a-classic-script
+            // "If evaluationStatus is an abrupt completion, then: ... Report the exception given
+            // by evaluationStatus.[[Value]] for script."
+            if let Err(e) = self.eval(&Some(node.clone()), self.env.clone()) {
+                self.report_exception(&e);
+                break;
+            }
         }
+        self.clean_up_after_running_script();
+    }
+
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#clean-up-after-running-script
+    fn clean_up_after_running_script(&mut self) {
+        self.execution_context_depth -= 1;
+
+        // "3. If the JavaScript execution context stack is now empty, perform a microtask
+        // checkpoint."
+        if self.execution_context_depth == 0 {
+            self.perform_microtask_checkpoint();
+        }
+    }
+
+    /// Runs the microtasks. The only microtask that we have is to notify mutation observers.
+    /// This must also be called when the parser has inserted nodes after the last script, so
+    /// that the observers get the records of them.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#perform-a-microtask-checkpoint
+    pub fn perform_microtask_checkpoint(&mut self) {
+        // "1. If the event loop's performing a microtask checkpoint is true, then return."
+        if self.performing_microtask_checkpoint {
+            return;
+        }
+
+        // "2. Set the event loop's performing a microtask checkpoint to true."
+        self.performing_microtask_checkpoint = true;
+
+        // "3. While the event loop's microtask queue is not empty: ..."
+        // A mutation observer microtask is queued whenever a record is queued, and callbacks can
+        // queue more records, so this loops until no observer has a record.
+        while self
+            .mutation_observers
+            .iter()
+            .any(|(observer, _)| observer.borrow().has_records())
+        {
+            self.notify_mutation_observers();
+        }
+
+        // "8. Set the event loop's performing a microtask checkpoint to false."
+        self.performing_microtask_checkpoint = false;
+    }
+
+    /// https://dom.spec.whatwg.org/#notify-mutation-observers
+    fn notify_mutation_observers(&mut self) {
+        // "2. Let notifySet be a clone of the surrounding agent's pending mutation observers."
+        // All observers created by scripts are regarded as pending.
+        let notify_set = self.mutation_observers.clone();
+
+        // "5. For each mo of notifySet:"
+        for (observer, callback) in notify_set {
+            // "1. Let records be a clone of mo's record queue."
+            // "2. Empty mo's record queue."
+            let records = observer.borrow_mut().take_records();
+
+            // "3. For each node of mo's node list, remove all transient registered observers
+            // whose observer is mo from node's registered observer list."
+            MutationObserver::remove_transient_observers(
+                &observer,
+                &mut self.document.borrow_mut(),
+            );
+
+            // "4. If records is not empty, then invoke mo's callback with « records, mo » and
+            // "report", and callback this value mo."
+            if records.is_empty() {
+                continue;
+            }
+            let function = match self.find_function(&RuntimeValue::StringLiteral(callback.clone()))
+            {
+                Some(f) => f,
+                None => {
+                    self.report_exception(&JsError::ReferenceError(format!(
+                        "{} is not defined",
+                        callback
+                    )));
+                    continue;
+                }
+            };
+            let this = RuntimeValue::MutationObserver {
+                object: observer.clone(),
+                property: None,
+            };
+            let env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
+            env.borrow_mut()
+                .add_variable("this".to_string(), Some(this.clone()));
+            let result = self.call_function(
+                &function,
+                vec![Some(Self::records_to_array(records)), Some(this)],
+                env,
+            );
+            if let Err(e) = result {
+                self.report_exception(&e);
+            }
+        }
+    }
+
+    fn records_to_array(records: Vec<MutationRecord>) -> RuntimeValue {
+        RuntimeValue::Array(
+            records
+                .into_iter()
+                .map(|r| Some(RuntimeValue::MutationRecord(r)))
+                .collect(),
+        )
+    }
+
+    /// Converts an object literal to a MutationObserverInit dictionary. A member that is not a
+    /// boolean is regarded as not present.
+    fn to_mutation_observer_init(
+        &self,
+        options: &HashMap<String, Option<RuntimeValue>>,
+    ) -> MutationObserverInit {
+        let flag = |name: &str| match options.get(name) {
+            Some(Some(RuntimeValue::Boolean(b))) => Some(*b),
+            _ => None,
+        };
+        MutationObserverInit {
+            child_list: flag("childList").unwrap_or(false),
+            attributes: flag("attributes"),
+            character_data: flag("characterData"),
+            subtree: flag("subtree").unwrap_or(false),
+            attribute_old_value: flag("attributeOldValue"),
+            character_data_old_value: flag("characterDataOldValue"),
+            attribute_filter: match options.get("attributeFilter") {
+                Some(Some(RuntimeValue::Array(names))) => {
+                    Some(names.iter().flatten().map(|n| self.to_string(n)).collect())
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::token::HtmlTokenizer;
+
+    /// Parses `html`, runs `js` on the document and returns the runtime.
+    fn run(html: &str, js: &str) -> JsRuntime {
+        let document = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let mut runtime = JsRuntime::new(document, String::new());
+        let program = JsParser::new(JsLexer::new(js.to_string())).parse_ast();
+        runtime.execute(&program);
+        runtime
+    }
+
+    /// Returns the value of the attribute `name` of the element whose id is `id`.
+    fn attribute(runtime: &JsRuntime, id: &str, name: &str) -> Option<String> {
+        let document = runtime.document.borrow();
+        let element = get_element_by_id(&document, Some(document.root()), &id.to_string())
+            .expect("the element should exist");
+        document.node(element).element()?.get_attribute(name)
+    }
+
+    #[test]
+    fn set_inner_html_twice() {
+        let runtime = run(
+            r#"<div id="t"><p>old</p></div>"#,
+            r#"var t = document.getElementById("t");
+               t.innerHTML = "<b>one</b>";
+               t.innerHTML = "<i>two</i>three";"#,
+        );
+        let document = runtime.document.borrow();
+        let target = get_element_by_id(&document, Some(document.root()), &"t".to_string())
+            .expect("the element should exist");
+
+        let mut children = Vec::new();
+        let mut child = document.node(target).first_child();
+        while let Some(c) = child {
+            assert_eq!(document.node(c).parent(), Some(target));
+            children.push(c);
+            child = document.node(c).next_sibling();
+        }
+        assert_eq!(children.len(), 2);
+        assert_eq!(serialize_children(&document, target), "<i>two</i>three");
+    }
+
+    #[test]
+    fn error_stops_the_script() {
+        let runtime = run(
+            r#"<div id="t"></div>"#,
+            r#"var t = document.getElementById("t");
+               t.className = "before";
+               missing();
+               t.className = "after";"#,
+        );
+        assert_eq!(attribute(&runtime, "t", "class").as_deref(), Some("before"));
+    }
+
+    #[test]
+    fn error_in_callback_is_reported() {
+        let mut runtime = run(
+            r#"<div id="t" onclick="missing()"></div>"#,
+            r#"function throws(e) { missing(); }
+               function sets(e) { e.currentTarget.className = "clicked"; }
+               var t = document.getElementById("t");
+               t.addEventListener("click", throws);
+               t.addEventListener("click", sets);"#,
+        );
+        let target = {
+            let document = runtime.document.borrow();
+            get_element_by_id(&document, Some(document.root()), &"t".to_string())
+                .expect("the element should exist")
+        };
+        // The errors of the event handler and the first listener don't stop the dispatch.
+        runtime.fire_event(target, "click", true, true);
+        assert_eq!(
+            attribute(&runtime, "t", "class").as_deref(),
+            Some("clicked")
+        );
+    }
+
+    #[test]
+    fn set_property_parses_only_the_value() {
+        let runtime = run(
+            r#"<div id="t" style="color: red"></div>"#,
+            r#"var s = document.getElementById("t").style;
+               s.setProperty("color", "blue; display: none");
+               s.setProperty("color", "blue !important");
+               s.setProperty("width", "10px", "important");
+               s.setProperty("height", "10px", "bogus");
+               s.margin = "1px 2px";
+               s.padding = "1px !important";
+               s.fontSize = "20px !important";"#,
+        );
+        // The value can't add another declaration or set the priority.
+        assert_eq!(
+            attribute(&runtime, "t", "style").as_deref(),
+            Some(
+                "color: red; width: 10px !important; margin-top: 1px; margin-right: 2px; \
+                 margin-bottom: 1px; margin-left: 2px;"
+            )
+        );
+    }
+
+    #[test]
+    fn missing_argument_is_undefined() {
+        let runtime = run(
+            r#"<div id="t"></div>"#,
+            r#"console.log();
+               document.getElementById();
+               document.getElementById("t").className = "done";"#,
+        );
+        assert_eq!(attribute(&runtime, "t", "class").as_deref(), Some("done"));
     }
 }
@@ -3,7 +3,7 @@
 use std::string::{String, ToString};
 use std::vec::Vec;
 
-static RESERVED_WORDS: [&str; 3] = ["var", "function", "return"];
+static RESERVED_WORDS: [&str; 4] = ["var", "function", "return", "new"];
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
@@ -55,7 +55,9 @@ impl JsLexer {
         return num;
     }
 
-    fn consume_string(&mut self) -> String {
+    /// Consumes a string literal enclosed by `quote`, which is '"' or '\''.
+    /// https://262.ecma-international.org/12.0/#prod-StringLiteral
+    fn consume_string(&mut self, quote: char) -> String {
         let mut result = String::new();
         self.pos += 1;
 
@@ -64,7 +66,7 @@ impl JsLexer {
                 return result;
             }
 
-            if self.input[self.pos] == '"' {
+            if self.input[self.pos] == quote {
                 self.pos += 1;
                 return result;
             }
@@ -94,17 +96,22 @@ impl JsLexer {
 
     fn contains(&self, keyword: &str) -> bool {
         for i in 0..keyword.len() {
-            if keyword
-                .chars()
-                .nth(i)
-                .expect("failed to access to i-th char")
-                != self.input[self.pos + i]
+            if self.pos + i >= self.input.len()
+                || keyword
+                    .chars()
+                    .nth(i)
+                    .expect("failed to access to i-th char")
+                    != self.input[self.pos + i]
             {
                 return false;
             }
         }
 
-        true
+        // A keyword is not a prefix of an identifier such as `newNode`.
+        match self.input.get(self.pos + keyword.len()) {
+            Some(c) => !(c.is_ascii_alphanumeric() || *c == '_' || *c == '$'),
+            None => true,
+        }
     }
 
     fn check_reserved_word(&self) -> Option<String> {
@@ -154,12 +161,12 @@ impl JsLexer {
         let c = self.input[self.pos];
 
         let token = match c {
-            '+' | '-' | ';' | '=' | '(' | ')' | '{' | '}' | ',' | '.' => {
+            '+' | '-' | ';' | '=' | '(' | ')' | '{' | '}' | '[' | ']' | ',' | '.' | ':' => {
                 let t = Token::Punctuator(c);
                 self.pos += 1;
                 t
             }
-            '"' => Token::StringLiteral(self.consume_string()),
+            '"' | '\'' => Token::StringLiteral(self.consume_string(c)),
             '0'..='9' => Token::Number(self.consume_number()),
             // https://262.ecma-international.org/12.0/#prod-IdentifierStart
             'a'..='z' | 'A'..='Z' | '_' | '$' => Token::Identifier(self.consume_identifier()),
@@ -1,9 +1,16 @@
 //! https://www.w3.org/TR/css-box-3/
 //! https://www.w3.org/TR/css-layout-api-1/
 
+use crate::renderer::css::cascade::{cascade, CascadedValues};
 use crate::renderer::css::cssom::*;
-use crate::renderer::css::token::CssToken;
+use crate::renderer::css::properties::{
+    property_definition, ComputedValue, PropertyDefinition, PROPERTIES,
+};
+use crate::renderer::css::token::{CssToken, CssTokenizer};
+use crate::renderer::css::user_agent::ua_style_sheet;
+use crate::renderer::css::values::{Length, LengthContext, Viewport};
 use crate::renderer::html::dom::*;
+use crate::renderer::html::mutation_observer::{MutationRecord, MutationRecordType};
 use crate::renderer::layout::color::*;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -14,173 +21,422 @@ pub struct RenderStyle {
     background_color: Option<Color>,
     color: Option<Color>,
     display: DisplayType,
-    height: Option<f64>,
-    width: Option<f64>,
-    margin: Option<BoxInfo>,
-    padding: Option<BoxInfo>,
-    font_size: Option<FontSize>,
+    height: Option<Length>,
+    width: Option<Length>,
+    margin: Option<BoxInfo<Length>>,
+    padding: Option<BoxInfo<Length>>,
+    /// The computed font size, which is always in px.
+    font_size: Option<Length>,
+    font_weight: Option<FontWeight>,
+    list_style_type: Option<ListStyleType>,
+    font_family: Option<String>,
+    line_height: Option<LineHeight>,
 }
 
 impl RenderStyle {
-    pub fn new(node: &Rc<RefCell<Node>>) -> Self {
+    pub fn new(kind: &NodeKind) -> Self {
         Self {
             background_color: None,
             color: None,
-            display: Self::default_display_type(node),
+            display: Self::initial_display_type(kind),
             width: None,
             height: None,
             margin: None,
             padding: None,
-            font_size: Self::default_font_size(node),
+            font_size: None,
+            font_weight: None,
+            list_style_type: None,
+            font_family: None,
+            line_height: None,
         }
     }
 
-    fn default_display_type(node: &Rc<RefCell<Node>>) -> DisplayType {
-        match &node.borrow().kind() {
+    /// Returns the initial value of the display property, which is "inline". The default
+    /// displays of HTML elements are in the user agent style sheet.
+    /// https://www.w3.org/TR/css-display-3/#the-display-properties
+    fn initial_display_type(kind: &NodeKind) -> DisplayType {
+        match kind {
+            // The document isn't an element, but it's the root of the render tree.
             NodeKind::Document => DisplayType::Block,
-            NodeKind::Element(element) => match element.kind() {
-                ElementKind::Html
-                | ElementKind::Body
-                | ElementKind::Div
-                | ElementKind::Ul
-                | ElementKind::Li
-                | ElementKind::H1
-                | ElementKind::P => DisplayType::Block,
-                ElementKind::Script | ElementKind::Head | ElementKind::Style => {
-                    DisplayType::DisplayNone
-                }
-                _ => DisplayType::Inline,
-            },
-            NodeKind::Text(_) => DisplayType::Inline,
+            _ => DisplayType::Inline,
         }
     }
 
-    fn default_font_size(node: &Rc<RefCell<Node>>) -> Option<FontSize> {
-        match &node.borrow().kind() {
-            NodeKind::Element(element) => match element.kind() {
-                ElementKind::H1 => Some(FontSize::XXLarge),
-                ElementKind::H2 => Some(FontSize::XLarge),
-                _ => None,
-            },
-            _ => None,
+    /// Finds the computed value of every property from `cascaded_values`. A property whose
+    /// cascaded value is "inherit", or an inherited property without a cascaded value, takes
+    /// the computed value of `parent_style`. The others take their initial values.
+    /// `root_font_size` is the computed font size of the root element, or None if this is the
+    /// style of the root element.
+    /// https://www.w3.org/TR/css-cascade-4/#defaulting
+    /// https://www.w3.org/TR/css-cascade-4/#computed
+    fn compute(
+        &mut self,
+        cascaded_values: &CascadedValues,
+        parent_style: Option<&RenderStyle>,
+        root_font_size: Option<f64>,
+        viewport: Viewport,
+    ) {
+        for declaration in cascaded_values.declarations() {
+            if property_definition(&declaration.property).is_none() {
+                println!(
+                    "warning: css property {} is not supported yet",
+                    declaration.property
+                );
+            }
         }
-    }
 
-    fn inherit(&mut self, parent_style: &RenderStyle) {
-        if self.color.is_none() {
-            self.color = Some(parent_style.color().clone());
-        }
-        if self.background_color.is_none() {
-            self.background_color = Some(parent_style.background_color().clone());
-        }
-        if self.height.is_none() {
-            self.height = Some(parent_style.height().clone());
-        }
-        if self.width.is_none() {
-            self.width = Some(parent_style.width().clone());
-        }
-        if self.margin.is_none() {
-            self.margin = Some(parent_style.margin().clone());
-        }
-        if self.padding.is_none() {
-            self.padding = Some(parent_style.padding().clone());
-        }
-        if self.font_size.is_none() {
-            self.font_size = Some(parent_style.font_size().clone());
+        for definition in PROPERTIES.iter() {
+            // "When specified in the font-size property itself, these units refer to the
+            // computed font metrics of the parent element (or the computed font metrics
+            // corresponding to the initial values of the font property, if there is no parent
+            // element)."
+            // https://www.w3.org/TR/css-values-4/#font-relative-lengths
+            let font_size = match parent_style {
+                Some(parent_style) if definition.name == "font-size" => parent_style.font_size(),
+                _ => self.font_size(),
+            };
+            // The font size of the root element is not computed yet when its font-size is
+            // computed, so `rem` in it is the initial value as the spec says.
+            let context = LengthContext {
+                font_size,
+                root_font_size: root_font_size.unwrap_or_else(|| self.font_size()),
+                viewport,
+            };
+
+            let cascaded_value = cascaded_values.get(definition.name).map(|d| &d.value);
+            let inherit = match cascaded_value {
+                // "If the cascaded value of a property is the inherit keyword, the property's
+                // specified and computed values are the inherited value."
+                // https://www.w3.org/TR/css-cascade-4/#inherit
+                Some(ComponentValue::Keyword(keyword))
+                    if keyword.eq_ignore_ascii_case("inherit") =>
+                {
+                    true
+                }
+                // "If the cascaded value of a property is the initial keyword, the property's
+                // specified value is its initial value."
+                // https://www.w3.org/TR/css-cascade-4/#initial
+                Some(ComponentValue::Keyword(keyword))
+                    if keyword.eq_ignore_ascii_case("initial") =>
+                {
+                    false
+                }
+                // "If the cascaded value of a property is the unset keyword, then if it is an
+                // inherited property, this is treated as inherit, and if it is not, this is
+                // treated as initial."
+                // https://www.w3.org/TR/css-cascade-4/#inherit-initial
+                Some(ComponentValue::Keyword(keyword)) if keyword.eq_ignore_ascii_case("unset") => {
+                    definition.inherited
+                }
+                Some(value) => {
+                    if self.set_property(definition, Some(value), &context) {
+                        continue;
+                    }
+                    // The value that the renderer doesn't support is ignored as if there were
+                    // no cascaded value.
+                    println!(
+                        "warning: css value {:?} for {} is not supported yet",
+                        value, definition.name
+                    );
+                    definition.inherited
+                }
+                // "If there is no cascaded value, ... inherited properties ... get the
+                // inherited value and the others get the initial value."
+                // https://www.w3.org/TR/css-cascade-4/#specified
+                None => definition.inherited,
+            };
+
+            match parent_style {
+                Some(parent_style) if inherit => self.inherit_property(definition, parent_style),
+                // "the root element, which has no parent element, ... the inherited value is
+                // the initial value of the property."
+                _ => {
+                    let initial = CssParser::new(CssTokenizer::new(definition.initial.to_string()))
+                        .parse_list_of_component_values();
+                    self.set_property(definition, initial.first(), &context);
+                }
+            }
         }
     }
 
-    pub fn background_color(&self) -> Color {
-        if let Some(ref bc) = self.background_color {
-            bc.clone()
-        } else {
-            Color::from_name("white")
+    /// Sets the computed value of the property of `definition` from `value`. None resets it to
+    /// the default of the renderer. Returns false if the value is not supported.
+    fn set_property(
+        &mut self,
+        definition: &PropertyDefinition,
+        value: Option<&ComponentValue>,
+        context: &LengthContext,
+    ) -> bool {
+        let value = match value {
+            Some(v) => v,
+            None => {
+                self.reset_property(definition.name);
+                return true;
+            }
+        };
+
+        // The lengths of the properties whose computed values are absolute lengths are
+        // converted to px here.
+        let length = Self::to_length(value).map(|length| match definition.computed_value {
+            ComputedValue::AbsoluteLength => length.compute(context),
+            ComputedValue::AsSpecified => length,
+        });
+
+        match definition.name {
+            "background-color" => match value {
+                // The background of the parent shows through a transparent background.
+                ComponentValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("transparent") => {
+                    self.background_color = None
+                }
+                _ => match Self::to_color(value) {
+                    Some(color) => self.background_color = Some(color),
+                    None => return false,
+                },
+            },
+            "color" => match Self::to_color(value) {
+                Some(color) => self.color = Some(color),
+                None => return false,
+            },
+            "display" => match Self::keyword(value).and_then(DisplayType::from_keyword) {
+                Some(display) => self.display = display,
+                None => return false,
+            },
+            "font-size" => {
+                let size = match Self::keyword(value) {
+                    Some(keyword) => FontSize::from_keyword(keyword).map(|size| size.to_px()),
+                    // "Percentages: refer to parent element's font size"
+                    // https://www.w3.org/TR/css-fonts-4/#font-size-prop
+                    None => length.map(|length| length.to_px(context, context.font_size)),
+                };
+                match size {
+                    // "Negative values are invalid."
+                    Some(size) if size >= 0.0 => self.font_size = Some(Length::Px(size)),
+                    _ => return false,
+                }
+            }
+            "font-weight" => match FontWeight::from_value(value) {
+                Some(weight) => self.font_weight = Some(weight),
+                None => return false,
+            },
+            // TODO: support the list of font families. Only the first one is used.
+            "font-family" => match value {
+                ComponentValue::Keyword(name)
+                | ComponentValue::InputToken(CssToken::StringToken(name)) => {
+                    self.font_family = Some(name.to_string())
+                }
+                _ => return false,
+            },
+            "list-style-type" => match Self::keyword(value).and_then(ListStyleType::from_keyword) {
+                Some(list_style_type) => self.list_style_type = Some(list_style_type),
+                None => return false,
+            },
+            "line-height" => {
+                let line_height = match value {
+                    ComponentValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("normal") => {
+                        LineHeight::Normal
+                    }
+                    // A number is inherited as it is, so that a child multiplies it by its own
+                    // font size.
+                    ComponentValue::Number(number) => LineHeight::Number(*number),
+                    // "Percentages: refer to the font size of the element itself"
+                    _ => match length {
+                        Some(length) => LineHeight::Px(length.to_px(context, context.font_size)),
+                        None => return false,
+                    },
+                };
+                // "Negative values are illegal."
+                match line_height {
+                    LineHeight::Number(v) | LineHeight::Px(v) if v < 0.0 => return false,
+                    _ => self.line_height = Some(line_height),
+                }
+            }
+            "width" | "height" => {
+                // "auto" is None, which is resolved in layout.
+                let length = match value {
+                    ComponentValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto") => {
+                        None
+                    }
+                    _ => match length {
+                        Some(length) => Some(length),
+                        None => return false,
+                    },
+                };
+                if definition.name == "width" {
+                    self.width = length;
+                } else {
+                    self.height = length;
+                }
+            }
+            // TODO: support "auto" margins.
+            "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => match length {
+                Some(length) => {
+                    let side = &definition.name["margin-".len()..];
+                    self.margin = Some(Self::set_side(self.margin.take(), side, length));
+                }
+                None => return false,
+            },
+            "padding-top" | "padding-right" | "padding-bottom" | "padding-left" => match length {
+                Some(length) => {
+                    let side = &definition.name["padding-".len()..];
+                    self.padding = Some(Self::set_side(self.padding.take(), side, length));
+                }
+                None => return false,
+            },
+            _ => return false,
         }
+        true
     }
 
-    pub fn color(&self) -> Color {
-        if let Some(ref c) = self.color {
-            c.clone()
-        } else {
-            Color::from_name("black")
+    /// Resets the property `name` to the default of the renderer, which is returned by the
+    /// getter of the property.
+    fn reset_property(&mut self, name: &str) {
+        match name {
+            "background-color" => self.background_color = None,
+            "color" => self.color = None,
+            "font-size" => self.font_size = None,
+            "font-weight" => self.font_weight = None,
+            "font-family" => self.font_family = None,
+            "list-style-type" => self.list_style_type = None,
+            "line-height" => self.line_height = None,
+            "width" => self.width = None,
+            "height" => self.height = None,
+            _ => {}
         }
     }
 
-    pub fn height(&self) -> f64 {
-        if let Some(h) = self.height {
-            h
-        } else {
-            0f64
+    /// Sets the property of `definition` to the computed value of `parent_style`.
+    /// https://www.w3.org/TR/css-cascade-4/#inheriting
+    fn inherit_property(&mut self, definition: &PropertyDefinition, parent_style: &RenderStyle) {
+        match definition.name {
+            "background-color" => self.background_color = parent_style.background_color.clone(),
+            "color" => self.color = parent_style.color.clone(),
+            "display" => self.display = parent_style.display,
+            "font-size" => self.font_size = parent_style.font_size,
+            "font-weight" => self.font_weight = parent_style.font_weight,
+            "font-family" => self.font_family = parent_style.font_family.clone(),
+            "list-style-type" => self.list_style_type = parent_style.list_style_type,
+            "line-height" => self.line_height = parent_style.line_height,
+            "width" => self.width = parent_style.width,
+            "height" => self.height = parent_style.height,
+            "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => {
+                let side = &definition.name["margin-".len()..];
+                let length = Self::get_side(&parent_style.margin, side);
+                self.margin = Some(Self::set_side(self.margin.take(), side, length));
+            }
+            "padding-top" | "padding-right" | "padding-bottom" | "padding-left" => {
+                let side = &definition.name["padding-".len()..];
+                let length = Self::get_side(&parent_style.padding, side);
+                self.padding = Some(Self::set_side(self.padding.take(), side, length));
+            }
+            _ => {}
         }
     }
 
-    pub fn display(&self) -> DisplayType {
-        self.display
+    fn keyword(value: &ComponentValue) -> Option<&str> {
+        match value {
+            ComponentValue::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
     }
 
-    pub fn width(&self) -> f64 {
-        if let Some(w) = self.width {
-            w
-        } else {
-            // 1200 is a default value defined at src/gui/browser_window/window.ui
-            1200.0f64
+    fn to_color(value: &ComponentValue) -> Option<Color> {
+        match value {
+            ComponentValue::Keyword(name) => Some(Color::from_name(&name.to_ascii_lowercase())),
+            ComponentValue::InputToken(CssToken::HashToken(color_code, _)) => {
+                Some(Color::from_code(&format!("#{}", color_code)))
+            }
+            _ => None,
         }
     }
 
-    pub fn margin(&self) -> BoxInfo {
-        if let Some(ref m) = self.margin {
-            m.clone()
-        } else {
-            BoxInfo::new(0.0, 0.0, 0.0, 0.0)
+    /// Returns the length that `value` represents.
+    fn to_length(value: &ComponentValue) -> Option<Length> {
+        match value {
+            ComponentValue::Length(length) => Some(*length),
+            // A number is treated as a length in px.
+            // https://quirks.spec.whatwg.org/#the-unitless-length-quirk
+            ComponentValue::Number(value) => Some(Length::Px(*value)),
+            _ => None,
         }
     }
 
-    pub fn padding(&self) -> BoxInfo {
-        if let Some(ref p) = self.padding {
-            p.clone()
-        } else {
-            BoxInfo::new(0.0, 0.0, 0.0, 0.0)
+    /// Returns the size of `side` ("top", "right", "bottom" or "left") in `sizes`, which is 0 if
+    /// `sizes` is None.
+    fn get_side(sizes: &Option<BoxInfo<Length>>, side: &str) -> Length {
+        match (sizes, side) {
+            (Some(sizes), "top") => sizes.top,
+            (Some(sizes), "right") => sizes.right,
+            (Some(sizes), "bottom") => sizes.bottom,
+            (Some(sizes), "left") => sizes.left,
+            _ => Length::Px(0.0),
         }
     }
 
-    pub fn font_size(&self) -> FontSize {
-        if let Some(ref s) = self.font_size {
-            s.clone()
-        } else {
-            FontSize::Medium
+    /// Returns `sizes` whose `side` ("top", "right", "bottom" or "left") is set to `value`. The
+    /// other sides are 0 if `sizes` is None.
+    fn set_side(sizes: Option<BoxInfo<Length>>, side: &str, value: Length) -> BoxInfo<Length> {
+        let zero = Length::Px(0.0);
+        let mut sizes = sizes.unwrap_or(BoxInfo::new(zero, zero, zero, zero));
+        match side {
+            "top" => sizes.top = value,
+            "right" => sizes.right = value,
+            "bottom" => sizes.bottom = value,
+            "left" => sizes.left = value,
+            _ => {}
         }
+        sizes
     }
 
-    pub fn margin_top(&self) -> f64 {
-        self.margin().top
+    /// Returns the background color, or None if the background is transparent.
+    pub fn background_color(&self) -> Option<Color> {
+        self.background_color.clone()
     }
 
-    pub fn margin_left(&self) -> f64 {
-        self.margin().left
+    pub fn color(&self) -> Color {
+        if let Some(ref c) = self.color {
+            c.clone()
+        } else {
+            Color::from_name("black")
+        }
     }
 
-    pub fn margin_right(&self) -> f64 {
-        self.margin().right
+    pub fn display(&self) -> DisplayType {
+        self.display
     }
 
-    pub fn margin_bottom(&self) -> f64 {
-        self.margin().bottom
+    /// Returns the computed font size in px.
+    pub fn font_size(&self) -> f64 {
+        match self.font_size {
+            Some(Length::Px(size)) => size,
+            _ => FontSize::Medium.to_px(),
+        }
     }
 
-    pub fn padding_top(&self) -> f64 {
-        self.padding().top
+    pub fn font_weight(&self) -> FontWeight {
+        self.font_weight.unwrap_or(FontWeight::Normal)
     }
 
-    pub fn padding_left(&self) -> f64 {
-        self.padding().left
+    pub fn list_style_type(&self) -> ListStyleType {
+        self.list_style_type.unwrap_or(ListStyleType::Disc)
     }
 
-    pub fn padding_right(&self) -> f64 {
-        self.padding().right
+    /// Returns the used line height in px.
+    /// https://www.w3.org/TR/css-inline-3/#line-height-property
+    pub fn line_height(&self) -> f64 {
+        match self.line_height {
+            // "The used value of the property is this number multiplied by the element's font
+            // size."
+            Some(LineHeight::Number(number)) => number * self.font_size(),
+            Some(LineHeight::Px(px)) => px,
+            // "normal: ... We recommend a used value for normal between 1.0 to 1.2."
+            _ => 1.2 * self.font_size(),
+        }
     }
 
-    pub fn padding_bottom(&self) -> f64 {
-        self.padding().bottom
+    /// Returns the font family name, or None for the default font.
+    /// https://www.w3.org/TR/css-fonts-4/#font-family-prop
+    pub fn font_family(&self) -> Option<String> {
+        self.font_family.clone()
     }
 }
 
@@ -190,39 +446,205 @@ pub enum DisplayType {
     Block,
     /// https://www.w3.org/TR/css-display-3/#valdef-display-inline
     Inline,
+    /// "The list-item keyword causes the element to generate a ::marker pseudo-element box
+    /// ... and a block-level box for the element itself."
+    /// https://www.w3.org/TR/css-display-3/#valdef-display-list-item
+    ListItem,
     /// https://www.w3.org/TR/css-display-3/#valdef-display-none
     DisplayNone,
 }
 
+impl DisplayType {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.to_ascii_lowercase().as_str() {
+            "block" => Some(DisplayType::Block),
+            "inline" => Some(DisplayType::Inline),
+            "list-item" => Some(DisplayType::ListItem),
+            "none" => Some(DisplayType::DisplayNone),
+            _ => None,
+        }
+    }
+}
+
+/// The sizes of the four sides of a box, such as margins and paddings. A style has the sizes as
+/// lengths, and they are resolved to px in layout.
 #[derive(Debug, Clone, PartialEq)]
-pub struct BoxInfo {
-    top: f64,
-    right: f64,
-    left: f64,
-    bottom: f64,
+pub struct BoxInfo<T = f64> {
+    top: T,
+    right: T,
+    bottom: T,
+    left: T,
 }
 
-impl BoxInfo {
-    fn new(top: f64, right: f64, left: f64, bottom: f64) -> Self {
+impl<T> BoxInfo<T> {
+    /// The sizes are in the same order as the values of shorthand properties such as margin.
+    fn new(top: T, right: T, bottom: T, left: T) -> Self {
         Self {
             top,
             right,
-            left,
             bottom,
+            left,
         }
     }
 }
 
+impl BoxInfo<Length> {
+    /// "Percentages: refer to logical width of containing block" for both of margins and
+    /// paddings.
+    /// https://www.w3.org/TR/css-box-3/#margin-physical
+    /// https://www.w3.org/TR/css-box-3/#padding-physical
+    fn to_px(&self, context: &LengthContext, containing_block_width: f64) -> BoxInfo {
+        BoxInfo::new(
+            self.top.to_px(context, containing_block_width),
+            self.right.to_px(context, containing_block_width),
+            self.bottom.to_px(context, containing_block_width),
+            self.left.to_px(context, containing_block_width),
+        )
+    }
+}
+
+/// The absolute-size keywords of the font-size property.
 /// https://www.w3.org/TR/css-fonts-4/#absolute-size-mapping
-/// https://docs.gtk.org/Pango/pango_markup.html
-/// align with pango markup syntax
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FontSize {
+    XXSmall,
+    XSmall,
+    Small,
     Medium,
+    Large,
     XLarge,
     XXLarge,
 }
 
+impl FontSize {
+    /// https://www.w3.org/TR/css-fonts-4/#valdef-font-size-absolute-size
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.to_ascii_lowercase().as_str() {
+            "xx-small" => Some(FontSize::XXSmall),
+            "x-small" => Some(FontSize::XSmall),
+            "small" => Some(FontSize::Small),
+            "medium" => Some(FontSize::Medium),
+            "large" => Some(FontSize::Large),
+            "x-large" => Some(FontSize::XLarge),
+            "xx-large" => Some(FontSize::XXLarge),
+            _ => None,
+        }
+    }
+
+    /// Returns the size in px, where medium is 16px.
+    /// https://www.w3.org/TR/css-fonts-4/#absolute-size-mapping
+    pub fn to_px(&self) -> f64 {
+        match self {
+            FontSize::XXSmall => 16.0 * 3.0 / 5.0,
+            FontSize::XSmall => 16.0 * 3.0 / 4.0,
+            FontSize::Small => 16.0 * 8.0 / 9.0,
+            FontSize::Medium => 16.0,
+            FontSize::Large => 16.0 * 6.0 / 5.0,
+            FontSize::XLarge => 24.0,
+            FontSize::XXLarge => 32.0,
+        }
+    }
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-weight-prop
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FontWeight {
+    Normal,
+    Bold,
+}
+
+impl FontWeight {
+    /// "normal: Same as 400. bold: Same as 700."
+    /// A number weight is rounded to normal or bold, because they're the only weights.
+    /// https://www.w3.org/TR/css-fonts-4/#font-weight-absolute-values
+    fn from_value(value: &ComponentValue) -> Option<Self> {
+        match value {
+            ComponentValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("normal") => {
+                Some(FontWeight::Normal)
+            }
+            ComponentValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("bold") => {
+                Some(FontWeight::Bold)
+            }
+            ComponentValue::Number(weight) if (1.0..=1000.0).contains(weight) => {
+                if *weight < 600.0 {
+                    Some(FontWeight::Normal)
+                } else {
+                    Some(FontWeight::Bold)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The computed value of the line-height property.
+/// https://www.w3.org/TR/css-inline-3/#line-height-property
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineHeight {
+    Normal,
+    Number(f64),
+    /// An absolute length in px.
+    Px(f64),
+}
+
+/// https://www.w3.org/TR/css-lists-3/#text-markers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ListStyleType {
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    None,
+}
+
+impl ListStyleType {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.to_ascii_lowercase().as_str() {
+            "disc" => Some(ListStyleType::Disc),
+            "circle" => Some(ListStyleType::Circle),
+            "square" => Some(ListStyleType::Square),
+            "decimal" => Some(ListStyleType::Decimal),
+            "none" => Some(ListStyleType::None),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of the marker of the `ordinal`-th list item, or None if the list item
+    /// has no marker.
+    /// https://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+    pub fn marker(&self, ordinal: usize) -> Option<String> {
+        match self {
+            ListStyleType::Disc => Some("\u{2022}".to_string()),
+            ListStyleType::Circle => Some("\u{25E6}".to_string()),
+            ListStyleType::Square => Some("\u{25AA}".to_string()),
+            // "the suffix is "." (U+002E FULL STOP)"
+            ListStyleType::Decimal => Some(format!("{}.", ordinal)),
+            ListStyleType::None => None,
+        }
+    }
+}
+
+/// The used values of the box of a render object in px, which are calculated in layout.
+/// https://www.w3.org/TR/css-cascade-4/#used
+#[derive(Debug, Clone, PartialEq)]
+struct UsedValues {
+    width: f64,
+    height: f64,
+    margin: BoxInfo,
+    padding: BoxInfo,
+}
+
+impl UsedValues {
+    fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            margin: BoxInfo::new(0.0, 0.0, 0.0, 0.0),
+            padding: BoxInfo::new(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct LayoutPosition {
     x: f64,
@@ -238,28 +660,35 @@ impl LayoutPosition {
 #[derive(Debug, Clone)]
 pub struct RenderObject {
     // Similar structure with Node in renderer/dom.rs.
-    node: Rc<RefCell<Node>>,
+    node: NodeId,
     first_child: Option<Rc<RefCell<RenderObject>>>,
     next_sibling: Option<Rc<RefCell<RenderObject>>>,
     // CSS information.
     pub style: RenderStyle,
     // Layout information.
     position: LayoutPosition,
+    used: UsedValues,
 }
 
 impl RenderObject {
-    fn new(node: Rc<RefCell<Node>>) -> Self {
+    fn new(node: NodeId, kind: &NodeKind) -> Self {
         Self {
-            node: node.clone(),
+            node,
             first_child: None,
             next_sibling: None,
-            style: RenderStyle::new(&node),
+            style: RenderStyle::new(kind),
             position: LayoutPosition::new(0.0, 0.0),
+            used: UsedValues::new(0.0, 0.0),
         }
     }
 
-    pub fn kind(&self) -> NodeKind {
-        self.node.borrow().kind().clone()
+    /// Returns the kind of the DOM node in `document` that this object renders.
+    pub fn kind<'a>(&self, document: &'a Document) -> &'a NodeKind {
+        document.node(self.node).kind()
+    }
+
+    pub fn node(&self) -> NodeId {
+        self.node
     }
 
     pub fn first_child(&self) -> Option<Rc<RefCell<RenderObject>>> {
@@ -270,117 +699,116 @@ impl RenderObject {
         self.next_sibling.as_ref().map(|n| n.clone())
     }
 
-    pub fn set_style(&mut self, declarations: Vec<Declaration>) {
-        for declaration in declarations {
-            match declaration.property.as_str() {
-                "background-color" => {
-                    if let ComponentValue::Keyword(value) = &declaration.value {
-                        self.style.background_color = Some(Color::from_name(value));
-                    }
+    /// Returns the used width in px.
+    pub fn width(&self) -> f64 {
+        self.used.width
+    }
 
-                    if let ComponentValue::InputToken(value) = &declaration.value {
-                        if let CssToken::HashToken(color_code) = value {
-                            self.style.background_color = Some(Color::from_code(color_code));
-                        }
-                    }
-                }
-                "color" => {
-                    if let ComponentValue::Keyword(value) = &declaration.value {
-                        self.style.color = Some(Color::from_name(value));
-                    }
+    /// Returns the used height in px.
+    pub fn height(&self) -> f64 {
+        self.used.height
+    }
 
-                    if let ComponentValue::InputToken(value) = &declaration.value {
-                        if let CssToken::HashToken(color_code) = value {
-                            self.style.color = Some(Color::from_code(color_code));
-                        }
-                    }
-                }
-                "height" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.height = Some(value);
-                    }
-                }
-                "width" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.width = Some(value);
-                    }
-                }
-                "margin" => {
-                    // TODO: support string (e.g. "auto")
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.margin = Some(BoxInfo::new(value, value, value, value));
-                    }
-                }
-                "margin-top" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.margin = match &self.style.margin {
-                            Some(m) => Some(BoxInfo::new(value, m.right, m.bottom, m.left)),
-                            None => Some(BoxInfo::new(value, 0.0, 0.0, 0.0)),
-                        };
-                    }
-                }
-                "margin-right" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.margin = match &self.style.margin {
-                            Some(m) => Some(BoxInfo::new(m.top, value, m.bottom, m.left)),
-                            None => Some(BoxInfo::new(0.0, value, 0.0, 0.0)),
-                        };
-                    }
-                }
-                "margin-bottom" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.margin = match &self.style.margin {
-                            Some(m) => Some(BoxInfo::new(m.top, m.right, value, m.left)),
-                            None => Some(BoxInfo::new(0.0, 0.0, value, 0.0)),
-                        };
-                    }
-                }
-                "margin-left" => {
-                    if let ComponentValue::Number(value) = declaration.value {
-                        self.style.margin = match &self.style.margin {
-                            Some(m) => Some(BoxInfo::new(m.top, m.right, m.bottom, value)),
-                            None => Some(BoxInfo::new(0.0, 0.0, 0.0, value)),
-                        };
-                    }
-                }
-                // TODO: support padding
-                _ => println!(
-                    "warning: css property {} is not supported yet",
-                    declaration.property,
-                ),
-            }
-        }
+    pub fn margin_top(&self) -> f64 {
+        self.used.margin.top
+    }
+
+    pub fn margin_left(&self) -> f64 {
+        self.used.margin.left
+    }
+
+    pub fn margin_right(&self) -> f64 {
+        self.used.margin.right
+    }
+
+    pub fn margin_bottom(&self) -> f64 {
+        self.used.margin.bottom
     }
 
-    fn layout(&mut self, parent_style: &RenderStyle, parent_position: &LayoutPosition) {
-        match parent_style.display {
+    pub fn padding_top(&self) -> f64 {
+        self.used.padding.top
+    }
+
+    pub fn padding_left(&self) -> f64 {
+        self.used.padding.left
+    }
+
+    pub fn padding_right(&self) -> f64 {
+        self.used.padding.right
+    }
+
+    pub fn padding_bottom(&self) -> f64 {
+        self.used.padding.bottom
+    }
+
+    /// Calculates the used values and the position of this object. `previous` is the previous
+    /// sibling, or the parent if this is the first child, and `containing_block` is the used
+    /// values of the parent.
+    fn layout(
+        &mut self,
+        previous: &RenderObject,
+        containing_block: &UsedValues,
+        root_font_size: f64,
+        viewport: Viewport,
+    ) {
+        let context = LengthContext {
+            font_size: self.style.font_size(),
+            root_font_size,
+            viewport,
+        };
+
+        // "Percentages: refer to width of containing block"
+        // https://www.w3.org/TR/CSS2/visudet.html#the-width-property
+        // TODO: calculate "auto" width of inline elements from their contents.
+        self.used.width = match self.style.width {
+            Some(w) => w.to_px(&context, containing_block.width),
+            None => containing_block.width,
+        };
+        // "The percentage is calculated with respect to the height of the generated box's
+        // containing block."
+        // https://www.w3.org/TR/CSS2/visudet.html#the-height-property
+        // TODO: calculate "auto" height from the contents.
+        self.used.height = match self.style.height {
+            Some(h) => h.to_px(&context, containing_block.height),
+            None => 0.0,
+        };
+        self.used.margin = match &self.style.margin {
+            Some(m) => m.to_px(&context, containing_block.width),
+            None => BoxInfo::new(0.0, 0.0, 0.0, 0.0),
+        };
+        self.used.padding = match &self.style.padding {
+            Some(p) => p.to_px(&context, containing_block.width),
+            None => BoxInfo::new(0.0, 0.0, 0.0, 0.0),
+        };
+
+        match previous.style.display {
             DisplayType::Inline => {
                 match self.style.display() {
-                    DisplayType::Block => {
+                    DisplayType::Block | DisplayType::ListItem => {
                         // TODO: set position property
-                        self.position.x = self.style.margin().left;
-                        self.position.y = self.style.margin().top + parent_style.height();
+                        self.position.x = self.used.margin.left;
+                        self.position.y = self.used.margin.top + previous.used.height;
                     }
                     DisplayType::Inline => {
-                        self.position.x = parent_position.x + parent_style.width();
-                        self.position.y = parent_position.y;
+                        self.position.x = previous.position.x + previous.used.width;
+                        self.position.y = previous.position.y;
                     }
                     DisplayType::DisplayNone => {}
                 }
             }
-            DisplayType::Block => {
+            DisplayType::Block | DisplayType::ListItem => {
                 match self.style.display() {
-                    DisplayType::Block => {
-                        self.position.x = self.style.margin().left;
-                        self.position.y = parent_position.y
-                            + parent_style.height()
-                            + parent_style.margin().bottom
-                            + self.style.margin().top;
+                    DisplayType::Block | DisplayType::ListItem => {
+                        self.position.x = self.used.margin.left;
+                        self.position.y = previous.position.y
+                            + previous.used.height
+                            + previous.used.margin.bottom
+                            + self.used.margin.top;
                     }
                     DisplayType::Inline => {
                         // TODO: set position property
                         self.position.x = 0.0;
-                        self.position.y = parent_style.height();
+                        self.position.y = previous.used.height;
                     }
                     DisplayType::DisplayNone => {}
                 }
@@ -388,77 +816,79 @@ impl RenderObject {
             DisplayType::DisplayNone => {}
         }
     }
-
-    fn is_node_selected(&self, selector: &Selector) -> bool {
-        match &self.kind() {
-            NodeKind::Element(e) => match selector {
-                Selector::TypeSelector(type_name) => {
-                    if Element::element_kind_to_string(e.kind()) == *type_name {
-                        return true;
-                    }
-                    return false;
-                }
-                Selector::ClassSelector(class_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name == "class" && attr.value == *class_name {
-                            return true;
-                        }
-                    }
-                    return false;
-                }
-                Selector::IdSelector(id_name) => {
-                    for attr in &e.attributes() {
-                        if attr.name == "id" && attr.value == *id_name {
-                            return true;
-                        }
-                    }
-                    return false;
-                }
-                Selector::UnknownSelector => false,
-            },
-            _ => false,
-        }
-    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RenderTree {
     pub root: Option<Rc<RefCell<RenderObject>>>,
+    viewport: Viewport,
 }
 
 impl RenderTree {
-    pub fn new(root: Rc<RefCell<Node>>, cssom: &StyleSheet) -> Self {
+    pub fn new(document: &Document, cssom: &StyleSheet, viewport: Viewport) -> Self {
         let mut tree = Self {
-            root: Self::create_render_tree(&Some(root), &None, cssom),
+            root: Self::create_render_tree(
+                document,
+                Some(document.root()),
+                &None,
+                None,
+                cssom,
+                viewport,
+            ),
+            viewport,
         };
 
-        tree.layout();
+        tree.layout(document);
 
         tree
     }
 
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// Returns the computed font size of the root element in px, which `rem` is relative to.
+    /// The root element is the first child of the document in the render tree.
+    fn root_font_size(&self) -> f64 {
+        match self
+            .root
+            .as_ref()
+            .and_then(|root| root.borrow().first_child())
+        {
+            Some(html) => html.borrow().style.font_size(),
+            None => FontSize::Medium.to_px(),
+        }
+    }
+
+    /// Creates the render object of `node`. `root_font_size` is the computed font size of the
+    /// root element, or None if `node` is the root element or the document.
     fn create_render_object(
-        node: &Option<Rc<RefCell<Node>>>,
+        document: &Document,
+        node: Option<NodeId>,
         parent_obj: &Option<Rc<RefCell<RenderObject>>>,
+        root_font_size: Option<f64>,
         cssom: &StyleSheet,
+        viewport: Viewport,
     ) -> Option<Rc<RefCell<RenderObject>>> {
         match node {
             Some(n) => {
-                let render_object = Rc::new(RefCell::new(RenderObject::new(n.clone())));
-                if let Some(parent) = parent_obj {
-                    render_object
-                        .borrow_mut()
-                        .style
-                        .inherit(&parent.borrow().style);
-                }
+                let kind = document.node(n).kind();
+                let render_object = Rc::new(RefCell::new(RenderObject::new(n, kind)));
 
-                // apply CSS rules to RenderObject.
-                for rule in &cssom.rules {
-                    if render_object.borrow().is_node_selected(&rule.selector) {
-                        render_object
-                            .borrow_mut()
-                            .set_style(rule.declarations.clone());
-                    }
+                // The document isn't an element and has no style. Its style is the initial
+                // style that the root element inherits.
+                if *kind != NodeKind::Document {
+                    // apply the cascaded values to RenderObject. The user agent style sheet
+                    // comes first, because its origin has the lowest precedence.
+                    let cascaded_values =
+                        cascade(&[&ua_style_sheet(), cssom], document, n, &viewport);
+                    let parent_style = parent_obj.as_ref().map(|p| p.borrow().style.clone());
+                    render_object.borrow_mut().style.compute(
+                        &cascaded_values,
+                        parent_style.as_ref(),
+                        root_font_size,
+                        viewport,
+                    );
                 }
 
                 if render_object.borrow().style.display() == DisplayType::DisplayNone {
@@ -473,11 +903,15 @@ impl RenderTree {
 
     /// Converts DOM tree to render tree.
     fn create_render_tree(
-        node: &Option<Rc<RefCell<Node>>>,
+        document: &Document,
+        node: Option<NodeId>,
         parent_obj: &Option<Rc<RefCell<RenderObject>>>,
+        root_font_size: Option<f64>,
         cssom: &StyleSheet,
+        viewport: Viewport,
     ) -> Option<Rc<RefCell<RenderObject>>> {
-        let render_object = Self::create_render_object(&node, parent_obj, cssom);
+        let render_object =
+            Self::create_render_object(document, node, parent_obj, root_font_size, cssom, viewport);
 
         if render_object.is_none() {
             return None;
@@ -485,62 +919,30 @@ impl RenderTree {
 
         match node {
             Some(n) => {
-                let original_first_child = n.borrow().first_child();
-                let original_next_sibling = n.borrow().next_sibling();
-                let mut first_child =
-                    Self::create_render_tree(&original_first_child, &render_object, cssom);
-                let mut next_sibling =
-                    Self::create_render_tree(&original_next_sibling, &None, cssom);
-
-                // if the original first child node is "display:none" and the original first child
-                // node has a next sibiling node, treat the next sibling node as a new first child
-                // node.
-                if first_child.is_none() && original_first_child.is_some() {
-                    let mut original_dom_node = original_first_child
-                        .expect("first child should exist")
-                        .borrow()
-                        .next_sibling();
-
-                    loop {
-                        first_child =
-                            Self::create_render_tree(&original_dom_node, &render_object, cssom);
-
-                        // check the next sibling node
-                        if first_child.is_none() && original_dom_node.is_some() {
-                            original_dom_node = original_dom_node
-                                .expect("next sibling should exist")
-                                .borrow()
-                                .next_sibling();
-                            continue;
-                        }
-
-                        break;
+                // The descendants of the root element resolve `rem` against its font size.
+                let children_root_font_size = match render_object {
+                    Some(ref obj) if document.document_element() == Some(n) => {
+                        Some(obj.borrow().style.font_size())
                     }
-                }
-
-                // if the original next sibling node is "display:none" and the original next
-                // sibling node has a next sibling node, treat the next sibling node as a new next
-                // sibling node.
-                if next_sibling.is_none() && n.borrow().next_sibling().is_some() {
-                    let mut original_dom_node = original_next_sibling
-                        .expect("first child should exist")
-                        .borrow()
-                        .next_sibling();
-
-                    loop {
-                        next_sibling = Self::create_render_tree(&original_dom_node, &None, cssom);
-
-                        if next_sibling.is_none() && original_dom_node.is_some() {
-                            original_dom_node = original_dom_node
-                                .expect("next sibling should exist")
-                                .borrow()
-                                .next_sibling();
-                            continue;
-                        }
-
-                        break;
-                    }
-                }
+                    _ => root_font_size,
+                };
+                let first_child = Self::create_first_render_tree(
+                    document,
+                    document.node(n).first_child(),
+                    &render_object,
+                    children_root_font_size,
+                    cssom,
+                    viewport,
+                );
+                // The siblings share the parent, whose style they inherit.
+                let next_sibling = Self::create_first_render_tree(
+                    document,
+                    document.node(n).next_sibling(),
+                    parent_obj,
+                    root_font_size,
+                    cssom,
+                    viewport,
+                );
 
                 let obj = match render_object {
                     Some(ref obj) => obj,
@@ -555,31 +957,344 @@ impl RenderTree {
         return render_object;
     }
 
+    /// Converts `node` and its following siblings to render trees, and returns the first one.
+    /// If `node` is "display:none", the next sibling node is treated as a new first node.
+    fn create_first_render_tree(
+        document: &Document,
+        node: Option<NodeId>,
+        parent_obj: &Option<Rc<RefCell<RenderObject>>>,
+        root_font_size: Option<f64>,
+        cssom: &StyleSheet,
+        viewport: Viewport,
+    ) -> Option<Rc<RefCell<RenderObject>>> {
+        let mut original_dom_node = node;
+        loop {
+            let render_object = Self::create_render_tree(
+                document,
+                original_dom_node,
+                parent_obj,
+                root_font_size,
+                cssom,
+                viewport,
+            );
+
+            // check the next sibling node
+            match original_dom_node {
+                Some(n) if render_object.is_none() => {
+                    original_dom_node = document.node(n).next_sibling();
+                }
+                _ => return render_object,
+            }
+        }
+    }
+
+    /// Returns the render object of `node` in the render tree whose root is `obj`.
+    fn find_render_object(
+        obj: &Option<Rc<RefCell<RenderObject>>>,
+        node: NodeId,
+    ) -> Option<Rc<RefCell<RenderObject>>> {
+        let mut current = obj.clone();
+        while let Some(o) = current {
+            if o.borrow().node == node {
+                return Some(o);
+            }
+            if let Some(found) = Self::find_render_object(&o.borrow().first_child(), node) {
+                return Some(found);
+            }
+            current = o.borrow().next_sibling();
+        }
+        None
+    }
+
+    /// Recreates the render objects of the children of `node`. If `node` isn't rendered, the
+    /// children of the nearest ancestor that is rendered are recreated instead.
+    fn recreate_children(&mut self, document: &Document, node: NodeId, cssom: &StyleSheet) {
+        let mut current = Some(node);
+        while let Some(n) = current {
+            if let Some(obj) = Self::find_render_object(&self.root, n) {
+                // The children of the document include the root element.
+                let root_font_size = match document.node(n).kind() {
+                    NodeKind::Document => None,
+                    _ => Some(self.root_font_size()),
+                };
+                let first_child = Self::create_first_render_tree(
+                    document,
+                    document.node(n).first_child(),
+                    &Some(obj.clone()),
+                    root_font_size,
+                    cssom,
+                    self.viewport,
+                );
+                obj.borrow_mut().first_child = first_child;
+                return;
+            }
+            current = document.node(n).parent();
+        }
+    }
+
+    /// Updates the render tree for the DOM changes in `records`. Only the render objects of the
+    /// changed parts are recreated, and then the layout is recalculated. Returns true if
+    /// anything is changed.
+    pub fn update(
+        &mut self,
+        records: &[MutationRecord],
+        document: &Document,
+        cssom: &StyleSheet,
+    ) -> bool {
+        if records.is_empty() {
+            return false;
+        }
+
+        // The DOM nodes whose children have to be recreated.
+        let mut dirty_nodes: Vec<NodeId> = Vec::new();
+        for record in records {
+            let node = match record.record_type {
+                MutationRecordType::ChildList => record.target,
+                // The attribute can change whether the element is rendered and the styles of the
+                // element and its descendants, so the element is recreated from its parent.
+                MutationRecordType::Attributes => match document.node(record.target).parent() {
+                    Some(parent) => parent,
+                    None => continue,
+                },
+                // A render object of a text reads the data from the DOM node, so only the layout
+                // has to be updated.
+                MutationRecordType::CharacterData => continue,
+            };
+            if !dirty_nodes.contains(&node) {
+                dirty_nodes.push(node);
+            }
+        }
+
+        for node in &dirty_nodes {
+            // skip the node if one of its ancestors is recreated
+            let mut ancestor = document.node(*node).parent();
+            let mut recreated_by_ancestor = false;
+            while let Some(a) = ancestor {
+                if dirty_nodes.contains(&a) {
+                    recreated_by_ancestor = true;
+                    break;
+                }
+                ancestor = document.node(a).parent();
+            }
+
+            if !recreated_by_ancestor {
+                self.recreate_children(document, *node, cssom);
+            }
+        }
+
+        self.layout(document);
+
+        true
+    }
+
+    /// Recreates the whole render tree and recalculates the layout, e.g. when the styles depend
+    /// on a state that is not in the DOM tree, such as which element is hovered.
+    pub fn restyle(&mut self, document: &Document, cssom: &StyleSheet) {
+        self.root = Self::create_render_tree(
+            document,
+            Some(document.root()),
+            &None,
+            None,
+            cssom,
+            self.viewport,
+        );
+        self.layout(document);
+    }
+
+    /// Sets the viewport, e.g. when the window is resized, and recreates the whole render tree,
+    /// because the media queries and the lengths relative to the viewport may have different
+    /// results.
+    pub fn set_viewport(&mut self, document: &Document, cssom: &StyleSheet, viewport: Viewport) {
+        self.viewport = viewport;
+        self.restyle(document, cssom);
+    }
+
     fn layout_node(
         &self,
         node: &Option<Rc<RefCell<RenderObject>>>,
-        parent_style: &RenderStyle,
-        parent_position: &LayoutPosition,
+        previous: &RenderObject,
+        containing_block: &UsedValues,
+        root_font_size: f64,
     ) {
         match node {
             Some(n) => {
-                n.borrow_mut().layout(parent_style, parent_position);
+                n.borrow_mut()
+                    .layout(previous, containing_block, root_font_size, self.viewport);
 
                 let first_child = n.borrow().first_child();
-                self.layout_node(&first_child, &n.borrow().style, &n.borrow().position);
+                let obj = n.borrow();
+                self.layout_node(&first_child, &obj, &obj.used, root_font_size);
 
-                let next_sibling = n.borrow().next_sibling();
-                self.layout_node(&next_sibling, &n.borrow().style, &n.borrow().position);
+                let next_sibling = obj.next_sibling();
+                self.layout_node(&next_sibling, &obj, containing_block, root_font_size);
             }
             None => return,
         }
     }
 
     /// Calculate the layout position.
-    fn layout(&mut self) {
-        let fake_node = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
-        let fake_style = RenderStyle::new(&fake_node);
-        let fake_position = LayoutPosition::new(0.0, 0.0);
-        self.layout_node(&self.root, &fake_style, &fake_position);
+    fn layout(&mut self, document: &Document) {
+        let fake_object = RenderObject::new(document.root(), &NodeKind::Document);
+        // "The containing block in which the root element lives is a rectangle called the
+        // initial containing block. For continuous media, it has the dimensions of the
+        // viewport"
+        // https://www.w3.org/TR/CSS2/visudet.html#containing-block-details
+        let initial_containing_block = UsedValues::new(self.viewport.width, self.viewport.height);
+        self.layout_node(
+            &self.root,
+            &fake_object,
+            &initial_containing_block,
+            self.root_font_size(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::token::CssTokenizer;
+    use crate::renderer::html::token::HtmlTokenizer;
+
+    /// Returns the render object of the element whose id is `id`.
+    fn find_by_id(
+        document: &Document,
+        obj: &Option<Rc<RefCell<RenderObject>>>,
+        id: &str,
+    ) -> Option<Rc<RefCell<RenderObject>>> {
+        let mut current = obj.clone();
+        while let Some(o) = current {
+            if let Some(element) = document.node(o.borrow().node()).element() {
+                if element.get_attribute("id").as_deref() == Some(id) {
+                    return Some(o.clone());
+                }
+            }
+            if let Some(found) = find_by_id(document, &o.borrow().first_child(), id) {
+                return Some(found);
+            }
+            current = o.borrow().next_sibling();
+        }
+        None
+    }
+
+    /// Creates the render tree of `html` with the author style sheet `css`, and returns the
+    /// render objects of the elements whose ids are `ids`.
+    fn render(html: &str, css: &str, ids: &[&str]) -> Vec<Rc<RefCell<RenderObject>>> {
+        let document = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = document.borrow();
+        let cssom = CssParser::new(CssTokenizer::new(css.to_string())).parse_stylesheet();
+        let tree = RenderTree::new(&document, &cssom, Viewport::new(800.0, 600.0));
+        ids.iter()
+            .map(|id| find_by_id(&document, &tree.root, id).expect("the element should exist"))
+            .collect()
+    }
+
+    #[test]
+    fn font_size_lengths() {
+        let objs = render(
+            r#"<div id="a"><div id="b"><div id="c"><div id="d"><p id="e">e"#,
+            "#a { font-size: 20px } #b { font-size: 2em } #c { font-size: 150% } \
+             #d { font-size: 12pt } #e { font-size: -1px }",
+            &["a", "b", "c", "d", "e"],
+        );
+        let sizes: Vec<f64> = objs.iter().map(|o| o.borrow().style.font_size()).collect();
+        // The invalid negative size is ignored, so the font size is inherited.
+        assert_eq!(sizes, vec![20.0, 40.0, 60.0, 16.0, 16.0]);
+    }
+
+    #[test]
+    fn siblings_inherit_from_parent() {
+        let objs = render(
+            r#"<div id="a"><p id="b">b</p><p id="c">c</p><p id="d">d</p></div>"#,
+            "#a { color: red; font-size: 20px; font-weight: bold; list-style-type: square; \
+             width: 200px; margin-left: 10px }",
+            &["b", "c", "d"],
+        );
+        for obj in objs {
+            let style = &obj.borrow().style;
+            assert_eq!(style.color(), Color::from_name("red"));
+            assert_eq!(style.font_size(), 20.0);
+            assert_eq!(style.font_weight(), FontWeight::Bold);
+            assert_eq!(style.list_style_type(), ListStyleType::Square);
+            // width and margins are not inherited.
+            assert_eq!(style.width, None);
+            assert_eq!(obj.borrow().margin_left(), 0.0);
+        }
+    }
+
+    #[test]
+    fn em_resolves_against_font_size() {
+        let objs = render(
+            r#"<div id="a">a</div>"#,
+            "#a { font-size: 10px; width: 3em; margin-left: 2ch }",
+            &["a"],
+        );
+        let a = objs[0].borrow();
+        assert_eq!(a.width(), 30.0);
+        assert_eq!(a.margin_left(), 10.0);
+    }
+
+    #[test]
+    fn rem_resolves_against_root_font_size() {
+        let objs = render(
+            r#"<html id="root"><body><p id="a">a</p><p id="b">b</p></body></html>"#,
+            "html { font-size: xx-large; width: 20rem } #a { width: 2rem } \
+             #b { font-size: 0.5rem; width: 2rem }",
+            &["root", "a", "b"],
+        );
+        // `rem` in the root element is relative to its own font size, except in font-size.
+        assert_eq!(objs[0].borrow().width(), 20.0 * 32.0);
+        assert_eq!(objs[1].borrow().width(), 64.0);
+        assert_eq!(objs[2].borrow().style.font_size(), 16.0);
+        assert_eq!(objs[2].borrow().width(), 64.0);
+    }
+
+    #[test]
+    fn nth_child() {
+        let objs = render(
+            r#"<div><p id="a">a</p><p id="b">b</p><p id="c">c</p></div>"#,
+            "p:nth-child(odd) { font-size: 10px } p:nth-child(-n+2) { font-weight: bold } \
+             p:nth-child(2n - 9223372036854775808) { color: red }",
+            &["a", "b", "c"],
+        );
+        let sizes: Vec<f64> = objs.iter().map(|o| o.borrow().style.font_size()).collect();
+        assert_eq!(sizes, vec![10.0, 16.0, 10.0]);
+        assert_eq!(objs[1].borrow().style.font_weight(), FontWeight::Bold);
+        assert_eq!(objs[2].borrow().style.font_weight(), FontWeight::Normal);
+        // A * n + B overflows, so nothing matches.
+        assert!(objs
+            .iter()
+            .all(|o| o.borrow().style.color() != Color::from_name("red")));
+    }
+
+    #[test]
+    fn line_height_inherits() {
+        let objs = render(
+            r#"<div id="a"><p id="b">b<span id="c">c</span></p></div><div id="d"><p id="e">e"#,
+            "#a { font-size: 10px; line-height: 2 } #c { font-size: 20px } \
+             #d { font-size: 10px; line-height: 150% } #e { font-size: 20px }",
+            &["a", "b", "c", "d", "e"],
+        );
+        let heights: Vec<f64> = objs
+            .iter()
+            .map(|o| o.borrow().style.line_height())
+            .collect();
+        // A number is multiplied by the font size of each element, but a percentage is computed
+        // to a length in the element that specifies it.
+        assert_eq!(heights, vec![20.0, 20.0, 40.0, 15.0, 15.0]);
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        let objs = render(
+            r#"<div id="a"><span id="b">b</span></div><p id="c">c</p>"#,
+            "#a { color: RED; font-weight: Bold } #b { display: BLOCK; font-weight: INITIAL } \
+             @supports (display: BLOCK) { #c { color: Blue } }",
+            &["a", "b", "c"],
+        );
+        assert_eq!(objs[0].borrow().style.color(), Color::from_name("red"));
+        assert_eq!(objs[0].borrow().style.font_weight(), FontWeight::Bold);
+        assert_eq!(objs[1].borrow().style.display(), DisplayType::Block);
+        assert_eq!(objs[1].borrow().style.font_weight(), FontWeight::Normal);
+        assert_eq!(objs[2].borrow().style.color(), Color::from_name("blue"));
     }
 }
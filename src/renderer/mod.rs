@@ -2,3 +2,4 @@ pub mod css;
 pub mod html;
 pub mod js;
 pub mod layout;
+pub mod page;
@@ -0,0 +1,295 @@
+//! A page loaded in the browser window.
+
+use crate::renderer::css::cssom::StyleSheet;
+use crate::renderer::css::values::Viewport;
+use crate::renderer::html::dom::{Document, ElementKind, NodeId};
+use crate::renderer::html::mutation_observer::{MutationObserver, MutationObserverInit};
+use crate::renderer::js::runtime::JsRuntime;
+use crate::renderer::layout::render_tree::RenderTree;
+use crate::url::ParsedUrl;
+use core::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::string::String;
+
+/// A navigation to `url` after `seconds`, requested by a meta element.
+/// https://html.spec.whatwg.org/multipage/semantics.html#attr-meta-http-equiv-refresh
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refresh {
+    pub seconds: u64,
+    pub url: String,
+}
+
+/// Parses the content attribute of a meta element in the refresh state. Returns the time and the
+/// URL string if any, or None if the content is invalid.
+/// https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn parse_refresh(input: &str) -> Option<(u64, Option<String>)> {
+    let is_ascii_whitespace = |c: char| c.is_ascii_whitespace();
+
+    // "3. Skip ASCII whitespace within input given position."
+    let input = input.trim_start_matches(is_ascii_whitespace);
+
+    // "4. Let time be 0."
+    // "5. Collect a sequence of code points that are ASCII digits from input given position, and
+    // let the result be timeString."
+    let digits_end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (time_string, input) = input.split_at(digits_end);
+
+    // "6. If timeString is the empty string, then: 1. If the code point in input pointed to by
+    // position is not U+002E (.), then return."
+    if time_string.is_empty() && !input.starts_with('.') {
+        return None;
+    }
+    // "7. Otherwise, set time to the result of parsing timeString using the rules for parsing
+    // non-negative integers."
+    let time = time_string.parse::<u64>().unwrap_or(0);
+
+    // "8. Collect a sequence of code points that are ASCII digits and U+002E FULL STOP
+    // characters (.) from input given position. Ignore any collected characters."
+    let input = input.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    // "9. Let urlRecord be document's URL."
+    // "10. If position is not past the end of input, then:"
+    if input.is_empty() {
+        return Some((time, None));
+    }
+    // "1. If the code point in input pointed to by position is not U+003B (;), U+002C (,), or
+    // ASCII whitespace, then return."
+    if !input.starts_with(|c: char| c == ';' || c == ',' || c.is_ascii_whitespace()) {
+        return None;
+    }
+    // "2. Skip ASCII whitespace within input given position."
+    // "3. If the code point in input pointed to by position is U+003B (;) or U+002C (,), then
+    // advance position to the next code point."
+    // "4. Skip ASCII whitespace within input given position."
+    let mut input = input.trim_start_matches(is_ascii_whitespace);
+    if input.starts_with([';', ',']) {
+        input = &input[1..];
+    }
+    input = input.trim_start_matches(is_ascii_whitespace);
+
+    // "11. If position is not past the end of input, then:"
+    if input.is_empty() {
+        return Some((time, None));
+    }
+    // "3. If the code point in input pointed to by position is U+0055 (U) or U+0075 (u), then
+    // advance position to the next code point. Otherwise, jump to the step labeled skip quotes."
+    // "4. - 5." do the same for "R" and "L".
+    if input
+        .get(..3)
+        .is_some_and(|s| s.eq_ignore_ascii_case("url"))
+    {
+        let rest = input[3..].trim_start_matches(is_ascii_whitespace);
+        // "7. If the code point in input pointed to by position is U+003D (=), then advance
+        // position to the next code point. Otherwise, jump to the step labeled parse."
+        if let Some(rest) = rest.strip_prefix('=') {
+            // "8. Skip ASCII whitespace within input given position."
+            input = rest.trim_start_matches(is_ascii_whitespace);
+        }
+    }
+    // "9. Skip quotes: If the code point in input pointed to by position is U+0027 (') or U+0022
+    // ("), then let quote be that code point, and advance position to the next code point."
+    // "10. Set urlString to the substring of input from the code point at position to the end
+    // of the string."
+    // "11. If quote is not the empty string, and there is a code point in urlString equal to
+    // quote, then truncate urlString at that code point, so that it and all subsequent code
+    // points are removed."
+    let url_string = match input.chars().next() {
+        Some(quote) if quote == '\'' || quote == '"' => {
+            let rest = &input[1..];
+            match rest.find(quote) {
+                Some(i) => &rest[..i],
+                None => rest,
+            }
+        }
+        _ => input,
+    };
+
+    Some((time, Some(url_string.to_string())))
+}
+
+/// The result of loading a URL. A page keeps the JavaScript runtime alive after the scripts in
+/// the document are executed, so that the browser window can dispatch events to the DOM tree.
+#[derive(Debug, Clone)]
+pub struct Page {
+    url: String,
+    document: Rc<RefCell<Document>>,
+    runtime: Rc<RefCell<JsRuntime>>,
+    cssom: StyleSheet,
+    render_tree: Rc<RefCell<RenderTree>>,
+    /// Observes all changes in the document to update the render tree.
+    observer: Rc<RefCell<MutationObserver>>,
+}
+
+impl Page {
+    pub fn new(
+        url: String,
+        document: Rc<RefCell<Document>>,
+        runtime: JsRuntime,
+        cssom: StyleSheet,
+        render_tree: RenderTree,
+    ) -> Self {
+        let observer = Rc::new(RefCell::new(MutationObserver::new()));
+        let options = MutationObserverInit {
+            child_list: true,
+            attributes: Some(true),
+            character_data: Some(true),
+            subtree: true,
+            ..Default::default()
+        };
+        let root = document.borrow().root();
+        MutationObserver::observe(&observer, &mut document.borrow_mut(), root, options)
+            .expect("the options to observe the document should be valid");
+
+        Self {
+            url,
+            document,
+            runtime: Rc::new(RefCell::new(runtime)),
+            cssom,
+            render_tree: Rc::new(RefCell::new(render_tree)),
+            observer,
+        }
+    }
+
+    pub fn document(&self) -> Rc<RefCell<Document>> {
+        self.document.clone()
+    }
+
+    pub fn render_tree(&self) -> Rc<RefCell<RenderTree>> {
+        self.render_tree.clone()
+    }
+
+    /// Returns the font files that are loaded for the @font-face rules of the page.
+    pub fn font_files(&self) -> Vec<PathBuf> {
+        self.cssom
+            .font_face_rules()
+            .iter()
+            .filter_map(|rule| rule.file.clone())
+            .collect()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#document.title
+    pub fn title(&self) -> String {
+        self.document.borrow().title()
+    }
+
+    /// Returns the navigation requested by the first meta element in the refresh state that has
+    /// a valid content attribute.
+    /// https://html.spec.whatwg.org/multipage/semantics.html#attr-meta-http-equiv-refresh
+    pub fn refresh(&self) -> Option<Refresh> {
+        let document = self.document.borrow();
+        let metas = Document::get_elements_by_tag_name(document.root(), "meta");
+        for meta in metas.elements(&document) {
+            let element = match document.node(meta).element() {
+                Some(e) if e.kind() == ElementKind::Meta => e,
+                _ => continue,
+            };
+
+            // The http-equiv attribute is an enumerated attribute, so the keyword is ASCII
+            // case-insensitive.
+            let http_equiv = element.get_attribute("http-equiv").unwrap_or_default();
+            if !http_equiv.eq_ignore_ascii_case("refresh") {
+                continue;
+            }
+
+            // "1. If the meta element has no content attribute, or if that attribute's value is
+            // the empty string, then return."
+            let content = match element.get_attribute("content") {
+                Some(c) if !c.is_empty() => c,
+                _ => continue,
+            };
+
+            // "2. Let input be the value of the element's content attribute."
+            // "3. Run the shared declarative refresh steps with the meta element's node document,
+            // input, and the meta element."
+            if let Some((seconds, url)) = parse_refresh(&content) {
+                // "Parse: Parse urlString relative to document. If that fails, return. Otherwise,
+                // set urlRecord to the resulting URL record."
+                let base = ParsedUrl::new(self.url.clone());
+                let url = base.join(&url.unwrap_or_default());
+                return Some(Refresh { seconds, url });
+            }
+        }
+        None
+    }
+
+    /// Fires an event that bubbles and is cancelable, such as `click`, at `target`. Returns false
+    /// if a listener canceled the event, so the caller must not run the default action.
+    /// https://dom.spec.whatwg.org/#concept-event-fire
+    pub fn fire_event(&self, target: NodeId, event_type: &str) -> bool {
+        self.runtime
+            .borrow_mut()
+            .fire_event(target, event_type, true, true)
+    }
+
+    /// Sets the element that the pointing device designates, and restyles the page if it's
+    /// changed. Returns true if the page has to be repainted.
+    /// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-hover
+    pub fn set_hovered_element(&self, element: Option<NodeId>) -> bool {
+        if self.document.borrow().hovered_element() == element {
+            return false;
+        }
+        self.document.borrow_mut().set_hovered_element(element);
+        self.restyle();
+        true
+    }
+
+    /// Sets the element being activated, and restyles the page if it's changed. Returns true if
+    /// the page has to be repainted.
+    /// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-active
+    pub fn set_active_element(&self, element: Option<NodeId>) -> bool {
+        if self.document.borrow().active_element() == element {
+            return false;
+        }
+        self.document.borrow_mut().set_active_element(element);
+        self.restyle();
+        true
+    }
+
+    /// Sets the focused element, and restyles the page if it's changed. Returns true if the page
+    /// has to be repainted.
+    /// https://html.spec.whatwg.org/multipage/interaction.html#focus-update-steps
+    pub fn set_focused_element(&self, element: Option<NodeId>) -> bool {
+        if self.document.borrow().focused_element() == element {
+            return false;
+        }
+        self.document.borrow_mut().set_focused_element(element);
+        self.restyle();
+        true
+    }
+
+    /// Sets the viewport when the window is resized or the device settings are changed, and
+    /// restyles the page if it's changed. Returns true if the page has to be repainted.
+    /// https://www.w3.org/TR/mediaqueries-5/#mq-list
+    pub fn set_viewport(&self, viewport: Viewport) -> bool {
+        if self.render_tree.borrow().viewport() == viewport {
+            return false;
+        }
+        self.render_tree
+            .borrow_mut()
+            .set_viewport(&self.document.borrow(), &self.cssom, viewport);
+        true
+    }
+
+    fn restyle(&self) {
+        self.render_tree
+            .borrow_mut()
+            .restyle(&self.document.borrow(), &self.cssom);
+    }
+
+    /// Updates the render tree for the DOM changes since the last update, such as the changes
+    /// made by event listeners. Returns true if the page has to be repainted.
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#update-the-rendering
+    pub fn update_rendering(&self) -> bool {
+        let records = self.observer.borrow_mut().take_records();
+        MutationObserver::remove_transient_observers(
+            &self.observer,
+            &mut self.document.borrow_mut(),
+        );
+        self.render_tree
+            .borrow_mut()
+            .update(&records, &self.document.borrow(), &self.cssom)
+    }
+}
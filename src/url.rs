@@ -29,7 +29,7 @@ impl Protocol {
 
 #[derive(Debug)]
 pub struct ParsedUrl {
-    _scheme: Protocol,
+    scheme: Protocol,
     pub host: String,
     pub port: u16,
     pub path: String,
@@ -104,10 +104,50 @@ impl ParsedUrl {
         };
 
         Self {
-            _scheme: scheme,
+            scheme,
             host,
             port,
             path,
         }
     }
+    /// Resolves `input`, which may be a relative URL, against this URL and returns the absolute
+    /// URL. Only an absolute URL, a scheme-relative URL, a path-absolute URL and a path-relative
+    /// URL are supported, and "." and ".." segments are not resolved.
+    /// https://url.spec.whatwg.org/#concept-basic-url-parser
+    /// https://datatracker.ietf.org/doc/html/rfc3986#section-5.2
+    pub fn join(&self, input: &str) -> String {
+        let input = input.trim();
+        if input.contains("://") {
+            return input.to_string();
+        }
+
+        // e.g. "//example.com/style.css"
+        if input.starts_with("//") {
+            return self.scheme.to_string() + ":" + input;
+        }
+
+        let origin = format!("{}://{}:{}", self.scheme.to_string(), self.host, self.port);
+
+        // An empty string refers to this URL itself.
+        if input.is_empty() {
+            return format!("{}/{}", origin, self.path);
+        }
+
+        // e.g. "/style.css"
+        if input.starts_with('/') {
+            return origin + input;
+        }
+
+        // e.g. "style.css". "Remove path's last item, if any." and append the input.
+        // https://url.spec.whatwg.org/#shorten-a-urls-path
+        let path = match self.path.find(['?', '#']) {
+            Some(i) => &self.path[..i],
+            None => &self.path,
+        };
+        let directory = match path.rfind('/') {
+            Some(i) => &path[..i + 1],
+            None => "",
+        };
+        format!("{}/{}{}", origin, directory, input)
+    }
 }